@@ -0,0 +1,901 @@
+//! Listas de control de acceso (ACL).
+//!
+//! Cada conexion actua en nombre de un usuario. Ademas de su contraseña,
+//! cada usuario tiene unas reglas que indican:
+//!
+//! * Los comandos que puede ejecutar, por nombre (`+get`, `-set`) o por
+//!   categoria (`+@read`, `-@admin`). Las reglas se aplican en orden, de
+//!   modo que la ultima que afecta a un comando decide si se permite.
+//! * Las claves a las que puede acceder, como patrones glob (`~cache:*`).
+//! * Los canales de pub/sub que puede utilizar, como patrones glob
+//!   (`&news.*`).
+//!
+//! El usuario `default` siempre existe. Las conexiones nuevas actuan en su
+//! nombre si no tiene contraseña (`nopass`); si la tiene tienen que
+//! autenticarse con `AUTH`. La opcion `requirepass` del servidor es la
+//! contraseña de `default`.
+//!
+//! Los usuarios se configuran con `ACL SETUSER` o se cargan de un fichero
+//! ACL, en el que cada linea define un usuario con el mismo formato que
+//! `ACL LIST`:
+//!
+//! ```text
+//! user default on nopass ~* &* +@all
+//! user reader on #<sha256 de la contraseña> ~cache:* resetchannels -@all +@read
+//! ```
+//!
+//! Los intentos denegados, por permisos o por autenticacion fallida, se
+//! registran en un log que se consulta con `ACL LOG`.
+
+use crate::{Frame, ServerError, ServerErrorKind, Session};
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Usuario que siempre existe y con el que se autentica `AUTH password`.
+pub(crate) const DEFAULT_USER: &str = "default";
+
+/// Numero maximo de entradas del log.
+const LOG_MAX_LEN: usize = 128;
+
+/// Los intentos denegados iguales a uno registrado hace menos de este
+/// tiempo, en milisegundos, se agrupan en la misma entrada del log.
+const LOG_GROUP_MILLIS: u64 = 60_000;
+
+/// Categoria de comandos.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Category {
+    /// Leen claves
+    Read,
+
+    /// Modifican claves
+    Write,
+
+    /// Operan sobre claves sin importar su valor
+    Keyspace,
+
+    /// Pub/sub
+    PubSub,
+
+    /// Administracion del servidor
+    Admin,
+
+    /// Pueden afectar al servidor o a otros clientes
+    Dangerous,
+
+    /// Afectan a la propia conexion
+    Connection,
+}
+
+/// Usuarios, log y fichero ACL del servidor.
+///
+/// Es un handle al estado compartido, de modo que puede clonarse y
+/// utilizarse desde cualquier conexion.
+#[derive(Clone, Debug)]
+pub(crate) struct AclHandle {
+    shared: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Usuarios, por nombre.
+    users: BTreeMap<String, User>,
+
+    /// Intentos denegados. La entrada mas reciente es la primera.
+    log: VecDeque<LogEntry>,
+
+    /// Identificador de la siguiente entrada del log.
+    next_entry_id: u64,
+
+    /// Fichero ACL, si esta configurado.
+    file: Option<PathBuf>,
+}
+
+/// Un usuario y sus permisos.
+#[derive(Clone, Debug)]
+struct User {
+    /// Solo se puede autenticar un usuario activo.
+    enabled: bool,
+
+    /// Se acepta cualquier contraseña.
+    nopass: bool,
+
+    /// Hashes SHA-256 de las contraseñas, en hexadecimal.
+    passwords: BTreeSet<String>,
+
+    /// Reglas de comandos, en el orden en el que se aplican.
+    commands: Vec<CommandRule>,
+
+    /// Patrones de las claves accesibles.
+    keys: Vec<String>,
+
+    /// Patrones de los canales accesibles.
+    channels: Vec<String>,
+}
+
+/// Permite (`+`) o deniega (`-`) comandos.
+#[derive(Clone, Debug, PartialEq)]
+struct CommandRule {
+    allow: bool,
+    target: Target,
+}
+
+/// Comandos a los que afecta una regla.
+#[derive(Clone, Debug, PartialEq)]
+enum Target {
+    /// Todos los comandos (`@all`)
+    All,
+
+    /// Los comandos de una categoria
+    Category(Category),
+
+    /// Un comando, por nombre
+    Command(String),
+}
+
+/// Intento denegado registrado en el log.
+#[derive(Debug)]
+struct LogEntry {
+    /// Numero de intentos agrupados en la entrada.
+    count: u64,
+
+    reason: Reason,
+
+    /// Comando, clave o canal denegado.
+    object: String,
+
+    username: String,
+
+    /// Descripcion del cliente.
+    client_info: String,
+
+    entry_id: u64,
+
+    /// Instantes, en milisegundos desde epoch, del primer y el ultimo
+    /// intento.
+    created: u64,
+    updated: u64,
+}
+
+/// Motivo por el que se ha denegado un intento.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Reason {
+    /// Autenticacion fallida
+    Auth,
+
+    /// Comando no permitido
+    Command,
+
+    /// Clave no permitida
+    Key,
+
+    /// Canal no permitido
+    Channel,
+}
+
+impl Category {
+    const ALL: [Category; 7] = [
+        Category::Read,
+        Category::Write,
+        Category::Keyspace,
+        Category::PubSub,
+        Category::Admin,
+        Category::Dangerous,
+        Category::Connection,
+    ];
+
+    /// Nombre de la categoria, sin `@`.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Category::Read => "read",
+            Category::Write => "write",
+            Category::Keyspace => "keyspace",
+            Category::PubSub => "pubsub",
+            Category::Admin => "admin",
+            Category::Dangerous => "dangerous",
+            Category::Connection => "connection",
+        }
+    }
+
+    /// Categorias existentes.
+    pub(crate) fn all() -> &'static [Category] {
+        &Category::ALL
+    }
+
+    fn from_name(name: &str) -> Option<Category> {
+        Category::ALL
+            .into_iter()
+            .find(|category| category.name() == name)
+    }
+}
+
+impl AclHandle {
+    /// Crea el estado con el usuario `default` sin contraseña y con todos
+    /// los permisos.
+    pub(crate) fn new() -> AclHandle {
+        let mut users = BTreeMap::new();
+        users.insert(DEFAULT_USER.to_string(), User::default_user());
+
+        AclHandle {
+            shared: Arc::new(Mutex::new(State {
+                users,
+                log: VecDeque::new(),
+                next_entry_id: 0,
+                file: None,
+            })),
+        }
+    }
+
+    /// Usuario en cuyo nombre actua una conexion nueva: `default` si no
+    /// requiere contraseña y `None` si hay que autenticarse.
+    pub(crate) fn initial_user(&self) -> Option<String> {
+        let state = self.shared.lock().unwrap();
+
+        match state.users.get(DEFAULT_USER) {
+            Some(user) if user.enabled && user.nopass => Some(DEFAULT_USER.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Retorna `true` si el usuario `default` no tiene contraseña.
+    pub(crate) fn default_nopass(&self) -> bool {
+        let state = self.shared.lock().unwrap();
+        state
+            .users
+            .get(DEFAULT_USER)
+            .is_some_and(|user| user.nopass)
+    }
+
+    /// Establece la contraseña de `default` (`requirepass`). Con `None`
+    /// deja de requerirla.
+    pub(crate) fn set_default_password(&self, password: Option<&str>) {
+        let mut state = self.shared.lock().unwrap();
+        let user = state
+            .users
+            .entry(DEFAULT_USER.to_string())
+            .or_insert_with(User::default_user);
+
+        user.passwords.clear();
+        user.nopass = password.is_none();
+        user.passwords.extend(password.map(hash_password));
+    }
+
+    /// Comprueba las credenciales de un usuario.
+    pub(crate) fn authenticate(&self, username: &str, password: &str) -> bool {
+        let state = self.shared.lock().unwrap();
+
+        match state.users.get(username) {
+            Some(user) if user.enabled => {
+                user.nopass || user.passwords.contains(&hash_password(password))
+            }
+            _ => false,
+        }
+    }
+
+    /// Comprueba que el usuario de la sesion puede ejecutar el comando
+    /// `command`, de las categorias dadas, sobre las claves y canales
+    /// dados. Los intentos denegados se registran en el log.
+    pub(crate) fn check(
+        &self,
+        session: &Session,
+        command: &str,
+        categories: &[Category],
+        keys: &[&str],
+        channels: &[&str],
+    ) -> Result<(), ServerError> {
+        let mut state = self.shared.lock().unwrap();
+
+        let username = match &session.user {
+            Some(username) => username,
+            None => return Err(noauth()),
+        };
+
+        // El usuario puede haberse eliminado tras autenticarse.
+        let user = match state.users.get(username) {
+            Some(user) => user,
+            None => return Err(noauth()),
+        };
+
+        let denied = if !user.can_run(command, categories) {
+            Some((
+                Reason::Command,
+                command,
+                format!(
+                    "this user has no permissions to run the '{}' command",
+                    command
+                ),
+            ))
+        } else if let Some(key) = keys.iter().find(|key| !user.can_access_key(key)) {
+            Some((
+                Reason::Key,
+                *key,
+                "this user has no permissions to access one of the keys used as arguments"
+                    .to_string(),
+            ))
+        } else {
+            channels
+                .iter()
+                .find(|channel| !user.can_access_channel(channel))
+                .map(|channel| {
+                    (
+                        Reason::Channel,
+                        *channel,
+                        "this user has no permissions to access one of the channels used as \
+                         arguments"
+                            .to_string(),
+                    )
+                })
+        };
+
+        match denied {
+            Some((reason, object, message)) => {
+                let username = username.clone();
+                state.log(reason, object, &username, session);
+                Err(ServerError::new(ServerErrorKind::NoPerm, message))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Registra un intento denegado en el log.
+    pub(crate) fn log(&self, reason: Reason, object: &str, username: &str, session: &Session) {
+        self.shared
+            .lock()
+            .unwrap()
+            .log(reason, object, username, session);
+    }
+
+    /// Crea o modifica un usuario aplicando las reglas en orden. Si alguna
+    /// regla no es valida el usuario no se modifica.
+    pub(crate) fn set_user(&self, username: &str, rules: &[String]) -> Result<(), ServerError> {
+        let mut state = self.shared.lock().unwrap();
+
+        let mut user = state.users.get(username).cloned().unwrap_or_else(User::new);
+
+        for rule in rules {
+            user.apply_rule(rule).map_err(|err| {
+                ServerError::err(format!("Error in ACL SETUSER modifier '{}': {}", rule, err))
+            })?;
+        }
+
+        state.users.insert(username.to_string(), user);
+
+        Ok(())
+    }
+
+    /// Descripcion de un usuario para `ACL GETUSER`, o `Null` si no existe.
+    pub(crate) fn user_frame(&self, username: &str) -> Frame {
+        let state = self.shared.lock().unwrap();
+
+        let user = match state.users.get(username) {
+            Some(user) => user,
+            None => return Frame::Null,
+        };
+
+        let mut flags = vec![bulk(if user.enabled { "on" } else { "off" })];
+        if user.nopass {
+            flags.push(bulk("nopass"));
+        }
+
+        let passwords = user.passwords.iter().map(|hash| bulk(hash)).collect();
+        let patterns = |prefix: &str, patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| format!("{}{}", prefix, pattern))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        Frame::Array(vec![
+            bulk("flags"),
+            Frame::Array(flags),
+            bulk("passwords"),
+            Frame::Array(passwords),
+            bulk("commands"),
+            bulk(&user.describe_commands()),
+            bulk("keys"),
+            bulk(&patterns("~", &user.keys)),
+            bulk("channels"),
+            bulk(&patterns("&", &user.channels)),
+        ])
+    }
+
+    /// Elimina usuarios y retorna cuantos existian. `default` no se puede
+    /// eliminar.
+    pub(crate) fn del_users(&self, usernames: &[String]) -> Result<u64, ServerError> {
+        if usernames.iter().any(|username| username == DEFAULT_USER) {
+            return Err(ServerError::err("The 'default' user cannot be removed"));
+        }
+
+        let mut state = self.shared.lock().unwrap();
+
+        Ok(usernames
+            .iter()
+            .filter(|username| state.users.remove(*username).is_some())
+            .count() as u64)
+    }
+
+    /// Nombres de los usuarios.
+    pub(crate) fn usernames(&self) -> Vec<String> {
+        self.shared.lock().unwrap().users.keys().cloned().collect()
+    }
+
+    /// Descripcion de cada usuario, como las lineas del fichero ACL.
+    pub(crate) fn list(&self) -> Vec<String> {
+        let state = self.shared.lock().unwrap();
+
+        state
+            .users
+            .iter()
+            .map(|(username, user)| user.describe(username))
+            .collect()
+    }
+
+    /// Ultimas `count` entradas del log, la mas reciente primero.
+    pub(crate) fn log_frame(&self, count: usize) -> Frame {
+        let state = self.shared.lock().unwrap();
+        let now = now_millis();
+
+        let entries = state
+            .log
+            .iter()
+            .take(count)
+            .map(|entry| {
+                let age = now.saturating_sub(entry.created) as f64 / 1000.0;
+
+                Frame::Array(vec![
+                    bulk("count"),
+                    Frame::Integer(entry.count),
+                    bulk("reason"),
+                    bulk(entry.reason.name()),
+                    bulk("context"),
+                    bulk("toplevel"),
+                    bulk("object"),
+                    bulk(&entry.object),
+                    bulk("username"),
+                    bulk(&entry.username),
+                    bulk("age-seconds"),
+                    bulk(&format!("{:.3}", age)),
+                    bulk("client-info"),
+                    bulk(&entry.client_info),
+                    bulk("entry-id"),
+                    Frame::Integer(entry.entry_id),
+                    bulk("timestamp-created"),
+                    Frame::Integer(entry.created),
+                    bulk("timestamp-last-updated"),
+                    Frame::Integer(entry.updated),
+                ])
+            })
+            .collect();
+
+        Frame::Array(entries)
+    }
+
+    /// Vacia el log.
+    pub(crate) fn reset_log(&self) {
+        self.shared.lock().unwrap().log.clear();
+    }
+
+    /// Configura el fichero ACL.
+    pub(crate) fn set_file(&self, path: Option<PathBuf>) {
+        self.shared.lock().unwrap().file = path;
+    }
+
+    /// Sustituye los usuarios por los del fichero ACL. Si el fichero no es
+    /// valido no se modifica ningun usuario.
+    pub(crate) fn load(&self) -> crate::Result<()> {
+        let path = self.file()?;
+        let contents = fs::read_to_string(&path)
+            .map_err(|err| format!("Error loading ACL file {}: {}", path.display(), err))?;
+
+        let mut users = BTreeMap::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (username, user) = parse_line(line)
+                .map_err(|err| format!("{}:{}: {}", path.display(), number + 1, err))?;
+            users.insert(username, user);
+        }
+
+        users
+            .entry(DEFAULT_USER.to_string())
+            .or_insert_with(User::default_user);
+
+        self.shared.lock().unwrap().users = users;
+
+        Ok(())
+    }
+
+    /// Guarda los usuarios en el fichero ACL.
+    pub(crate) fn save(&self) -> crate::Result<()> {
+        let path = self.file()?;
+
+        let mut contents = self.list().join("\n");
+        contents.push('\n');
+
+        // Se escribe un fichero temporal que sustituye al anterior, de modo
+        // que nunca queda a medias.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    fn file(&self) -> crate::Result<PathBuf> {
+        let state = self.shared.lock().unwrap();
+
+        state.file.clone().ok_or_else(|| {
+            "This Redis instance is not configured to use an ACL file. You may want to specify \
+             users via the ACL SETUSER command and then issue a CONFIG REWRITE (assuming you \
+             have a Redis configuration file set) in order to store users in the Redis \
+             configuration."
+                .into()
+        })
+    }
+}
+
+impl State {
+    fn log(&mut self, reason: Reason, object: &str, username: &str, session: &Session) {
+        let now = now_millis();
+        let client_info = match session.peer_ip {
+            Some(ip) => format!("addr={}", ip),
+            None => "addr=?".to_string(),
+        };
+
+        // Los intentos repetidos se agrupan en la entrada existente, que
+        // pasa a ser la mas reciente.
+        let existing = self.log.iter().position(|entry| {
+            entry.reason == reason
+                && entry.object == object
+                && entry.username == username
+                && entry.client_info == client_info
+                && now.saturating_sub(entry.updated) < LOG_GROUP_MILLIS
+        });
+
+        let entry = match existing.and_then(|index| self.log.remove(index)) {
+            Some(mut entry) => {
+                entry.count += 1;
+                entry.updated = now;
+                entry
+            }
+            None => {
+                self.next_entry_id += 1;
+                LogEntry {
+                    count: 1,
+                    reason,
+                    object: object.to_string(),
+                    username: username.to_string(),
+                    client_info,
+                    entry_id: self.next_entry_id - 1,
+                    created: now,
+                    updated: now,
+                }
+            }
+        };
+
+        self.log.push_front(entry);
+        self.log.truncate(LOG_MAX_LEN);
+    }
+}
+
+impl User {
+    /// Usuario nuevo: desactivado, sin contraseñas y sin permisos.
+    fn new() -> User {
+        User {
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            commands: vec![],
+            keys: vec![],
+            channels: vec![],
+        }
+    }
+
+    /// El usuario `default` inicial: sin contraseña y con todos los
+    /// permisos.
+    fn default_user() -> User {
+        User {
+            enabled: true,
+            nopass: true,
+            passwords: BTreeSet::new(),
+            commands: vec![CommandRule {
+                allow: true,
+                target: Target::All,
+            }],
+            keys: vec!["*".to_string()],
+            channels: vec!["*".to_string()],
+        }
+    }
+
+    /// Aplica una regla de `ACL SETUSER`.
+    fn apply_rule(&mut self, rule: &str) -> Result<(), &'static str> {
+        match rule {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.keys = vec!["*".to_string()],
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => self.channels = vec!["*".to_string()],
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => self.set_all_commands(true),
+            "nocommands" => self.set_all_commands(false),
+            "reset" => *self = User::new(),
+            _ => {
+                let (prefix, value) = rule.split_at(rule.chars().next().map_or(0, char::len_utf8));
+
+                match prefix {
+                    ">" => {
+                        self.passwords.insert(hash_password(value));
+                        self.nopass = false;
+                    }
+                    "<" => {
+                        if !self.passwords.remove(&hash_password(value)) {
+                            return Err("no such password");
+                        }
+                    }
+                    "#" => {
+                        if !is_password_hash(value) {
+                            return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters");
+                        }
+                        self.passwords.insert(value.to_string());
+                        self.nopass = false;
+                    }
+                    "!" => {
+                        if !self.passwords.remove(value) {
+                            return Err("no such password");
+                        }
+                    }
+                    "~" => add_pattern(&mut self.keys, value),
+                    "&" => add_pattern(&mut self.channels, value),
+                    "+" | "-" => self.add_command_rule(prefix == "+", value)?,
+                    _ => return Err("Syntax error"),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `+@all` y `-@all` sustituyen al resto de reglas de comandos.
+    fn set_all_commands(&mut self, allow: bool) {
+        self.commands = vec![CommandRule {
+            allow,
+            target: Target::All,
+        }];
+    }
+
+    fn add_command_rule(&mut self, allow: bool, name: &str) -> Result<(), &'static str> {
+        let target = match name.strip_prefix('@') {
+            Some("all") => {
+                self.set_all_commands(allow);
+                return Ok(());
+            }
+            Some(category) => Target::Category(
+                Category::from_name(&category.to_lowercase())
+                    .ok_or("Unknown command or category name in ACL")?,
+            ),
+            None if name.is_empty() => return Err("Syntax error"),
+            None => Target::Command(name.to_lowercase()),
+        };
+
+        // Una regla repetida solo cuenta en su ultima posicion.
+        self.commands.retain(|rule| rule.target != target);
+        self.commands.push(CommandRule { allow, target });
+
+        Ok(())
+    }
+
+    /// La ultima regla que afecta al comando decide si se permite.
+    fn can_run(&self, command: &str, categories: &[Category]) -> bool {
+        self.commands
+            .iter()
+            .rev()
+            .find(|rule| match &rule.target {
+                Target::All => true,
+                Target::Category(category) => categories.contains(category),
+                Target::Command(name) => name == command,
+            })
+            .is_some_and(|rule| rule.allow)
+    }
+
+    fn can_access_key(&self, key: &str) -> bool {
+        self.keys
+            .iter()
+            .any(|pattern| glob_match(pattern.as_bytes(), key.as_bytes()))
+    }
+
+    fn can_access_channel(&self, channel: &str) -> bool {
+        self.channels
+            .iter()
+            .any(|pattern| glob_match(pattern.as_bytes(), channel.as_bytes()))
+    }
+
+    /// Reglas de comandos como se escriben en `ACL SETUSER`.
+    fn describe_commands(&self) -> String {
+        if self.commands.is_empty() {
+            return "-@all".to_string();
+        }
+
+        self.commands
+            .iter()
+            .map(|rule| {
+                let sign = if rule.allow { "+" } else { "-" };
+                match &rule.target {
+                    Target::All => format!("{}@all", sign),
+                    Target::Category(category) => format!("{}@{}", sign, category.name()),
+                    Target::Command(name) => format!("{}{}", sign, name),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Linea que describe el usuario en `ACL LIST` y en el fichero ACL.
+    fn describe(&self, username: &str) -> String {
+        let mut parts = vec![
+            format!("user {}", username),
+            (if self.enabled { "on" } else { "off" }).to_string(),
+        ];
+
+        if self.nopass {
+            parts.push("nopass".to_string());
+        }
+        parts.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        parts.extend(self.keys.iter().map(|pattern| format!("~{}", pattern)));
+
+        if self.channels.is_empty() {
+            parts.push("resetchannels".to_string());
+        }
+        parts.extend(self.channels.iter().map(|pattern| format!("&{}", pattern)));
+
+        parts.push(self.describe_commands());
+        parts.join(" ")
+    }
+}
+
+impl Reason {
+    fn name(self) -> &'static str {
+        match self {
+            Reason::Auth => "auth",
+            Reason::Command => "command",
+            Reason::Key => "key",
+            Reason::Channel => "channel",
+        }
+    }
+}
+
+/// Parsea una linea del fichero ACL: `user <nombre> <reglas...>`.
+fn parse_line(line: &str) -> Result<(String, User), String> {
+    let mut parts = line.split_whitespace();
+
+    let username = match (parts.next(), parts.next()) {
+        (Some("user"), Some(username)) => username.to_string(),
+        _ => return Err("lines must start with \"user <username>\"".to_string()),
+    };
+
+    let mut user = User::new();
+    for rule in parts {
+        user.apply_rule(rule)
+            .map_err(|err| format!("error in user rule '{}': {}", rule, err))?;
+    }
+
+    Ok((username, user))
+}
+
+/// Añade un patron si no estaba ya.
+fn add_pattern(patterns: &mut Vec<String>, pattern: &str) {
+    if !patterns.iter().any(|existing| existing == pattern) {
+        patterns.push(pattern.to_string());
+    }
+}
+
+/// Hash SHA-256 de una contraseña, en hexadecimal.
+fn hash_password(password: &str) -> String {
+    format!("{:x}", Sha256::digest(password.as_bytes()))
+}
+
+fn is_password_hash(hash: &str) -> bool {
+    hash.len() == 64
+        && hash
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+}
+
+fn noauth() -> ServerError {
+    ServerError::new(ServerErrorKind::NoAuth, "Authentication required.")
+}
+
+fn bulk(value: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(value.as_bytes()))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Comprueba si `string` encaja con el patron glob `pattern`.
+///
+/// Soporta `*` (cualquier secuencia), `?` (cualquier caracter), clases de
+/// caracteres (`[abc]`, `[a-z]`, `[^a]`) y `\` para escapar el siguiente
+/// caracter.
+pub(crate) fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => string.is_empty(),
+        Some((b'*', rest)) => {
+            // Varios `*` seguidos equivalen a uno.
+            let rest = match rest.iter().position(|byte| *byte != b'*') {
+                Some(start) => &rest[start..],
+                None => return true,
+            };
+            (0..=string.len()).any(|skip| glob_match(rest, &string[skip..]))
+        }
+        Some((b'?', rest)) => !string.is_empty() && glob_match(rest, &string[1..]),
+        Some((b'[', rest)) => match string.split_first() {
+            Some((byte, string)) => match match_class(rest, *byte) {
+                Some((true, rest)) => glob_match(rest, string),
+                _ => false,
+            },
+            None => false,
+        },
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            string.first() == Some(&rest[0]) && glob_match(&rest[1..], &string[1..])
+        }
+        Some((byte, rest)) => string.first() == Some(byte) && glob_match(rest, &string[1..]),
+    }
+}
+
+/// Comprueba si `byte` pertenece a la clase de caracteres al principio de
+/// `pattern`, tras el `[`. Retorna el resultado y el resto del patron, o
+/// `None` si la clase no se cierra.
+fn match_class(pattern: &[u8], byte: u8) -> Option<(bool, &[u8])> {
+    let (negate, mut pattern) = match pattern.split_first() {
+        Some((b'^', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+
+    let mut matched = false;
+
+    loop {
+        match pattern {
+            [] => return None,
+            [b']', rest @ ..] => return Some((matched != negate, rest)),
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == byte;
+                pattern = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (low, high) = if start <= end {
+                    (*start, *end)
+                } else {
+                    (*end, *start)
+                };
+                matched |= (low..=high).contains(&byte);
+                pattern = rest;
+            }
+            [other, rest @ ..] => {
+                matched |= *other == byte;
+                pattern = rest;
+            }
+        }
+    }
+}
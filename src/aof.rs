@@ -0,0 +1,626 @@
+//! Persistencia mediante un fichero de solo-anyadir (AOF, append only file).
+//!
+//! Cada escritura sobre la `Db` se registra en el fichero como la trama del
+//! comando que la reproduce. Al arrancar el servidor se vuelven a ejecutar
+//! todos los comandos del fichero para reconstruir la base de datos, antes
+//! de aceptar ninguna conexion.
+//!
+//! Las expiraciones se registran en tiempo absoluto (`SET ... PXAT`), asi que
+//! reiniciar el servidor no alarga la vida de las claves.
+//!
+//! El fichero crece con cada escritura. `BGREWRITEAOF` lo reescribe en
+//! segundo plano con el minimo numero de comandos que reproducen el estado
+//! actual.
+
+use crate::cmd::{Command, Set};
+use crate::db::{Db, WriteFeed};
+use crate::frame::FrameError;
+use crate::{Error, Frame, ServerError, ServerErrorKind};
+
+use std::fmt;
+use std::io::{self, Cursor};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use tracing::{error, info, warn};
+
+/// Politica de sincronizacion con el disco (`appendfsync`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fsync {
+    /// Se sincroniza tras cada escritura. No se responde al cliente hasta
+    /// que la escritura es persistente.
+    Always,
+
+    /// Se sincroniza una vez por segundo. Se pueden perder las escrituras
+    /// del ultimo segundo.
+    EverySec,
+
+    /// Nunca se sincroniza explicitamente. El sistema operativo decide cuando
+    /// se escriben los datos en el disco.
+    No,
+}
+
+/// Configuracion del fichero AOF.
+#[derive(Clone, Debug)]
+pub struct AofConfig {
+    /// Ruta del fichero.
+    pub path: PathBuf,
+
+    /// Politica de sincronizacion con el disco.
+    pub fsync: Fsync,
+}
+
+/// Acceso a la tarea que gestiona el fichero AOF. Se guarda en la `Db` para
+/// que los comandos puedan utilizarlo.
+#[derive(Clone, Debug)]
+pub(crate) struct AofHandle {
+    fsync: Fsync,
+
+    /// Solicitudes de reescritura del fichero.
+    rewrite_tx: mpsc::Sender<()>,
+
+    /// Estado de la sincronizacion de las escrituras con el fichero.
+    synced_rx: watch::Receiver<SyncState>,
+}
+
+/// Escrituras persistentes y fallidas, por su numero de secuencia.
+#[derive(Clone, Debug, Default)]
+struct SyncState {
+    /// Numero de secuencia de la ultima escritura persistente.
+    synced: u64,
+
+    /// Escrituras que no se han podido registrar o sincronizar en el ultimo
+    /// error, con su causa.
+    failed: Option<(RangeInclusive<u64>, String)>,
+
+    /// Causa del ultimo error mientras no se haya vuelto a poder escribir en
+    /// el fichero. Mientras tanto se rechazan las escrituras.
+    error: Option<String>,
+}
+
+/// Tarea que gestiona el fichero AOF. El servidor la detiene al pararse.
+#[derive(Debug)]
+pub(crate) struct Aof {
+    stop_tx: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+/// Estado de la tarea que escribe en el fichero.
+struct Writer {
+    db: Db,
+    config: AofConfig,
+
+    /// Fichero abierto en modo 'append'.
+    file: File,
+
+    /// Longitud del fichero hasta la ultima escritura completa.
+    len: u64,
+
+    /// `true` si el fichero puede terminar con una escritura a medias, que
+    /// hay que truncar antes de volver a escribir.
+    torn: bool,
+
+    /// Escrituras codificadas pendientes de escribir en el fichero. Tras un
+    /// error se conservan para reintentarlo.
+    buf: Vec<u8>,
+
+    /// Escrituras pendientes de registrar.
+    feed: WriteFeed,
+
+    /// Numero de secuencia de la ultima escritura registrada.
+    last_seq: u64,
+
+    /// `true` si hay datos escritos que no se han sincronizado.
+    dirty: bool,
+
+    synced_tx: watch::Sender<SyncState>,
+}
+
+impl AofConfig {
+    /// Fichero en `path` con la politica por defecto, `everysec`.
+    pub fn new(path: impl Into<PathBuf>) -> AofConfig {
+        AofConfig {
+            path: path.into(),
+            fsync: Fsync::EverySec,
+        }
+    }
+}
+
+impl FromStr for Fsync {
+    type Err = String;
+
+    fn from_str(src: &str) -> Result<Fsync, String> {
+        match &src.to_lowercase()[..] {
+            "always" => Ok(Fsync::Always),
+            "everysec" => Ok(Fsync::EverySec),
+            "no" => Ok(Fsync::No),
+            _ => Err(format!("invalid appendfsync policy '{}'", src)),
+        }
+    }
+}
+
+impl fmt::Display for Fsync {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fsync::Always => "always".fmt(fmt),
+            Fsync::EverySec => "everysec".fmt(fmt),
+            Fsync::No => "no".fmt(fmt),
+        }
+    }
+}
+
+impl AofHandle {
+    /// Solicita la reescritura del fichero en segundo plano.
+    pub(crate) fn rewrite(&self) -> Result<(), ServerError> {
+        self.rewrite_tx.try_send(()).map_err(|_| {
+            ServerError::err("Background append only file rewriting already in progress")
+        })
+    }
+
+    /// Con la politica `always` espera a que la escritura `seq` sea
+    /// persistente. Con el resto de politicas retorna inmediatamente.
+    ///
+    /// Si no se ha podido registrar o sincronizar retorna un error
+    /// `MISCONF`, que se envia al cliente en lugar de confirmar la
+    /// escritura. Solo les ocurre a las escrituras ya aplicadas cuando falla
+    /// el fichero: las siguientes se rechazan antes de aplicarlas (ver
+    /// `check_writable`).
+    pub(crate) async fn wait_synced(&self, seq: u64) -> Result<(), ServerError> {
+        if self.fsync != Fsync::Always {
+            return Ok(());
+        }
+
+        // Si la tarea ha terminado el canal se cierra y ya no hay nada
+        // que esperar.
+        let mut synced_rx = self.synced_rx.clone();
+        loop {
+            {
+                let state = synced_rx.borrow();
+                match &state.failed {
+                    Some((failed, cause)) if failed.contains(&seq) => {
+                        return Err(ServerError::new(
+                            ServerErrorKind::MisConf,
+                            format!("Errors writing to the AOF file: {}", cause),
+                        ));
+                    }
+                    _ if state.synced >= seq => return Ok(()),
+                    _ => {}
+                }
+            }
+
+            if synced_rx.changed().await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Falla con `MISCONF` mientras no se pueda escribir en el fichero tras
+    /// un error.
+    pub(crate) fn check_writable(&self) -> Result<(), ServerError> {
+        match &self.synced_rx.borrow().error {
+            Some(cause) => Err(ServerError::new(
+                ServerErrorKind::MisConf,
+                format!("Errors writing to the AOF file: {}", cause),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Aof {
+    /// Carga el fichero en la `Db` y lanza la tarea que registra las
+    /// escrituras posteriores.
+    pub(crate) async fn start(db: &Db, config: AofConfig) -> crate::Result<Aof> {
+        load(db, &config.path).await?;
+
+        let file = open(&config.path).await?;
+        let len = file.metadata().await?.len();
+
+        let (rewrite_tx, rewrite_rx) = mpsc::channel(1);
+        let (synced_tx, synced_rx) = watch::channel(SyncState::default());
+        let (stop_tx, stop_rx) = oneshot::channel();
+
+        db.set_aof(Some(AofHandle {
+            fsync: config.fsync,
+            rewrite_tx,
+            synced_rx,
+        }));
+
+        let writer = Writer {
+            db: db.clone(),
+            config,
+            file,
+            len,
+            torn: false,
+            buf: Vec::new(),
+            feed: db.watch_writes(),
+            last_seq: 0,
+            dirty: false,
+            synced_tx,
+        };
+
+        let task = tokio::spawn(writer.run(rewrite_rx, stop_rx));
+
+        Ok(Aof { stop_tx, task })
+    }
+
+    /// Detiene la tarea. Las escrituras pendientes se registran y se
+    /// sincronizan antes de terminar.
+    pub(crate) async fn shutdown(self) {
+        let _ = self.stop_tx.send(());
+        let _ = self.task.await;
+    }
+}
+
+impl Writer {
+    /// Registra las escrituras hasta que se solicite la parada.
+    async fn run(mut self, mut rewrite_rx: mpsc::Receiver<()>, mut stop_rx: oneshot::Receiver<()>) {
+        let mut interval = time::interval(Duration::from_secs(1));
+
+        loop {
+            let res = tokio::select! {
+                Some(write) = self.feed.recv() => self.append(write).await,
+                Some(()) = rewrite_rx.recv() => self.rewrite().await,
+                _ = interval.tick() => self.sync_pending().await,
+                _ = &mut stop_rx => break,
+            };
+
+            match res {
+                Ok(()) => self.recovered(),
+                Err(err) => {
+                    error!(cause = %err, path = ?self.config.path, "append only file error");
+                    self.fail(&err);
+                }
+            }
+        }
+
+        // Las escrituras que aun estan en el canal tambien se registran.
+        encode_pending(&mut self.feed, &mut self.buf, &mut self.last_seq);
+
+        let res = async {
+            self.write().await?;
+            self.file.sync_data().await
+        };
+
+        match res.await {
+            Ok(()) => {
+                let last_seq = self.last_seq;
+                self.synced_tx.send_modify(|state| state.synced = last_seq);
+            }
+            Err(err) => {
+                error!(cause = %err, path = ?self.config.path, "append only file error");
+                self.fail(&err);
+            }
+        }
+
+        self.db.set_aof(None);
+    }
+
+    /// Registra la escritura recibida y todas las que ya esten en el canal.
+    async fn append(&mut self, (seq, frame): (u64, Frame)) -> io::Result<()> {
+        frame.encode(&mut self.buf);
+        self.last_seq = self.last_seq.max(seq);
+
+        encode_pending(&mut self.feed, &mut self.buf, &mut self.last_seq);
+
+        self.write().await?;
+
+        if self.config.fsync == Fsync::Always {
+            self.sync().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Escribe en el fichero las escrituras pendientes.
+    ///
+    /// Si falla, el fichero se trunca hasta la ultima escritura completa
+    /// para que no termine con un comando a medias, y las escrituras se
+    /// conservan para reintentarlo. Si no se ha podido truncar se vuelve a
+    /// intentar antes de escribir nada mas.
+    async fn write(&mut self) -> io::Result<()> {
+        if self.torn {
+            self.file.set_len(self.len).await?;
+            self.torn = false;
+        }
+
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        let res = async {
+            self.file.write_all(&self.buf).await?;
+            self.file.flush().await
+        };
+
+        if let Err(err) = res.await {
+            self.torn = self.file.set_len(self.len).await.is_err();
+            return Err(err);
+        }
+
+        self.len += self.buf.len() as u64;
+        self.buf.clear();
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Reintenta las escrituras que han fallado y, salvo con la politica
+    /// `no`, sincroniza los datos escritos desde la ultima sincronizacion.
+    /// Con `always` solo quedan datos sin sincronizar tras un error.
+    async fn sync_pending(&mut self) -> io::Result<()> {
+        self.write().await?;
+
+        if self.dirty && self.config.fsync != Fsync::No {
+            self.sync().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data().await?;
+        self.dirty = false;
+
+        let last_seq = self.last_seq;
+        self.synced_tx.send_modify(|state| state.synced = last_seq);
+        Ok(())
+    }
+
+    /// Marca como fallidas las escrituras registradas desde la ultima
+    /// sincronizacion, de modo que los clientes que las esperan reciban el
+    /// error en lugar de la confirmacion, y rechaza las siguientes hasta que
+    /// se vuelva a poder escribir en el fichero.
+    fn fail(&mut self, err: &io::Error) {
+        let last_seq = self.last_seq;
+        self.synced_tx.send_modify(|state| {
+            if last_seq > state.synced {
+                state.failed = Some((state.synced + 1..=last_seq, err.to_string()));
+            }
+            state.error = Some(err.to_string());
+        });
+    }
+
+    /// Vuelve a aceptar escrituras una vez se ha podido escribir en el
+    /// fichero tras un error.
+    fn recovered(&mut self) {
+        if self.synced_tx.borrow().error.is_none() {
+            return;
+        }
+
+        info!(path = ?self.config.path, "append only file writes resumed");
+        self.synced_tx.send_modify(|state| state.error = None);
+    }
+
+    /// Reescribe el fichero con un `SET` por cada clave.
+    ///
+    /// Se obtiene una copia de la base de datos junto con un nuevo
+    /// `WriteFeed`. Las escrituras anteriores a la copia que aun estan en el
+    /// `WriteFeed` actual se registran en el fichero actual, que sigue siendo
+    /// valido hasta que el nuevo lo substituye. Las posteriores se registraran
+    /// en el nuevo fichero.
+    async fn rewrite(&mut self) -> io::Result<()> {
+        info!("background append only file rewriting started");
+
+        let (snapshot, feed) = self.db.snapshot_and_watch();
+        let mut old_feed = std::mem::replace(&mut self.feed, feed);
+
+        // Se completa el fichero actual.
+        encode_pending(&mut old_feed, &mut self.buf, &mut self.last_seq);
+        drop(old_feed);
+
+        self.write().await?;
+
+        // Se escribe el nuevo fichero en una ruta temporal.
+        let mut buf = Vec::new();
+        for entry in &snapshot {
+            Set::absolute_frame(&entry.key, &entry.value, entry.expires_at).encode(&mut buf);
+        }
+
+        // Tambien se incluyen las escrituras posteriores a la copia que ya
+        // se han recibido. Con la politica `always` alguna de ellas podria
+        // estar confirmada como persistente solo en el fichero anterior.
+        encode_pending(&mut self.feed, &mut buf, &mut self.last_seq);
+
+        let tmp_path = rewrite_path(&self.config.path);
+
+        let res = async {
+            let mut tmp = File::create(&tmp_path).await?;
+            tmp.write_all(&buf).await?;
+            tmp.flush().await?;
+            tmp.sync_all().await?;
+            fs::rename(&tmp_path, &self.config.path).await
+        };
+
+        if let Err(err) = res.await {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(err);
+        }
+
+        self.file = open(&self.config.path).await?;
+        self.len = self.file.metadata().await?.len();
+        self.dirty = false;
+
+        let last_seq = self.last_seq;
+        self.synced_tx.send_modify(|state| state.synced = last_seq);
+
+        info!(
+            keys = snapshot.len(),
+            "background append only file rewriting finished"
+        );
+
+        Ok(())
+    }
+}
+
+/// Resultado de verificar el contenido de un fichero AOF con `check`.
+#[derive(Debug)]
+pub struct AofCheck {
+    /// Comandos validos desde el principio del fichero, junto con su
+    /// desplazamiento en el fichero.
+    pub commands: Vec<(u64, Frame)>,
+
+    /// Longitud de la parte valida del fichero.
+    pub valid_len: u64,
+
+    /// Error encontrado a continuacion de la parte valida, si lo hay.
+    pub error: Option<AofError>,
+}
+
+/// Error encontrado al verificar un fichero AOF.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AofError {
+    /// El fichero termina con un comando incompleto, normalmente porque el
+    /// servidor se detuvo a mitad de una escritura. Truncando el fichero en
+    /// `offset` se obtiene un fichero valido.
+    Truncated { offset: u64 },
+
+    /// El comando que empieza en `offset` no es valido.
+    Corrupted { offset: u64, reason: String },
+}
+
+impl fmt::Display for AofError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AofError::Truncated { offset } => {
+                write!(fmt, "incomplete command at offset {}", offset)
+            }
+            AofError::Corrupted { offset, reason } => {
+                write!(fmt, "bad command at offset {}: {}", offset, reason)
+            }
+        }
+    }
+}
+
+/// Verifica el contenido de un fichero AOF trama a trama.
+///
+/// Cada trama debe ser un comando de escritura valido. La verificacion se
+/// detiene en el primer error.
+pub fn check(data: &[u8]) -> AofCheck {
+    let mut buf = Cursor::new(data);
+    let mut commands = Vec::new();
+
+    let error = loop {
+        let offset = buf.position();
+
+        if offset as usize == data.len() {
+            break None;
+        }
+
+        match Frame::check(&mut buf) {
+            Ok(()) => {}
+            Err(FrameError::Incomplete) => break Some(AofError::Truncated { offset }),
+            Err(err) => {
+                let reason = err.to_string();
+                break Some(AofError::Corrupted { offset, reason });
+            }
+        }
+
+        buf.set_position(offset);
+
+        let frame = match Frame::parse(&mut buf) {
+            Ok(frame) => frame,
+            Err(err) => {
+                let reason = err.to_string();
+                break Some(AofError::Corrupted { offset, reason });
+            }
+        };
+
+        match Command::from_frame(frame.clone()) {
+            Ok(cmd) if cmd.is_write() => commands.push((offset, frame)),
+            Ok(cmd) => {
+                let reason = format!("'{}' is not a write command", cmd.get_name());
+                break Some(AofError::Corrupted { offset, reason });
+            }
+            Err(err) => {
+                let reason = err.to_string();
+                break Some(AofError::Corrupted { offset, reason });
+            }
+        }
+    };
+
+    let valid_len = match &error {
+        Some(AofError::Truncated { offset }) | Some(AofError::Corrupted { offset, .. }) => *offset,
+        None => data.len() as u64,
+    };
+
+    AofCheck {
+        commands,
+        valid_len,
+        error,
+    }
+}
+
+/// Ejecuta sobre la `Db` todos los comandos del fichero. Si el fichero no
+/// existe la base de datos se queda vacia.
+///
+/// Si el fichero termina con un comando incompleto (el servidor se detuvo a
+/// mitad de una escritura) ese comando se descarta y se trunca el fichero.
+/// Cualquier otro error en el fichero impide arrancar el servidor.
+pub(crate) async fn load(db: &Db, path: &Path) -> crate::Result<()> {
+    let data = match fs::read(path).await {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let check = check(&data);
+
+    match check.error {
+        None => {}
+        Some(AofError::Truncated { offset }) => {
+            warn!(
+                offset,
+                ?path,
+                "truncating incomplete command at the end of the append only file"
+            );
+
+            let file = OpenOptions::new().write(true).open(path).await?;
+            file.set_len(offset).await?;
+        }
+        Some(err) => {
+            return Err(Error::Protocol(format!("bad append only file: {}", err)));
+        }
+    }
+
+    let commands = check.commands.len();
+
+    for (_, frame) in check.commands {
+        // Los comandos ya se han validado.
+        Command::from_frame(frame)
+            .and_then(|cmd| cmd.apply_write(db))
+            .map_err(|err| Error::Protocol(err.to_string()))?;
+    }
+
+    info!(commands, ?path, "append only file loaded");
+
+    Ok(())
+}
+
+/// Abre el fichero para anyadir datos al final, creandolo si no existe.
+async fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+/// Ruta temporal donde se escribe el fichero al reescribirlo.
+fn rewrite_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".rewrite");
+    PathBuf::from(tmp)
+}
+
+/// Codifica en `buf` las escrituras que ya estan en el canal.
+fn encode_pending(feed: &mut WriteFeed, buf: &mut Vec<u8>, last_seq: &mut u64) {
+    while let Ok((seq, frame)) = feed.try_recv() {
+        frame.encode(buf);
+        *last_seq = (*last_seq).max(seq);
+    }
+}
@@ -0,0 +1,152 @@
+//! mini-redis persistence checker.
+//!
+//! Validates an append only file or a snapshot written by `mini-redis-server`,
+//! the equivalent of `redis-check-aof` and `redis-check-rdb`. The kind of file
+//! is detected from its contents.
+
+use mini_redis::{aof, rdb, Frame};
+
+use bytes::Bytes;
+use clap::Parser;
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::process;
+use std::time::UNIX_EPOCH;
+
+#[derive(Parser, Debug)]
+#[clap(
+    name = "mini-redis-check",
+    version,
+    author,
+    about = "Check append only files and snapshots"
+)]
+struct Cli {
+    /// Append only file or snapshot to check
+    file: PathBuf,
+
+    /// Truncate an append only file that ends with an incomplete command
+    #[clap(long)]
+    fix: bool,
+
+    /// Print the contents of the file as commands
+    #[clap(long)]
+    dump: bool,
+}
+
+fn main() -> mini_redis::Result<()> {
+    let cli = Cli::parse();
+
+    let data = std::fs::read(&cli.file)?;
+
+    // Snapshots start with a fixed header, anything else must be an append
+    // only file.
+    let valid = if data.starts_with(b"MINIREDIS") {
+        check_snapshot(&cli, &data)
+    } else {
+        check_aof(&cli, &data)?
+    };
+
+    if !valid {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Checks an append only file. Returns `true` if the file is valid, possibly
+/// after fixing it.
+fn check_aof(cli: &Cli, data: &[u8]) -> mini_redis::Result<bool> {
+    let check = aof::check(data);
+
+    if cli.dump {
+        for (_, frame) in &check.commands {
+            println!("{}", format_command(frame));
+        }
+    }
+
+    println!(
+        "AOF analyzed: size={}, ok_up_to={}, diff={}, commands={}",
+        data.len(),
+        check.valid_len,
+        data.len() as u64 - check.valid_len,
+        check.commands.len()
+    );
+
+    match check.error {
+        None => {
+            println!("AOF is valid");
+            Ok(true)
+        }
+        Some(aof::AofError::Truncated { offset }) if cli.fix => {
+            let file = OpenOptions::new().write(true).open(&cli.file)?;
+            file.set_len(offset)?;
+            file.sync_all()?;
+
+            println!("AOF truncated at offset {}: successfully fixed", offset);
+            Ok(true)
+        }
+        Some(err @ aof::AofError::Truncated { .. }) => {
+            println!("AOF is not valid: {}", err);
+            println!("The file can be fixed with --fix, discarding the incomplete command");
+            Ok(false)
+        }
+        Some(err) => {
+            println!("AOF is not valid: {}", err);
+            Ok(false)
+        }
+    }
+}
+
+/// Checks a snapshot. Returns `true` if the file is valid.
+fn check_snapshot(cli: &Cli, data: &[u8]) -> bool {
+    let entries = match rdb::decode(data) {
+        Ok(entries) => entries,
+        Err(err) => {
+            println!("Snapshot is not valid: {}", err);
+            return false;
+        }
+    };
+
+    if cli.dump {
+        for entry in &entries {
+            let mut frame = vec![
+                Frame::Bulk("SET".into()),
+                Frame::Bulk(entry.key.clone().into()),
+                Frame::Bulk(entry.value.clone()),
+            ];
+
+            if let Some(when) = entry.expires_at {
+                let ms = when
+                    .duration_since(UNIX_EPOCH)
+                    .map(|since| since.as_millis())
+                    .unwrap_or(0);
+
+                frame.push(Frame::Bulk("PXAT".into()));
+                frame.push(Frame::Bulk(Bytes::from(ms.to_string())));
+            }
+
+            println!("{}", format_command(&Frame::Array(frame)));
+        }
+    }
+
+    println!("Snapshot is valid: {} keys", entries.len());
+
+    true
+}
+
+/// Formats a command as a line of quoted arguments, like `redis-cli` does.
+fn format_command(frame: &Frame) -> String {
+    let parts = match frame {
+        Frame::Array(parts) => &parts[..],
+        frame => std::slice::from_ref(frame),
+    };
+
+    parts
+        .iter()
+        .map(|part| match part {
+            Frame::Bulk(bytes) => format!("{:?}", String::from_utf8_lossy(bytes)),
+            part => format!("{:?}", part.to_string()),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1,8 +1,10 @@
-use mini_redis::{client, DEFAULT_PORT};
+use mini_redis::client::{self, Credentials, TlsOptions};
+use mini_redis::DEFAULT_PORT;
 
 use bytes::Bytes;
 use clap::{Parser, Subcommand};
 use std::num::ParseIntError;
+use std::path::PathBuf;
 use std::str;
 use std::time::Duration;
 
@@ -22,6 +24,30 @@ struct Cli {
 
     #[clap(long, default_value_t = DEFAULT_PORT)]
     port: u16,
+
+    /// Password to authenticate the connection with
+    #[clap(long)]
+    pass: Option<String>,
+
+    /// User to authenticate as, together with --pass
+    #[clap(long, requires = "pass")]
+    user: Option<String>,
+
+    /// Connect using TLS
+    #[clap(long, requires = "cacert")]
+    tls: bool,
+
+    /// PEM file with the CA certificates used to verify the server
+    #[clap(long)]
+    cacert: Option<PathBuf>,
+
+    /// PEM file with the client certificate, for servers that verify clients
+    #[clap(long, requires_all = &["tls", "key"])]
+    cert: Option<PathBuf>,
+
+    /// PEM file with the private key of the client certificate
+    #[clap(long, requires = "cert")]
+    key: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -84,8 +110,26 @@ async fn main() -> mini_redis::Result<()> {
     // Get the remote address to connect to
     let addr = format!("{}:{}", cli.host, cli.port);
 
-    // Establish a connection
-    let mut client = client::connect(&addr).await?;
+    // Establish a connection, encrypted if TLS is requested
+    let mut client = match cli.cacert {
+        Some(cacert) if cli.tls => {
+            let mut options = TlsOptions::new(&cli.host, cacert);
+            if let (Some(cert), Some(key)) = (cli.cert, cli.key) {
+                options = options.with_client_cert(cert, key);
+            }
+            client::connect_tls(&addr, &options).await?
+        }
+        _ => client::connect(&addr).await?,
+    };
+
+    // Authenticate the connection if a password is given
+    if let Some(password) = cli.pass {
+        let credentials = Credentials {
+            username: cli.user,
+            password,
+        };
+        client.auth(&credentials).await?;
+    }
 
     // Process the requested command
     match cli.command {
@@ -125,7 +169,7 @@ async fn main() -> mini_redis::Result<()> {
             println!("OK");
         }
         Command::Publish { channel, message } => {
-            client.publish(&channel, message.into()).await?;
+            client.publish(&channel, message).await?;
             println!("Publish OK");
         }
         Command::Subscribe { channels } => {
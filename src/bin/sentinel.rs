@@ -0,0 +1,142 @@
+//! mini-redis sentinel.
+//!
+//! Monitors mini-redis masters and their replicas and promotes a replica
+//! when a master fails. See `mini_redis::sentinel`.
+//!
+//! The `clap` crate is used for parsing arguments.
+
+use mini_redis::client::{Credentials, TlsOptions};
+use mini_redis::sentinel::{self, MasterConfig, SentinelConfig, DEFAULT_SENTINEL_PORT};
+
+use clap::Parser;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::signal;
+
+#[tokio::main]
+pub async fn main() -> mini_redis::Result<()> {
+    // See https://docs.rs/tracing for more info
+    tracing_subscriber::fmt::try_init()?;
+
+    let cli = Cli::parse();
+    let port = cli.port.unwrap_or(DEFAULT_SENTINEL_PORT);
+
+    // Bind a TCP listener
+    let listener = TcpListener::bind(&format!("127.0.0.1:{}", port)).await?;
+
+    // Monitored masters, given as "<name> <host> <port> <quorum>"
+    let mut masters = vec![];
+    for monitor in &cli.monitor {
+        let parts: Vec<_> = monitor.split_whitespace().collect();
+        match &parts[..] {
+            [name, host, port, quorum] => masters.push(MasterConfig {
+                name: name.to_string(),
+                host: host.to_string(),
+                port: port
+                    .parse()
+                    .map_err(|_| format!("invalid monitor port: {}", port))?,
+                quorum: quorum
+                    .parse()
+                    .map_err(|_| format!("invalid monitor quorum: {}", quorum))?,
+            }),
+            _ => return Err("monitor must be given as \"<name> <host> <port> <quorum>\"".into()),
+        }
+    }
+
+    // Other sentinels, given as "<host> <port>"
+    let mut sentinels = vec![];
+    for sentinel in &cli.sentinel {
+        let parts: Vec<_> = sentinel.split_whitespace().collect();
+        match &parts[..] {
+            [host, port] => sentinels.push((
+                host.to_string(),
+                port.parse()
+                    .map_err(|_| format!("invalid sentinel port: {}", port))?,
+            )),
+            _ => return Err("sentinel must be given as \"<host> <port>\"".into()),
+        }
+    }
+
+    // TLS with the instances. The server name is replaced by the host of
+    // each of them
+    let tls = match (cli.tls_ca_cert_file, cli.tls_cert_file, cli.tls_key_file) {
+        (Some(ca_cert_file), None, None) => Some(TlsOptions::new("", ca_cert_file)),
+        (Some(ca_cert_file), Some(cert_file), Some(key_file)) => {
+            Some(TlsOptions::new("", ca_cert_file).with_client_cert(cert_file, key_file))
+        }
+        (None, None, None) => None,
+        _ => {
+            return Err(
+                "tls-cert-file and tls-key-file must be given together, with tls-ca-cert-file"
+                    .into(),
+            )
+        }
+    };
+
+    let config = SentinelConfig {
+        masters,
+        sentinels,
+        down_after: Duration::from_millis(cli.down_after_milliseconds),
+        failover_timeout: Duration::from_millis(cli.failover_timeout),
+        auth: cli.auth_pass.map(|password| Credentials {
+            username: cli.auth_user,
+            password,
+        }),
+        tls,
+    };
+
+    sentinel::run(listener, config, signal::ctrl_c()).await
+}
+
+#[derive(Parser, Debug)]
+#[clap(
+    name = "mini-redis-sentinel",
+    version,
+    author,
+    about = "Monitors Redis masters and fails over to a replica"
+)]
+struct Cli {
+    #[clap(long)]
+    port: Option<u16>,
+
+    /// Master to monitor, as "<name> <host> <port> <quorum>". May be given
+    /// several times
+    #[clap(long)]
+    monitor: Vec<String>,
+
+    /// Another sentinel monitoring the same masters, as "<host> <port>". May
+    /// be given several times
+    #[clap(long)]
+    sentinel: Vec<String>,
+
+    /// Time without replies after which a master is considered down
+    #[clap(long, default_value = "30000")]
+    down_after_milliseconds: u64,
+
+    /// Minimum time between two failover attempts of the same master, in
+    /// milliseconds
+    #[clap(long, default_value = "180000")]
+    failover_timeout: u64,
+
+    /// User to authenticate as with the masters and replicas
+    #[clap(long)]
+    auth_user: Option<String>,
+
+    /// Password to authenticate with the masters and replicas
+    #[clap(long)]
+    auth_pass: Option<String>,
+
+    /// Connect to the masters and replicas over TLS, verifying their
+    /// certificates with the CAs in this PEM file
+    #[clap(long)]
+    tls_ca_cert_file: Option<String>,
+
+    /// Certificate presented to masters and replicas that verify their
+    /// clients
+    #[clap(long)]
+    tls_cert_file: Option<String>,
+
+    /// PEM file with the private key of the TLS certificate
+    #[clap(long)]
+    tls_key_file: Option<String>,
+}
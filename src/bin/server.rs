@@ -6,10 +6,12 @@
 //!
 //! The `clap` crate is used for parsing arguments.
 
-use mini_redis::{server, DEFAULT_PORT};
+use mini_redis::config::Config;
+use mini_redis::server::{self, Listeners};
 
 use clap::Parser;
-use tokio::net::TcpListener;
+use std::fs;
+use std::path::PathBuf;
 use tokio::signal;
 
 #[cfg(feature = "otel")]
@@ -33,31 +35,214 @@ pub async fn main() -> mini_redis::Result<()> {
     set_up_logging()?;
 
     let cli = Cli::parse();
-    let port = cli.port.unwrap_or(DEFAULT_PORT);
 
-    // Bind a TCP listener
-    let listener = TcpListener::bind(&format!("127.0.0.1:{}", port)).await?;
+    // Start from the config file, if any, and apply the command line on top
+    let mut config = match &cli.config_file {
+        Some(path) => Config::load(path)?,
+        None => Config::new(),
+    };
 
-    server::run(listener, signal::ctrl_c()).await;
+    for (name, value) in cli.overrides() {
+        config
+            .set(name, &value)
+            .map_err(|err| format!("--{}: {}", name, err))?;
+    }
 
-    Ok(())
+    let server_config = config.server_config()?;
+    let listeners = Listeners::bind(&server_config).await?;
+    let unixsocket = server_config.unixsocket.clone();
+
+    let res = server::run_with_config(listeners, server_config, signal::ctrl_c()).await;
+
+    if let Some(path) = &unixsocket {
+        let _ = fs::remove_file(path);
+    }
+
+    res
 }
 
+/// Every option overrides the directive of the same name in the config file.
 #[derive(Parser, Debug)]
 #[clap(name = "mini-redis-server", version, author, about = "A Redis server")]
 struct Cli {
+    /// Config file in the redis.conf format, one "<directive> <value>" per
+    /// line
+    config_file: Option<PathBuf>,
+
+    /// Addresses to listen on, separated by spaces
+    #[clap(long)]
+    bind: Option<String>,
+
+    /// TCP port to listen on. 0 disables the TCP listener
     #[clap(long)]
     port: Option<u16>,
+
+    /// Also listen on a Unix domain socket at this path
+    #[clap(long)]
+    unixsocket: Option<String>,
+
+    /// Permissions of the Unix domain socket, in octal (e.g. 700)
+    #[clap(long)]
+    unixsocketperm: Option<String>,
+
+    /// Maximum number of connected clients
+    #[clap(long)]
+    maxclients: Option<usize>,
+
+    /// Close connections idle for this many seconds. 0 never closes them
+    #[clap(long)]
+    timeout: Option<u64>,
+
+    /// Send TCP keepalives to idle clients every this many seconds, to
+    /// detect dead peers. 0 disables them
+    #[clap(long)]
+    tcp_keepalive: Option<u64>,
+
+    /// Number of databases. Only database 0 is used
+    #[clap(long)]
+    databases: Option<usize>,
+
+    /// Maximum length of a single bulk string, in bytes
+    #[clap(long)]
+    proto_max_bulk_len: Option<usize>,
+
+    /// Maximum number of elements in a request array
+    #[clap(long)]
+    max_multibulk_len: Option<usize>,
+
+    /// Maximum nesting depth of request arrays
+    #[clap(long)]
+    max_nesting_depth: Option<usize>,
+
+    /// Maximum size of a client's unparsed input buffer, in bytes
+    #[clap(long)]
+    client_query_buffer_limit: Option<usize>,
+
+    /// Directory where the snapshot and the append only file are stored
+    #[clap(long)]
+    dir: Option<String>,
+
+    /// Name of the snapshot file, loaded on startup
+    #[clap(long)]
+    dbfilename: Option<String>,
+
+    /// Save a snapshot after <seconds> if at least <changes> writes were
+    /// performed, given as "<seconds> <changes> ...". An empty string disables
+    /// automatic saving
+    #[clap(long)]
+    save: Option<String>,
+
+    /// Log every write to an append only file, replayed on startup
+    #[clap(long)]
+    appendonly: bool,
+
+    /// Name of the append only file
+    #[clap(long)]
+    appendfilename: Option<String>,
+
+    /// When to fsync the append only file: always, everysec or no
+    #[clap(long)]
+    appendfsync: Option<String>,
+
+    /// Start as a replica of the given master, as "<host> <port>"
+    #[clap(long)]
+    replicaof: Option<String>,
+
+    /// User to authenticate as with the master and the other cluster nodes
+    #[clap(long)]
+    masteruser: Option<String>,
+
+    /// Password to authenticate with the master and the other cluster nodes
+    #[clap(long)]
+    masterauth: Option<String>,
+
+    /// Run as a cluster node. Hash slots are assigned with the CLUSTER
+    /// commands
+    #[clap(long)]
+    cluster_enabled: bool,
+
+    /// Require clients to authenticate with AUTH and this password before
+    /// issuing any other command
+    #[clap(long)]
+    requirepass: Option<String>,
+
+    /// File the users and their permissions are loaded from, one
+    /// "user <name> <rules...>" line per user
+    #[clap(long)]
+    aclfile: Option<String>,
+
+    /// Accept TLS connections only, with the certificate in this PEM file
+    #[clap(long)]
+    tls_cert_file: Option<String>,
+
+    /// PEM file with the private key of the TLS certificate
+    #[clap(long)]
+    tls_key_file: Option<String>,
+
+    /// Require clients to present a certificate signed by one of the CAs in
+    /// this PEM file. The certificates of the master and the other cluster
+    /// nodes are verified with them too
+    #[clap(long)]
+    tls_ca_cert_file: Option<String>,
+}
+
+impl Cli {
+    /// Config directives given on the command line, as name and value.
+    fn overrides(&self) -> Vec<(&'static str, String)> {
+        let string = |value: &Option<String>| value.clone();
+        let number = |value: Option<usize>| value.map(|value| value.to_string());
+        let flag = |value: bool| value.then(|| "yes".to_string());
+
+        [
+            ("bind", string(&self.bind)),
+            ("port", self.port.map(|port| port.to_string())),
+            ("unixsocket", string(&self.unixsocket)),
+            ("unixsocketperm", string(&self.unixsocketperm)),
+            ("maxclients", number(self.maxclients)),
+            ("timeout", self.timeout.map(|secs| secs.to_string())),
+            (
+                "tcp-keepalive",
+                self.tcp_keepalive.map(|secs| secs.to_string()),
+            ),
+            ("databases", number(self.databases)),
+            ("proto-max-bulk-len", number(self.proto_max_bulk_len)),
+            ("max-multibulk-len", number(self.max_multibulk_len)),
+            ("max-nesting-depth", number(self.max_nesting_depth)),
+            (
+                "client-query-buffer-limit",
+                number(self.client_query_buffer_limit),
+            ),
+            ("dir", string(&self.dir)),
+            ("dbfilename", string(&self.dbfilename)),
+            ("save", string(&self.save)),
+            ("appendonly", flag(self.appendonly)),
+            ("appendfilename", string(&self.appendfilename)),
+            ("appendfsync", string(&self.appendfsync)),
+            ("replicaof", string(&self.replicaof)),
+            ("masteruser", string(&self.masteruser)),
+            ("masterauth", string(&self.masterauth)),
+            ("cluster-enabled", flag(self.cluster_enabled)),
+            ("requirepass", string(&self.requirepass)),
+            ("aclfile", string(&self.aclfile)),
+            ("tls-cert-file", string(&self.tls_cert_file)),
+            ("tls-key-file", string(&self.tls_key_file)),
+            ("tls-ca-cert-file", string(&self.tls_ca_cert_file)),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
 }
 
 #[cfg(not(feature = "otel"))]
 fn set_up_logging() -> mini_redis::Result<()> {
     // See https://docs.rs/tracing for more info
-    tracing_subscriber::fmt::try_init()
+    tracing_subscriber::fmt::try_init()?;
+    Ok(())
 }
 
 #[cfg(feature = "otel")]
-fn set_up_logging() -> Result<(), TryInitError> {
+fn set_up_logging() -> mini_redis::Result<()> {
     // Set the global propagator to X-Ray propagator
     // Note: If you need to pass the x-amzn-trace-id across services in the same trace,
     // you will need this line. However, this requires additional code not pictured here.
@@ -91,4 +276,5 @@ fn set_up_logging() -> Result<(), TryInitError> {
         .with(filter)
         .with(fmt::Layer::default())
         .try_init()
+        .map_err(|err: TryInitError| mini_redis::Error::Other(err.into()))
 }
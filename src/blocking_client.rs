@@ -3,17 +3,19 @@
 //! Provides a blocking connect and methods for issuing the supported commands.
 
 use bytes::Bytes;
+use std::path::Path;
 use std::time::Duration;
 use tokio::net::ToSocketAddrs;
 use tokio::runtime::Runtime;
 
-pub use crate::client::Message;
+pub use crate::client::{Credentials, Message};
 
 /// Established connection with a Redis server.
 ///
-/// Backed by a single `TcpStream`, `BlockingClient` provides basic network
-/// client functionality (no pooling, retrying, ...). Connections are
-/// established using the [`connect`](fn@connect) function.
+/// Backed by a single `TcpStream` or `UnixStream`, `BlockingClient` provides
+/// basic network client functionality (no pooling, retrying, ...).
+/// Connections are established using the [`connect`](fn@connect) and
+/// [`connect_unix`](fn@connect_unix) functions.
 ///
 /// Requests are issued using the various methods of `Client`.
 pub struct BlockingClient {
@@ -79,7 +81,65 @@ pub fn connect<T: ToSocketAddrs>(addr: T) -> crate::Result<BlockingClient> {
     Ok(BlockingClient { inner, rt })
 }
 
+/// Establish a connection with the Redis server located at `addr` and
+/// authenticate it with `credentials`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::blocking_client::{self, Credentials};
+///
+/// fn main() {
+///     let credentials = Credentials::with_username("default", "secret");
+///     let mut client =
+///         blocking_client::connect_with_credentials("localhost:6379", &credentials).unwrap();
+///
+///     client.set("foo", "bar".into()).unwrap();
+/// }
+/// ```
+pub fn connect_with_credentials<T: ToSocketAddrs>(
+    addr: T,
+    credentials: &Credentials,
+) -> crate::Result<BlockingClient> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let inner = rt.block_on(crate::client::connect_with_credentials(addr, credentials))?;
+
+    Ok(BlockingClient { inner, rt })
+}
+
+/// Establish a connection with the Redis server listening on the Unix
+/// domain socket at `path`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::blocking_client;
+///
+/// fn main() {
+///     let mut client = blocking_client::connect_unix("/tmp/mini-redis.sock").unwrap();
+///
+///     client.set("foo", "bar".into()).unwrap();
+/// }
+/// ```
+pub fn connect_unix(path: impl AsRef<Path>) -> crate::Result<BlockingClient> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let inner = rt.block_on(crate::client::connect_unix(path))?;
+
+    Ok(BlockingClient { inner, rt })
+}
+
 impl BlockingClient {
+    /// Authenticate the connection with `credentials`.
+    pub fn auth(&mut self, credentials: &Credentials) -> crate::Result<()> {
+        self.rt.block_on(self.inner.auth(credentials))
+    }
+
     /// Get the value of key.
     ///
     /// If the key does not exist the special value `None` is returned.
@@ -237,6 +297,7 @@ impl BlockingSubscriber {
 
     /// Convert the subscriber into an `Iterator` yielding new messages published
     /// on subscribed channels.
+    #[allow(clippy::should_implement_trait)]
     pub fn into_iter(self) -> impl Iterator<Item = crate::Result<Message>> {
         SubscriberIterator {
             inner: self.inner,
@@ -1,11 +1,15 @@
-use crate::client::Client;
-use crate::Result;
+use crate::client::{self, Client};
+use crate::cmd::{Get, Ping, Publish, Set};
+use crate::{Connection, Error, Frame, Result};
 
 use bytes::Bytes;
+use std::collections::VecDeque;
+use std::time::Duration;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::oneshot;
+use tracing::debug;
 
-/// Create a new client request buffer
+/// Create a new multiplexed client
 ///
 /// The `Client` performs Redis commands directly on the TCP connection. Only a
 /// single request may be in-flight at a given time and operations require
@@ -15,106 +19,183 @@ use tokio::sync::oneshot;
 /// The strategy for dealing with this class of problem is to spawn a dedicated
 /// Tokio task to manage the Redis connection and using "message passing" to
 /// operate on the connection. Commands are pushed into a channel. The
-/// connection task pops commands off of the channel and applies them to the
-/// Redis connection. When the response is received, it is forwarded to the
-/// original requester.
+/// connection task pops commands off of the channel and writes them to the
+/// Redis connection as soon as they arrive, without waiting for the responses
+/// to the previous ones. Redis answers the requests of a connection in order,
+/// so each response is forwarded to the oldest requester still waiting.
 ///
 /// The returned `Buffer` handle may be cloned before passing the new handle to
-/// separate tasks.
+/// separate tasks. All the clones share the same connection.
 pub fn buffer(client: Client) -> Buffer {
     // Setting the message limit to a hard coded value of 32. in a real-app, the
     // buffer size should be configurable, but we don't need to do that here.
     let (tx, rx) = channel(32);
 
     // Spawn a task to process requests for the connection.
-    tokio::spawn(async move { run(client, rx).await });
+    tokio::spawn(async move { run(client.into_connection(), rx).await });
 
     // Return the `Buffer` handle.
     Buffer { tx }
 }
 
-// Enum used to message pass the requested command from the `Buffer` handle
-#[derive(Debug)]
-enum Command {
-    Get(String),
-    Set(String, Bytes),
-}
-
 // Message type sent over the channel to the connection task.
 //
-// `Command` is the command to forward to the connection.
+// `Frame` is the command to forward to the connection.
 //
 // `oneshot::Sender` is a channel type that sends a **single** value. It is used
 // here to send the response received from the connection back to the original
 // requester.
-type Message = (Command, oneshot::Sender<Result<Option<Bytes>>>);
-
-/// Receive commands sent through the channel and forward them to client. The
-/// response is returned back to the caller via a `oneshot`.
-async fn run(mut client: Client, mut rx: Receiver<Message>) {
-    // Repeatedly pop messages from the channel. A return value of `None`
-    // indicates that all `Buffer` handles have dropped and there will never be
-    // another message sent on the channel.
-    while let Some((cmd, tx)) = rx.recv().await {
-        // The command is forwarded to the connection
-        let response = match cmd {
-            Command::Get(key) => client.get(&key).await,
-            Command::Set(key, value) => client.set(&key, value).await.map(|_| None),
-        };
-
-        // Send the response back to the caller.
-        //
-        // Failing to send the message indicates the `rx` half dropped
-        // before receiving the message. This is a normal runtime event.
-        let _ = tx.send(response);
+type Message = (Frame, oneshot::Sender<Result<Frame>>);
+
+/// Receive commands sent through the channel and forward them to the
+/// connection. Responses are returned back to the callers via their `oneshot`,
+/// in the same order the commands were written.
+async fn run(mut connection: Connection, mut rx: Receiver<Message>) {
+    // Requesters waiting for a response, oldest first.
+    let mut in_flight: VecDeque<oneshot::Sender<Result<Frame>>> = VecDeque::new();
+
+    // `false` once every `Buffer` handle has been dropped.
+    let mut open = true;
+
+    while open || !in_flight.is_empty() {
+        tokio::select! {
+            // A new request. It is written right away, along with any other
+            // request already queued in the channel.
+            msg = rx.recv(), if open => {
+                let (frame, tx) = match msg {
+                    Some(msg) => msg,
+                    None => {
+                        // No more requests will ever arrive. Keep running
+                        // until the in-flight ones are answered.
+                        open = false;
+                        continue;
+                    }
+                };
+
+                let mut res = connection.feed_frame(&frame).await;
+                in_flight.push_back(tx);
+
+                while res.is_ok() {
+                    match rx.try_recv() {
+                        Ok((frame, tx)) => {
+                            res = connection.feed_frame(&frame).await;
+                            in_flight.push_back(tx);
+                        }
+                        Err(_) => break,
+                    }
+                }
+
+                if let Err(err) = res.and(connection.flush().await) {
+                    debug!(cause = ?err, "failed to write requests");
+                    break;
+                }
+            }
+            // A response, for the oldest request in flight.
+            res = connection.read_frame(), if !in_flight.is_empty() => {
+                let frame = match res {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break,
+                    Err(err) => {
+                        debug!(cause = ?err, "failed to read response");
+                        break;
+                    }
+                };
+
+                // Failing to send the message indicates the `rx` half dropped
+                // before receiving the message. This is a normal runtime event.
+                if let Some(tx) = in_flight.pop_front() {
+                    let _ = tx.send(client::check_response(frame));
+                }
+            }
+        }
+    }
+
+    // The connection failed. Every request still waiting for a response
+    // fails. Closing the channel makes any later request fail too.
+    rx.close();
+
+    for tx in in_flight.drain(..) {
+        let _ = tx.send(Err(Error::ConnectionClosed));
+    }
+
+    while let Ok((_, tx)) = rx.try_recv() {
+        let _ = tx.send(Err(Error::ConnectionClosed));
     }
 }
 
+/// Handle to a connection shared by several tasks.
+///
+/// Provides the same API as `Client`, except for pub/sub, with methods taking
+/// `&self` so that several requests may be in flight concurrently over the
+/// single connection.
 #[derive(Clone)]
 pub struct Buffer {
     tx: Sender<Message>,
 }
 
 impl Buffer {
-    /// Get the value of a key.
+    /// Ping to the server.
     ///
-    /// Same as `Client::get` but requests are **buffered** until the associated
-    /// connection has the ability to send the request.
-    pub async fn get(&mut self, key: &str) -> Result<Option<Bytes>> {
-        // Initialize a new `Get` command to send via the channel.
-        let get = Command::Get(key.into());
+    /// Same as `Client::ping` but several requests may be in flight at once.
+    pub async fn ping(&self, msg: Option<String>) -> Result<Bytes> {
+        client::ping_response(self.request(Ping::new(msg).into_frame()).await?)
+    }
 
-        // Initialize a new oneshot to be used to receive the response back from the connection.
-        let (tx, rx) = oneshot::channel();
+    /// Get the value of a key.
+    ///
+    /// Same as `Client::get` but several requests may be in flight at once.
+    pub async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        client::get_response(self.request(Get::new(key).into_frame()).await?)
+    }
 
-        // Send the request
-        self.tx.send((get, tx)).await?;
+    /// Set `key` to hold the given `value`.
+    ///
+    /// Same as `Client::set` but several requests may be in flight at once.
+    pub async fn set(&self, key: &str, value: Bytes) -> Result<()> {
+        let frame = Set::new(key, value, None).into_frame();
+        client::set_response(self.request(frame).await?)
+    }
 
-        // Await the response
-        match rx.await {
-            Ok(res) => res,
-            Err(err) => Err(err.into()),
-        }
+    /// Set `key` to hold the given `value`. The value expires after `expiration`
+    ///
+    /// Same as `Client::set_expires` but several requests may be in flight at
+    /// once.
+    pub async fn set_expires(&self, key: &str, value: Bytes, expiration: Duration) -> Result<()> {
+        let frame = Set::new(key, value, Some(expiration)).into_frame();
+        client::set_response(self.request(frame).await?)
     }
 
-    /// Set `key` to hold the given `value`.
+    /// Posts `message` to the given `channel`.
     ///
-    /// Same as `Client::set` but requests are **buffered** until the associated
-    /// connection has the ability to send the request
-    pub async fn set(&mut self, key: &str, value: Bytes) -> Result<()> {
-        // Initialize a new `Set` command to send via the channel.
-        let set = Command::Set(key.into(), value);
+    /// Same as `Client::publish` but several requests may be in flight at once.
+    pub async fn publish(&self, channel: &str, message: Bytes) -> Result<u64> {
+        let frame = Publish::new(channel, message).into_frame();
+        client::publish_response(self.request(frame).await?)
+    }
 
+    /// Send a command, given as the array frame to send, and wait for its
+    /// response.
+    ///
+    /// Error responses are returned as `Err(Error::Server)`. The command must
+    /// be answered with exactly one frame: the connection task hands every
+    /// frame it reads to the oldest request in flight, so a command such as
+    /// `SUBSCRIBE` would give the following requests the wrong responses.
+    /// That is why it is not public.
+    async fn request(&self, frame: Frame) -> Result<Frame> {
         // Initialize a new oneshot to be used to receive the response back from the connection.
         let (tx, rx) = oneshot::channel();
 
-        // Send the request
-        self.tx.send((set, tx)).await?;
+        // Send the request. If the connection task is gone, the connection
+        // has failed.
+        self.tx
+            .send((frame, tx))
+            .await
+            .map_err(|_| Error::ConnectionClosed)?;
 
         // Await the response
         match rx.await {
-            Ok(res) => res.map(|_| ()),
-            Err(err) => Err(err.into()),
+            Ok(res) => res,
+            Err(_) => Err(Error::ConnectionClosed),
         }
     }
 }
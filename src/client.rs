@@ -2,31 +2,40 @@
 //!
 //! Provides an async connect and methods for issuing the supported commands.
 
-use crate::cmd::{Get, Ping, Publish, Set, Subscribe, Unsubscribe};
-use crate::{Connection, Frame};
+use crate::cluster;
+use crate::cmd::{Auth, Dump, Get, Ping, Publish, Restore, Set, Subscribe, Unsubscribe, Wait};
+use crate::{server, tls, Connection, Db, Frame, ServerError, ShardError};
 
 use async_stream::try_stream;
 use bytes::Bytes;
-use std::io::{Error, ErrorKind};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::net::{TcpStream, ToSocketAddrs, UnixStream};
+use tokio::time::{self, Instant};
 use tokio_stream::Stream;
 use tracing::{debug, instrument};
 
 /// Established connection with a Redis server.
 ///
-/// Backed by a single `TcpStream`, `Client` provides basic network client
-/// functionality (no pooling, retrying, ...). Connections are established using
-/// the [`connect`](fn@connect) function.
+/// Backed by a single `TcpStream`, optionally encrypted with TLS, a
+/// `UnixStream` or an in-process pipe, `Client` provides basic network client
+/// functionality (no pooling, retrying, ...). Connections are established
+/// using the [`connect`](fn@connect), [`connect_tls`](fn@connect_tls),
+/// [`connect_unix`](fn@connect_unix) and
+/// [`connect_in_process`](fn@connect_in_process) functions.
 ///
 /// Requests are issued using the various methods of `Client`.
 pub struct Client {
-    /// The TCP connection decorated with the redis protocol encoder / decoder
-    /// implemented using a buffered `TcpStream`.
+    /// The connection decorated with the redis protocol encoder / decoder
+    /// implemented using a buffered transport.
     ///
-    /// When `Listener` receives an inbound connection, the `TcpStream` is
-    /// passed to `Connection::new`, which initializes the associated buffers.
-    /// `Connection` allows the handler to operate at the "frame" level and keep
+    /// The `TcpStream`, the TLS stream wrapping it or the `UnixStream` is
+    /// passed to
+    /// `Connection::boxed`, which initializes the associated buffers.
+    /// `Connection` allows the client to operate at the "frame" level and keep
     /// the byte level protocol parsing details encapsulated in `Connection`.
     connection: Connection,
 }
@@ -44,6 +53,70 @@ pub struct Subscriber {
     subscribed_channels: Vec<String>,
 }
 
+/// A batch of commands sent to the server in a single write.
+///
+/// Created with [`Client::pipeline`]. Commands are queued with the builder
+/// methods and sent with [`execute`](Pipeline::execute), which waits for all
+/// the responses.
+pub struct Pipeline<'a> {
+    /// The client used to send the commands.
+    client: &'a mut Client,
+
+    /// The queued commands, already converted to frames.
+    frames: Vec<Frame>,
+}
+
+/// Connections with several independent servers, each one holding a part of
+/// the keys.
+///
+/// Keys are placed on a consistent-hash ring where every server owns a number
+/// of virtual nodes, so adding or removing a server only moves the keys of
+/// the ring segments it owns. As in a cluster, only the `{hashtag}` of a key
+/// is hashed when it has one, so related keys can be kept on the same server.
+///
+/// Each server is a shard with its own [`Client`]. A failing shard only fails
+/// the requests for its keys; its connection is established again on the
+/// next request. Connecting to a shard gives up after a short timeout, and a
+/// shard that failed is not retried for a while, doubling the wait on every
+/// failure, so its requests fail fast instead of delaying the rest. They fail
+/// with [`Error::Shard`](crate::Error::Shard), holding the address of the
+/// server and the error it failed with. Requests involving several shards
+/// are sent to all of them at the same time.
+/// Sharded clients are created using the
+/// [`connect_sharded`](fn@connect_sharded) function.
+///
+/// Only the key and channel commands of `Client` are provided. `ping` returns
+/// the response of every server. Commands that belong to a single connection
+/// (`subscribe`, `wait`, `pipeline`) have no sharded equivalent: use
+/// [`shard_for`](ShardedClient::shard_for) to find the server and
+/// [`connect`](fn@connect) to it.
+pub struct ShardedClient {
+    /// The servers, in the order they were given.
+    shards: Vec<Shard>,
+
+    /// The consistent-hash ring: the point of each virtual node and the
+    /// shard it belongs to.
+    ring: BTreeMap<u32, usize>,
+}
+
+/// A server of a `ShardedClient`.
+struct Shard {
+    /// Address of the server.
+    addr: String,
+
+    /// Connection with the server, `None` while disconnected.
+    client: Option<Client>,
+
+    /// Consecutive failures of the server.
+    failures: u32,
+
+    /// After a failure, the server is not connected to again until then.
+    retry_at: Option<Instant>,
+
+    /// The error of the last failure, returned until `retry_at`.
+    error: Option<ShardError>,
+}
+
 /// A message received on a subscribed channel.
 #[derive(Debug, Clone)]
 pub struct Message {
@@ -51,6 +124,33 @@ pub struct Message {
     pub content: Bytes,
 }
 
+/// Credentials sent with `AUTH` to a server that requires authentication.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    /// The user to authenticate as. When `None` the server uses the
+    /// `default` user.
+    pub username: Option<String>,
+
+    /// The user's password.
+    pub password: String,
+}
+
+/// Options for establishing TLS connections with [`connect_tls`](fn@connect_tls).
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    /// The name the server certificate must be valid for, usually the host
+    /// name of the server.
+    pub server_name: String,
+
+    /// PEM file with the certificates of the CAs trusted to sign the server
+    /// certificate.
+    pub ca_cert_file: PathBuf,
+
+    /// PEM files with the certificate and private key presented to servers
+    /// that verify their clients. `None` to connect without a certificate.
+    pub client_cert: Option<(PathBuf, PathBuf)>,
+}
+
 /// Establish a connection with the Redis server located at `addr`.
 ///
 /// `addr` may be any type that can be asynchronously converted to a
@@ -81,11 +181,295 @@ pub async fn connect<T: ToSocketAddrs>(addr: T) -> crate::Result<Client> {
 
     // Initialize the connection state. This allocates read/write buffers to
     // perform redis protocol frame parsing.
-    let connection = Connection::new(socket);
+    let connection = Connection::boxed(socket);
 
     Ok(Client { connection })
 }
 
+/// Establish a connection with the Redis server located at `addr` and
+/// authenticate it with `credentials`.
+///
+/// Fails if the connection cannot be established or the server rejects the
+/// credentials.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::client::{self, Credentials};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let credentials = Credentials::new("secret");
+///     let mut client = client::connect_with_credentials("localhost:6379", &credentials)
+///         .await
+///         .unwrap();
+///
+///     client.set("foo", "bar".into()).await.unwrap();
+/// }
+/// ```
+pub async fn connect_with_credentials<T: ToSocketAddrs>(
+    addr: T,
+    credentials: &Credentials,
+) -> crate::Result<Client> {
+    let mut client = connect(addr).await?;
+    client.auth(credentials).await?;
+
+    Ok(client)
+}
+
+/// Establish a TLS connection with the Redis server located at `addr`.
+///
+/// The server certificate is verified with the CAs in `options`, and must be
+/// valid for `options.server_name`. Fails if the connection cannot be
+/// established or the TLS handshake fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::client::{self, TlsOptions};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let options = TlsOptions::new("localhost", "ca.crt");
+///     let mut client = client::connect_tls("localhost:6379", &options)
+///         .await
+///         .unwrap();
+///
+///     client.set("foo", "bar".into()).await.unwrap();
+/// }
+/// ```
+pub async fn connect_tls<T: ToSocketAddrs>(addr: T, options: &TlsOptions) -> crate::Result<Client> {
+    let (connector, server_name) = tls::connector(options)?;
+
+    let socket = TcpStream::connect(addr).await?;
+    let socket = connector.connect(server_name, socket).await?;
+
+    let connection = Connection::boxed(socket);
+
+    Ok(Client { connection })
+}
+
+/// Establish a connection with the Redis server listening on the Unix
+/// domain socket at `path`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::client;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut client = client::connect_unix("/tmp/mini-redis.sock").await.unwrap();
+///
+///     client.set("foo", "bar".into()).await.unwrap();
+/// }
+/// ```
+pub async fn connect_unix(path: impl AsRef<Path>) -> crate::Result<Client> {
+    let socket = UnixStream::connect(path).await?;
+
+    let connection = Connection::boxed(socket);
+
+    Ok(Client { connection })
+}
+
+/// Capacity, in bytes, of each direction of the in-process pipe created by
+/// `connect_in_process`.
+const IN_PROCESS_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Create a client for the `db` of this same process, without any network
+/// involved.
+///
+/// Requests are sent over an in-memory pipe (`tokio::io::duplex`) to a task
+/// that applies them to `db` exactly as the server does with a remote
+/// connection, authentication and permissions included. Code written against
+/// `Client` can therefore switch between an embedded and a remote store by
+/// changing only how the client is created.
+///
+/// # Examples
+///
+/// ```
+/// use mini_redis::{client, DbDropGuard};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let guard = DbDropGuard::new();
+///     let db = guard.db();
+///     let mut client = client::connect_in_process(&db);
+///
+///     client.set("foo", "bar".into()).await.unwrap();
+///     assert_eq!(Some("bar".into()), db.get("foo"));
+/// }
+/// ```
+pub fn connect_in_process(db: &Db) -> Client {
+    let (client, server) = tokio::io::duplex(IN_PROCESS_BUFFER_SIZE);
+    server::serve_in_process(db.clone(), server);
+
+    let connection = Connection::boxed(client);
+
+    Client { connection }
+}
+
+/// How a server or a sentinel connects to the other nodes of a deployment:
+/// the master of a replica, the nodes of a cluster, the target of `MIGRATE`
+/// and the instances watched by a sentinel.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PeerOptions {
+    /// Credentials the connections are authenticated with, if any.
+    pub(crate) credentials: Option<Credentials>,
+
+    /// TLS options of the connections. The certificate of every node is
+    /// verified against its host, so `server_name` is ignored.
+    pub(crate) tls: Option<TlsOptions>,
+}
+
+/// Establish a connection with the node listening on `host:port`, encrypted
+/// and authenticated as `options` dictate.
+pub(crate) async fn connect_peer(
+    host: &str,
+    port: u16,
+    options: &PeerOptions,
+) -> crate::Result<Client> {
+    let mut client = match &options.tls {
+        Some(tls) => {
+            let tls = TlsOptions {
+                server_name: host.to_string(),
+                ..tls.clone()
+            };
+            connect_tls((host, port), &tls).await?
+        }
+        None => connect((host, port)).await?,
+    };
+
+    if let Some(credentials) = &options.credentials {
+        client.auth(credentials).await?;
+    }
+
+    Ok(client)
+}
+
+/// Number of points each server gets on the consistent-hash ring of a
+/// `ShardedClient`.
+const VIRTUAL_NODES: usize = 160;
+
+/// Hash used to place keys and servers on the ring.
+const RING_HASH: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// How long connecting to a shard can take.
+const SHARD_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Wait before retrying a shard after its first failure. It doubles with
+/// every consecutive failure, up to `SHARD_MAX_BACKOFF`.
+const SHARD_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest wait before retrying a shard.
+const SHARD_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Establish connections with the Redis servers located at `addrs`, sharding
+/// the keys among them.
+///
+/// Every address is given as "host:port". Servers that cannot be reached do
+/// not make the function fail: only the requests for their keys fail until
+/// they are available. An error is returned if `addrs` is empty.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::client;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut client = client::connect_sharded(&["127.0.0.1:6379", "127.0.0.1:6380"])
+///         .await
+///         .unwrap();
+///
+///     client.set("foo", "bar".into()).await.unwrap();
+/// }
+/// ```
+pub async fn connect_sharded(addrs: &[impl ToString]) -> crate::Result<ShardedClient> {
+    if addrs.is_empty() {
+        return Err("no servers to shard the keys".into());
+    }
+
+    let mut shards = Vec::with_capacity(addrs.len());
+    let mut ring = BTreeMap::new();
+
+    for (index, addr) in addrs.iter().enumerate() {
+        let addr = addr.to_string();
+
+        for vnode in 0..VIRTUAL_NODES {
+            let point = RING_HASH.checksum(format!("{}-{}", addr, vnode).as_bytes());
+            ring.insert(point, index);
+        }
+
+        shards.push(Shard {
+            addr,
+            client: None,
+            failures: 0,
+            retry_at: None,
+            error: None,
+        });
+    }
+
+    // All the servers are connected to at the same time.
+    let mut client = ShardedClient { shards, ring };
+    let requests = (0..addrs.len()).map(|index| (index, ())).collect();
+    for (index, res) in client
+        .on_shards(requests, |client, ()| async move { (client, Ok(())) })
+        .await
+        .into_iter()
+        .enumerate()
+    {
+        if let Err(err) = res {
+            debug!(addr = %client.shards[index].addr, %err, "shard unavailable");
+        }
+    }
+
+    Ok(client)
+}
+
+impl Credentials {
+    /// Credentials for the `default` user, the one the `requirepass`
+    /// password belongs to.
+    pub fn new(password: impl Into<String>) -> Credentials {
+        Credentials {
+            username: None,
+            password: password.into(),
+        }
+    }
+
+    /// Credentials for the given user.
+    pub fn with_username(username: impl Into<String>, password: impl Into<String>) -> Credentials {
+        Credentials {
+            username: Some(username.into()),
+            password: password.into(),
+        }
+    }
+}
+
+impl TlsOptions {
+    /// Options for verifying the certificate of the server named
+    /// `server_name` with the CAs in `ca_cert_file`, without presenting a
+    /// client certificate.
+    pub fn new(server_name: impl Into<String>, ca_cert_file: impl Into<PathBuf>) -> TlsOptions {
+        TlsOptions {
+            server_name: server_name.into(),
+            ca_cert_file: ca_cert_file.into(),
+            client_cert: None,
+        }
+    }
+
+    /// Present the certificate in `cert_file`, with the private key in
+    /// `key_file`, to servers that verify their clients.
+    pub fn with_client_cert(
+        mut self,
+        cert_file: impl Into<PathBuf>,
+        key_file: impl Into<PathBuf>,
+    ) -> TlsOptions {
+        self.client_cert = Some((cert_file.into(), key_file.into()));
+        self
+    }
+}
+
 impl Client {
     /// Ping to the server.
     ///
@@ -116,11 +500,7 @@ impl Client {
 
         self.connection.write_frame(&frame).await?;
 
-        match self.read_response().await? {
-            Frame::Simple(value) => Ok(value.into()),
-            Frame::Bulk(value) => Ok(value),
-            frame => Err(frame.to_error()),
-        }
+        ping_response(self.read_response().await?)
     }
 
     /// Get the value of key.
@@ -154,15 +534,7 @@ impl Client {
         self.connection.write_frame(&frame).await?;
 
         // Wait for the response from the server
-        //
-        // Both `Simple` and `Bulk` frames are accepted. `Null` represents the
-        // key not being present and `None` is returned.
-        match self.read_response().await? {
-            Frame::Simple(value) => Ok(Some(value.into())),
-            Frame::Bulk(value) => Ok(Some(value)),
-            Frame::Null => Ok(None),
-            frame => Err(frame.to_error()),
-        }
+        get_response(self.read_response().await?)
     }
 
     /// Set `key` to hold the given `value`.
@@ -263,12 +635,8 @@ impl Client {
         // socket, waiting if necessary.
         self.connection.write_frame(&frame).await?;
 
-        // Wait for the response from the server. On success, the server
-        // responds simply with `OK`. Any other response indicates an error.
-        match self.read_response().await? {
-            Frame::Simple(response) if response == "OK" => Ok(()),
-            frame => Err(frame.to_error()),
-        }
+        // Wait for the response from the server.
+        set_response(self.read_response().await?)
     }
 
     /// Posts `message` to the given `channel`.
@@ -303,12 +671,171 @@ impl Client {
         self.connection.write_frame(&frame).await?;
 
         // Read the response
+        publish_response(self.read_response().await?)
+    }
+
+    /// Serialize the value stored at `key`.
+    ///
+    /// The returned payload includes the type of the value and a checksum and
+    /// can be loaded into this or another server with [`restore`]. If the key
+    /// does not exist, `None` is returned.
+    ///
+    /// [`restore`]: Client::restore
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let payload = client.dump("foo").await.unwrap().unwrap();
+    ///     client.restore("bar", payload, None, false).await.unwrap();
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn dump(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        let frame = Dump::new(key).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        // `DUMP` replies like `GET`: a bulk string or `nil`.
+        get_response(self.read_response().await?)
+    }
+
+    /// Create `key` from a `payload` obtained with [`dump`].
+    ///
+    /// The key expires after `ttl`, if given. If the key already exists the
+    /// server replies with a `BUSYKEY` error, unless `replace` is set.
+    ///
+    /// [`dump`]: Client::dump
+    #[instrument(skip(self, payload))]
+    pub async fn restore(
+        &mut self,
+        key: &str,
+        payload: Bytes,
+        ttl: Option<Duration>,
+        replace: bool,
+    ) -> crate::Result<()> {
+        let frame = Restore::new(key, payload, ttl, replace).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        set_response(self.read_response().await?)
+    }
+
+    /// Block until the writes previously sent on this connection are
+    /// acknowledged by at least `numreplicas` replicas, or `timeout` elapses.
+    ///
+    /// Returns the number of replicas that acknowledged the writes. A zero
+    /// `timeout` blocks forever.
+    #[instrument(skip(self))]
+    pub async fn wait(&mut self, numreplicas: u64, timeout: Duration) -> crate::Result<u64> {
+        let frame = Wait::new(numreplicas, timeout).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
         match self.read_response().await? {
-            Frame::Integer(response) => Ok(response),
+            Frame::Integer(replicas) => Ok(replicas),
             frame => Err(frame.to_error()),
         }
     }
 
+    /// Authenticate the connection with `credentials`.
+    ///
+    /// Servers that require a password reject every other command until the
+    /// connection is authenticated.
+    #[instrument(skip(self, credentials))]
+    pub async fn auth(&mut self, credentials: &Credentials) -> crate::Result<()> {
+        let frame = Auth::new(credentials.username.as_deref(), &credentials.password).into_frame();
+
+        // The request is not logged, as it holds the password.
+        self.connection.write_frame(&frame).await?;
+
+        set_response(self.read_response().await?)
+    }
+
+    /// Send any command, given as its name followed by its arguments, and
+    /// return the response as a raw `Frame`.
+    ///
+    /// Useful for the commands without a dedicated method, such as `CONFIG`,
+    /// `ACL` or `CLUSTER`. Error replies are returned as
+    /// `Err(Error::Server)`, like in every other method.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let timeout = client.command(&["config", "get", "timeout"]).await.unwrap();
+    ///     println!("Got = {:?}", timeout);
+    /// }
+    /// ```
+    #[instrument(skip(self))]
+    pub async fn command(&mut self, args: &[&str]) -> crate::Result<Frame> {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        self.read_response().await
+    }
+
+    /// Start a pipeline of commands.
+    ///
+    /// Pipelining sends several commands without waiting for the response to
+    /// each one of them, saving a round trip per command. The responses are
+    /// returned in the same order as the commands were queued.
+    ///
+    /// # Examples
+    ///
+    /// Demonstrates basic usage.
+    ///
+    /// ```no_run
+    /// use mini_redis::client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = client::connect("localhost:6379").await.unwrap();
+    ///
+    ///     let replies = client
+    ///         .pipeline()
+    ///         .set("foo", "bar".into())
+    ///         .get("foo")
+    ///         .execute()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     assert_eq!(2, replies.len());
+    /// }
+    /// ```
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline {
+            client: self,
+            frames: vec![],
+        }
+    }
+
     /// Subscribes the client to the specified channels.
     ///
     /// Once a client issues a subscribe command, it may no longer issue any
@@ -333,7 +860,7 @@ impl Client {
     /// The core `SUBSCRIBE` logic, used by misc subscribe fns
     async fn subscribe_cmd(&mut self, channels: &[String]) -> crate::Result<()> {
         // Convert the `Subscribe` command into a frame
-        let frame = Subscribe::new(&channels).into_frame();
+        let frame = Subscribe::new(channels).into_frame();
 
         debug!(request = ?frame);
 
@@ -369,30 +896,441 @@ impl Client {
         Ok(())
     }
 
+    /// Gives up the client, returning the underlying connection. Used to
+    /// drive the connection from a dedicated task, see `crate::buffer`.
+    pub(crate) fn into_connection(self) -> Connection {
+        self.connection
+    }
+
     /// Reads a response frame from the socket.
     ///
-    /// If an `Error` frame is received, it is converted to `Err`.
+    /// If an `Error` frame is received, it is converted to
+    /// `Err(Error::Server)`. The connection remains usable after such an
+    /// error.
     async fn read_response(&mut self) -> crate::Result<Frame> {
         let response = self.connection.read_frame().await?;
 
         debug!(?response);
 
         match response {
-            // Error frames are converted to `Err`
-            Some(Frame::Error(msg)) => Err(msg.into()),
-            Some(frame) => Ok(frame),
+            // Error frames are converted to a typed `ServerError`
+            Some(frame) => check_response(frame),
             None => {
                 // Receiving `None` here indicates the server has closed the
                 // connection without sending a frame. This is unexpected and is
-                // represented as a "connection reset by peer" error.
-                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by server");
+                // represented as a `ConnectionClosed` error.
+                Err(crate::Error::ConnectionClosed)
+            }
+        }
+    }
+}
+
+impl Pipeline<'_> {
+    /// Queue a `PING` command.
+    pub fn ping(&mut self, msg: Option<String>) -> &mut Self {
+        self.command(Ping::new(msg).into_frame())
+    }
+
+    /// Queue a `GET` command.
+    pub fn get(&mut self, key: &str) -> &mut Self {
+        self.command(Get::new(key).into_frame())
+    }
+
+    /// Queue a `SET` command.
+    pub fn set(&mut self, key: &str, value: Bytes) -> &mut Self {
+        self.command(Set::new(key, value, None).into_frame())
+    }
+
+    /// Queue a `SET` command with an expiration.
+    pub fn set_expires(&mut self, key: &str, value: Bytes, expiration: Duration) -> &mut Self {
+        self.command(Set::new(key, value, Some(expiration)).into_frame())
+    }
+
+    /// Queue a `DUMP` command.
+    pub fn dump(&mut self, key: &str) -> &mut Self {
+        self.command(Dump::new(key).into_frame())
+    }
+
+    /// Queue a `RESTORE` command.
+    pub fn restore(
+        &mut self,
+        key: &str,
+        payload: Bytes,
+        ttl: Option<Duration>,
+        replace: bool,
+    ) -> &mut Self {
+        self.command(Restore::new(key, payload, ttl, replace).into_frame())
+    }
+
+    /// Queue a `PUBLISH` command.
+    pub fn publish(&mut self, channel: &str, message: Bytes) -> &mut Self {
+        self.command(Publish::new(channel, message).into_frame())
+    }
+
+    /// Queue an arbitrary command, given as the array frame to send.
+    pub fn command(&mut self, frame: Frame) -> &mut Self {
+        self.frames.push(frame);
+        self
+    }
+
+    /// Send all the queued commands in a single write and wait for their
+    /// responses.
+    ///
+    /// The returned `Vec` holds one entry per command, in the same order.
+    /// Commands rejected by the server are reported as `Err(Error::Server)`
+    /// in their entry without affecting the others. The outer `Err` is
+    /// returned if the connection fails.
+    ///
+    /// A pipeline mixes commands with different reply types (`GET` returns a
+    /// value, `SET` a status, `DUMP` a serialized payload, arbitrary
+    /// commands anything), so each reply is returned as the raw `Frame`
+    /// rather than the typed value the matching `Client` method returns.
+    /// `Frame` compares with strings, and `Frame::Null` is a missing key.
+    #[instrument(skip(self))]
+    pub async fn execute(&mut self) -> crate::Result<Vec<crate::Result<Frame>>> {
+        let frames = std::mem::take(&mut self.frames);
+        let connection = &mut self.client.connection;
 
+        // Encode every frame in the write buffer, then send them together.
+        for frame in &frames {
+            debug!(request = ?frame);
+            connection.feed_frame(frame).await?;
+        }
+        connection.flush().await?;
+
+        let mut responses = Vec::with_capacity(frames.len());
+
+        for _ in 0..frames.len() {
+            let response = match self.client.read_response().await {
+                Ok(frame) => Ok(frame),
+                // A server error only affects the command it answers
+                Err(crate::Error::Server(err)) => Err(crate::Error::Server(err)),
+                // Any other error means the connection is no longer usable
+                Err(err) => return Err(err),
+            };
+
+            responses.push(response);
+        }
+
+        Ok(responses)
+    }
+}
+
+impl ShardedClient {
+    /// Returns the addresses of the servers, in the order they were given.
+    pub fn shards(&self) -> Vec<&str> {
+        self.shards
+            .iter()
+            .map(|shard| shard.addr.as_str())
+            .collect()
+    }
+
+    /// Returns the address of the server that holds `key`.
+    pub fn shard_for(&self, key: &str) -> &str {
+        &self.shards[self.shard(key)].addr
+    }
+
+    /// Ping every server.
+    ///
+    /// Returns the response of each server, in the order they were given.
+    #[instrument(skip(self))]
+    pub async fn ping(&mut self, msg: Option<String>) -> Vec<crate::Result<Bytes>> {
+        let requests = (0..self.shards.len())
+            .map(|index| (index, msg.clone()))
+            .collect();
+
+        self.on_shards(requests, |mut client, msg| async move {
+            let response = client.ping(msg).await;
+            (client, response)
+        })
+        .await
+    }
+
+    /// Get the value of key from the server that holds it.
+    ///
+    /// If the key does not exist the special value `None` is returned.
+    #[instrument(skip(self))]
+    pub async fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        let index = self.shard(key);
+        let response = self.client(index).await?.get(key).await;
+        self.check(index, response)
+    }
+
+    /// Get the values of several keys, sending a single pipeline to each
+    /// server involved.
+    ///
+    /// Returns a result per key, in the same order as the keys, so a failing
+    /// server only fails the keys it holds.
+    #[instrument(skip(self))]
+    pub async fn get_many(&mut self, keys: &[&str]) -> Vec<crate::Result<Option<Bytes>>> {
+        let mut by_shard = BTreeMap::<usize, Vec<usize>>::new();
+        for (i, key) in keys.iter().enumerate() {
+            by_shard.entry(self.shard(key)).or_default().push(i);
+        }
+
+        let mut values: Vec<_> = keys.iter().map(|_| Ok(None)).collect();
+
+        let requests = by_shard
+            .iter()
+            .map(|(index, positions)| {
+                let keys: Vec<String> = positions.iter().map(|i| keys[*i].to_string()).collect();
+                (*index, keys)
+            })
+            .collect();
+
+        let responses = self
+            .on_shards(requests, |mut client, keys| async move {
+                let mut pipeline = client.pipeline();
+                for key in &keys {
+                    pipeline.get(key);
+                }
+                let responses = pipeline.execute().await;
+                (client, responses)
+            })
+            .await;
+
+        for ((index, positions), responses) in by_shard.into_iter().zip(responses) {
+            match responses {
+                Ok(responses) => {
+                    for (i, response) in positions.into_iter().zip(responses) {
+                        values[i] = response.and_then(get_response);
+                    }
+                }
+                Err(err) => {
+                    // Every key of the shard fails with the same error.
+                    // `check` leaves either an error sent by the server or
+                    // a failure of the shard, both cheap to clone.
+                    let err = match err {
+                        crate::Error::Server(err) => Ok(err),
+                        crate::Error::Shard(err) => Err(err),
+                        err => Err(ShardError::new(&self.shards[index].addr, err)),
+                    };
+                    for i in positions {
+                        values[i] = Err(match &err {
+                            Ok(err) => err.clone().into(),
+                            Err(err) => err.clone().into(),
+                        });
+                    }
+                }
+            }
+        }
+
+        values
+    }
+
+    /// Set `key` to hold the given `value` in the server that holds it.
+    #[instrument(skip(self))]
+    pub async fn set(&mut self, key: &str, value: Bytes) -> crate::Result<()> {
+        let index = self.shard(key);
+        let response = self.client(index).await?.set(key, value).await;
+        self.check(index, response)
+    }
+
+    /// Set `key` to hold the given `value` in the server that holds it. The
+    /// value expires after `expiration`.
+    #[instrument(skip(self))]
+    pub async fn set_expires(
+        &mut self,
+        key: &str,
+        value: Bytes,
+        expiration: Duration,
+    ) -> crate::Result<()> {
+        let index = self.shard(key);
+        let response = self
+            .client(index)
+            .await?
+            .set_expires(key, value, expiration)
+            .await;
+        self.check(index, response)
+    }
+
+    /// Post `message` to the given `channel`. Channels are sharded like keys,
+    /// so subscribers must connect to [`shard_for`](ShardedClient::shard_for)
+    /// the channel.
+    ///
+    /// Returns the number of subscribers currently listening on the channel.
+    #[instrument(skip(self))]
+    pub async fn publish(&mut self, channel: &str, message: Bytes) -> crate::Result<u64> {
+        let index = self.shard(channel);
+        let response = self.client(index).await?.publish(channel, message).await;
+        self.check(index, response)
+    }
+
+    /// Serialize the value stored at `key` in the server that holds it. See
+    /// [`Client::dump`].
+    #[instrument(skip(self))]
+    pub async fn dump(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        let index = self.shard(key);
+        let response = self.client(index).await?.dump(key).await;
+        self.check(index, response)
+    }
+
+    /// Create `key` in the server that holds it. See [`Client::restore`].
+    #[instrument(skip(self, payload))]
+    pub async fn restore(
+        &mut self,
+        key: &str,
+        payload: Bytes,
+        ttl: Option<Duration>,
+        replace: bool,
+    ) -> crate::Result<()> {
+        let index = self.shard(key);
+        let response = self
+            .client(index)
+            .await?
+            .restore(key, payload, ttl, replace)
+            .await;
+        self.check(index, response)
+    }
+
+    /// Index of the shard that holds `key`: the first virtual node at or
+    /// after the key's point on the ring, wrapping around.
+    fn shard(&self, key: &str) -> usize {
+        let point = RING_HASH.checksum(cluster::hash_tag(key.as_bytes()));
+
+        let (_, index) = self
+            .ring
+            .range(point..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .expect("the ring has virtual nodes");
+
+        *index
+    }
+
+    /// Returns the connection with a shard, connecting if needed.
+    async fn client(&mut self, index: usize) -> crate::Result<&mut Client> {
+        if self.shards[index].client.is_none() {
+            self.shards[index].available()?;
+            let res = connect_shard(&self.shards[index].addr).await;
+            self.shards[index].client = Some(self.check(index, res)?);
+        }
+
+        Ok(self.shards[index].client.as_mut().unwrap())
+    }
+
+    /// Sends a request to several shards at the same time, connecting to
+    /// the ones that are disconnected. `request` receives the connection
+    /// with a shard and returns it along with the response.
+    ///
+    /// Returns the response of each shard, in the order of `requests`.
+    async fn on_shards<R, T, F, Fut>(
+        &mut self,
+        requests: Vec<(usize, R)>,
+        request: F,
+    ) -> Vec<crate::Result<T>>
+    where
+        R: Send + 'static,
+        T: Send + 'static,
+        F: Fn(Client, R) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = (Client, crate::Result<T>)> + Send,
+    {
+        let tasks: Vec<_> = requests
+            .into_iter()
+            .map(|(index, req)| {
+                let shard = &mut self.shards[index];
+                let available = shard.available();
+                let client = shard.client.take();
+                let addr = shard.addr.clone();
+                let request = request.clone();
+
+                let task = tokio::spawn(async move {
+                    let client = match (available, client) {
+                        (_, Some(client)) => client,
+                        (Err(err), None) => return (None, Err(err)),
+                        (Ok(()), None) => match connect_shard(&addr).await {
+                            Ok(client) => client,
+                            Err(err) => return (None, Err(err)),
+                        },
+                    };
+
+                    let (client, response) = request(client, req).await;
+                    (Some(client), response)
+                });
+
+                (index, task)
+            })
+            .collect();
+
+        let mut responses = Vec::with_capacity(tasks.len());
+        for (index, task) in tasks {
+            let (client, response) = match task.await {
+                Ok(res) => res,
+                Err(err) => std::panic::resume_unwind(err.into_panic()),
+            };
+
+            self.shards[index].client = client;
+            responses.push(self.check(index, response));
+        }
+
+        responses
+    }
+
+    /// Drops the connection with a shard if `response` shows it is no longer
+    /// usable, and waits before connecting to it again. The failure is
+    /// returned as an `Error::Shard`. Errors sent by the server keep the
+    /// connection.
+    fn check<T>(&mut self, index: usize, response: crate::Result<T>) -> crate::Result<T> {
+        let shard = &mut self.shards[index];
+
+        match response {
+            // The shard is still being waited on, it was not contacted.
+            Err(crate::Error::Shard(err)) => Err(err.into()),
+            Err(crate::Error::Server(err)) => {
+                shard.succeeded();
                 Err(err.into())
             }
+            Err(err) => {
+                debug!(addr = %shard.addr, %err, "shard failed");
+                Err(shard.failed(err).into())
+            }
+            Ok(value) => {
+                shard.succeeded();
+                Ok(value)
+            }
         }
     }
 }
 
+impl Shard {
+    /// Fails without connecting while the server is being waited on after a
+    /// failure. The error of that failure is returned meanwhile.
+    fn available(&self) -> crate::Result<()> {
+        match (self.retry_at, &self.error) {
+            (Some(retry_at), Some(err)) if Instant::now() < retry_at => Err(err.clone().into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Drops the connection and delays the next attempt to connect. Returns
+    /// `err` along with the address of the server.
+    fn failed(&mut self, err: crate::Error) -> ShardError {
+        self.client = None;
+
+        let backoff = SHARD_BACKOFF
+            .saturating_mul(2u32.saturating_pow(self.failures))
+            .min(SHARD_MAX_BACKOFF);
+        self.failures = self.failures.saturating_add(1);
+        self.retry_at = Some(Instant::now() + backoff);
+
+        let err = ShardError::new(&self.addr, err);
+        self.error = Some(err.clone());
+        err
+    }
+
+    fn succeeded(&mut self) {
+        self.failures = 0;
+        self.retry_at = None;
+        self.error = None;
+    }
+}
+
+/// Connects to a shard, failing with `Error::Timeout` after
+/// `SHARD_CONNECT_TIMEOUT`.
+async fn connect_shard(addr: &str) -> crate::Result<Client> {
+    time::timeout(SHARD_CONNECT_TIMEOUT, connect(addr)).await?
+}
+
 impl Subscriber {
     /// Returns the set of channels currently subscribed to.
     pub fn get_subscribed(&self) -> &[String] {
@@ -459,7 +1397,7 @@ impl Subscriber {
     /// Unsubscribe to a list of new channels
     #[instrument(skip(self))]
     pub async fn unsubscribe(&mut self, channels: &[String]) -> crate::Result<()> {
-        let frame = Unsubscribe::new(&channels).into_frame();
+        let frame = Unsubscribe::new(channels).into_frame();
 
         debug!(request = ?frame);
 
@@ -507,3 +1445,50 @@ impl Subscriber {
         Ok(())
     }
 }
+
+/// Decodes the response to a `PING` command.
+pub(crate) fn ping_response(frame: Frame) -> crate::Result<Bytes> {
+    match frame {
+        Frame::Simple(value) => Ok(value.into()),
+        Frame::Bulk(value) => Ok(value),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Decodes the response to a `GET` command.
+///
+/// Both `Simple` and `Bulk` frames are accepted. `Null` represents the key
+/// not being present and `None` is returned.
+pub(crate) fn get_response(frame: Frame) -> crate::Result<Option<Bytes>> {
+    match frame {
+        Frame::Simple(value) => Ok(Some(value.into())),
+        Frame::Bulk(value) => Ok(Some(value)),
+        Frame::Null => Ok(None),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Decodes the response to a `SET` command. On success, the server responds
+/// simply with `OK`. Any other response indicates an error.
+pub(crate) fn set_response(frame: Frame) -> crate::Result<()> {
+    match frame {
+        Frame::Simple(response) if response == "OK" => Ok(()),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Decodes the response to a `PUBLISH` command: the number of subscribers.
+pub(crate) fn publish_response(frame: Frame) -> crate::Result<u64> {
+    match frame {
+        Frame::Integer(response) => Ok(response),
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// Converts an `Error` frame received from the server to `Err`.
+pub(crate) fn check_response(frame: Frame) -> crate::Result<Frame> {
+    match frame {
+        Frame::Error(msg) => Err(crate::Error::Server(ServerError::parse(&msg))),
+        frame => Ok(frame),
+    }
+}
@@ -0,0 +1,440 @@
+//! Modo cluster: reparto de las claves entre varios nodos.
+//!
+//! El espacio de claves se divide en 16384 slots. El slot de una clave es el
+//! CRC16 de la clave modulo 16384. Si la clave contiene un `{hashtag}` no
+//! vacio solo se utiliza el hashtag, de modo que claves relacionadas como
+//! `{user:1}:name` y `{user:1}:email` caen en el mismo slot.
+//!
+//! Cada nodo sirve los slots que tiene asignados. Para una clave de otro
+//! nodo se responde `-MOVED <slot> <host>:<port>` y el cliente debe repetir
+//! el comando en ese nodo. Los comandos con varias claves solo se aceptan si
+//! todas estan en el mismo slot (`-CROSSSLOT`).
+//!
+//! # Migracion de slots
+//!
+//! Un slot se mueve de un nodo a otro sin dejar de atenderlo:
+//!
+//! 1. `CLUSTER SETSLOT <slot> IMPORTING <origen>` en el destino.
+//! 2. `CLUSTER SETSLOT <slot> MIGRATING <destino>` en el origen.
+//! 3. Se mueven las claves con `CLUSTER GETKEYSINSLOT` y `MIGRATE`.
+//! 4. `CLUSTER SETSLOT <slot> NODE <destino>` en ambos nodos.
+//!
+//! Mientras tanto, el origen responde `-ASK <slot> <host>:<port>` para las
+//! claves que ya no tiene, y el destino solo las atiende si el cliente envia
+//! antes `ASKING`.
+//!
+//! La configuracion es estatica: cada nodo se asigna sus slots con `CLUSTER
+//! ADDSLOTS` y despues los nodos se conocen con `CLUSTER MEET`, que obtiene
+//! los slots que sirve el otro nodo en ese momento. No hay ningun protocolo
+//! entre nodos que propague los cambios posteriores: se aplican en cada nodo
+//! con `CLUSTER SETSLOT`.
+
+use crate::client::{self, PeerOptions};
+use crate::{Frame, ServerError, ServerErrorKind};
+
+use bytes::Bytes;
+use crc::{Crc, CRC_16_XMODEM};
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Numero de slots del cluster.
+pub const SLOTS: u16 = 16384;
+
+const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_XMODEM);
+
+/// Slot al que pertenece una clave.
+pub fn key_slot(key: &[u8]) -> u16 {
+    CRC16.checksum(hash_tag(key)) % SLOTS
+}
+
+/// Parte de la clave que se utiliza para calcular el slot: el contenido del
+/// primer `{...}` si no esta vacio, o toda la clave.
+pub(crate) fn hash_tag(key: &[u8]) -> &[u8] {
+    if let Some(start) = key.iter().position(|b| *b == b'{') {
+        if let Some(len) = key[start + 1..].iter().position(|b| *b == b'}') {
+            if len > 0 {
+                return &key[start + 1..start + 1 + len];
+            }
+        }
+    }
+
+    key
+}
+
+/// Estado del cluster visto por este nodo.
+///
+/// Es un handle que se puede clonar: todos los clones comparten el estado.
+#[derive(Debug, Clone)]
+pub(crate) struct ClusterHandle {
+    shared: Arc<Mutex<State>>,
+}
+
+/// Un nodo del cluster.
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+    pub(crate) id: String,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Identificador de este nodo.
+    myself: String,
+
+    /// Nodos conocidos, incluido este.
+    nodes: HashMap<String, Node>,
+
+    /// Nodo al que esta asignado cada slot.
+    slots: Vec<Option<String>>,
+
+    /// Slots de este nodo que se estan moviendo a otro nodo.
+    migrating: HashMap<u16, String>,
+
+    /// Slots que se estan moviendo a este nodo desde otro.
+    importing: HashMap<u16, String>,
+}
+
+/// Nuevo estado de un slot con `CLUSTER SETSLOT`.
+#[derive(Debug)]
+pub(crate) enum SlotState {
+    Importing(String),
+    Migrating(String),
+    Node(String),
+    Stable,
+}
+
+impl Node {
+    /// Direccion del nodo con el formato de las redirecciones.
+    fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+impl ClusterHandle {
+    /// Crea el estado de un nodo que todavia no tiene slots ni conoce a
+    /// ningun otro nodo. `host` y `port` son la direccion en la que los
+    /// clientes pueden conectarse a el.
+    pub(crate) fn new(host: String, port: u16) -> ClusterHandle {
+        let myself = Node {
+            id: new_node_id(),
+            host,
+            port,
+        };
+
+        let state = State {
+            myself: myself.id.clone(),
+            nodes: HashMap::from([(myself.id.clone(), myself)]),
+            slots: vec![None; SLOTS as usize],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+        };
+
+        ClusterHandle {
+            shared: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Identificador de este nodo.
+    pub(crate) fn myself(&self) -> String {
+        self.shared.lock().unwrap().myself.clone()
+    }
+
+    /// Comprueba si este nodo puede atender un comando sobre `keys`.
+    ///
+    /// `asking` indica que el cliente ha enviado `ASKING` y `exists` si una
+    /// clave esta en la base de datos local. Retorna el error con el que hay
+    /// que responder en caso contrario.
+    pub(crate) fn route(
+        &self,
+        keys: &[&str],
+        asking: bool,
+        exists: impl Fn(&str) -> bool,
+    ) -> Result<(), ServerError> {
+        let slot = match keys.first() {
+            Some(key) => key_slot(key.as_bytes()),
+            None => return Ok(()),
+        };
+
+        if keys.iter().any(|key| key_slot(key.as_bytes()) != slot) {
+            return Err(ServerError::new(
+                ServerErrorKind::CrossSlot,
+                "Keys in request don't hash to the same slot",
+            ));
+        }
+
+        let state = self.shared.lock().unwrap();
+
+        match &state.slots[slot as usize] {
+            Some(owner) if *owner == state.myself => {
+                let target = match state.migrating.get(&slot) {
+                    Some(target) => target,
+                    None => return Ok(()),
+                };
+
+                // Las claves que ya no estan pueden haberse movido al nodo
+                // de destino.
+                let missing = keys.iter().filter(|key| !exists(key)).count();
+
+                if missing == 0 {
+                    Ok(())
+                } else if missing == keys.len() {
+                    Err(redirect(ServerErrorKind::Ask, slot, &state.nodes[target]))
+                } else {
+                    Err(ServerError::new(
+                        ServerErrorKind::TryAgain,
+                        "Multiple keys request during rehashing of slot",
+                    ))
+                }
+            }
+            _ if asking && state.importing.contains_key(&slot) => Ok(()),
+            Some(owner) => Err(redirect(ServerErrorKind::Moved, slot, &state.nodes[owner])),
+            None => Err(ServerError::new(
+                ServerErrorKind::ClusterDown,
+                "Hash slot not served",
+            )),
+        }
+    }
+
+    /// Añade un nodo conocido junto con los slots que sirve. Los slots que
+    /// ya estaban asignados no se modifican.
+    pub(crate) fn add_node(&self, node: Node, slots: &[u16]) {
+        let mut state = self.shared.lock().unwrap();
+
+        for slot in slots {
+            state.slots[*slot as usize].get_or_insert_with(|| node.id.clone());
+        }
+
+        state.nodes.insert(node.id.clone(), node);
+    }
+
+    /// Asigna slots a este nodo. Falla sin asignar ninguno si alguno ya
+    /// estaba asignado.
+    pub(crate) fn add_slots(&self, slots: &[u16]) -> Result<(), ServerError> {
+        let mut state = self.shared.lock().unwrap();
+
+        if let Some(slot) = slots
+            .iter()
+            .find(|slot| state.slots[**slot as usize].is_some())
+        {
+            return Err(ServerError::err(format!("Slot {} is already busy", slot)));
+        }
+
+        let myself = state.myself.clone();
+        for slot in slots {
+            state.slots[*slot as usize] = Some(myself.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Cambia el estado de un slot (`CLUSTER SETSLOT`).
+    pub(crate) fn set_slot(&self, slot: u16, slot_state: SlotState) -> Result<(), ServerError> {
+        let mut state = self.shared.lock().unwrap();
+        let state = &mut *state;
+
+        let unknown = |id: &str| ServerError::err(format!("I don't know about node {}", id));
+        let mine = state.slots[slot as usize].as_ref() == Some(&state.myself);
+
+        match slot_state {
+            SlotState::Migrating(id) => {
+                if !mine {
+                    return Err(ServerError::err(format!(
+                        "I'm not the owner of hash slot {}",
+                        slot
+                    )));
+                }
+                if !state.nodes.contains_key(&id) {
+                    return Err(unknown(&id));
+                }
+                state.migrating.insert(slot, id);
+            }
+            SlotState::Importing(id) => {
+                if mine {
+                    return Err(ServerError::err(format!(
+                        "I'm already the owner of hash slot {}",
+                        slot
+                    )));
+                }
+                if !state.nodes.contains_key(&id) {
+                    return Err(unknown(&id));
+                }
+                state.importing.insert(slot, id);
+            }
+            SlotState::Node(id) => {
+                if !state.nodes.contains_key(&id) {
+                    return Err(unknown(&id));
+                }
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+                state.slots[slot as usize] = Some(id);
+            }
+            SlotState::Stable => {
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Respuesta de `CLUSTER SLOTS`: cada rango de slots consecutivos del
+    /// mismo nodo como `[inicio, fin, [host, puerto, id]]`.
+    pub(crate) fn slots_frame(&self) -> Frame {
+        let state = self.shared.lock().unwrap();
+
+        let ranges = state
+            .ranges()
+            .into_iter()
+            .map(|(start, end, id)| {
+                let node = &state.nodes[id];
+                Frame::Array(vec![
+                    Frame::Integer(start as u64),
+                    Frame::Integer(end as u64),
+                    Frame::Array(vec![
+                        bulk(&node.host),
+                        Frame::Integer(node.port as u64),
+                        bulk(&node.id),
+                    ]),
+                ])
+            })
+            .collect();
+
+        Frame::Array(ranges)
+    }
+
+    /// Respuesta de `CLUSTER SHARDS`: los slots y la descripcion de cada
+    /// nodo.
+    pub(crate) fn shards_frame(&self) -> Frame {
+        let state = self.shared.lock().unwrap();
+        let ranges = state.ranges();
+
+        let mut nodes: Vec<_> = state.nodes.values().collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let shards = nodes
+            .into_iter()
+            .map(|node| {
+                let slots = ranges
+                    .iter()
+                    .filter(|(_, _, id)| **id == node.id)
+                    .flat_map(|(start, end, _)| {
+                        [Frame::Integer(*start as u64), Frame::Integer(*end as u64)]
+                    })
+                    .collect();
+
+                let description = Frame::Array(vec![
+                    bulk("id"),
+                    bulk(&node.id),
+                    bulk("port"),
+                    Frame::Integer(node.port as u64),
+                    bulk("ip"),
+                    bulk(&node.host),
+                    bulk("endpoint"),
+                    bulk(&node.host),
+                    bulk("role"),
+                    bulk("master"),
+                    bulk("replication-offset"),
+                    Frame::Integer(0),
+                    bulk("health"),
+                    bulk("online"),
+                ]);
+
+                Frame::Array(vec![
+                    bulk("slots"),
+                    Frame::Array(slots),
+                    bulk("nodes"),
+                    Frame::Array(vec![description]),
+                ])
+            })
+            .collect();
+
+        Frame::Array(shards)
+    }
+}
+
+impl State {
+    /// Rangos de slots consecutivos asignados al mismo nodo.
+    fn ranges(&self) -> Vec<(u16, u16, &String)> {
+        let mut ranges: Vec<(u16, u16, &String)> = vec![];
+
+        for (slot, owner) in self.slots.iter().enumerate() {
+            let slot = slot as u16;
+            let owner = match owner {
+                Some(owner) => owner,
+                None => continue,
+            };
+
+            match ranges.last_mut() {
+                Some((_, end, id)) if *end + 1 == slot && *id == owner => *end = slot,
+                _ => ranges.push((slot, slot, owner)),
+            }
+        }
+
+        ranges
+    }
+}
+
+/// Obtiene el identificador del nodo que escucha en `host:port` y los slots
+/// que sirve (`CLUSTER MEET`). La conexion se establece segun `options`
+/// (ver `Config::peer_options`).
+pub(crate) async fn meet(
+    host: String,
+    port: u16,
+    options: &PeerOptions,
+) -> crate::Result<(Node, Vec<u16>)> {
+    let mut client = client::connect_peer(&host, port, options).await?;
+
+    let mut pipeline = client.pipeline();
+    pipeline.command(Frame::Array(vec![bulk("cluster"), bulk("myid")]));
+    pipeline.command(Frame::Array(vec![bulk("cluster"), bulk("slots")]));
+
+    let mut responses = pipeline.execute().await?.into_iter();
+    let (id, ranges) = match (responses.next(), responses.next()) {
+        (Some(id), Some(ranges)) => (id?, ranges?),
+        _ => return Err(crate::Error::ConnectionClosed),
+    };
+
+    let id = match id {
+        Frame::Bulk(id) => String::from_utf8(id.to_vec())
+            .map_err(|_| crate::Error::Protocol("invalid node id".into()))?,
+        frame => return Err(frame.to_error()),
+    };
+
+    // Cada rango es `[inicio, fin, [host, puerto, id]]`; solo interesan los
+    // del propio nodo.
+    let mut slots = vec![];
+    if let Frame::Array(ranges) = ranges {
+        for range in ranges {
+            if let Frame::Array(range) = range {
+                match &range[..] {
+                    [Frame::Integer(start), Frame::Integer(end), Frame::Array(node), ..] if matches!(node.get(2), Some(Frame::Bulk(node_id)) if *node_id == id.as_bytes()) =>
+                    {
+                        slots.extend((*start as u16..=*end as u16).filter(|slot| *slot < SLOTS));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok((Node { id, host, port }, slots))
+}
+
+/// Error de redireccion a otro nodo.
+fn redirect(kind: ServerErrorKind, slot: u16, node: &Node) -> ServerError {
+    ServerError::new(kind, format!("{} {}", slot, node.addr()))
+}
+
+fn bulk(value: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(value.as_bytes()))
+}
+
+/// Genera un identificador de nodo: 40 caracteres hexadecimales aleatorios.
+fn new_node_id() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40)
+        .map(|_| char::from_digit(rng.gen_range(0..16), 16).unwrap())
+        .collect()
+}
@@ -0,0 +1,368 @@
+//! Redis cluster client.
+//!
+//! Provides a [`ClusterClient`] that routes every command to the node that
+//! serves the slot of its key, following the cluster's redirects.
+
+use crate::client::{self, get_response, set_response, Client};
+use crate::cluster::{self, SLOTS};
+use crate::cmd::{Del, Get, Set};
+use crate::{Error, Frame, ServerErrorKind};
+
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::time;
+use tracing::{debug, instrument};
+
+/// Maximum number of redirects followed for a single command.
+const MAX_REDIRECTS: usize = 16;
+
+/// Time to wait before retrying a command rejected with `TRYAGAIN`.
+const TRYAGAIN_DELAY: Duration = Duration::from_millis(20);
+
+/// Established connections with the nodes of a Redis cluster.
+///
+/// The client keeps a copy of the cluster's slot map, obtained with
+/// `CLUSTER SLOTS`, and a [`Client`] per node. Each command is sent to the
+/// node serving the slot of its key. When the node answers with a `MOVED`
+/// redirect the slot map is refreshed and the command is retried on the new
+/// node; an `ASK` redirect retries the command once on the given node,
+/// preceded by `ASKING`.
+///
+/// Connections are established using the [`connect`](fn@connect) function.
+pub struct ClusterClient {
+    /// Addresses used to bootstrap the slot map, as "host:port".
+    seeds: Vec<String>,
+
+    /// Address of the node serving each slot, if known.
+    slots: Vec<Option<String>>,
+
+    /// Connections to the nodes, by address. Opened on first use.
+    nodes: HashMap<String, Client>,
+}
+
+/// Where to send a command.
+enum Target {
+    /// The node serving the slot, according to the slot map.
+    Slot,
+
+    /// A node given by an `ASK` redirect.
+    Ask(String),
+}
+
+/// Establish a connection with the Redis cluster the `seeds` nodes belong to.
+///
+/// Each seed is an address given as "host:port". The slot map is requested
+/// from the first seed that can be reached. Connections with the remaining
+/// nodes are opened when a command is first routed to them.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_redis::cluster_client;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut client = cluster_client::connect(&["127.0.0.1:7000", "127.0.0.1:7001"])
+///         .await
+///         .unwrap();
+///
+///     client.set("foo", "bar".into()).await.unwrap();
+/// }
+/// ```
+pub async fn connect(seeds: &[impl ToString]) -> crate::Result<ClusterClient> {
+    let mut client = ClusterClient {
+        seeds: seeds.iter().map(ToString::to_string).collect(),
+        slots: vec![None; SLOTS as usize],
+        nodes: HashMap::new(),
+    };
+
+    client.refresh_slots().await?;
+
+    Ok(client)
+}
+
+impl ClusterClient {
+    /// Get the value of key.
+    ///
+    /// If the key does not exist the special value `None` is returned.
+    #[instrument(skip(self))]
+    pub async fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        let frame = Get::new(key).into_frame();
+        get_response(self.request(key, frame).await?)
+    }
+
+    /// Get the values of several keys.
+    ///
+    /// The keys are grouped by slot and each group is sent as a pipeline to
+    /// the node serving it. The values are returned in the same order as the
+    /// keys, with `None` for the keys that do not exist.
+    #[instrument(skip(self))]
+    pub async fn get_many(&mut self, keys: &[&str]) -> crate::Result<Vec<Option<Bytes>>> {
+        let mut values = vec![None; keys.len()];
+
+        for (slot, indexes) in by_slot(keys) {
+            let frames = indexes
+                .iter()
+                .map(|i| Get::new(keys[*i]).into_frame())
+                .collect();
+
+            let responses = self.request_slot(slot, frames).await?;
+
+            for (i, response) in indexes.into_iter().zip(responses) {
+                let response = match response {
+                    // Redirected keys are retried one by one.
+                    Err(Error::Server(err)) if is_redirect(err.kind()) => {
+                        self.request(keys[i], Get::new(keys[i]).into_frame())
+                            .await?
+                    }
+                    response => response?,
+                };
+
+                values[i] = get_response(response)?;
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Set `key` to hold the given `value`.
+    #[instrument(skip(self))]
+    pub async fn set(&mut self, key: &str, value: Bytes) -> crate::Result<()> {
+        let frame = Set::new(key, value, None).into_frame();
+        set_response(self.request(key, frame).await?)
+    }
+
+    /// Set `key` to hold the given `value`. The value expires after
+    /// `expiration`.
+    #[instrument(skip(self))]
+    pub async fn set_expires(
+        &mut self,
+        key: &str,
+        value: Bytes,
+        expiration: Duration,
+    ) -> crate::Result<()> {
+        let frame = Set::new(key, value, Some(expiration)).into_frame();
+        set_response(self.request(key, frame).await?)
+    }
+
+    /// Remove the given keys, returning how many of them existed.
+    ///
+    /// A `DEL` command is sent for the keys of each slot.
+    #[instrument(skip(self))]
+    pub async fn del(&mut self, keys: &[&str]) -> crate::Result<u64> {
+        let mut deleted = 0;
+
+        for (_, indexes) in by_slot(keys) {
+            let slot_keys: Vec<_> = indexes.iter().map(|i| keys[*i]).collect();
+            let frame = Del::new(&slot_keys).into_frame();
+
+            match self.request(slot_keys[0], frame).await? {
+                Frame::Integer(n) => deleted += n,
+                frame => return Err(frame.to_error()),
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Send a command on `key`, given as its array frame, to the node that
+    /// serves the key and return the response.
+    ///
+    /// Redirects are followed, so the response is never a `MOVED` or `ASK`
+    /// error.
+    pub async fn request(&mut self, key: &str, frame: Frame) -> crate::Result<Frame> {
+        let slot = cluster::key_slot(key.as_bytes());
+        let mut target = Target::Slot;
+
+        for _ in 0..MAX_REDIRECTS {
+            let response = match &target {
+                Target::Slot => self.request_slot(slot, vec![frame.clone()]).await?,
+                Target::Ask(addr) => {
+                    let asking = Frame::Array(vec![Frame::Bulk(Bytes::from("asking"))]);
+                    let mut responses =
+                        self.request_node(addr, vec![asking, frame.clone()]).await?;
+                    responses.split_off(1)
+                }
+            };
+
+            let err = match response.into_iter().next() {
+                Some(Err(Error::Server(err))) => err,
+                Some(response) => return response,
+                None => return Err(Error::ConnectionClosed),
+            };
+
+            debug!(%err, "cluster redirect");
+
+            target = match err.kind() {
+                ServerErrorKind::Moved => {
+                    let addr = redirect_addr(err.message())?;
+                    // The node that redirected knows better than the map,
+                    // which may come from a node not updated yet.
+                    let _ = self.refresh_slots().await;
+                    self.slots[slot as usize] = Some(addr);
+                    Target::Slot
+                }
+                ServerErrorKind::Ask => Target::Ask(redirect_addr(err.message())?),
+                ServerErrorKind::TryAgain => {
+                    time::sleep(TRYAGAIN_DELAY).await;
+                    Target::Slot
+                }
+                _ => return Err(Error::Server(err)),
+            };
+        }
+
+        Err("too many cluster redirects".into())
+    }
+
+    /// Request the slot map to the known nodes, trying each one until one of
+    /// them replies.
+    pub async fn refresh_slots(&mut self) -> crate::Result<()> {
+        // Connected nodes first, then the seeds.
+        let mut addrs: Vec<_> = self.nodes.keys().cloned().collect();
+        addrs.extend(
+            self.seeds
+                .iter()
+                .filter(|addr| !self.nodes.contains_key(*addr))
+                .cloned(),
+        );
+
+        let mut last_err = Error::from("no cluster nodes available");
+
+        for addr in addrs {
+            let request = Frame::Array(vec![
+                Frame::Bulk(Bytes::from("cluster")),
+                Frame::Bulk(Bytes::from("slots")),
+            ]);
+
+            match self.request_node(&addr, vec![request]).await {
+                Ok(mut responses) => match responses.pop() {
+                    Some(Ok(frame)) => {
+                        self.slots = slot_map(frame)?;
+                        return Ok(());
+                    }
+                    Some(Err(err)) => last_err = err,
+                    None => last_err = Error::ConnectionClosed,
+                },
+                Err(err) => last_err = err,
+            }
+        }
+
+        Err(last_err)
+    }
+
+    /// Send commands to the node serving `slot`. If the slot is not in the
+    /// map they are sent to any node, which will redirect them.
+    async fn request_slot(
+        &mut self,
+        slot: u16,
+        frames: Vec<Frame>,
+    ) -> crate::Result<Vec<crate::Result<Frame>>> {
+        let addr = match &self.slots[slot as usize] {
+            Some(addr) => addr.clone(),
+            None => self
+                .slots
+                .iter()
+                .flatten()
+                .chain(&self.seeds)
+                .next()
+                .cloned()
+                .ok_or("no cluster nodes available")?,
+        };
+
+        self.request_node(&addr, frames).await
+    }
+
+    /// Send commands as a pipeline to the node at `addr`, connecting to it
+    /// if needed. A connection that fails is discarded, so the next request
+    /// opens a new one.
+    async fn request_node(
+        &mut self,
+        addr: &str,
+        frames: Vec<Frame>,
+    ) -> crate::Result<Vec<crate::Result<Frame>>> {
+        if !self.nodes.contains_key(addr) {
+            let client = client::connect(addr).await?;
+            self.nodes.insert(addr.to_string(), client);
+        }
+
+        let client = self.nodes.get_mut(addr).unwrap();
+        let mut pipeline = client.pipeline();
+        for frame in frames {
+            pipeline.command(frame);
+        }
+
+        let responses = pipeline.execute().await;
+        if responses.is_err() {
+            self.nodes.remove(addr);
+        }
+
+        responses
+    }
+}
+
+/// Groups the positions of `keys` by slot.
+fn by_slot(keys: &[&str]) -> BTreeMap<u16, Vec<usize>> {
+    let mut slots = BTreeMap::<u16, Vec<usize>>::new();
+
+    for (i, key) in keys.iter().enumerate() {
+        slots
+            .entry(cluster::key_slot(key.as_bytes()))
+            .or_default()
+            .push(i);
+    }
+
+    slots
+}
+
+fn is_redirect(kind: &ServerErrorKind) -> bool {
+    matches!(
+        kind,
+        ServerErrorKind::Moved | ServerErrorKind::Ask | ServerErrorKind::TryAgain
+    )
+}
+
+/// Address in a `MOVED` or `ASK` error message, given as "slot host:port".
+fn redirect_addr(message: &str) -> crate::Result<String> {
+    match message.split_once(' ') {
+        Some((_, addr)) => Ok(addr.to_string()),
+        None => Err(Error::Protocol(format!("invalid redirect: {}", message))),
+    }
+}
+
+/// Decodes the response to `CLUSTER SLOTS`: an array of slot ranges as
+/// `[start, end, [host, port, ...], ...]`.
+fn slot_map(frame: Frame) -> crate::Result<Vec<Option<String>>> {
+    let mut slots = vec![None; SLOTS as usize];
+
+    let ranges = match frame {
+        Frame::Array(ranges) => ranges,
+        frame => return Err(frame.to_error()),
+    };
+
+    for range in ranges {
+        let (start, end, host, port) = match &range {
+            Frame::Array(range) => match &range[..] {
+                [Frame::Integer(start), Frame::Integer(end), Frame::Array(node), ..] => match &node
+                    [..]
+                {
+                    [Frame::Bulk(host), Frame::Integer(port), ..] => (*start, *end, host, *port),
+                    _ => return Err(range_error(&range)),
+                },
+                _ => return Err(range_error(&range)),
+            },
+            _ => return Err(range_error(&range)),
+        };
+
+        let addr = format!("{}:{}", String::from_utf8_lossy(host), port);
+        for slot in start..=end.min(SLOTS as u64 - 1) {
+            slots[slot as usize] = Some(addr.clone());
+        }
+    }
+
+    Ok(slots)
+}
+
+fn range_error(range: &impl std::fmt::Debug) -> Error {
+    Error::Protocol(format!("invalid CLUSTER SLOTS range: {:?}", range))
+}
@@ -13,10 +13,60 @@ pub use subscribe::{Subscribe, Unsubscribe};
 mod ping;
 pub use ping::Ping;
 
+mod del;
+pub use del::Del;
+
+mod dump;
+pub use dump::Dump;
+
+mod restore;
+pub use restore::Restore;
+
+mod migrate;
+pub use migrate::Migrate;
+
+mod cluster;
+pub use cluster::{Asking, Cluster};
+
+mod replicaof;
+pub use replicaof::ReplicaOf;
+
+mod psync;
+pub use psync::{Psync, ReplConf};
+
+mod wait;
+pub use wait::Wait;
+
+mod role;
+pub use role::Role;
+
+mod bgrewriteaof;
+pub use bgrewriteaof::BgRewriteAof;
+
+mod save;
+pub use save::{BgSave, LastSave, Save};
+
+mod auth;
+pub use auth::Auth;
+
+mod acl;
+pub use acl::Acl;
+
+mod config;
+pub use config::Config;
+
+mod info;
+pub use info::Info;
+
+mod custom;
+pub(crate) use custom::Commands;
+pub use custom::{CommandHandler, Custom};
+
 mod unknown;
 pub use unknown::Unknown;
 
-use crate::{Connection, Db, Frame, Parse, ParseError, Shutdown};
+use crate::acl::Category;
+use crate::{Connection, Db, Frame, Parse, ParseError, ServerError, Session, Shutdown};
 
 /// Enumeracion de los comandos REDIS soportados.
 #[derive(Debug)]
@@ -27,6 +77,26 @@ pub enum Command {
     Subscribe(Subscribe),
     Unsubscribe(Unsubscribe),
     Ping(Ping),
+    Del(Del),
+    Dump(Dump),
+    Restore(Restore),
+    Migrate(Migrate),
+    Cluster(Cluster),
+    Asking(Asking),
+    ReplicaOf(ReplicaOf),
+    Psync(Psync),
+    ReplConf(ReplConf),
+    Wait(Wait),
+    Role(Role),
+    BgRewriteAof(BgRewriteAof),
+    Save(Save),
+    BgSave(BgSave),
+    LastSave(LastSave),
+    Auth(Auth),
+    Acl(Acl),
+    Config(Config),
+    Info(Info),
+    Custom(Custom),
     Unknown(Unknown),
 }
 
@@ -37,35 +107,78 @@ impl Command {
     ///
     /// # Retorno
     /// En caso de exito una variante del comando es retornada,
-    /// en caso contrario se retornara `crate::Error'.
-    pub fn from_frame(frame: Frame) -> crate::Result<Command> {
+    /// en caso contrario se retornara el `ServerError` que hay que
+    /// enviarle al cliente. Estos errores son recuperables: la conexion
+    /// puede seguir utilizandose.
+    pub fn from_frame(frame: Frame) -> Result<Command, ServerError> {
+        Command::from_frame_with(frame, &Commands::new())
+    }
+
+    /// Igual que `from_frame` pero reconociendo tambien los comandos
+    /// añadidos en `commands`, que tienen prioridad sobre los propios.
+    pub(crate) fn from_frame_with(
+        frame: Frame,
+        commands: &Commands,
+    ) -> Result<Command, ServerError> {
         // El valor es decorado con un `Parse`. Parse proporciona
         // una API tipo "cursor" que permite parsear los comandos mas facilmente.
-        let mut parse = Parse::new(frame)?;
+        let mut parse = Parse::new(frame).map_err(ServerError::err)?;
 
         // Una vez tenemos todos los frames del array accesibles a traves del
         // parseador, obtenemos el primer frame que debe ser una String
         // que contiene el nombre del comando.
         // Este nombre del comando se pasa a minusculas para buscar
         // la coinicidencia con el comando.
-        let command_name = parse.next_string()?.to_lowercase();
+        let command_name = parse
+            .next_string()
+            .map_err(ServerError::err)?
+            .to_lowercase();
 
         // Una vez identificado el comando se deriva a cada comando el
         // procesado del resto de parametros.
-        let command = match &command_name[..] {
-            "get" => Command::Get(Get::parse_frames(&mut parse)?),
-            "publish" => Command::Publish(Publish::parse_frames(&mut parse)?),
-            "set" => Command::Set(Set::parse_frames(&mut parse)?),
-            "subscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parse)?),
-            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parse)?),
-            "ping" => Command::Ping(Ping::parse_frames(&mut parse)?),
-            _ => {
-                // No se ha reconicido elcomando asi que se retorna
-                // el comando `Unknown`.
-                //
-                // En este caso se utiliza return para evitar que la ejecucion
-                // siga su curso normal.
-                return Ok(Command::Unknown(Unknown::new(command_name)));
+        let command = if let Some(handler) = commands.get(&command_name) {
+            // Los comandos añadidos tienen prioridad sobre los propios.
+            Custom::parse_frames(command_name.clone(), handler, &mut parse).map(Command::Custom)
+        } else {
+            match &command_name[..] {
+                "get" => Get::parse_frames(&mut parse).map(Command::Get),
+                "publish" => Publish::parse_frames(&mut parse).map(Command::Publish),
+                "set" => Set::parse_frames(&mut parse).map(Command::Set),
+                "subscribe" => Subscribe::parse_frames(&mut parse).map(Command::Subscribe),
+                "unsubscribe" => Unsubscribe::parse_frames(&mut parse).map(Command::Unsubscribe),
+                "ping" => Ping::parse_frames(&mut parse).map(Command::Ping),
+                "del" => Del::parse_frames(&mut parse).map(Command::Del),
+                "dump" => Dump::parse_frames(&mut parse).map(Command::Dump),
+                "restore" => Restore::parse_frames(&mut parse).map(Command::Restore),
+                "restore-asking" => Restore::parse_frames(&mut parse)
+                    .map(Restore::asking)
+                    .map(Command::Restore),
+                "migrate" => Migrate::parse_frames(&mut parse).map(Command::Migrate),
+                "cluster" => Cluster::parse_frames(&mut parse).map(Command::Cluster),
+                "asking" => Asking::parse_frames(&mut parse).map(Command::Asking),
+                "replicaof" | "slaveof" => {
+                    ReplicaOf::parse_frames(&mut parse).map(Command::ReplicaOf)
+                }
+                "psync" => Psync::parse_frames(&mut parse).map(Command::Psync),
+                "replconf" => ReplConf::parse_frames(&mut parse).map(Command::ReplConf),
+                "wait" => Wait::parse_frames(&mut parse).map(Command::Wait),
+                "role" => Role::parse_frames(&mut parse).map(Command::Role),
+                "bgrewriteaof" => BgRewriteAof::parse_frames(&mut parse).map(Command::BgRewriteAof),
+                "save" => Save::parse_frames(&mut parse).map(Command::Save),
+                "bgsave" => BgSave::parse_frames(&mut parse).map(Command::BgSave),
+                "lastsave" => LastSave::parse_frames(&mut parse).map(Command::LastSave),
+                "auth" => Auth::parse_frames(&mut parse).map(Command::Auth),
+                "acl" => Acl::parse_frames(&mut parse).map(Command::Acl),
+                "config" => Config::parse_frames(&mut parse).map(Command::Config),
+                "info" => Info::parse_frames(&mut parse).map(Command::Info),
+                _ => {
+                    // No se ha reconicido elcomando asi que se retorna
+                    // el comando `Unknown`.
+                    //
+                    // En este caso se utiliza return para evitar que la ejecucion
+                    // siga su curso normal.
+                    return Ok(Command::Unknown(Unknown::new(command_name)));
+                }
             }
         };
 
@@ -73,11 +186,22 @@ impl Command {
         // el comando correctamente), se hace una verificacion final
         // llamando al metodo `finish()` del parseador para verificar
         // que no quiedan argumentos para consumir en el parseador.
-        // Que queden argumentos por consumir indica una trama irregular.
-        parse.finish()?;
+        // Que queden argumentos por consumir indica un numero de argumentos
+        // incorrecto, igual que si faltan.
+        let command = command.and_then(|command| {
+            parse.finish().map_err(|_| ParseError::EndOfStream)?;
+            Ok(command)
+        });
 
-        // El comando ha sido parseado satisfactoriamente
-        Ok(command)
+        // Los errores de parseado se traducen al error que enviaria Redis.
+        command.map_err(|err| match err {
+            ParseError::EndOfStream => ServerError::err(format!(
+                "wrong number of arguments for '{}' command",
+                command_name
+            )),
+            ParseError::Syntax => ServerError::err(err),
+            ParseError::Other(err) => ServerError::err(err),
+        })
     }
 
     /// Aplica el comando a la instancia `Db`proporcionada.
@@ -88,25 +212,208 @@ impl Command {
     /// Para la aplicacion de los comandos sobre las base de datos y su
     /// posterior respuesta se invocan especificamente a un metodo segun
     /// el comando (tienen distinta firma).
+    ///
+    /// Antes se comprueba que el usuario de la conexion tiene permiso para
+    /// ejecutarlo (ver `acl`). Si no lo tiene se responde con `-NOPERM`.
     pub(crate) async fn apply(
         self,
         db: &Db,
         dst: &mut Connection,
         shutdown: &mut Shutdown,
+        session: &mut Session,
     ) -> crate::Result<()> {
         use Command::*;
 
+        if let Err(err) = self.check_acl(db, session) {
+            dst.write_frame(&err.into()).await?;
+            return Ok(());
+        }
+
         match self {
             Get(cmd) => cmd.apply(db, dst).await,
             Publish(cmd) => cmd.apply(db, dst).await,
             Set(cmd) => cmd.apply(db, dst).await,
-            Subscribe(cmd) => cmd.apply(db, dst, shutdown).await,
+            Subscribe(cmd) => cmd.apply(db, dst, shutdown, session).await,
             Ping(cmd) => cmd.apply(dst).await,
+            Del(cmd) => cmd.apply(db, dst).await,
+            Dump(cmd) => cmd.apply(db, dst).await,
+            Restore(cmd) => cmd.apply(db, dst).await,
+            Migrate(cmd) => cmd.apply(db, dst).await,
+            Cluster(cmd) => cmd.apply(db, dst).await,
+            Asking(cmd) => cmd.apply(db, dst, session).await,
+            ReplicaOf(cmd) => cmd.apply(db, dst).await,
+            Psync(cmd) => cmd.apply(db, dst, shutdown, session).await,
+            ReplConf(cmd) => cmd.apply(dst, session).await,
+            Wait(cmd) => cmd.apply(db, dst, shutdown, session).await,
+            Role(cmd) => cmd.apply(db, dst).await,
+            BgRewriteAof(cmd) => cmd.apply(db, dst).await,
+            Save(cmd) => cmd.apply(db, dst).await,
+            BgSave(cmd) => cmd.apply(db, dst).await,
+            LastSave(cmd) => cmd.apply(db, dst).await,
+            Auth(cmd) => cmd.apply(db, dst, session).await,
+            Acl(cmd) => cmd.apply(db, dst, session).await,
+            Config(cmd) => cmd.apply(db, dst).await,
+            Info(cmd) => cmd.apply(db, dst).await,
+            Custom(cmd) => cmd.apply(db, dst).await,
             Unknown(cmd) => cmd.apply(dst).await,
             // El comando 'Unsubscribe' no opera sobre la base de datos.
             // Solo puede recibir comandos dentro del contexto del
             // comando `Subscribe`.
-            Unsubscribe(_) => Err("`Unsubscribe` is unsupported in this context".into()),
+            Unsubscribe(_) => {
+                let response = ServerError::err("`Unsubscribe` is unsupported in this context");
+                dst.write_frame(&response.into()).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Retorna `true` si el comando modifica la base de datos.
+    pub(crate) fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Command::Set(_) | Command::Del(_) | Command::Restore(_)
+        )
+    }
+
+    /// Retorna `true` si el comando puede modificar claves, y por tanto no
+    /// se acepta en una replica. Ademas de las escrituras incluye `MIGRATE`,
+    /// que se difunde como `DEL`, y los comandos añadidos que declaran que
+    /// escriben.
+    pub(crate) fn modifies_keys(&self) -> bool {
+        match self {
+            Command::Migrate(_) => true,
+            Command::Custom(cmd) => cmd.is_write(),
+            cmd => cmd.is_write(),
+        }
+    }
+
+    /// Claves sobre las que opera el comando. En modo cluster determinan el
+    /// nodo que tiene que atenderlo.
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        match self {
+            Command::Get(cmd) => vec![cmd.key()],
+            Command::Set(cmd) => vec![cmd.key()],
+            Command::Dump(cmd) => vec![cmd.key()],
+            Command::Restore(cmd) => vec![cmd.key()],
+            Command::Del(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::Custom(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Comprueba que el usuario de la sesion puede ejecutar el comando y
+    /// acceder a sus claves y canales.
+    pub(crate) fn check_acl(&self, db: &Db, session: &Session) -> Result<(), ServerError> {
+        // Un comando desconocido se responde como tal.
+        if self.allowed_unauthenticated() || matches!(self, Command::Unknown(_)) {
+            return Ok(());
+        }
+
+        let channels = match self {
+            Command::Publish(cmd) => vec![cmd.channel()],
+            Command::Subscribe(cmd) => cmd.channels().iter().map(String::as_str).collect(),
+            _ => vec![],
+        };
+
+        db.acl().check(
+            session,
+            self.get_name(),
+            self.acl_categories(),
+            &self.acl_keys(),
+            &channels,
+        )
+    }
+
+    /// Categorias del comando para las reglas de los usuarios.
+    fn acl_categories(&self) -> &'static [Category] {
+        use Category::*;
+
+        match self {
+            Command::Get(_) => &[Read],
+            Command::Set(_) => &[Write],
+            Command::Del(_) => &[Write, Keyspace],
+            Command::Dump(_) => &[Read, Keyspace],
+            Command::Restore(_) | Command::Migrate(_) => &[Write, Keyspace, Dangerous],
+            Command::Publish(_) | Command::Subscribe(_) | Command::Unsubscribe(_) => &[PubSub],
+            Command::Ping(_) | Command::Auth(_) | Command::Asking(_) | Command::Wait(_) => {
+                &[Connection]
+            }
+            Command::Acl(cmd) if cmd.is_whoami() => &[Connection],
+            Command::Cluster(_)
+            | Command::ReplicaOf(_)
+            | Command::Psync(_)
+            | Command::ReplConf(_)
+            | Command::Role(_)
+            | Command::BgRewriteAof(_)
+            | Command::Save(_)
+            | Command::BgSave(_)
+            | Command::LastSave(_)
+            | Command::Acl(_)
+            | Command::Config(_) => &[Admin, Dangerous],
+            Command::Info(_) => &[Dangerous],
+            Command::Custom(cmd) if cmd.is_write() => &[Write],
+            Command::Custom(cmd) if !cmd.keys().is_empty() => &[Read],
+            Command::Custom(_) | Command::Unknown(_) => &[],
+        }
+    }
+
+    /// Claves a las que accede el comando. Ademas de las que determinan el
+    /// nodo en modo cluster incluyen las que `MIGRATE` envia a otro nodo.
+    fn acl_keys(&self) -> Vec<&str> {
+        match self {
+            Command::Migrate(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            cmd => cmd.keys(),
+        }
+    }
+
+    /// Retorna `true` si el comando puede quedarse esperando: a las
+    /// replicas (`WAIT`), a mensajes (`SUBSCRIBE`), a otro nodo (`MIGRATE`,
+    /// `CLUSTER MEET`) o indefinidamente (`PSYNC`). Los comandos añadidos
+    /// tambien pueden esperar a cualquier cosa.
+    pub(crate) fn may_block(&self) -> bool {
+        matches!(
+            self,
+            Command::Wait(_)
+                | Command::Subscribe(_)
+                | Command::Psync(_)
+                | Command::Migrate(_)
+                | Command::Cluster(_)
+                | Command::Custom(_)
+        )
+    }
+
+    /// Retorna `true` si el comando se acepta en una conexion que no se ha
+    /// autenticado.
+    pub(crate) fn allowed_unauthenticated(&self) -> bool {
+        matches!(self, Command::Auth(_))
+    }
+
+    /// Retorna `true` si el comando se tiene que aceptar como si la conexion
+    /// hubiera enviado `ASKING` antes (`RESTORE-ASKING`).
+    pub(crate) fn is_asking(&self) -> bool {
+        matches!(self, Command::Restore(cmd) if cmd.is_asking())
+    }
+
+    /// Aplica un comando de escritura sobre la `Db` sin ninguna conexion
+    /// de por medio. Se utiliza para reproducir los comandos registrados
+    /// en el fichero AOF.
+    ///
+    /// Cualquier comando que no modifique la base de datos es un error.
+    pub(crate) fn apply_write(self, db: &Db) -> Result<(), ServerError> {
+        match self {
+            Command::Set(cmd) => {
+                cmd.apply_write(db);
+                Ok(())
+            }
+            Command::Del(cmd) => {
+                cmd.apply_write(db);
+                Ok(())
+            }
+            Command::Restore(cmd) => cmd.apply_write(db),
+            cmd => Err(ServerError::err(format!(
+                "'{}' is not a write command",
+                cmd.get_name()
+            ))),
         }
     }
 
@@ -114,11 +421,31 @@ impl Command {
     pub(crate) fn get_name(&self) -> &str {
         match self {
             Command::Get(_) => "get",
-            Command::Publish(_) => "pub",
+            Command::Publish(_) => "publish",
             Command::Set(_) => "set",
             Command::Subscribe(_) => "subscribe",
             Command::Unsubscribe(_) => "unsubscribe",
             Command::Ping(_) => "ping",
+            Command::Del(_) => "del",
+            Command::Dump(_) => "dump",
+            Command::Restore(_) => "restore",
+            Command::Migrate(_) => "migrate",
+            Command::Cluster(_) => "cluster",
+            Command::Asking(_) => "asking",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::Psync(_) => "psync",
+            Command::ReplConf(_) => "replconf",
+            Command::Wait(_) => "wait",
+            Command::Role(_) => "role",
+            Command::BgRewriteAof(_) => "bgrewriteaof",
+            Command::Save(_) => "save",
+            Command::BgSave(_) => "bgsave",
+            Command::LastSave(_) => "lastsave",
+            Command::Auth(_) => "auth",
+            Command::Acl(_) => "acl",
+            Command::Config(_) => "config",
+            Command::Info(_) => "info",
+            Command::Custom(cmd) => cmd.get_name(),
             Command::Unknown(cmd) => cmd.get_name(),
         }
     }
@@ -0,0 +1,189 @@
+use crate::acl::Category;
+use crate::{Connection, Db, Frame, Parse, ParseError, ServerError, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Consulta y configuracion de los usuarios y sus permisos (ver `acl`).
+#[derive(Debug)]
+pub struct Acl {
+    subcommand: Subcommand,
+}
+
+/// Subcomandos de `ACL`.
+#[derive(Debug)]
+enum Subcommand {
+    /// Crea o modifica un usuario
+    SetUser(String, Vec<String>),
+
+    /// Permisos de un usuario
+    GetUser(String),
+
+    /// Elimina usuarios
+    DelUser(Vec<String>),
+
+    /// Descripcion de todos los usuarios
+    List,
+
+    /// Nombres de los usuarios
+    Users,
+
+    /// Usuario de la conexion
+    WhoAmI,
+
+    /// Categorias de comandos
+    Cat,
+
+    /// Ultimos intentos denegados, o vacia el log
+    Log(Option<usize>),
+    LogReset,
+
+    /// Carga los usuarios del fichero ACL
+    Load,
+
+    /// Guarda los usuarios en el fichero ACL
+    Save,
+}
+
+/// Numero de entradas de `ACL LOG` si no se indica otro.
+const DEFAULT_LOG_COUNT: usize = 10;
+
+impl Acl {
+    /// Parsea una instancia de `Acl` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// ACL SETUSER username [rule [rule ...]]
+    /// ACL GETUSER username
+    /// ACL DELUSER username [username ...]
+    /// ACL LIST
+    /// ACL USERS
+    /// ACL WHOAMI
+    /// ACL CAT
+    /// ACL LOG [count | RESET]
+    /// ACL LOAD
+    /// ACL SAVE
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Acl, ParseError> {
+        let subcommand = match &parse.next_string()?.to_uppercase()[..] {
+            "SETUSER" => Subcommand::SetUser(parse.next_string()?, rest(parse)?),
+            "GETUSER" => Subcommand::GetUser(parse.next_string()?),
+            "DELUSER" => {
+                let mut usernames = vec![parse.next_string()?];
+                usernames.extend(rest(parse)?);
+                Subcommand::DelUser(usernames)
+            }
+            "LIST" => Subcommand::List,
+            "USERS" => Subcommand::Users,
+            "WHOAMI" => Subcommand::WhoAmI,
+            "CAT" => Subcommand::Cat,
+            "LOG" => match parse.next_string() {
+                Ok(arg) if arg.eq_ignore_ascii_case("reset") => Subcommand::LogReset,
+                Ok(arg) => Subcommand::Log(Some(
+                    arg.parse()
+                        .map_err(|_| "value is out of range, must be positive")?,
+                )),
+                Err(ParseError::EndOfStream) => Subcommand::Log(None),
+                Err(err) => return Err(err),
+            },
+            "LOAD" => Subcommand::Load,
+            "SAVE" => Subcommand::Save,
+            subcommand => {
+                return Err(format!(
+                    "unknown subcommand '{}'. Try ACL HELP.",
+                    subcommand.to_lowercase()
+                )
+                .into())
+            }
+        };
+
+        Ok(Acl { subcommand })
+    }
+
+    /// Retorna `true` si es `ACL WHOAMI`, que puede ejecutar cualquier
+    /// usuario.
+    pub(crate) fn is_whoami(&self) -> bool {
+        matches!(self.subcommand, Subcommand::WhoAmI)
+    }
+
+    /// Aplica el comando `Acl` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst, session))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        session: &Session,
+    ) -> crate::Result<()> {
+        let response = match self.subcommand.apply(db, session) {
+            Ok(response) => response,
+            Err(err) => err.into(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+impl Subcommand {
+    fn apply(self, db: &Db, session: &Session) -> Result<Frame, ServerError> {
+        let acl = db.acl();
+        let ok = || Frame::Simple("OK".to_string());
+        let bulks = |values: Vec<String>| {
+            Frame::Array(
+                values
+                    .into_iter()
+                    .map(|value| Frame::Bulk(Bytes::from(value)))
+                    .collect(),
+            )
+        };
+
+        match self {
+            Subcommand::SetUser(username, rules) => acl.set_user(&username, &rules).map(|_| ok()),
+            Subcommand::GetUser(username) => Ok(acl.user_frame(&username)),
+            Subcommand::DelUser(usernames) => acl.del_users(&usernames).map(Frame::Integer),
+            Subcommand::List => Ok(bulks(acl.list())),
+            Subcommand::Users => Ok(bulks(acl.usernames())),
+            Subcommand::WhoAmI => match &session.user {
+                Some(username) => Ok(Frame::Bulk(Bytes::from(username.clone()))),
+                None => Ok(Frame::Null),
+            },
+            Subcommand::Cat => Ok(bulks(
+                Category::all()
+                    .iter()
+                    .map(|category| category.name().to_string())
+                    .collect(),
+            )),
+            Subcommand::Log(count) => Ok(acl.log_frame(count.unwrap_or(DEFAULT_LOG_COUNT))),
+            Subcommand::LogReset => {
+                acl.reset_log();
+                Ok(ok())
+            }
+            Subcommand::Load => acl
+                .load()
+                .map(|_| ok())
+                .map_err(|err| ServerError::err(err.to_string())),
+            Subcommand::Save => acl.save().map(|_| ok()).map_err(|err| {
+                ServerError::err(format!(
+                    "There was an error trying to save the ACLs: {}",
+                    err
+                ))
+            }),
+        }
+    }
+}
+
+/// Lee el resto de argumentos.
+fn rest(parse: &mut Parse) -> Result<Vec<String>, ParseError> {
+    let mut values = vec![];
+
+    loop {
+        match parse.next_string() {
+            Ok(value) => values.push(value),
+            Err(ParseError::EndOfStream) => return Ok(values),
+            Err(err) => return Err(err),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::acl::{Reason, DEFAULT_USER};
+use crate::{Connection, Db, Frame, Parse, ParseError, ServerError, ServerErrorKind, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Autentica la conexion.
+///
+/// Si el usuario `default` tiene contraseña (`requirepass`) las conexiones
+/// solo pueden enviar `AUTH` hasta que se autentican. A partir de entonces
+/// actuan en nombre del usuario autenticado (ver `acl`).
+#[derive(Debug)]
+pub struct Auth {
+    /// Usuario. Si es `None` se utiliza `default`.
+    username: Option<String>,
+
+    /// Contraseña
+    password: String,
+}
+
+impl Auth {
+    /// Crea el comando
+    pub fn new(username: Option<&str>, password: &str) -> Auth {
+        Auth {
+            username: username.map(str::to_string),
+            password: password.to_string(),
+        }
+    }
+
+    /// Parsea una instancia de `Auth` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// AUTH [username] password
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Auth, ParseError> {
+        let first = parse.next_string()?;
+
+        match parse.next_string() {
+            Ok(password) => Ok(Auth {
+                username: Some(first),
+                password,
+            }),
+            Err(ParseError::EndOfStream) => Ok(Auth {
+                username: None,
+                password: first,
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Comprueba las credenciales y cambia el usuario de la sesion. Los
+    /// intentos fallidos se registran en `ACL LOG`. La respuesta es escrita
+    /// en ´dst´.
+    #[instrument(skip(self, db, dst, session))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        session: &mut Session,
+    ) -> crate::Result<()> {
+        let acl = db.acl();
+        let username = self.username.as_deref().unwrap_or(DEFAULT_USER);
+
+        let response = if self.username.is_none() && acl.default_nopass() {
+            // `default` no necesita contraseña, pero `AUTH password` indica
+            // un error de configuracion.
+            ServerError::err(
+                "AUTH <password> called without any password configured for the default user. \
+                 Are you sure your configuration is correct?",
+            )
+            .into()
+        } else if acl.authenticate(username, &self.password) {
+            session.user = Some(username.to_string());
+            Frame::Simple("OK".to_string())
+        } else {
+            acl.log(Reason::Auth, "AUTH", username, session);
+            ServerError::new(
+                ServerErrorKind::WrongPass,
+                "invalid username-password pair or user is disabled.",
+            )
+            .into()
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Convierte este comando en su representacion en un Frame.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("auth".as_bytes()));
+        if let Some(username) = self.username {
+            frame.push_bulk(Bytes::from(username.into_bytes()));
+        }
+        frame.push_bulk(Bytes::from(self.password.into_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,48 @@
+use crate::{Connection, Db, Frame, Parse, ParseError, ServerError};
+
+use tracing::{debug, instrument};
+
+/// Reescribe el fichero AOF en segundo plano.
+///
+/// El nuevo fichero contiene el minimo numero de comandos necesarios para
+/// reconstruir el estado actual de la base de datos. Mientras se reescribe
+/// el servidor sigue atendiendo a los clientes.
+#[derive(Debug, Default)]
+pub struct BgRewriteAof {}
+
+impl BgRewriteAof {
+    /// Crea el comando
+    pub fn new() -> BgRewriteAof {
+        BgRewriteAof {}
+    }
+
+    /// Parsea una instancia de `BgRewriteAof` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// BGREWRITEAOF
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<BgRewriteAof, ParseError> {
+        // El comando no tiene argumentos.
+        Ok(BgRewriteAof {})
+    }
+
+    /// Solicita la reescritura a la tarea que gestiona el fichero AOF.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.aof() {
+            Some(aof) => match aof.rewrite() {
+                Ok(()) => Frame::Simple("Background append only file rewriting started".into()),
+                Err(err) => err.into(),
+            },
+            None => ServerError::err("append only file is not enabled").into(),
+        };
+
+        debug!(?response);
+
+        // Se envia la respuesta al cliente
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use crate::cluster::{self, ClusterHandle, SlotState};
+use crate::{Connection, Db, Frame, Parse, ParseError, ServerError, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Consulta y configuracion del cluster (ver `cluster`).
+#[derive(Debug)]
+pub struct Cluster {
+    subcommand: Subcommand,
+}
+
+/// Subcomandos de `CLUSTER`.
+#[derive(Debug)]
+enum Subcommand {
+    /// Slot de una clave
+    KeySlot(String),
+
+    /// Numero de claves locales de un slot
+    CountKeysInSlot(u16),
+
+    /// Hasta `count` claves locales de un slot
+    GetKeysInSlot(u16, u64),
+
+    /// Asigna slots a este nodo
+    AddSlots(Vec<u16>),
+
+    /// Cambia el estado de un slot
+    SetSlot(u16, SlotState),
+
+    /// Rangos de slots de cada nodo
+    Slots,
+
+    /// Nodos del cluster y sus slots
+    Shards,
+
+    /// Añade un nodo al cluster
+    Meet(String, u16),
+
+    /// Identificador de este nodo
+    MyId,
+}
+
+/// Indica que el siguiente comando de la conexion se dirige a un slot que
+/// este nodo esta importando, tras una redireccion `-ASK`.
+#[derive(Debug, Default)]
+pub struct Asking;
+
+impl Cluster {
+    /// Parsea una instancia de `Cluster` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// CLUSTER KEYSLOT key
+    /// CLUSTER COUNTKEYSINSLOT slot
+    /// CLUSTER GETKEYSINSLOT slot count
+    /// CLUSTER ADDSLOTS slot [slot ...]
+    /// CLUSTER SETSLOT slot IMPORTING node-id | MIGRATING node-id | NODE node-id | STABLE
+    /// CLUSTER SLOTS
+    /// CLUSTER SHARDS
+    /// CLUSTER MEET host port
+    /// CLUSTER MYID
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Cluster, ParseError> {
+        use ParseError::EndOfStream;
+
+        let subcommand = match &parse.next_string()?.to_uppercase()[..] {
+            "KEYSLOT" => Subcommand::KeySlot(parse.next_string()?),
+            "COUNTKEYSINSLOT" => Subcommand::CountKeysInSlot(next_slot(parse)?),
+            "GETKEYSINSLOT" => Subcommand::GetKeysInSlot(next_slot(parse)?, parse.next_int()?),
+            "ADDSLOTS" => {
+                let mut slots = vec![next_slot(parse)?];
+                loop {
+                    match next_slot(parse) {
+                        Ok(slot) => slots.push(slot),
+                        Err(EndOfStream) => break,
+                        Err(err) => return Err(err),
+                    }
+                }
+                Subcommand::AddSlots(slots)
+            }
+            "SETSLOT" => {
+                let slot = next_slot(parse)?;
+                let state = match &parse.next_string()?.to_uppercase()[..] {
+                    "IMPORTING" => SlotState::Importing(parse.next_string()?),
+                    "MIGRATING" => SlotState::Migrating(parse.next_string()?),
+                    "NODE" => SlotState::Node(parse.next_string()?),
+                    "STABLE" => SlotState::Stable,
+                    _ => return Err("Invalid CLUSTER SETSLOT action or number of arguments".into()),
+                };
+                Subcommand::SetSlot(slot, state)
+            }
+            "SLOTS" => Subcommand::Slots,
+            "SHARDS" => Subcommand::Shards,
+            "MEET" => {
+                let host = parse.next_string()?;
+                let port = parse
+                    .next_string()?
+                    .parse()
+                    .map_err(|_| "Invalid node port")?;
+                Subcommand::Meet(host, port)
+            }
+            "MYID" => Subcommand::MyId,
+            subcommand => {
+                return Err(format!(
+                    "unknown subcommand '{}'. Try CLUSTER HELP.",
+                    subcommand.to_lowercase()
+                )
+                .into())
+            }
+        };
+
+        Ok(Cluster { subcommand })
+    }
+
+    /// Aplica el comando `Cluster` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.cluster() {
+            Some(cluster) => match self.subcommand.apply(db, &cluster).await {
+                Ok(response) => response,
+                Err(err) => err.into(),
+            },
+            None => disabled().into(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+impl Subcommand {
+    async fn apply(self, db: &Db, cluster: &ClusterHandle) -> Result<Frame, ServerError> {
+        let ok = || Frame::Simple("OK".to_string());
+
+        match self {
+            Subcommand::KeySlot(key) => {
+                Ok(Frame::Integer(cluster::key_slot(key.as_bytes()) as u64))
+            }
+            Subcommand::CountKeysInSlot(slot) => {
+                Ok(Frame::Integer(db.keys_in_slot(slot).len() as u64))
+            }
+            Subcommand::GetKeysInSlot(slot, count) => {
+                let keys = db
+                    .keys_in_slot(slot)
+                    .into_iter()
+                    .take(count as usize)
+                    .map(|key| Frame::Bulk(Bytes::from(key)))
+                    .collect();
+                Ok(Frame::Array(keys))
+            }
+            Subcommand::AddSlots(slots) => cluster.add_slots(&slots).map(|_| ok()),
+            Subcommand::SetSlot(slot, state) => cluster.set_slot(slot, state).map(|_| ok()),
+            Subcommand::Slots => Ok(cluster.slots_frame()),
+            Subcommand::Shards => Ok(cluster.shards_frame()),
+            Subcommand::Meet(host, port) => {
+                let meet = async {
+                    let options = db.config().peer_options()?;
+                    cluster::meet(host, port, &options).await
+                };
+                let (node, slots) = meet.await.map_err(|err| {
+                    ServerError::err(format!("Can't connect to the node: {}", err))
+                })?;
+                cluster.add_node(node, &slots);
+                Ok(ok())
+            }
+            Subcommand::MyId => Ok(Frame::Bulk(Bytes::from(cluster.myself()))),
+        }
+    }
+}
+
+impl Asking {
+    /// Parsea una instancia de `Asking` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// ASKING
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Asking, ParseError> {
+        Ok(Asking)
+    }
+
+    /// Marca la sesion para que el siguiente comando se acepte aunque su
+    /// slot se este importando. La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst, session))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        session: &mut Session,
+    ) -> crate::Result<()> {
+        let response = match db.cluster() {
+            Some(_) => {
+                session.asking = true;
+                Frame::Simple("OK".to_string())
+            }
+            None => disabled().into(),
+        };
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+/// Lee un numero de slot valido.
+fn next_slot(parse: &mut Parse) -> Result<u16, ParseError> {
+    match parse.next_int()? {
+        slot if slot < cluster::SLOTS as u64 => Ok(slot as u16),
+        _ => Err("Invalid or out of range slot".into()),
+    }
+}
+
+fn disabled() -> ServerError {
+    ServerError::err("This instance has cluster support disabled")
+}
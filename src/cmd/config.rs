@@ -0,0 +1,129 @@
+use crate::{Connection, Db, Frame, Parse, ParseError, ServerError};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Consulta y modificacion de la configuracion del servidor (ver `config`).
+#[derive(Debug)]
+pub struct Config {
+    subcommand: Subcommand,
+}
+
+/// Subcomandos de `CONFIG`.
+#[derive(Debug)]
+enum Subcommand {
+    /// Parametros que encajan con alguno de los patrones
+    Get(Vec<String>),
+
+    /// Modifica parametros
+    Set(Vec<(String, String)>),
+
+    /// Pone a cero las estadisticas
+    ResetStat,
+
+    /// Guarda la configuracion en su fichero
+    Rewrite,
+}
+
+impl Config {
+    /// Parsea una instancia de `Config` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// CONFIG GET pattern [pattern ...]
+    /// CONFIG SET parameter value [parameter value ...]
+    /// CONFIG RESETSTAT
+    /// CONFIG REWRITE
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Config, ParseError> {
+        let subcommand = match &parse.next_string()?.to_uppercase()[..] {
+            "GET" => {
+                let mut patterns = vec![parse.next_string()?];
+                loop {
+                    match parse.next_string() {
+                        Ok(pattern) => patterns.push(pattern),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(err) => return Err(err),
+                    }
+                }
+                Subcommand::Get(patterns)
+            }
+            "SET" => {
+                let mut params = vec![(parse.next_string()?, parse.next_string()?)];
+                loop {
+                    match parse.next_string() {
+                        Ok(name) => params.push((name, parse.next_string()?)),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(err) => return Err(err),
+                    }
+                }
+                Subcommand::Set(params)
+            }
+            "RESETSTAT" => Subcommand::ResetStat,
+            "REWRITE" => Subcommand::Rewrite,
+            subcommand => {
+                return Err(format!(
+                    "unknown subcommand '{}'. Try CONFIG HELP.",
+                    subcommand.to_lowercase()
+                )
+                .into())
+            }
+        };
+
+        Ok(Config { subcommand })
+    }
+
+    /// Aplica el comando `Config` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match self.subcommand.apply(db) {
+            Ok(response) => response,
+            Err(err) => err.into(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+impl Subcommand {
+    fn apply(self, db: &Db) -> Result<Frame, ServerError> {
+        let config = db.config();
+        let ok = || Frame::Simple("OK".to_string());
+
+        match self {
+            Subcommand::Get(patterns) => Ok(Frame::Array(
+                config
+                    .get(&patterns)
+                    .into_iter()
+                    .flat_map(|(name, value)| [name, value])
+                    .map(|value| Frame::Bulk(Bytes::from(value)))
+                    .collect(),
+            )),
+            Subcommand::Set(params) => {
+                let updated = config.set(&params)?;
+
+                // La contraseña de `requirepass` es la del usuario `default`.
+                if params
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case("requirepass"))
+                {
+                    db.acl().set_default_password(updated.requirepass());
+                }
+
+                Ok(ok())
+            }
+            Subcommand::ResetStat => {
+                db.stats().reset();
+                Ok(ok())
+            }
+            Subcommand::Rewrite => config
+                .rewrite()
+                .map(|_| ok())
+                .map_err(|err| ServerError::err(format!("Rewriting config file: {}", err))),
+        }
+    }
+}
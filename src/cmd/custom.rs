@@ -0,0 +1,157 @@
+use crate::{Connection, Db, Frame, Parse, ParseError, ServerError};
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tracing::{debug, instrument};
+
+/// Comando añadido por la aplicacion que arranca el servidor (ver
+/// `server::Builder::command`).
+///
+/// Recibe la `Db` y los argumentos del comando, sin el nombre, y retorna la
+/// respuesta que se envia al cliente. Se implementa para cualquier closure
+/// con esa firma.
+///
+/// Las claves que declara `keys` se tratan como las de los comandos propios:
+/// en modo cluster determinan el nodo que atiende el comando y se comprueban
+/// con los patrones de claves del usuario. Los comandos que declaran
+/// `is_write` se rechazan en una replica y pertenecen a la categoria
+/// `@write`. Los closures no declaran claves ni escrituras, asi que se
+/// saltan estas comprobaciones; para tenerlas hay que implementar el trait.
+pub trait CommandHandler: Send + Sync + 'static {
+    /// Ejecuta el comando.
+    fn call(&self, db: &Db, args: Vec<Bytes>) -> Result<Frame, ServerError>;
+
+    /// Posiciones en `args` de las claves del comando. Por defecto ninguna.
+    fn keys(&self, args: &[Bytes]) -> Vec<usize> {
+        let _ = args;
+        vec![]
+    }
+
+    /// Retorna `true` si el comando modifica claves. Por defecto `false`.
+    fn is_write(&self) -> bool {
+        false
+    }
+}
+
+impl<F> CommandHandler for F
+where
+    F: Fn(&Db, Vec<Bytes>) -> Result<Frame, ServerError> + Send + Sync + 'static,
+{
+    fn call(&self, db: &Db, args: Vec<Bytes>) -> Result<Frame, ServerError> {
+        self(db, args)
+    }
+}
+
+/// Comandos añadidos, por nombre en minusculas.
+#[derive(Clone, Default)]
+pub(crate) struct Commands {
+    handlers: HashMap<String, Arc<dyn CommandHandler>>,
+}
+
+/// Invocacion de un comando añadido.
+pub struct Custom {
+    name: String,
+    args: Vec<Bytes>,
+    keys: Vec<String>,
+    handler: Arc<dyn CommandHandler>,
+}
+
+impl Custom {
+    /// Parsea una instancia de `Custom` desde el frame que se ha recibido.
+    /// Todos los argumentos se pasan al handler sin interpretarlos.
+    pub(crate) fn parse_frames(
+        name: String,
+        handler: Arc<dyn CommandHandler>,
+        parse: &mut Parse,
+    ) -> Result<Custom, ParseError> {
+        let mut args = vec![];
+
+        loop {
+            match parse.next_bytes() {
+                Ok(arg) => args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        // Las posiciones que no existen se ignoran.
+        let keys = handler
+            .keys(&args)
+            .into_iter()
+            .filter_map(|i| args.get(i))
+            .map(|key| String::from_utf8_lossy(key).to_string())
+            .collect();
+
+        Ok(Custom {
+            name,
+            args,
+            keys,
+            handler,
+        })
+    }
+
+    pub(crate) fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Claves del comando, segun `CommandHandler::keys`.
+    pub(crate) fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Retorna `true` si el comando modifica claves.
+    pub(crate) fn is_write(&self) -> bool {
+        self.handler.is_write()
+    }
+
+    /// Aplica el comando a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match self.handler.call(db, self.args) {
+            Ok(response) => response,
+            Err(err) => err.into(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+impl Commands {
+    pub(crate) fn new() -> Commands {
+        Commands::default()
+    }
+
+    /// Añade un comando. Si ya habia uno con el mismo nombre lo sustituye.
+    pub(crate) fn insert(&mut self, name: &str, handler: impl CommandHandler) {
+        self.handlers.insert(name.to_lowercase(), Arc::new(handler));
+    }
+
+    /// Handler del comando `name`, en minusculas.
+    pub(crate) fn get(&self, name: &str) -> Option<Arc<dyn CommandHandler>> {
+        self.handlers.get(name).cloned()
+    }
+}
+
+impl fmt::Debug for Commands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+impl fmt::Debug for Custom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Custom")
+            .field("name", &self.name)
+            .field("args", &self.args)
+            .field("keys", &self.keys)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Elimina una o varias claves.
+///
+/// Se retorna el numero de claves que existian y se han eliminado.
+#[derive(Debug)]
+pub struct Del {
+    /// Claves que se eliminan
+    keys: Vec<String>,
+}
+
+impl Del {
+    /// Crea el comando
+    pub fn new(keys: &[impl ToString]) -> Del {
+        Del {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+        }
+    }
+
+    /// Claves que se eliminan
+    pub(crate) fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parsea una instancia de `Del` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// DEL key [key ...]
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Del, ParseError> {
+        use ParseError::EndOfStream;
+
+        // Se requiere al menos una clave.
+        let mut keys = vec![parse.next_string()?];
+
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(EndOfStream) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Del { keys })
+    }
+
+    /// Aplica el comando `Del` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let removed = self.apply_write(db);
+
+        let response = match db.sync_writes().await {
+            Ok(()) => Frame::Integer(removed),
+            Err(err) => err.into(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Aplica la escritura sobre la `Db` sin enviar ninguna respuesta.
+    /// Retorna el numero de claves eliminadas.
+    pub(crate) fn apply_write(self, db: &Db) -> u64 {
+        db.del(&self.keys)
+    }
+
+    /// Convierte este comando en su representacion en un Frame.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("del".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
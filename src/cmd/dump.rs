@@ -0,0 +1,63 @@
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Serializa el valor de una clave en un formato que puede restaurarse con
+/// `RESTORE`, en este o en otro servidor.
+///
+/// Si la clave no existe se retorna 'nil'.
+#[derive(Debug)]
+pub struct Dump {
+    /// Clave cuyo valor se serializa
+    key: String,
+}
+
+impl Dump {
+    /// Crea el comando
+    pub fn new(key: impl ToString) -> Dump {
+        Dump {
+            key: key.to_string(),
+        }
+    }
+
+    /// Clave sobre la que opera el comando
+    pub(crate) fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parsea una instancia de `Dump` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// DUMP key
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Dump, ParseError> {
+        let key = parse.next_string()?;
+
+        Ok(Dump { key })
+    }
+
+    /// Aplica el comando `Dump` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.dump(&self.key) {
+            Some(payload) => Frame::Bulk(payload),
+            None => Frame::Null,
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Convierte este comando en su representacion en un Frame.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("dump".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame
+    }
+}
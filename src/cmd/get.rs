@@ -1,4 +1,4 @@
-use crate::{Connection, Db, Frame, Parse};
+use crate::{Connection, Db, Frame, Parse, ParseError};
 
 use bytes::Bytes;
 use tracing::{debug, instrument};
@@ -23,6 +23,11 @@ impl Get {
         }
     }
 
+    /// Clave sobre la que opera el comando
+    pub(crate) fn key(&self) -> &str {
+        &self.key
+    }
+
     /// Parsea una instancia de `Get` desde el frame que se ha recibido.
     ///
     /// Como parametro para el parseado se recibe una instancia de
@@ -34,7 +39,7 @@ impl Get {
     ///
     /// Retorna el valor asociado a la clave o Err si el frame esta mal
     /// formado.
-    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Get> {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Get, ParseError> {
         // El primer argumento 'GET' ya ha sido consumido.
         //
         // El parseador nos permite acceder a los argumentos pendientes
@@ -51,7 +56,10 @@ impl Get {
     #[instrument(skip(self, db, dst))]
     pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
         // Obtiene el valor desde la base de base de datos
-        let response = if let Some(value) = db.get(&self.key) {
+        let value = db.get(&self.key);
+        db.stats().keyspace_read(value.is_some());
+
+        let response = if let Some(value) = value {
             // Si hay una entrada para la clave
             Frame::Bulk(value)
         } else {
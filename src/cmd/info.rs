@@ -0,0 +1,90 @@
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use std::fmt::Write;
+use tracing::{debug, instrument};
+
+/// Informacion y estadisticas del servidor.
+///
+/// La respuesta es un bulk string con una seccion por grupo de valores, cada
+/// uno en una linea con el formato `nombre:valor`:
+///
+/// ```text
+/// # Clients
+/// connected_clients:1
+/// ```
+#[derive(Debug, Default)]
+pub struct Info {
+    /// Seccion pedida. Si es `None` se responde con todas.
+    section: Option<String>,
+}
+
+impl Info {
+    /// Crea el comando
+    pub fn new(section: Option<String>) -> Info {
+        Info { section }
+    }
+
+    /// Parsea una instancia de `Info` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// INFO [section]
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Info, ParseError> {
+        match parse.next_string() {
+            Ok(section) => Ok(Info::new(Some(section.to_lowercase()))),
+            Err(ParseError::EndOfStream) => Ok(Info::new(None)),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Aplica el comando `Info` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let stats = db.stats();
+
+        let mut clients: Vec<(String, String)> = stats
+            .clients()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        clients.extend(db.config().get(&["maxclients".to_string()]));
+
+        let counters = stats
+            .counters()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        let sections = [("Clients", clients), ("Stats", counters)];
+
+        let mut info = String::new();
+        for (title, values) in sections {
+            let wanted = match &self.section {
+                Some(section) => {
+                    matches!(&section[..], "all" | "default") || section.eq_ignore_ascii_case(title)
+                }
+                None => true,
+            };
+            if !wanted {
+                continue;
+            }
+
+            if !info.is_empty() {
+                info.push_str("\r\n");
+            }
+            let _ = write!(info, "# {}\r\n", title);
+            for (name, value) in values {
+                let _ = write!(info, "{}:{}\r\n", name, value);
+            }
+        }
+
+        let response = Frame::Bulk(Bytes::from(info));
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
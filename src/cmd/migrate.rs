@@ -0,0 +1,248 @@
+use crate::client::{self, Credentials, PeerOptions};
+use crate::cmd::{Parse, ParseError, Restore};
+use crate::{Connection, Db, Error, Frame, ServerError, ServerErrorKind};
+
+use std::time::{Duration, SystemTime};
+use tokio::time;
+use tracing::{debug, instrument};
+
+/// Mueve una o varias claves a otra instancia.
+///
+/// Las claves se envian a la instancia de destino como comandos `RESTORE`
+/// utilizando el propio cliente del crate y, si la instancia de destino las
+/// acepta, se eliminan de esta. Con `COPY` no se eliminan.
+///
+/// Una clave que se modifica mientras se esta migrando no se elimina, de
+/// modo que la escritura no se pierde.
+///
+/// La conexion con el destino se autentica con las credenciales de `AUTH` o
+/// `AUTH2`, y se cifra si el servidor usa TLS (ver `Config::peer_options`).
+#[derive(Debug)]
+pub struct Migrate {
+    /// Host de la instancia de destino
+    host: String,
+
+    /// Puerto de la instancia de destino
+    port: u16,
+
+    /// Claves que se migran
+    keys: Vec<String>,
+
+    /// Base de datos de destino. Solo existe la base de datos `0`.
+    db: u64,
+
+    /// Tiempo maximo para conectar y para esperar las respuestas
+    timeout: Duration,
+
+    /// No se eliminan las claves locales
+    copy: bool,
+
+    /// Se sobreescriben las claves que ya existen en el destino
+    replace: bool,
+
+    /// Credenciales con las que se autentica la conexion con el destino
+    auth: Option<Credentials>,
+}
+
+impl Migrate {
+    /// Claves que se migran
+    pub(crate) fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Parsea una instancia de `Migrate` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE]
+    ///     [AUTH password | AUTH2 username password] [KEYS key [key ...]]
+    ///
+    /// Con `KEYS` la clave tiene que ser una string vacia.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Migrate, ParseError> {
+        use ParseError::EndOfStream;
+
+        let host = parse.next_string()?;
+        let port = u16::try_from(parse.next_int()?).map_err(|_| "invalid port")?;
+        let key = parse.next_string()?;
+        let db = parse.next_int()?;
+        let timeout = Duration::from_millis(parse.next_int()?);
+
+        let mut migrate = Migrate {
+            host,
+            port,
+            keys: vec![],
+            db,
+            timeout,
+            copy: false,
+            replace: false,
+            auth: None,
+        };
+
+        loop {
+            match parse.next_string() {
+                Ok(s) if s.to_uppercase() == "COPY" => migrate.copy = true,
+                Ok(s) if s.to_uppercase() == "REPLACE" => migrate.replace = true,
+                Ok(s) if s.to_uppercase() == "AUTH" => {
+                    migrate.auth = Some(Credentials::new(parse.next_string()?));
+                }
+                Ok(s) if s.to_uppercase() == "AUTH2" => {
+                    let username = parse.next_string()?;
+                    let password = parse.next_string()?;
+                    migrate.auth = Some(Credentials::with_username(username, password));
+                }
+                Ok(s) if s.to_uppercase() == "KEYS" => {
+                    if !key.is_empty() {
+                        return Err(ParseError::Syntax);
+                    }
+
+                    // El resto de argumentos son las claves.
+                    loop {
+                        match parse.next_string() {
+                            Ok(key) => migrate.keys.push(key),
+                            Err(EndOfStream) => break,
+                            Err(err) => return Err(err),
+                        }
+                    }
+                }
+                Ok(_) => return Err(ParseError::Syntax),
+                Err(EndOfStream) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        if migrate.keys.is_empty() {
+            migrate.keys.push(key);
+        }
+
+        // Igual que Redis, un timeout de 0 se interpreta como un segundo.
+        if migrate.timeout.is_zero() {
+            migrate.timeout = Duration::from_secs(1);
+        }
+
+        Ok(migrate)
+    }
+
+    /// Aplica el comando `Migrate` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match self.migrate(db).await {
+            Ok(response) => response,
+            Err(err) => err.into(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Envia las claves a la instancia de destino y elimina las que se han
+    /// restaurado correctamente.
+    async fn migrate(&self, db: &Db) -> Result<Frame, ServerError> {
+        if self.db != 0 {
+            return Err(ServerError::err("DB index is out of range"));
+        }
+
+        let entries: Vec<_> = self.keys.iter().filter_map(|key| db.entry(key)).collect();
+
+        if entries.is_empty() {
+            return Ok(Frame::Simple("NOKEY".to_string()));
+        }
+
+        let options = PeerOptions {
+            credentials: self.auth.clone(),
+            tls: db
+                .config()
+                .peer_options()
+                .map_err(|err| ServerError::err(err.to_string()))?
+                .tls,
+        };
+
+        let connect = client::connect_peer(&self.host, self.port, &options);
+        let mut target = match time::timeout(self.timeout, connect).await {
+            Ok(Ok(target)) => target,
+            // El destino ha rechazado las credenciales.
+            Ok(Err(Error::Server(err))) => {
+                return Err(ServerError::err(format!(
+                    "Target instance replied with error: {}",
+                    err
+                )))
+            }
+            _ => {
+                return Err(ServerError::new(
+                    ServerErrorKind::IoErr,
+                    "error or timeout connecting to the client",
+                ))
+            }
+        };
+
+        // Todas las claves se envian en un unico pipeline. Cada una lleva el
+        // tiempo de vida que le queda.
+        let now = SystemTime::now();
+        let mut pipeline = target.pipeline();
+
+        for entry in &entries {
+            let ttl = entry.expires_at.map(|when| {
+                // Un ttl de 0 significa que la clave no expira, asi que una
+                // clave a punto de expirar se envia con el minimo.
+                when.duration_since(now)
+                    .unwrap_or(Duration::ZERO)
+                    .max(Duration::from_millis(1))
+            });
+
+            let restore = Restore::new(
+                &entry.key,
+                crate::rdb::dump_value(&entry.value),
+                ttl,
+                self.replace,
+            );
+
+            // En modo cluster el destino puede estar importando el slot de
+            // la clave, y solo la acepta con `RESTORE-ASKING`.
+            let restore = match db.cluster() {
+                Some(_) => restore.asking(),
+                None => restore,
+            };
+
+            pipeline.command(restore.into_frame());
+        }
+
+        let responses = time::timeout(self.timeout, pipeline.execute())
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .ok_or_else(|| {
+                ServerError::new(
+                    ServerErrorKind::IoErr,
+                    "error or timeout reading to target instance",
+                )
+            })?;
+
+        let mut error = None;
+
+        for (entry, response) in entries.iter().zip(responses) {
+            match response {
+                Ok(_) if !self.copy => {
+                    db.remove_if(&entry.key, &entry.value);
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+
+        // Las claves eliminadas se persisten antes de responder.
+        db.sync_writes().await?;
+
+        match error {
+            Some(err) => Err(ServerError::err(format!(
+                "Target instance replied with error: {}",
+                err
+            ))),
+            None => Ok(Frame::Simple("OK".to_string())),
+        }
+    }
+}
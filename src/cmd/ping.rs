@@ -28,7 +28,7 @@ impl Ping {
     /// # Formato del comando
     /// PING [message]
     ///
-    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Ping> {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Ping, ParseError> {
         // El primer argumento 'PING' ya ha sido consumido.
         //
         // El parseador nos permite acceder a los argumentos pendientes
@@ -37,7 +37,7 @@ impl Ping {
         match parse.next_string() {
             Ok(msg) => Ok(Ping::new(Some(msg))),
             Err(ParseError::EndOfStream) => Ok(Ping::default()),
-            Err(e) => Err(e.into()),
+            Err(e) => Err(e),
         }
     }
 
@@ -0,0 +1,203 @@
+use crate::replication::{self, Replicas, Sync};
+use crate::{rdb, Command, Connection, Db, Frame, Parse, ParseError, Session, Shutdown};
+
+use bytes::Bytes;
+use tracing::{debug, info, instrument};
+
+/// Sincroniza una replica con este servidor.
+///
+/// La conexion pasa a ser el enlace con la replica: tras la sincronizacion
+/// inicial se le envian todas las escrituras (ver `replication`).
+#[derive(Debug)]
+pub struct Psync {
+    /// Identificador de replicacion del master del que la replica tiene
+    /// los datos, o `?` si no tiene ninguno.
+    replid: String,
+
+    /// Siguiente byte que necesita la replica, o `None` si se pide una
+    /// sincronizacion completa (`-1`).
+    offset: Option<u64>,
+}
+
+/// Configuracion de la conexion de replicacion.
+///
+/// La replica la envia antes de `PSYNC`, con `listening-port` para anunciar
+/// el puerto en el que escucha, y despues, con `REPLCONF ACK offset`, para
+/// confirmar el offset que ha procesado. El resto de opciones se aceptan
+/// pero se ignoran.
+#[derive(Debug)]
+pub struct ReplConf {
+    /// Offset confirmado con `ACK`.
+    ack: Option<u64>,
+
+    /// Puerto anunciado con `listening-port`.
+    listening_port: Option<u16>,
+}
+
+impl Psync {
+    /// Parsea una instancia de `Psync` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// PSYNC replicationid offset
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Psync, ParseError> {
+        let replid = parse.next_string()?;
+
+        let offset = match &parse.next_string()?[..] {
+            "-1" => None,
+            offset => Some(offset.parse().map_err(|_| "invalid offset")?),
+        };
+
+        Ok(Psync { replid, offset })
+    }
+
+    /// Envia la sincronizacion inicial y despues las escrituras a medida que
+    /// se producen, hasta que la replica se desconecta o el servidor para.
+    #[instrument(skip(self, db, dst, shutdown, session))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+        session: &Session,
+    ) -> crate::Result<()> {
+        // El offset que envia la replica es el del siguiente byte que
+        // necesita.
+        let offset = self.offset.and_then(|offset| offset.checked_sub(1));
+
+        let (sync, mut feed) = db.psync(&self.replid, offset);
+
+        // La replica se registra para `WAIT` y `ROLE` mientras dure la
+        // conexion.
+        let addr = session
+            .peer_ip
+            .zip(session.listening_port)
+            .map(|(ip, port)| (ip.to_string(), port));
+        let replica = RegisteredReplica::new(db.replicas(), addr);
+        let mut getack = replica.replicas.watch_getack();
+
+        match sync {
+            Sync::Full {
+                replid,
+                offset,
+                entries,
+            } => {
+                info!(%replid, offset, keys = entries.len(), "full sync requested by replica");
+
+                let response = Frame::Simple(format!("FULLRESYNC {} {}", replid, offset));
+                dst.write_frame(&response).await?;
+
+                let data = tokio::task::spawn_blocking(move || rdb::encode(&entries))
+                    .await
+                    .map_err(|err| crate::Error::Other(err.into()))?;
+
+                dst.write_frame(&Frame::Bulk(Bytes::from(data))).await?;
+            }
+            Sync::Partial { replid, frames } => {
+                info!(%replid, writes = frames.len(), "partial sync requested by replica");
+
+                dst.feed_frame(&Frame::Simple(format!("CONTINUE {}", replid)))
+                    .await?;
+                for frame in &frames {
+                    dst.feed_frame(frame).await?;
+                }
+                dst.flush().await?;
+            }
+        }
+
+        loop {
+            tokio::select! {
+                res = feed.recv() => match res {
+                    Some((_, frame)) => dst.write_frame(&frame).await?,
+                    None => return Ok(()),
+                },
+                res = dst.read_frame() => match res? {
+                    Some(frame) => match Command::from_frame(frame) {
+                        Ok(Command::ReplConf(ReplConf { ack: Some(offset), .. })) => {
+                            replica.replicas.ack(replica.id, offset);
+                        }
+                        cmd => debug!(?cmd, "ignoring replica request"),
+                    },
+                    None => return Ok(()),
+                },
+                Ok(()) = getack.changed() => {
+                    let request = replication::request(&["replconf", "getack", "*"]);
+                    dst.write_frame(&request).await?;
+                }
+                _ = shutdown.recv() => return Ok(()),
+            }
+        }
+    }
+}
+
+impl ReplConf {
+    /// Parsea una instancia de `ReplConf` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// REPLCONF option value [option value ...]
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ReplConf, ParseError> {
+        use ParseError::EndOfStream;
+
+        let mut ack = None;
+        let mut listening_port = None;
+
+        loop {
+            match parse.next_string() {
+                Ok(option) if option.to_lowercase() == "ack" => {
+                    ack = Some(parse.next_int()?);
+                }
+                Ok(option) if option.to_lowercase() == "listening-port" => {
+                    let port = parse.next_string()?;
+                    listening_port = Some(port.parse().map_err(|_| "invalid port")?);
+                }
+                Ok(_) => {
+                    parse.next_string()?;
+                }
+                Err(EndOfStream) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(ReplConf {
+            ack,
+            listening_port,
+        })
+    }
+
+    /// Responde `OK` a la configuracion. `ACK` no tiene respuesta. La
+    /// respuesta es escrita en ´dst´.
+    #[instrument(skip(self, dst, session))]
+    pub(crate) async fn apply(
+        self,
+        dst: &mut Connection,
+        session: &mut Session,
+    ) -> crate::Result<()> {
+        if let Some(port) = self.listening_port {
+            session.listening_port = Some(port);
+        }
+
+        if self.ack.is_none() {
+            dst.write_frame(&Frame::Simple("OK".to_string())).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Replica registrada en `Replicas`. Se elimina del registro al salir de
+/// `Psync::apply`, sea cual sea el motivo.
+struct RegisteredReplica {
+    replicas: Replicas,
+    id: u64,
+}
+
+impl RegisteredReplica {
+    fn new(replicas: Replicas, addr: Option<(String, u16)>) -> RegisteredReplica {
+        let id = replicas.add(addr);
+        RegisteredReplica { replicas, id }
+    }
+}
+
+impl Drop for RegisteredReplica {
+    fn drop(&mut self) {
+        self.replicas.remove(self.id);
+    }
+}
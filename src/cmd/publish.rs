@@ -1,4 +1,4 @@
-use crate::{Connection, Db, Frame, Parse};
+use crate::{Connection, Db, Frame, Parse, ParseError};
 
 use bytes::Bytes;
 
@@ -31,6 +31,11 @@ impl Publish {
         }
     }
 
+    /// Canal en el que se publica el mensaje
+    pub(crate) fn channel(&self) -> &str {
+        &self.channel
+    }
+
     /// Parsea una instancia de `Publish` desde el frame que se ha recibido.
     ///
     /// Como parametro para el parseado se recibe una instancia de
@@ -42,8 +47,7 @@ impl Publish {
     ///
     /// Retorna el mensaje que se ha publicado o Err si la trama esta
     /// mal formada.
-    ///
-    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Publish> {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Publish, ParseError> {
         // El primer argumento 'PUBLISH' ya ha sido consumido.
         let channel = parse.next_string()?;
         let message = parse.next_bytes()?;
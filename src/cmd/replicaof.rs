@@ -0,0 +1,58 @@
+use crate::{replication, Connection, Db, Frame, Parse, ParseError};
+
+use tracing::{debug, instrument};
+
+/// Convierte este servidor en replica de otro, o de nuevo en master con
+/// `REPLICAOF NO ONE`.
+///
+/// Una replica solo acepta escrituras de su master: el resto de clientes
+/// reciben un error `READONLY`.
+#[derive(Debug)]
+pub struct ReplicaOf {
+    /// Host y puerto del master, o `None` para dejar de replicar.
+    master: Option<(String, u16)>,
+}
+
+impl ReplicaOf {
+    /// Crea el comando
+    pub fn new(master: Option<(String, u16)>) -> ReplicaOf {
+        ReplicaOf { master }
+    }
+
+    /// Parsea una instancia de `ReplicaOf` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// REPLICAOF host port | NO ONE
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ReplicaOf, ParseError> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?;
+
+        if host.to_uppercase() == "NO" && port.to_uppercase() == "ONE" {
+            return Ok(ReplicaOf { master: None });
+        }
+
+        let port = port.parse().map_err(|_| "Invalid master port")?;
+
+        Ok(ReplicaOf {
+            master: Some((host, port)),
+        })
+    }
+
+    /// Aplica el comando `ReplicaOf` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = if replication::replicaof(db, self.master).await {
+            Frame::Simple("OK".to_string())
+        } else {
+            Frame::Simple("OK Already connected to specified master".to_string())
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use crate::cmd::{Parse, ParseError};
+use crate::{Connection, Db, Frame, ServerError};
+
+use bytes::Bytes;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, instrument};
+
+/// Crea una clave a partir de un valor serializado con `DUMP`.
+///
+/// Si la clave ya existe se retorna un error `BUSYKEY`, salvo que se
+/// indique `REPLACE`.
+#[derive(Debug)]
+pub struct Restore {
+    /// Clave que se crea
+    key: String,
+
+    /// Tiempo de vida en milisegundos. `0` indica que la clave no expira.
+    ttl: u64,
+
+    /// Valor serializado con `DUMP`
+    payload: Bytes,
+
+    /// Sobreescribe la clave si ya existe
+    replace: bool,
+
+    /// `ttl` es el instante de la expiracion en milisegundos desde 'epoch'
+    /// en lugar de un tiempo relativo.
+    absttl: bool,
+
+    /// Se ha recibido como `RESTORE-ASKING`: en modo cluster se acepta
+    /// aunque el slot se este importando, como si se hubiera enviado
+    /// `ASKING` antes.
+    asking: bool,
+}
+
+impl Restore {
+    /// Crea el comando. Sin `ttl` la clave no expira.
+    pub fn new(
+        key: impl ToString,
+        payload: Bytes,
+        ttl: Option<Duration>,
+        replace: bool,
+    ) -> Restore {
+        Restore {
+            key: key.to_string(),
+            ttl: ttl.map(|ttl| ttl.as_millis() as u64).unwrap_or(0),
+            payload,
+            replace,
+            absttl: false,
+            asking: false,
+        }
+    }
+
+    /// Envia el comando como `RESTORE-ASKING`. Es lo que utiliza `MIGRATE`
+    /// para mover claves a un nodo que esta importando su slot.
+    pub(crate) fn asking(mut self) -> Restore {
+        self.asking = true;
+        self
+    }
+
+    /// Retorna `true` si el comando se ha recibido como `RESTORE-ASKING`.
+    pub(crate) fn is_asking(&self) -> bool {
+        self.asking
+    }
+
+    /// Clave sobre la que opera el comando
+    pub(crate) fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parsea una instancia de `Restore` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// RESTORE key ttl serialized-value [REPLACE] [ABSTTL]
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Restore, ParseError> {
+        use ParseError::EndOfStream;
+
+        let key = parse.next_string()?;
+        let ttl = parse.next_int()?;
+        let payload = parse.next_bytes()?;
+
+        let mut restore = Restore {
+            key,
+            ttl,
+            payload,
+            replace: false,
+            absttl: false,
+            asking: false,
+        };
+
+        // Las opciones pueden aparecer en cualquier orden.
+        loop {
+            match parse.next_string() {
+                Ok(s) if s.to_uppercase() == "REPLACE" => restore.replace = true,
+                Ok(s) if s.to_uppercase() == "ABSTTL" => restore.absttl = true,
+                Ok(_) => return Err(ParseError::Syntax),
+                Err(EndOfStream) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(restore)
+    }
+
+    /// Aplica el comando `Restore` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        // Igual que `SET`, con `appendfsync always` no se responde hasta que
+        // la escritura es persistente.
+        let res = match self.apply_write(db) {
+            Ok(()) => db.sync_writes().await,
+            Err(err) => Err(err),
+        };
+
+        let response = match res {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => err.into(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Aplica la escritura sobre la `Db` sin enviar ninguna respuesta.
+    pub(crate) fn apply_write(self, db: &Db) -> Result<(), ServerError> {
+        let expire = match self.ttl {
+            0 => None,
+            ms if self.absttl => {
+                // Si el instante ya ha pasado la clave expira inmediatamente.
+                let when = UNIX_EPOCH + Duration::from_millis(ms);
+                Some(
+                    when.duration_since(SystemTime::now())
+                        .unwrap_or(Duration::ZERO),
+                )
+            }
+            ms => Some(Duration::from_millis(ms)),
+        };
+
+        db.restore(self.key, &self.payload, expire, self.replace)
+    }
+
+    /// Convierte este comando en su representacion en un Frame.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        let name = if self.asking {
+            "restore-asking"
+        } else {
+            "restore"
+        };
+        frame.push_bulk(Bytes::from(name.as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_int(self.ttl);
+        frame.push_bulk(self.payload);
+        if self.replace {
+            frame.push_bulk(Bytes::from("replace".as_bytes()));
+        }
+        if self.absttl {
+            frame.push_bulk(Bytes::from("absttl".as_bytes()));
+        }
+        frame
+    }
+}
@@ -0,0 +1,90 @@
+use crate::replication;
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Rol de la instancia en la replicacion.
+///
+/// Un master responde con su offset y sus replicas, y una replica con su
+/// master y el estado de la conexion con el:
+///
+/// ```text
+/// master <offset> [[<ip> <port> <offset>] ...]
+/// slave <host> <port> connect|connected <offset>
+/// ```
+#[derive(Debug, Default)]
+pub struct Role;
+
+impl Role {
+    /// Crea el comando
+    pub fn new() -> Role {
+        Role
+    }
+
+    /// Parsea una instancia de `Role` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// ROLE
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Role, ParseError> {
+        Ok(Role)
+    }
+
+    /// Aplica el comando `Role` a la instancia de `Db` especificada.
+    ///
+    /// La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let bulk = |value: String| Frame::Bulk(Bytes::from(value));
+
+        let response = match db.role() {
+            replication::Role::Master { offset, replicas } => {
+                let replicas = replicas
+                    .into_iter()
+                    .map(|(ip, port, offset)| {
+                        Frame::Array(vec![
+                            bulk(ip),
+                            bulk(port.to_string()),
+                            bulk(offset.to_string()),
+                        ])
+                    })
+                    .collect();
+
+                Frame::Array(vec![
+                    bulk("master".to_string()),
+                    Frame::Integer(offset),
+                    Frame::Array(replicas),
+                ])
+            }
+            replication::Role::Replica {
+                host,
+                port,
+                connected,
+                offset,
+            } => {
+                let state = if connected { "connected" } else { "connect" };
+
+                Frame::Array(vec![
+                    bulk("slave".to_string()),
+                    bulk(host),
+                    Frame::Integer(port as u64),
+                    bulk(state.to_string()),
+                    Frame::Integer(offset),
+                ])
+            }
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Convierte este comando en su representacion en un Frame.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("role".as_bytes()));
+        frame
+    }
+}
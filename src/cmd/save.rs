@@ -0,0 +1,121 @@
+use crate::{Connection, Db, Frame, Parse, ParseError, ServerError};
+
+use tracing::{debug, instrument};
+
+/// Guarda un snapshot de la base de datos y responde cuando ha terminado.
+#[derive(Debug, Default)]
+pub struct Save {}
+
+/// Guarda un snapshot de la base de datos en segundo plano.
+#[derive(Debug, Default)]
+pub struct BgSave {}
+
+/// Retorna el instante, en segundos desde 'epoch', del ultimo snapshot
+/// guardado correctamente.
+#[derive(Debug, Default)]
+pub struct LastSave {}
+
+impl Save {
+    /// Crea el comando
+    pub fn new() -> Save {
+        Save {}
+    }
+
+    /// Parsea una instancia de `Save` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// SAVE
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Save, ParseError> {
+        // El comando no tiene argumentos.
+        Ok(Save {})
+    }
+
+    /// Guarda el snapshot. La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.rdb() {
+            Some(rdb) => match rdb.save(db).await {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(err) => err.into(),
+            },
+            None => not_configured(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+impl BgSave {
+    /// Crea el comando
+    pub fn new() -> BgSave {
+        BgSave {}
+    }
+
+    /// Parsea una instancia de `BgSave` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// BGSAVE
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<BgSave, ParseError> {
+        // El comando no tiene argumentos.
+        Ok(BgSave {})
+    }
+
+    /// Inicia el guardado del snapshot. La respuesta es escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.rdb() {
+            Some(rdb) => match rdb.bgsave(db) {
+                Ok(()) => Frame::Simple("Background saving started".to_string()),
+                Err(err) => err.into(),
+            },
+            None => not_configured(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+impl LastSave {
+    /// Crea el comando
+    pub fn new() -> LastSave {
+        LastSave {}
+    }
+
+    /// Parsea una instancia de `LastSave` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// LASTSAVE
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<LastSave, ParseError> {
+        // El comando no tiene argumentos.
+        Ok(LastSave {})
+    }
+
+    /// Responde con el instante del ultimo snapshot. La respuesta es
+    /// escrita en ´dst´.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.rdb() {
+            Some(rdb) => Frame::Integer(rdb.last_save()),
+            None => not_configured(),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+/// Respuesta cuando el servidor no tiene configurado un fichero de snapshot.
+fn not_configured() -> Frame {
+    ServerError::err("snapshot persistence is not configured").into()
+}
@@ -2,7 +2,7 @@ use crate::cmd::{Parse, ParseError};
 use crate::{Connection, Db, Frame};
 
 use bytes::Bytes;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing::{debug, instrument};
 
 /// Asigna el valor de una clave
@@ -30,6 +30,11 @@ impl Set {
         }
     }
 
+    /// Clave sobre la que opera el comando
+    pub(crate) fn key(&self) -> &str {
+        &self.key
+    }
+
     /// Parsea una instancia de `Set` desde el frame que se ha recibido.
     ///
     /// Como parametro para el parseado se recibe una instancia de
@@ -37,13 +42,17 @@ impl Set {
     /// que pueden ser consumidos.
     ///
     /// # Formato del comando
-    /// SET key value [EX seconds|PX milliseconds]
+    /// SET key value [EX seconds|PX milliseconds|EXAT timestamp|PXAT timestamp-milliseconds]
+    ///
+    /// Las expiraciones absolutas (`EXAT`, `PXAT`) se convierten en el tiempo
+    /// que falta hasta ese instante. Si ya ha pasado la clave expira
+    /// inmediatamente.
     ///
     /// # Retorno
     /// Retorna el valor asociado a la clave o Err si el frame esta mal
     /// formado.
     ///
-    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Set> {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Set, ParseError> {
         use ParseError::EndOfStream;
 
         // Se lee la clave (este campo es requerido)
@@ -66,19 +75,28 @@ impl Set {
                 let ms = parse.next_int()?;
                 Some(Duration::from_millis(ms))
             }
+            Ok(s) if s.to_uppercase() == "EXAT" => {
+                // Instante de la expiracion en segundos desde 'epoch'
+                let secs = parse.next_int()?;
+                Some(until(UNIX_EPOCH + Duration::from_secs(secs)))
+            }
+            Ok(s) if s.to_uppercase() == "PXAT" => {
+                // Instante de la expiracion en milisegundos desde 'epoch'
+                let ms = parse.next_int()?;
+                Some(until(UNIX_EPOCH + Duration::from_millis(ms)))
+            }
             Ok(_) => {
                 // No se soportan otras opciones
-                return Err("currently `SET` only supports the expiration option".into());
+                return Err(ParseError::Syntax);
             }
             Err(EndOfStream) => {
                 // No hay nada que leer (no hay opciones)
                 Option::None
             }
             Err(err) => {
-                // Cualquier error pasa por aqui..
-                // All other errors are bubbled up, resulting in the connection
-                // being terminated.
-                return Err(err.into());
+                // Cualquier otro error se propaga y acabara en una respuesta
+                // de error al cliente.
+                return Err(err);
             }
         };
 
@@ -91,10 +109,15 @@ impl Set {
     #[instrument(skip(self, db, dst))]
     pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
         // asigna a una clave y valor y opcionalmente una caducidad.
-        db.set(self.key, self.value, self.expire);
-
-        // Prepara la respuesta satisfactoria.
-        let response = Frame::Simple("OK".to_string());
+        self.apply_write(db);
+
+        // Con `appendfsync always` no se responde hasta que la escritura
+        // es persistente. Si no se ha podido persistir se responde con el
+        // error.
+        let response = match db.sync_writes().await {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => err.into(),
+        };
 
         debug!(?response);
 
@@ -104,6 +127,35 @@ impl Set {
         Ok(())
     }
 
+    /// Aplica la escritura sobre la `Db` sin enviar ninguna respuesta.
+    pub(crate) fn apply_write(self, db: &Db) {
+        db.set(self.key, self.value, self.expire);
+    }
+
+    /// Trama de un `SET` con la expiracion en tiempo absoluto (`PXAT`).
+    ///
+    /// Es la forma en la que se registran las escrituras para que el
+    /// comando pueda reproducirse en otro momento con el mismo resultado.
+    pub(crate) fn absolute_frame(
+        key: &str,
+        value: &Bytes,
+        expires_at: Option<SystemTime>,
+    ) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("set".as_bytes()));
+        frame.push_bulk(Bytes::from(key.to_string()));
+        frame.push_bulk(value.clone());
+        if let Some(when) = expires_at {
+            let ms = when
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_millis())
+                .unwrap_or(0);
+            frame.push_bulk(Bytes::from("pxat".as_bytes()));
+            frame.push_bulk(Bytes::from(ms.to_string()));
+        }
+        frame
+    }
+
     /// Convierte este comando en su representacion en un Frame.
     pub(crate) fn into_frame(self) -> Frame {
         let mut frame = Frame::array();
@@ -117,3 +169,9 @@ impl Set {
         frame
     }
 }
+
+/// Tiempo que falta hasta el instante `when`, o cero si ya ha pasado.
+fn until(when: SystemTime) -> Duration {
+    when.duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO)
+}
@@ -1,5 +1,5 @@
 use crate::cmd::{Parse, ParseError, Unknown};
-use crate::{Command, Connection, Db, Frame, Shutdown};
+use crate::{Command, Connection, Db, Frame, Session, Shutdown};
 
 use bytes::Bytes;
 use std::pin::Pin;
@@ -41,6 +41,11 @@ impl Subscribe {
         }
     }
 
+    /// Canales a los que se subscribe el comando
+    pub(crate) fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
     ///
     /// Parsea una instancia de `Set` desde el frame que se ha recibido.
     ///
@@ -54,7 +59,7 @@ impl Subscribe {
     /// # Retorno
     /// Retorna la string `SUBSCRIBE` o Err el el frame esta mal formado.
     ///
-    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Subscribe> {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Subscribe, ParseError> {
         use ParseError::EndOfStream;
 
         // La string `SUBSCRIBE` ya ha sido consumido.
@@ -71,8 +76,8 @@ impl Subscribe {
                 // El error `EndOfStream` indica que no hay nada mas que parsear.
                 Err(EndOfStream) => break,
 
-                // Cualquier otro error implica que hay que cerrar la conexion
-                Err(err) => return Err(err.into()),
+                // Cualquier otro error se propaga
+                Err(err) => return Err(err),
             }
         }
 
@@ -96,6 +101,7 @@ impl Subscribe {
         db: &Db,
         dst: &mut Connection,
         shutdown: &mut Shutdown,
+        session: &Session,
     ) -> crate::Result<()> {
         // Cada canal individual de una subscripcion es gestionada
         // mediante un canal `sync::broadcast`. Los mensajes son repartidos
@@ -158,7 +164,7 @@ impl Subscribe {
                     // Tenemos un frame, hay que extraer el comando y ejecutarlo
                     // aunque solo los soportados dentro del contexto de un
                     // subscribe.
-                    handle_command(frame, &mut self.channels, &mut subscriptions, dst).await?;
+                    handle_command(frame, &mut self.channels, &mut subscriptions, db, dst, session).await?;
                 }
 
                 // SELECT 3 - Peticion de parada del servidor
@@ -222,10 +228,29 @@ async fn handle_command(
     frame: Frame,
     subscribe_to: &mut Vec<String>,
     subscriptions: &mut StreamMap<String, Messages>,
+    db: &Db,
     dst: &mut Connection,
+    session: &Session,
 ) -> crate::Result<()> {
     // Se utiliza de nuevo `Command::from_frame` para determinar que comando se ha recibido.
-    match Command::from_frame(frame)? {
+    // Un comando mal formado se responde con el error correspondiente sin
+    // abandonar el contexto de la subscripcion.
+    let command = match Command::from_frame(frame) {
+        Ok(command) => command,
+        Err(err) => {
+            dst.write_frame(&err.into()).await?;
+            return Ok(());
+        }
+    };
+
+    // Como fuera de este contexto, el usuario tiene que tener permiso para
+    // el comando y para los canales a los que se subscribe (ver `acl`).
+    if let Err(err) = command.check_acl(db, session) {
+        dst.write_frame(&err.into()).await?;
+        return Ok(());
+    }
+
+    match command {
         Command::Subscribe(subscribe) => {
             // Se realiza la subscripcion
             // la lista de subcripciones recibidas en el comando se carga
@@ -233,7 +258,7 @@ async fn handle_command(
             // Yo creo que aqui hay un error porque ademas abria que incorporar
             // en el StreamMap la subscripcion....
             // (ahora no estoy preparado para verfiicar esto)
-            subscribe_to.extend(subscribe.channels.into_iter());
+            subscribe_to.extend(subscribe.channels);
         }
 
         Command::Unsubscribe(mut unsubscribe) => {
@@ -0,0 +1,104 @@
+use crate::{Connection, Db, Frame, Parse, ParseError, ServerError, Session, Shutdown};
+
+use tokio::time::{self, Duration, Instant};
+use tracing::{debug, instrument};
+
+/// Bloquea la conexion hasta que al menos `numreplicas` replicas han
+/// confirmado todas las escrituras realizadas por ella, o hasta que pasa el
+/// `timeout`.
+///
+/// Se responde con el numero de replicas que las han confirmado.
+#[derive(Debug)]
+pub struct Wait {
+    /// Numero de replicas que se esperan
+    numreplicas: u64,
+
+    /// Tiempo maximo de espera. Con `0` se espera indefinidamente.
+    timeout: Duration,
+}
+
+impl Wait {
+    /// Crea el comando
+    pub fn new(numreplicas: u64, timeout: Duration) -> Wait {
+        Wait {
+            numreplicas,
+            timeout,
+        }
+    }
+
+    /// Parsea una instancia de `Wait` desde el frame que se ha recibido.
+    ///
+    /// # Formato del comando
+    /// WAIT numreplicas timeout
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Wait, ParseError> {
+        let numreplicas = parse.next_int()?;
+        let timeout = Duration::from_millis(parse.next_int()?);
+
+        Ok(Wait {
+            numreplicas,
+            timeout,
+        })
+    }
+
+    /// Espera las confirmaciones de las replicas. La respuesta es escrita
+    /// en ´dst´.
+    #[instrument(skip(self, db, dst, shutdown, session))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        shutdown: &mut Shutdown,
+        session: &Session,
+    ) -> crate::Result<()> {
+        if db.is_replica() {
+            let response = ServerError::err("WAIT cannot be used with replica instances.");
+            dst.write_frame(&response.into()).await?;
+            return Ok(());
+        }
+
+        let replicas = db.replicas();
+        let offset = session.write_offset;
+
+        // El receptor se crea antes de comprobar las confirmaciones para no
+        // perder ninguna.
+        let mut acked = replicas.watch_acks();
+        let deadline = (!self.timeout.is_zero()).then(|| Instant::now() + self.timeout);
+
+        if replicas.acked(offset) < self.numreplicas as usize {
+            replicas.request_acks();
+        }
+
+        loop {
+            if replicas.acked(offset) >= self.numreplicas as usize {
+                break;
+            }
+
+            tokio::select! {
+                res = acked.changed() => {
+                    if res.is_err() {
+                        break;
+                    }
+                }
+                _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => break,
+                _ = shutdown.recv() => break,
+            }
+        }
+
+        let response = Frame::Integer(replicas.acked(offset) as u64);
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Convierte este comando en su representacion en un Frame.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk("wait".into());
+        frame.push_int(self.numreplicas);
+        frame.push_int(self.timeout.as_millis() as u64);
+        frame
+    }
+}
@@ -0,0 +1,870 @@
+//! Configuracion del servidor con el formato de `redis.conf`.
+//!
+//! Cada linea del fichero es una directiva seguida de sus argumentos, que
+//! pueden ir entre comillas si contienen espacios:
+//!
+//! ```text
+//! # Comentario
+//! bind 127.0.0.1 ::1
+//! port 6380
+//! save 900 1 300 10
+//! requirepass "una contraseña"
+//! ```
+//!
+//! `Config` guarda el valor de cada parametro como texto, tal y como se
+//! escribe en el fichero y como lo retorna `CONFIG GET`, y a partir de ellos
+//! construye el `ServerConfig` con el que arranca el servidor. Los valores de
+//! la linea de comandos se aplican con `Config::set` despues de cargar el
+//! fichero.
+//!
+//! Algunos parametros se pueden modificar con el servidor en marcha mediante
+//! `CONFIG SET`; el resto solo se aplican al arrancar. `CONFIG REWRITE`
+//! guarda los valores actuales en el fichero de configuracion.
+
+use crate::acl::glob_match;
+use crate::aof::{AofConfig, Fsync};
+use crate::client::{Credentials, PeerOptions, TlsOptions};
+use crate::frame::Limits;
+use crate::rdb::{RdbConfig, SavePoint};
+use crate::server::ServerConfig;
+use crate::tls::TlsConfig;
+use crate::ServerError;
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Parametros de configuracion del servidor.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Valor de cada parametro, indexado por su nombre.
+    values: BTreeMap<&'static str, String>,
+
+    /// Fichero del que se ha cargado la configuracion, en el que la guarda
+    /// `CONFIG REWRITE`.
+    file: Option<PathBuf>,
+}
+
+/// Acceso a la configuracion con el servidor en marcha. Se guarda en la
+/// `Db`.
+#[derive(Clone, Debug)]
+pub(crate) struct ConfigHandle {
+    shared: Arc<Mutex<Config>>,
+}
+
+/// Descripcion de un parametro.
+struct Param {
+    name: &'static str,
+
+    /// Valor que tiene si no se configura.
+    default: &'static str,
+
+    /// Si se puede modificar con `CONFIG SET`.
+    mutable: bool,
+
+    /// Comprueba que un valor es valido.
+    check: fn(&str) -> Result<(), String>,
+}
+
+/// Parametros soportados.
+const PARAMS: &[Param] = &[
+    Param {
+        name: "bind",
+        default: "127.0.0.1",
+        mutable: false,
+        check: check_bind,
+    },
+    Param {
+        name: "port",
+        default: "6379",
+        mutable: false,
+        check: check_port,
+    },
+    Param {
+        name: "unixsocket",
+        default: "",
+        mutable: false,
+        check: check_any,
+    },
+    Param {
+        name: "unixsocketperm",
+        default: "0",
+        mutable: false,
+        check: check_octal,
+    },
+    Param {
+        name: "maxclients",
+        default: "10000",
+        mutable: true,
+        check: check_positive,
+    },
+    Param {
+        name: "timeout",
+        default: "0",
+        mutable: true,
+        check: check_integer,
+    },
+    Param {
+        name: "tcp-keepalive",
+        default: "300",
+        mutable: true,
+        check: check_integer,
+    },
+    Param {
+        name: "databases",
+        default: "16",
+        mutable: false,
+        check: check_positive,
+    },
+    Param {
+        name: "dir",
+        default: ".",
+        mutable: false,
+        check: check_not_empty,
+    },
+    Param {
+        name: "dbfilename",
+        default: "dump.rdb",
+        mutable: false,
+        check: check_not_empty,
+    },
+    Param {
+        name: "save",
+        default: "3600 1 300 100 60 10000",
+        mutable: false,
+        check: check_save,
+    },
+    Param {
+        name: "appendonly",
+        default: "no",
+        mutable: false,
+        check: check_bool,
+    },
+    Param {
+        name: "appendfilename",
+        default: "appendonly.aof",
+        mutable: false,
+        check: check_not_empty,
+    },
+    Param {
+        name: "appendfsync",
+        default: "everysec",
+        mutable: false,
+        check: check_fsync,
+    },
+    Param {
+        name: "replicaof",
+        default: "",
+        mutable: false,
+        check: check_replicaof,
+    },
+    Param {
+        name: "masteruser",
+        default: "",
+        mutable: true,
+        check: check_any,
+    },
+    Param {
+        name: "masterauth",
+        default: "",
+        mutable: true,
+        check: check_any,
+    },
+    Param {
+        name: "cluster-enabled",
+        default: "no",
+        mutable: false,
+        check: check_bool,
+    },
+    Param {
+        name: "requirepass",
+        default: "",
+        mutable: true,
+        check: check_any,
+    },
+    Param {
+        name: "aclfile",
+        default: "",
+        mutable: false,
+        check: check_any,
+    },
+    Param {
+        name: "tls-cert-file",
+        default: "",
+        mutable: false,
+        check: check_any,
+    },
+    Param {
+        name: "tls-key-file",
+        default: "",
+        mutable: false,
+        check: check_any,
+    },
+    Param {
+        name: "tls-ca-cert-file",
+        default: "",
+        mutable: false,
+        check: check_any,
+    },
+    Param {
+        name: "proto-max-bulk-len",
+        default: "536870912",
+        mutable: true,
+        check: check_positive,
+    },
+    Param {
+        name: "max-multibulk-len",
+        default: "1048576",
+        mutable: true,
+        check: check_positive,
+    },
+    Param {
+        name: "max-nesting-depth",
+        default: "32",
+        mutable: true,
+        check: check_positive,
+    },
+    Param {
+        name: "client-query-buffer-limit",
+        default: "1073741824",
+        mutable: true,
+        check: check_positive,
+    },
+];
+
+impl Config {
+    /// Configuracion con el valor por defecto de cada parametro.
+    pub fn new() -> Config {
+        Config {
+            values: PARAMS
+                .iter()
+                .map(|param| (param.name, param.default.to_string()))
+                .collect(),
+            file: None,
+        }
+    }
+
+    /// Carga la configuracion del fichero `path`. Los parametros que no
+    /// aparecen en el fichero tienen su valor por defecto.
+    ///
+    /// Los errores indican el fichero y la linea en la que se han producido.
+    pub fn load(path: impl Into<PathBuf>) -> crate::Result<Config> {
+        let path = path.into();
+        let contents = fs::read_to_string(&path)
+            .map_err(|err| format!("Error reading config file {}: {}", path.display(), err))?;
+
+        let mut config = Config::new();
+
+        // `save` puede aparecer en varias lineas, cada una con sus propias
+        // condiciones.
+        let mut save_lines = 0;
+
+        for (number, line) in contents.lines().enumerate() {
+            let error = |err: String| format!("{}:{}: {}", path.display(), number + 1, err);
+
+            let (name, args) = match parse_line(line).map_err(error)? {
+                Some(directive) => directive,
+                None => continue,
+            };
+
+            let mut value = args.join(" ");
+            if name.eq_ignore_ascii_case("save") {
+                save_lines += 1;
+                if save_lines > 1 && !value.is_empty() {
+                    value = format!("{} {}", config.values["save"], value);
+                }
+            }
+
+            config.set(&name, &value).map_err(error)?;
+        }
+
+        config.file = Some(path);
+
+        Ok(config)
+    }
+
+    /// Establece el valor de un parametro. Falla si el parametro no existe
+    /// o el valor no es valido.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let param = find(name)
+            .ok_or_else(|| format!("Bad directive or wrong number of arguments '{}'", name))?;
+        (param.check)(value)?;
+
+        self.values.insert(param.name, value.to_string());
+
+        Ok(())
+    }
+
+    /// Valor de un parametro, o `None` si el parametro no existe.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let param = find(name)?;
+        Some(&self.values[param.name])
+    }
+
+    /// Fichero del que se ha cargado la configuracion.
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    /// Construye la configuracion con la que arranca el servidor.
+    pub fn server_config(&self) -> crate::Result<ServerConfig> {
+        let dir = PathBuf::from(&self.values["dir"]);
+
+        let appendonly = (self.values["appendonly"] == "yes").then(|| AofConfig {
+            path: dir.join(&self.values["appendfilename"]),
+            fsync: self.values["appendfsync"].parse().unwrap(),
+        });
+
+        let snapshot = Some(RdbConfig {
+            path: dir.join(&self.values["dbfilename"]),
+            save_points: SavePoint::parse_list(&self.values["save"])?,
+        });
+
+        let replicaof = match &self.values["replicaof"] {
+            value if value.is_empty() => None,
+            value => {
+                let parts: Vec<_> = value.split_whitespace().collect();
+                Some((parts[0].to_string(), parts[1].parse().unwrap()))
+            }
+        };
+
+        let tls = match (self.path("tls-cert-file"), self.path("tls-key-file")) {
+            (Some(cert_file), Some(key_file)) => Some(TlsConfig {
+                cert_file,
+                key_file,
+                ca_cert_file: self.path("tls-ca-cert-file"),
+            }),
+            (None, None) => None,
+            _ => return Err("tls-cert-file and tls-key-file must be given together".into()),
+        };
+
+        Ok(ServerConfig {
+            bind: self.values["bind"]
+                .split_whitespace()
+                .map(|addr| addr.parse().unwrap())
+                .collect(),
+            port: self.number("port") as u16,
+            unixsocket: self.path("unixsocket"),
+            unixsocketperm: match u32::from_str_radix(&self.values["unixsocketperm"], 8).unwrap() {
+                0 => None,
+                mode => Some(mode),
+            },
+            maxclients: self.maxclients(),
+            timeout: self.timeout(),
+            tcp_keepalive: self.tcp_keepalive(),
+            databases: self.number("databases") as usize,
+            limits: self.limits(),
+            appendonly,
+            snapshot,
+            replicaof,
+            masteruser: self.string("masteruser"),
+            masterauth: self.string("masterauth"),
+            cluster_enabled: self.values["cluster-enabled"] == "yes",
+            requirepass: self.requirepass().map(str::to_string),
+            aclfile: self.path("aclfile"),
+            tls,
+            config_file: self.file.clone(),
+        })
+    }
+
+    /// Nombre y valor de los parametros cuyo nombre encaja con el patron
+    /// `pattern` (ver `acl::glob_match`).
+    pub(crate) fn matching(&self, pattern: &str) -> Vec<(&'static str, &str)> {
+        let pattern = pattern.to_lowercase();
+
+        self.values
+            .iter()
+            .filter(|(name, _)| glob_match(pattern.as_bytes(), name.as_bytes()))
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect()
+    }
+
+    /// Numero maximo de clientes conectados a la vez.
+    pub(crate) fn maxclients(&self) -> usize {
+        self.number("maxclients") as usize
+    }
+
+    /// Tiempo de inactividad tras el que se cierra la conexion de un
+    /// cliente. Cero si no se cierran nunca.
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_secs(self.number("timeout"))
+    }
+
+    /// Intervalo de los keepalive TCP de las conexiones. Cero si no se
+    /// envian.
+    pub(crate) fn tcp_keepalive(&self) -> Duration {
+        Duration::from_secs(self.number("tcp-keepalive"))
+    }
+
+    /// Limites del protocolo para las conexiones nuevas.
+    pub(crate) fn limits(&self) -> Limits {
+        Limits {
+            max_bulk_len: self.number("proto-max-bulk-len") as usize,
+            max_array_len: self.number("max-multibulk-len") as usize,
+            max_depth: self.number("max-nesting-depth") as usize,
+            max_query_buffer: self.number("client-query-buffer-limit") as usize,
+        }
+    }
+
+    /// Como se conecta el servidor con los demas nodos: se autentica con
+    /// `masteruser` y `masterauth` y, si acepta conexiones TLS, cifra la
+    /// conexion presentando su propio certificado y verificando el del otro
+    /// nodo con las CA de `tls-ca-cert-file`.
+    ///
+    /// Falla si el servidor usa TLS sin `tls-ca-cert-file`, ya que no tiene
+    /// con que verificar a los demas nodos.
+    pub(crate) fn peer_options(&self) -> crate::Result<PeerOptions> {
+        let credentials = self.string("masterauth").map(|password| Credentials {
+            username: self.string("masteruser"),
+            password,
+        });
+
+        let tls = match (self.path("tls-cert-file"), self.path("tls-key-file")) {
+            (Some(cert_file), Some(key_file)) => {
+                let ca_cert_file = self.path("tls-ca-cert-file").ok_or(
+                    "TLS connections to other nodes require tls-ca-cert-file to verify them",
+                )?;
+                Some(TlsOptions::new("", ca_cert_file).with_client_cert(cert_file, key_file))
+            }
+            _ => None,
+        };
+
+        Ok(PeerOptions { credentials, tls })
+    }
+
+    /// Contraseña del usuario `default`, si tiene.
+    pub(crate) fn requirepass(&self) -> Option<&str> {
+        Some(self.values["requirepass"].as_str()).filter(|value| !value.is_empty())
+    }
+
+    /// Guarda la configuracion en el fichero del que se cargo.
+    ///
+    /// Se conservan los comentarios y el orden de las lineas del fichero.
+    /// Las directivas se sustituyen por el valor actual de su parametro y
+    /// los parametros que no aparecian y no tienen su valor por defecto se
+    /// añaden al final.
+    pub(crate) fn rewrite(&self) -> crate::Result<()> {
+        let path = self
+            .file
+            .as_ref()
+            .ok_or("The server is running without a config file")?;
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut lines = vec![];
+        let mut written = HashSet::new();
+
+        for line in contents.lines() {
+            let param = match parse_line(line) {
+                Ok(Some((name, _))) => find(&name),
+                _ => None,
+            };
+
+            match param {
+                // Solo se conserva la primera linea de cada parametro.
+                Some(param) if written.insert(param.name) => {
+                    lines.push(self.directive(param.name));
+                }
+                Some(_) => {}
+                None => lines.push(line.to_string()),
+            }
+        }
+
+        for param in PARAMS {
+            if !written.contains(param.name) && self.values[param.name] != param.default {
+                lines.push(self.directive(param.name));
+            }
+        }
+
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Linea del fichero de configuracion con el valor de un parametro.
+    fn directive(&self, name: &str) -> String {
+        let value = &self.values[name];
+
+        // Los parametros con varios argumentos se escriben tal cual, el
+        // resto entre comillas si es necesario.
+        if matches!(name, "bind" | "save" | "replicaof") && !value.is_empty() {
+            format!("{} {}", name, value)
+        } else {
+            format!("{} {}", name, quote(value))
+        }
+    }
+
+    /// Valor de un parametro numerico, ya validado.
+    fn number(&self, name: &str) -> u64 {
+        self.values[name].parse().unwrap()
+    }
+
+    /// Valor de un parametro de texto, o `None` si esta vacio.
+    fn string(&self, name: &str) -> Option<String> {
+        Some(&self.values[name])
+            .filter(|value| !value.is_empty())
+            .cloned()
+    }
+
+    /// Valor de un parametro que es una ruta, o `None` si esta vacio.
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        Some(&self.values[name])
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
+}
+
+impl From<&ServerConfig> for Config {
+    /// Parametros equivalentes a un `ServerConfig`. El directorio de los
+    /// ficheros de persistencia es el del snapshot, o el del fichero AOF si
+    /// no hay snapshots.
+    fn from(server: &ServerConfig) -> Config {
+        let mut config = Config::new();
+        let mut set = |name: &'static str, value: String| {
+            config.values.insert(name, value);
+        };
+
+        let bind = server.bind.iter().map(IpAddr::to_string);
+        set("bind", bind.collect::<Vec<_>>().join(" "));
+        set("port", server.port.to_string());
+        set("unixsocket", display(&server.unixsocket));
+        set(
+            "unixsocketperm",
+            format!("{:o}", server.unixsocketperm.unwrap_or(0)),
+        );
+        set("maxclients", server.maxclients.to_string());
+        set("timeout", server.timeout.as_secs().to_string());
+        set("tcp-keepalive", server.tcp_keepalive.as_secs().to_string());
+        set("databases", server.databases.to_string());
+
+        let dir = server
+            .snapshot
+            .as_ref()
+            .map(|rdb| &rdb.path)
+            .or(server.appendonly.as_ref().map(|aof| &aof.path))
+            .and_then(|path| path.parent())
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf();
+        let file_name = |path: &Path| match path.strip_prefix(&dir) {
+            Ok(name) => name.display().to_string(),
+            Err(_) => path.display().to_string(),
+        };
+        set("dir", dir.display().to_string());
+
+        match &server.snapshot {
+            Some(rdb) => {
+                set("dbfilename", file_name(&rdb.path));
+                let save_points = rdb
+                    .save_points
+                    .iter()
+                    .map(|point| format!("{} {}", point.seconds, point.changes));
+                set("save", save_points.collect::<Vec<_>>().join(" "));
+            }
+            None => set("save", String::new()),
+        }
+
+        if let Some(aof) = &server.appendonly {
+            set("appendonly", "yes".to_string());
+            set("appendfilename", file_name(&aof.path));
+            set("appendfsync", aof.fsync.to_string());
+        }
+
+        if let Some((host, port)) = &server.replicaof {
+            set("replicaof", format!("{} {}", host, port));
+        }
+        set("masteruser", server.masteruser.clone().unwrap_or_default());
+        set("masterauth", server.masterauth.clone().unwrap_or_default());
+        if server.cluster_enabled {
+            set("cluster-enabled", "yes".to_string());
+        }
+        set(
+            "requirepass",
+            server.requirepass.clone().unwrap_or_default(),
+        );
+        set("aclfile", display(&server.aclfile));
+
+        if let Some(tls) = &server.tls {
+            set("tls-cert-file", tls.cert_file.display().to_string());
+            set("tls-key-file", tls.key_file.display().to_string());
+            set("tls-ca-cert-file", display(&tls.ca_cert_file));
+        }
+
+        set("proto-max-bulk-len", server.limits.max_bulk_len.to_string());
+        set("max-multibulk-len", server.limits.max_array_len.to_string());
+        set("max-nesting-depth", server.limits.max_depth.to_string());
+        set(
+            "client-query-buffer-limit",
+            server.limits.max_query_buffer.to_string(),
+        );
+
+        config.file = server.config_file.clone();
+        config
+    }
+}
+
+impl ConfigHandle {
+    pub(crate) fn new(config: Config) -> ConfigHandle {
+        ConfigHandle {
+            shared: Arc::new(Mutex::new(config)),
+        }
+    }
+
+    /// Nombre y valor de los parametros que encajan con alguno de los
+    /// patrones (`CONFIG GET`).
+    pub(crate) fn get(&self, patterns: &[String]) -> Vec<(String, String)> {
+        let config = self.shared.lock().unwrap();
+
+        let mut params = BTreeMap::new();
+        for pattern in patterns {
+            for (name, value) in config.matching(pattern) {
+                params.insert(name.to_string(), value.to_string());
+            }
+        }
+
+        params.into_iter().collect()
+    }
+
+    /// Modifica varios parametros a la vez (`CONFIG SET`). Si alguno no se
+    /// puede modificar no se modifica ninguno.
+    pub(crate) fn set(&self, params: &[(String, String)]) -> Result<Config, ServerError> {
+        let mut config = self.shared.lock().unwrap();
+        let mut updated = config.clone();
+
+        for (name, value) in params {
+            let param = find(name).ok_or_else(|| {
+                ServerError::err(format!(
+                    "Unknown option or number of arguments for CONFIG SET - '{}'",
+                    name
+                ))
+            })?;
+
+            let failed = |reason: &str| {
+                ServerError::err(format!(
+                    "CONFIG SET failed (possibly related to argument '{}') - {}",
+                    param.name, reason
+                ))
+            };
+
+            if !param.mutable {
+                return Err(failed("can't set immutable config"));
+            }
+
+            updated.set(param.name, value).map_err(|err| failed(&err))?;
+        }
+
+        *config = updated.clone();
+
+        Ok(updated)
+    }
+
+    /// Guarda la configuracion actual en su fichero (`CONFIG REWRITE`).
+    pub(crate) fn rewrite(&self) -> crate::Result<()> {
+        self.shared.lock().unwrap().rewrite()
+    }
+
+    /// Numero maximo de clientes conectados a la vez. Las conexiones que
+    /// lo superan se rechazan.
+    pub(crate) fn maxclients(&self) -> usize {
+        self.shared.lock().unwrap().maxclients()
+    }
+
+    /// Tiempo de inactividad tras el que se cierra la conexion de un
+    /// cliente. Cero si no se cierran nunca.
+    pub(crate) fn timeout(&self) -> Duration {
+        self.shared.lock().unwrap().timeout()
+    }
+
+    /// Intervalo de los keepalive TCP para las conexiones nuevas.
+    pub(crate) fn tcp_keepalive(&self) -> Duration {
+        self.shared.lock().unwrap().tcp_keepalive()
+    }
+
+    /// Limites del protocolo para las conexiones nuevas.
+    pub(crate) fn limits(&self) -> Limits {
+        self.shared.lock().unwrap().limits()
+    }
+
+    /// Como se conecta el servidor con los demas nodos (ver
+    /// `Config::peer_options`).
+    pub(crate) fn peer_options(&self) -> crate::Result<PeerOptions> {
+        self.shared.lock().unwrap().peer_options()
+    }
+}
+
+/// Busca un parametro por su nombre, sin distinguir mayusculas.
+fn find(name: &str) -> Option<&'static Param> {
+    PARAMS
+        .iter()
+        .find(|param| param.name.eq_ignore_ascii_case(name))
+}
+
+/// Separa una linea del fichero en la directiva y sus argumentos. Retorna
+/// `None` si la linea esta vacia o es un comentario.
+fn parse_line(line: &str) -> Result<Option<(String, Vec<String>)>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let mut args = vec![];
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let quote = match chars.peek() {
+            None => break,
+            Some(&c) if c == '"' || c == '\'' => chars.next(),
+            Some(_) => None,
+        };
+
+        let mut arg = String::new();
+        loop {
+            match (chars.next(), quote) {
+                (None, None) => break,
+                (None, Some(_)) => return Err("Unbalanced quotes in configuration line".into()),
+                (Some(c), Some(q)) if c == q => {
+                    if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                        return Err("Closing quote must be followed by a space".into());
+                    }
+                    break;
+                }
+                (Some('\\'), Some('"')) => match chars.next() {
+                    Some('n') => arg.push('\n'),
+                    Some('t') => arg.push('\t'),
+                    Some(c) => arg.push(c),
+                    None => return Err("Unbalanced quotes in configuration line".into()),
+                },
+                (Some(c), None) if c.is_whitespace() => break,
+                (Some(c), _) => arg.push(c),
+            }
+        }
+
+        args.push(arg);
+    }
+
+    let name = args.remove(0);
+
+    Ok(Some((name, args)))
+}
+
+/// Escribe un valor entre comillas si esta vacio o contiene espacios o
+/// comillas.
+fn quote(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+        return value.to_string();
+    }
+
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+/// Ruta como texto, vacio si es `None`.
+fn display(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+fn check_any(_value: &str) -> Result<(), String> {
+    Ok(())
+}
+
+fn check_not_empty(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Err("argument must not be empty".into());
+    }
+
+    Ok(())
+}
+
+fn check_integer(value: &str) -> Result<(), String> {
+    value
+        .parse::<u64>()
+        .map(|_| ())
+        .map_err(|_| "argument couldn't be parsed into an integer".into())
+}
+
+fn check_positive(value: &str) -> Result<(), String> {
+    match value.parse::<u64>() {
+        Ok(0) => Err("argument must be greater than 0".into()),
+        Ok(_) => Ok(()),
+        Err(_) => Err("argument couldn't be parsed into an integer".into()),
+    }
+}
+
+fn check_port(value: &str) -> Result<(), String> {
+    value
+        .parse::<u16>()
+        .map(|_| ())
+        .map_err(|_| "argument must be between 0 and 65535 inclusive".into())
+}
+
+fn check_octal(value: &str) -> Result<(), String> {
+    u32::from_str_radix(value, 8)
+        .map(|_| ())
+        .map_err(|_| "argument couldn't be parsed into an octal number".into())
+}
+
+fn check_bool(value: &str) -> Result<(), String> {
+    match value {
+        "yes" | "no" => Ok(()),
+        _ => Err("argument must be 'yes' or 'no'".into()),
+    }
+}
+
+fn check_bind(value: &str) -> Result<(), String> {
+    if value.split_whitespace().next().is_none() {
+        return Err("at least one address is required".into());
+    }
+
+    for addr in value.split_whitespace() {
+        addr.parse::<IpAddr>()
+            .map_err(|_| format!("invalid bind address '{}'", addr))?;
+    }
+
+    Ok(())
+}
+
+fn check_save(value: &str) -> Result<(), String> {
+    SavePoint::parse_list(value).map(|_| ())
+}
+
+fn check_fsync(value: &str) -> Result<(), String> {
+    value.parse::<Fsync>().map(|_| ())
+}
+
+fn check_replicaof(value: &str) -> Result<(), String> {
+    let parts: Vec<_> = value.split_whitespace().collect();
+    match &parts[..] {
+        [] => Ok(()),
+        [_, port] => port
+            .parse::<u16>()
+            .map(|_| ())
+            .map_err(|_| format!("invalid replicaof port '{}'", port)),
+        _ => Err("replicaof must be given as \"<host> <port>\"".into()),
+    }
+}
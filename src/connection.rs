@@ -1,15 +1,21 @@
-use crate::frame::{self, Frame};
+use crate::frame::{self, Frame, FrameError, Limits};
 
 use bytes::{Buf, BytesMut};
+use std::fmt;
 use std::io::{self, Cursor};
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
 
 /// Send and receive `Frame` values from a remote peer.
 ///
 /// When implementing networking protocols, a message on that protocol is
 /// often composed of several smaller messages known as frames. The purpose of
-/// `Connection` is to read and write frames on the underlying `TcpStream`.
+/// `Connection` is to read and write frames on the underlying transport.
+///
+/// The transport is any byte stream implementing `AsyncRead` and
+/// `AsyncWrite`: a `TcpStream`, a TLS stream wrapping one, etc. The server
+/// and the client handle connections over different transports alike, so by
+/// default the transport type is erased behind a `Box<dyn Transport>` (see
+/// [`Connection::boxed`]).
 ///
 /// To read frames, the `Connection` uses an internal buffer, which is filled
 /// up until there are enough bytes to create a full frame. Once this happens,
@@ -18,20 +24,40 @@ use tokio::net::TcpStream;
 /// When sending frames, the frame is first encoded into the write buffer.
 /// The contents of the write buffer are then written to the socket.
 #[derive(Debug)]
-pub struct Connection {
-    // The `TcpStream`. It is decorated with a `BufWriter`, which provides write
+pub struct Connection<T = Box<dyn Transport>> {
+    // The transport. It is decorated with a `BufWriter`, which provides write
     // level buffering. The `BufWriter` implementation provided by Tokio is
     // sufficient for our needs.
-    stream: BufWriter<TcpStream>,
+    stream: BufWriter<T>,
 
     // The buffer for reading frames.
     buffer: BytesMut,
+
+    // Limits enforced while parsing inbound frames. Protects the process
+    // against peers declaring huge or deeply nested frames.
+    limits: Limits,
 }
 
+/// A byte stream a `Connection` can be established over.
+///
+/// Implemented for every type that is `AsyncRead + AsyncWrite`, so it never
+/// needs to be implemented by hand.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug> Transport for T {}
+
 impl Connection {
+    /// Create a new `Connection` backed by `socket`, erasing the type of the
+    /// transport.
+    pub fn boxed(socket: impl Transport + 'static) -> Connection {
+        Connection::new(Box::new(socket))
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
     /// Create a new `Connection`, backed by `socket`. Read and write buffers
     /// are initialized.
-    pub fn new(socket: TcpStream) -> Connection {
+    pub fn new(socket: T) -> Connection<T> {
         Connection {
             stream: BufWriter::new(socket),
             // Default to a 4KB read buffer. For the use case of mini redis,
@@ -39,9 +65,15 @@ impl Connection {
             // value to their specific use case. There is a high likelihood that
             // a larger read buffer will work better.
             buffer: BytesMut::with_capacity(4 * 1024),
+            limits: Limits::default(),
         }
     }
 
+    /// Replace the protocol limits enforced when reading frames.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
     /// Read a single `Frame` value from the underlying stream.
     ///
     /// The function waits until it has retrieved enough data to parse a frame.
@@ -50,9 +82,12 @@ impl Connection {
     ///
     /// # Returns
     ///
-    /// On success, the received frame is returned. If the `TcpStream`
+    /// On success, the received frame is returned. If the transport
     /// is closed in a way that doesn't break a frame in half, it returns
     /// `None`. Otherwise, an error is returned.
+    ///
+    /// If the peer sends data violating the configured `Limits`, a
+    /// `FrameError` is returned. The connection can not be used any further.
     pub async fn read_frame(&mut self) -> crate::Result<Option<Frame>> {
         loop {
             // Attempt to parse a frame from the buffered data. If enough data
@@ -61,6 +96,21 @@ impl Connection {
                 return Ok(Some(frame));
             }
 
+            // A frame is still incomplete and the buffer already holds as much
+            // data as we are willing to accept. Refuse to keep growing it.
+            if self.buffer.len() >= self.limits.max_query_buffer {
+                return Err(
+                    FrameError::from("protocol error; max query buffer length exceeded").into(),
+                );
+            }
+
+            // Before waiting for the peer, make sure it has received every
+            // response written so far. Writes may have been held back while
+            // more pipelined frames were available (see `write_frame`).
+            if !self.stream.buffer().is_empty() {
+                self.stream.flush().await?;
+            }
+
             // There is not enough buffered data to read a frame. Attempt to
             // read more data from the socket.
             //
@@ -74,7 +124,7 @@ impl Connection {
                 if self.buffer.is_empty() {
                     return Ok(None);
                 } else {
-                    return Err("connection reset by peer".into());
+                    return Err(crate::Error::ConnectionClosed);
                 }
             }
         }
@@ -98,7 +148,7 @@ impl Connection {
         // parse of the frame, and allows us to skip allocating data structures
         // to hold the frame data unless we know the full frame has been
         // received.
-        match Frame::check(&mut buf) {
+        match Frame::check_with_limits(&mut buf, &self.limits) {
             Ok(_) => {
                 // The `check` function will have advanced the cursor until the
                 // end of the frame. Since the cursor had position set to zero
@@ -149,14 +199,31 @@ impl Connection {
     ///
     /// The `Frame` value is written to the socket using the various `write_*`
     /// functions provided by `AsyncWrite`. Calling these functions directly on
-    /// a `TcpStream` is **not** advised, as this will result in a large number of
+    /// the transport is **not** advised, as this will result in a large number of
     /// syscalls. However, it is fine to call these functions on a *buffered*
     /// write stream. The data will be written to the buffer. Once the buffer is
     /// full, it is flushed to the underlying socket.
+    ///
+    /// The buffer is flushed once the frame is encoded **unless** another
+    /// complete frame is already waiting in the read buffer. In that case the
+    /// peer is pipelining requests: the responses are coalesced and flushed
+    /// together, at the latest when `read_frame` has to wait for more data.
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.feed_frame(frame).await?;
+
+        if self.has_buffered_frame() {
+            return Ok(());
+        }
+
+        self.flush().await
+    }
+
+    /// Encode a single `Frame` value into the write buffer without flushing
+    /// it. Used to queue several frames that are sent in a single write with
+    /// `flush`.
+    pub async fn feed_frame(&mut self, frame: &Frame) -> io::Result<()> {
         // Arrays are encoded by encoding each entry. All other frame types are
-        // considered literals. For now, mini-redis is not able to encode
-        // recursive frame structures. See below for more details.
+        // considered literals. See below for how nested arrays are handled.
         match frame {
             Frame::Array(val) => {
                 // Encode the frame type prefix. For an array, it is `*`.
@@ -174,12 +241,23 @@ impl Connection {
             _ => self.write_value(frame).await?,
         }
 
-        // Ensure the encoded frame is written to the socket. The calls above
-        // are to the buffered stream and writes. Calling `flush` writes the
-        // remaining contents of the buffer to the socket.
+        Ok(())
+    }
+
+    /// Ensure the encoded frames are written to the socket. The calls to
+    /// `feed_frame` write to the buffered stream. Calling `flush` writes the
+    /// remaining contents of the buffer to the socket.
+    pub async fn flush(&mut self) -> io::Result<()> {
         self.stream.flush().await
     }
 
+    /// Returns `true` if the read buffer holds at least one complete frame,
+    /// i.e. the next call to `read_frame` will not wait for the peer.
+    fn has_buffered_frame(&self) -> bool {
+        let mut buf = Cursor::new(&self.buffer[..]);
+        Frame::check_with_limits(&mut buf, &self.limits).is_ok()
+    }
+
     /// Write a frame literal to the stream
     async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
         match frame {
@@ -209,10 +287,14 @@ impl Connection {
                 self.stream.write_all(b"\r\n").await?;
             }
             // Encoding an `Array` from within a value cannot be done using a
-            // recursive strategy. In general, async fns do not support
-            // recursion. Mini-redis has not needed to encode nested arrays yet,
-            // so for now it is skipped.
-            Frame::Array(_val) => unreachable!(),
+            // recursive strategy, as async fns do not support recursion.
+            // Nested arrays are encoded into a temporary buffer with the
+            // synchronous `Frame::encode` instead.
+            Frame::Array(_) => {
+                let mut buf = Vec::new();
+                frame.encode(&mut buf);
+                self.stream.write_all(&buf).await?;
+            }
         }
 
         Ok(())
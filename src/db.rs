@@ -1,9 +1,21 @@
-use tokio::sync::{broadcast, Notify};
+use crate::acl::AclHandle;
+use crate::aof::AofHandle;
+use crate::cluster::{self, ClusterHandle};
+use crate::cmd::{Commands, Del, Set};
+use crate::config::{Config, ConfigHandle};
+use crate::rdb::{self, RdbHandle};
+use crate::replication::{self, Backlog, MasterLink, Replicas, Role, Sync};
+use crate::stats::Stats;
+use crate::{Frame, ServerError, ServerErrorKind};
+
+use tokio::sync::{broadcast, mpsc, Notify};
 use tokio::time::{self, Duration, Instant};
 
 use bytes::Bytes;
 use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use tracing::debug;
 
 /// Un envoltorio alrededor de una instancia `Db`.
@@ -27,13 +39,40 @@ pub struct DbDropGuard {
 ///
 /// Cuando un 'Db' es creado se lanza tambien una tarea. Esta tarea es
 /// utilizada para gestionar la expiracion de los valores. La tarea funcionara
-/// hasta que todas las instancias de 'Db' son borradas, momento en el que
-/// terminara.
+/// hasta que se elimine el `DbDropGuard` que la envuelve, que es la forma de
+/// crear un `Db` (ver `DbDropGuard::new`). Tiene que hacerse desde un runtime
+/// de Tokio.
+///
+/// Los metodos publicos equivalen a los comandos de claves y pub/sub del
+/// mismo nombre (`GET`, `SET`, `DEL`, `DUMP`, `RESTORE`, `PUBLISH` y
+/// `SUBSCRIBE`) y se pueden usar sin ninguna conexion de por medio: las
+/// escrituras se registran en el fichero AOF y se envian a las replicas
+/// igual que las de los clientes. Hay dos diferencias con la red:
+///
+/// * Las comprobaciones propias de cada conexion (usuario, replica de solo
+///   lectura, slots del cluster) no se aplican.
+/// * Las escrituras retornan sin esperar a que sean persistentes. Con
+///   `appendfsync always` hay que llamar a `sync_writes`, que es lo que hace
+///   `SET` antes de responder. Cada `Db` obtenido con `DbDropGuard::db`
+///   espera solo a sus propias escrituras, igual que cada conexion.
+///
+/// El resto de comandos (`PING`, `AUTH`, `ACL`, `CONFIG`, `INFO`, `WAIT`,
+/// `SAVE`, `BGSAVE`, `LASTSAVE`, `BGREWRITEAOF`, `REPLICAOF`, `ROLE`,
+/// `CLUSTER`, `MIGRATE`...) administran el servidor o la conexion y no
+/// tienen un metodo equivalente. Para usarlos, o para tener las
+/// comprobaciones de la conexion, `client::connect_in_process` crea un
+/// `Client` que ejecuta los comandos sobre la `Db` como si llegaran por la
+/// red.
 #[derive(Debug, Clone)]
 pub struct Db {
     /// Gestiona el estado compartido. La tarea secundaria que gestiona
     /// las expiraciones tambien poseera un `Arc<Shared>`.
     shared: Arc<Shared>,
+
+    /// Numero de secuencia de la ultima escritura hecha con este `Db` o sus
+    /// clones. Cada conexion tiene el suyo (ver `Db::handle`), de modo que
+    /// `sync_writes` solo espera a sus propias escrituras.
+    last_write: Arc<AtomicU64>,
 }
 
 #[derive(Debug)]
@@ -57,6 +96,10 @@ struct Shared {
     /// La tarea en segundo plano espera a que se notifique esto, luego verifica
     /// los valores caducados o la señal de parada.
     background_task: Notify,
+
+    /// Estadisticas del servidor. Son contadores atomicos, asi que no
+    /// necesitan el mutex.
+    stats: Stats,
 }
 
 #[derive(Debug)]
@@ -86,6 +129,50 @@ struct State {
     /// ocurre cuando todos los values de 'Db' han sido Drop. Asignando este
     /// valor a 'true' se marca a la tarea secundaria para que se detenga.
     shutdown: bool,
+
+    /// Canales por los que se difunde cada escritura (ver `Db::watch_writes`).
+    /// Los canales cuyo receptor se ha eliminado se descartan en la siguiente
+    /// escritura.
+    feeds: Vec<mpsc::UnboundedSender<(u64, Frame)>>,
+
+    /// Numero de secuencia de la ultima escritura.
+    write_seq: u64,
+
+    /// Fichero AOF (append only file) si la persistencia esta activada.
+    aof: Option<AofHandle>,
+
+    /// Fichero de snapshot, si esta configurado.
+    rdb: Option<RdbHandle>,
+
+    /// Identificador de replicacion de esta instancia.
+    replid: String,
+
+    /// Ultimas escrituras difundidas a las replicas. Se crea con la primera
+    /// replica que se conecta.
+    backlog: Option<Backlog>,
+
+    /// Conexion con el master si esta instancia es una replica.
+    master: Option<MasterLink>,
+
+    /// Replicas conectadas a esta instancia.
+    replicas: Replicas,
+
+    /// Estado del cluster si el modo cluster esta activado.
+    cluster: Option<ClusterHandle>,
+
+    /// Puerto en el que escucha el servidor. Las replicas lo anuncian al
+    /// master.
+    listening_port: Option<u16>,
+
+    /// Usuarios y sus permisos.
+    acl: AclHandle,
+
+    /// Configuracion del servidor.
+    config: ConfigHandle,
+
+    /// Comandos añadidos con `server::Builder::command`. Los atienden tanto
+    /// las conexiones remotas como los clientes en el mismo proceso.
+    commands: Arc<Commands>,
 }
 
 /// Entrada en el almacen Key/Value
@@ -101,18 +188,50 @@ struct Entry {
     expires_at: Option<Instant>,
 }
 
+/// Copia de una entrada del almacen Key/Value.
+///
+/// La expiracion se expresa en tiempo absoluto, de modo que sigue siendo
+/// valida fuera de este proceso (por ejemplo tras reiniciar el servidor).
+#[derive(Debug, Clone)]
+pub struct EntrySnapshot {
+    /// Clave de la entrada.
+    pub key: String,
+
+    /// Valor almacenado.
+    pub value: Bytes,
+
+    /// Instante en el que la entrada expira.
+    pub expires_at: Option<SystemTime>,
+}
+
+/// Receptor de las escrituras realizadas sobre una `Db`.
+///
+/// Cada escritura se recibe como la trama del comando que la reproduce,
+/// junto con su numero de secuencia. Las expiraciones de estos comandos
+/// siempre son absolutas.
+pub(crate) type WriteFeed = mpsc::UnboundedReceiver<(u64, Frame)>;
+
 impl DbDropGuard {
     /// Crea un nuevo 'DbDropGuard' que recubre a una instancia de 'Db'.
     /// Este envoltorio permite realiza la purga de la Bd cuando esta instancia
     /// es 'droped'.
-    pub(crate) fn new() -> DbDropGuard {
+    pub fn new() -> DbDropGuard {
         DbDropGuard { db: Db::new() }
     }
 
     /// Obtiene el recurso compartido. Internamente es un
     /// 'Arc', asi que se incremete el contador de referencias.
-    pub(crate) fn db(&self) -> Db {
-        self.db.clone()
+    ///
+    /// Cada llamada retorna un acceso que lleva la cuenta de sus propias
+    /// escrituras (ver `Db::sync_writes`).
+    pub fn db(&self) -> Db {
+        self.db.handle()
+    }
+}
+
+impl Default for DbDropGuard {
+    fn default() -> DbDropGuard {
+        DbDropGuard::new()
     }
 }
 
@@ -128,8 +247,11 @@ impl Db {
     /// Crea una nueva instancia de 'Db' que no contiene ninguna entrada. Tambien
     /// crea la tarea que gestiona las expiraciones proporcionandole el primero
     /// clon de la base de datos.
+    ///
+    /// La tarea solo se para al eliminar el `DbDropGuard` que la envuelve,
+    /// por eso fuera del crate las instancias se crean con
+    /// `DbDropGuard::new`.
     pub(crate) fn new() -> Db {
-
         // Se crea el objeto que contiene el estado
         let state = State {
             entries: HashMap::new(),
@@ -137,6 +259,19 @@ impl Db {
             expirations: BTreeMap::new(),
             next_id: 0,
             shutdown: false,
+            feeds: Vec::new(),
+            write_seq: 0,
+            aof: None,
+            rdb: None,
+            replid: replication::new_replid(),
+            backlog: None,
+            master: None,
+            replicas: Replicas::new(),
+            cluster: None,
+            listening_port: None,
+            acl: AclHandle::new(),
+            config: ConfigHandle::new(Config::new()),
+            commands: Arc::new(Commands::new()),
         };
 
         // Para acceder al estado hay que conseguir el acceso exclusivo
@@ -147,6 +282,7 @@ impl Db {
         let shared = Shared {
             state_mutex: mutex,
             background_task: Notify::new(),
+            stats: Stats::default(),
         };
 
         // Se envuelve con un Arc para poder compartiro entre varios threads
@@ -157,8 +293,20 @@ impl Db {
         tokio::spawn(purge_expired_tasks(arc_shared.clone()));
 
         // Se instancia un 'Db'
-        Db { shared: arc_shared }
+        Db {
+            shared: arc_shared,
+            last_write: Arc::new(AtomicU64::new(0)),
+        }
+    }
 
+    /// Nuevo acceso a la misma base de datos, que lleva la cuenta de sus
+    /// propias escrituras para `sync_writes`. El servidor crea uno para cada
+    /// conexion.
+    pub(crate) fn handle(&self) -> Db {
+        Db {
+            shared: self.shared.clone(),
+            last_write: Arc::new(AtomicU64::new(0)),
+        }
     }
 
     /// Obtiene el valor asociado con una clave.
@@ -166,7 +314,7 @@ impl Db {
     /// Retorna 'None' si no hay un valor asociado con la clave.
     /// Get the value associated with a key. Esto puede a que nunca de
     /// le asigno un valor a la clave o a que el valor expiro.
-    pub(crate) fn get(&self, key: &str) -> Option<Bytes> {
+    pub fn get(&self, key: &str) -> Option<Bytes> {
         // Se adquire el bloqueo
         let state = self.shared.state_mutex.lock().unwrap();
 
@@ -177,90 +325,427 @@ impl Db {
         state.entries.get(key).map(|entry| entry.data.clone())
     }
 
+    /// Obtiene una copia de la entrada de una clave, incluida su expiracion.
+    pub(crate) fn entry(&self, key: &str) -> Option<EntrySnapshot> {
+        let state = self.shared.state_mutex.lock().unwrap();
+
+        state.entries.get(key).map(|entry| EntrySnapshot {
+            key: key.to_string(),
+            value: entry.data.clone(),
+            expires_at: entry.expires_at.map(to_system_time),
+        })
+    }
+
+    /// Retorna 'true' si la clave tiene un valor asociado.
+    pub(crate) fn contains_key(&self, key: &str) -> bool {
+        let state = self.shared.state_mutex.lock().unwrap();
+        state.entries.contains_key(key)
+    }
+
+    /// Claves que pertenecen a un slot del cluster.
+    pub(crate) fn keys_in_slot(&self, slot: u16) -> Vec<String> {
+        let state = self.shared.state_mutex.lock().unwrap();
+
+        state
+            .entries
+            .keys()
+            .filter(|key| cluster::key_slot(key.as_bytes()) == slot)
+            .cloned()
+            .collect()
+    }
+
     /// Establece un valor asociado con una clave junto con un periodo de
     /// vencimiento que es opcional.
     ///
     /// Si ya hay un valor asociado con la clave, el nuevo valor substituira
-    /// al anterior.
-    pub(crate) fn set(&self, key: String, value: Bytes, expire: Option<Duration>) {
+    /// al anterior. No espera a que la escritura sea persistente (ver
+    /// `sync_writes`).
+    pub fn set(&self, key: String, value: Bytes, expire: Option<Duration>) {
         let notify = {
             // Se adquire el bloqueo
             let mut state = self.shared.state_mutex.lock().unwrap();
 
-            // El Id almacenado en el estado es el que se utilizara para esta operacion.
-            let id = state.next_id;
-
-            // Se incremente el Id para proxima insercion. Gracias a la
-            // proteccion del bloqueo cada operacion 'set' tiene garantizado un Id unico.
-            state.next_id += 1;
-
-            // En caso de que se haya especificado una duracion para la expiracion
-            // del valor, se convierte este duracion en el momento exacto de
-            // la expiracion.
-            //
-            // Tambien se programa la expiracion en el mapa de expiraciones.
-            //
-            // En caso de que la nueva expiracion resulta ser la proxima a ejecutar
-            // se le enviara una notificacion a la tarea subyacente.
-            let (notify, expires_at) = if expire.is_some() {
-                // Se calcula cuando la clave expirara.
-                let when = Instant::now() + expire.unwrap();
-
-                // Unicamente se notificara a la tarea de gestion de las expiraciones si
-                // la expiracion del nuevo valor que se esta estableciendo resulta
-                // ser la proxima expiracion a ejecutarse.
-                let notify = state
-                    .next_expiration()
-                    .map(|expiration| expiration > when)
-                    .unwrap();
-
-                // Track the expiration.
-                state.expirations.insert((when, id), key.clone());
-
-                // Resultado
-                (notify, Some(when))
-            } else {
-                (false, None)
-            };
-
-            // Se asigna la clave el nuevo valor en el HashMap principal.
-            // Si para esta misma clave habia un valor anterior, este se
-            // obtendra como resultado de la ejecucion.
-            let prev = state.entries.insert(
-                key,
-                Entry {
-                    id,
-                    data: value,
-                    expires_at,
-                },
-            );
-
-            // Si previamente habia un valor asociado a la clave y ese valor tenia
-            // definida una expiracion entonces hay que aliminar la correpondiente
-            // entrada de mapa de expiraciones.
-            if let Some(prev) = prev {
-                if let Some(when) = prev.expires_at {
-                    // clear expiration
-                    state.expirations.remove(&(when, prev.id));
-                }
+            // El mutex se libera al salir del bloque, antes de notificar la
+            // tarea en segundo plano. Esto ayuda a reducir la contención al
+            // evitar que la tarea en segundo plano se active y no pueda
+            // adquirir el mutex debido a que esta función aún lo retiene.
+            let notify = state.insert(key, value, expire);
+            self.wrote(state.write_seq);
+            notify
+        };
+
+        if notify {
+            // Finalmente, solo se notifica a la tarea en segundo plano si necesita
+            // actualizar su estado para reflejar un nuevo vencimiento.
+            self.shared.background_task.notify_one();
+        }
+    }
+
+    /// Elimina las claves indicadas (`DEL`). Retorna el numero de claves
+    /// que existian. Igual que `set`, no espera a que la escritura sea
+    /// persistente.
+    pub fn del(&self, keys: &[impl AsRef<str>]) -> u64 {
+        keys.iter().filter(|key| self.remove(key.as_ref())).count() as u64
+    }
+
+    /// Serializa el valor de una clave en el formato de `DUMP`. Retorna
+    /// `None` si la clave no existe.
+    pub fn dump(&self, key: &str) -> Option<Bytes> {
+        self.get(key).map(|value| rdb::dump_value(&value))
+    }
+
+    /// Crea una clave a partir del valor serializado por `dump` (`RESTORE`),
+    /// con la expiracion `expire` si se indica.
+    ///
+    /// Falla con `BUSYKEY` si la clave ya existe y no se indica `replace`, o
+    /// si `payload` no es un valor serializado valido. Igual que `set`, no
+    /// espera a que la escritura sea persistente.
+    pub fn restore(
+        &self,
+        key: String,
+        payload: &[u8],
+        expire: Option<Duration>,
+        replace: bool,
+    ) -> Result<(), ServerError> {
+        let value = rdb::restore_value(payload)
+            .ok_or_else(|| ServerError::err("DUMP payload version or checksum are wrong"))?;
+
+        if !self.insert_restored(key, value, expire, replace) {
+            return Err(ServerError::new(
+                ServerErrorKind::BusyKey,
+                "Target key name already exists.",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Establece el valor de una clave restaurada.
+    ///
+    /// A diferencia de `set`, si la clave ya existe y no se indica `replace`
+    /// el valor no se modifica y se retorna `false`. La comprobacion y la
+    /// escritura se hacen con el bloqueo adquirido.
+    fn insert_restored(
+        &self,
+        key: String,
+        value: Bytes,
+        expire: Option<Duration>,
+        replace: bool,
+    ) -> bool {
+        let notify = {
+            let mut state = self.shared.state_mutex.lock().unwrap();
+
+            if !replace && state.entries.contains_key(&key) {
+                return false;
             }
 
-            // Se liberta el mutex antes de notificar la tarea en segundo plano.
-            // Esto ayuda a reducir la contención al evitar que la tarea en segundo
-            // plano se active y no pueda adquirir el mutex debido a que esta función
-            // aún lo retiene.
-            //drop(state);
+            let notify = state.insert(key, value, expire);
+            self.wrote(state.write_seq);
+            notify
+        };
+
+        if notify {
+            self.shared.background_task.notify_one();
+        }
+
+        true
+    }
+
+    /// Elimina una clave. Retorna `true` si la clave existia.
+    pub(crate) fn remove(&self, key: &str) -> bool {
+        let mut state = self.shared.state_mutex.lock().unwrap();
+
+        let removed = state.remove(key);
+        if removed {
+            self.wrote(state.write_seq);
+        }
+        removed
+    }
+
+    /// Elimina una clave solo si todavia contiene `value`. Permite eliminar
+    /// una clave que se ha copiado a otro sitio sin perder una escritura
+    /// posterior a la copia.
+    pub(crate) fn remove_if(&self, key: &str, value: &Bytes) -> bool {
+        let mut state = self.shared.state_mutex.lock().unwrap();
+
+        match state.entries.get(key) {
+            Some(entry) if entry.data == *value => {
+                state.remove(key);
+                self.wrote(state.write_seq);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Anota `seq` como la ultima escritura hecha con este `Db`. Se llama
+    /// con el bloqueo adquirido, justo despues de la escritura.
+    fn wrote(&self, seq: u64) {
+        self.last_write.fetch_max(seq, Ordering::Relaxed);
+    }
+
+    /// Retorna un `WriteFeed` por el que se recibiran todas las escrituras
+    /// posteriores a esta llamada, junto con una copia de todas las entradas
+    /// en ese mismo instante.
+    ///
+    /// Ambas cosas se obtienen con el bloqueo adquirido, asi que aplicando
+    /// sobre la copia las escrituras recibidas se obtiene siempre el estado
+    /// actual de la base de datos.
+    pub(crate) fn snapshot_and_watch(&self) -> (Vec<EntrySnapshot>, WriteFeed) {
+        let mut state = self.shared.state_mutex.lock().unwrap();
+
+        let snapshot = state.snapshot();
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        state.feeds.push(tx);
+
+        (snapshot, rx)
+    }
+
+    /// Retorna una copia de todas las entradas junto con el numero de
+    /// secuencia de la ultima escritura incluida en ella.
+    ///
+    /// El bloqueo solo se mantiene mientras se copian las entradas. Los
+    /// valores son `Bytes`, asi que no se copian los datos.
+    pub(crate) fn snapshot(&self) -> (Vec<EntrySnapshot>, u64) {
+        let state = self.shared.state_mutex.lock().unwrap();
+        (state.snapshot(), state.write_seq)
+    }
+
+    /// Numero de secuencia de la ultima escritura. Se incrementa con cada
+    /// escritura, asi que la diferencia entre dos valores es el numero de
+    /// escrituras realizadas entre ambos.
+    pub(crate) fn write_seq(&self) -> u64 {
+        self.shared.state_mutex.lock().unwrap().write_seq
+    }
+
+    /// Sustituye todas las entradas por `entries`, descartando las que ya
+    /// han expirado.
+    ///
+    /// Las claves anteriores se eliminan una a una, asi que tanto el borrado
+    /// como las nuevas entradas se difunden como cualquier otra escritura.
+    pub(crate) fn replace_all(&self, entries: Vec<EntrySnapshot>) {
+        let notify = {
+            let mut state = self.shared.state_mutex.lock().unwrap();
+
+            let keys: Vec<String> = state.entries.keys().cloned().collect();
+            for key in keys {
+                state.remove(&key);
+            }
+
+            let now = SystemTime::now();
+            let mut notify = false;
+
+            for entry in entries {
+                let expire = match entry.expires_at {
+                    Some(when) => match when.duration_since(now) {
+                        Ok(expire) => Some(expire),
+                        Err(_) => continue,
+                    },
+                    None => None,
+                };
+
+                notify |= state.insert(entry.key, entry.value, expire);
+            }
 
             notify
         };
 
         if notify {
-            // Finalmente, solo se notifica a la tarea en segundo plano si necesita
-            // actualizar su estado para reflejar un nuevo vencimiento.
             self.shared.background_task.notify_one();
         }
     }
 
+    /// Atiende el `PSYNC` de una replica que ya tiene los datos hasta
+    /// `offset` del master `replid`.
+    ///
+    /// Retorna los datos que hay que enviarle y un `WriteFeed` con las
+    /// escrituras posteriores. Ambos se obtienen con el bloqueo adquirido,
+    /// asi que la replica no pierde ni repite ninguna escritura.
+    pub(crate) fn psync(&self, replid: &str, offset: Option<u64>) -> (Sync, WriteFeed) {
+        let mut state = self.shared.state_mutex.lock().unwrap();
+        let state = &mut *state;
+
+        let backlog = state.backlog.get_or_insert_with(Backlog::new);
+
+        let frames = match offset {
+            Some(offset) if replid == state.replid => backlog.since(offset),
+            _ => None,
+        };
+
+        let sync = match frames {
+            Some(frames) => Sync::Partial {
+                replid: state.replid.clone(),
+                frames,
+            },
+            None => Sync::Full {
+                replid: state.replid.clone(),
+                offset: backlog.offset(),
+                entries: state.snapshot(),
+            },
+        };
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        state.feeds.push(tx);
+
+        (sync, rx)
+    }
+
+    /// Replicas conectadas a esta instancia.
+    pub(crate) fn replicas(&self) -> Replicas {
+        self.shared.state_mutex.lock().unwrap().replicas.clone()
+    }
+
+    /// Offset de replicacion tras la ultima escritura, o `0` si todavia no
+    /// se ha conectado ninguna replica.
+    pub(crate) fn repl_offset(&self) -> u64 {
+        let state = self.shared.state_mutex.lock().unwrap();
+        state.backlog.as_ref().map(Backlog::offset).unwrap_or(0)
+    }
+
+    /// Rol de la instancia en la replicacion.
+    pub(crate) fn role(&self) -> Role {
+        let state = self.shared.state_mutex.lock().unwrap();
+
+        match &state.master {
+            Some(master) => master.role(),
+            None => Role::Master {
+                offset: state.backlog.as_ref().map(Backlog::offset).unwrap_or(0),
+                replicas: state.replicas.list(),
+            },
+        }
+    }
+
+    /// Guarda el puerto en el que escucha el servidor.
+    pub(crate) fn set_listening_port(&self, port: u16) {
+        self.shared.state_mutex.lock().unwrap().listening_port = Some(port);
+    }
+
+    /// Puerto en el que escucha el servidor, si se conoce.
+    pub(crate) fn listening_port(&self) -> Option<u16> {
+        self.shared.state_mutex.lock().unwrap().listening_port
+    }
+
+    /// Retorna los usuarios y sus permisos.
+    pub(crate) fn acl(&self) -> AclHandle {
+        self.shared.state_mutex.lock().unwrap().acl.clone()
+    }
+
+    /// Establece la configuracion del servidor.
+    pub(crate) fn set_config(&self, config: ConfigHandle) {
+        self.shared.state_mutex.lock().unwrap().config = config;
+    }
+
+    /// Retorna la configuracion del servidor.
+    pub(crate) fn config(&self) -> ConfigHandle {
+        self.shared.state_mutex.lock().unwrap().config.clone()
+    }
+
+    /// Establece los comandos añadidos por la aplicacion.
+    pub(crate) fn set_commands(&self, commands: Arc<Commands>) {
+        self.shared.state_mutex.lock().unwrap().commands = commands;
+    }
+
+    /// Retorna los comandos añadidos por la aplicacion.
+    pub(crate) fn commands(&self) -> Arc<Commands> {
+        self.shared.state_mutex.lock().unwrap().commands.clone()
+    }
+
+    /// Retorna las estadisticas del servidor.
+    pub(crate) fn stats(&self) -> &Stats {
+        &self.shared.stats
+    }
+
+    /// Establece (o elimina) la conexion con el master. Retorna la
+    /// conexion anterior, que hay que parar.
+    pub(crate) fn set_master(&self, master: Option<MasterLink>) -> Option<MasterLink> {
+        let mut state = self.shared.state_mutex.lock().unwrap();
+        std::mem::replace(&mut state.master, master)
+    }
+
+    /// Retorna `true` si esta instancia es una replica.
+    pub(crate) fn is_replica(&self) -> bool {
+        self.shared.state_mutex.lock().unwrap().master.is_some()
+    }
+
+    /// Master del que esta instancia es replica, si lo es.
+    pub(crate) fn master_addr(&self) -> Option<(String, u16)> {
+        let state = self.shared.state_mutex.lock().unwrap();
+
+        state.master.as_ref().map(|master| {
+            let (host, port) = master.master();
+            (host.to_string(), port)
+        })
+    }
+
+    /// Retorna un `WriteFeed` por el que se recibiran todas las escrituras
+    /// posteriores a esta llamada.
+    pub(crate) fn watch_writes(&self) -> WriteFeed {
+        let mut state = self.shared.state_mutex.lock().unwrap();
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        state.feeds.push(tx);
+
+        rx
+    }
+
+    /// Asocia (o desasocia) el fichero AOF a la base de datos.
+    pub(crate) fn set_aof(&self, aof: Option<AofHandle>) {
+        self.shared.state_mutex.lock().unwrap().aof = aof;
+    }
+
+    /// Retorna el fichero AOF asociado a la base de datos, si lo hay.
+    pub(crate) fn aof(&self) -> Option<AofHandle> {
+        self.shared.state_mutex.lock().unwrap().aof.clone()
+    }
+
+    /// Asocia (o desasocia) el fichero de snapshot a la base de datos.
+    pub(crate) fn set_rdb(&self, rdb: Option<RdbHandle>) {
+        self.shared.state_mutex.lock().unwrap().rdb = rdb;
+    }
+
+    /// Retorna el fichero de snapshot asociado a la base de datos, si lo hay.
+    pub(crate) fn rdb(&self) -> Option<RdbHandle> {
+        self.shared.state_mutex.lock().unwrap().rdb.clone()
+    }
+
+    /// Activa el modo cluster con el estado dado.
+    pub(crate) fn set_cluster(&self, cluster: Option<ClusterHandle>) {
+        self.shared.state_mutex.lock().unwrap().cluster = cluster;
+    }
+
+    /// Retorna el estado del cluster si el modo cluster esta activado.
+    pub(crate) fn cluster(&self) -> Option<ClusterHandle> {
+        self.shared.state_mutex.lock().unwrap().cluster.clone()
+    }
+
+    /// Espera a que las escrituras realizadas con este `Db` (o sus clones)
+    /// sean persistentes, cuando la politica `appendfsync` es `always`. Las
+    /// escrituras de otras conexiones no se esperan.
+    ///
+    /// Con cualquier otra politica (o sin AOF) retorna inmediatamente. Los
+    /// comandos de escritura no responden al cliente hasta entonces. Si las
+    /// escrituras no se han podido registrar en el fichero retorna un error
+    /// `MISCONF`, que los comandos envian en lugar de confirmarlas.
+    pub async fn sync_writes(&self) -> Result<(), ServerError> {
+        let seq = self.last_write.load(Ordering::Relaxed);
+
+        match self.aof() {
+            Some(aof) => aof.wait_synced(seq).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Falla con `MISCONF` si no se pueden persistir las escrituras porque
+    /// el fichero AOF ha fallado. Las escrituras de los clientes se rechazan
+    /// antes de aplicarlas hasta que se vuelve a poder escribir en el.
+    pub(crate) fn check_writable(&self) -> Result<(), ServerError> {
+        match self.aof() {
+            Some(aof) => aof.check_writable(),
+            None => Ok(()),
+        }
+    }
+
     /// Retorna un 'tokio::sync::broadcast::Receiver' para el canal requerido.
     ///
     /// El 'Receiver' recibido se puede utilizar para recibir valores difundidos
@@ -400,6 +885,128 @@ impl Shared {
 }
 
 impl State {
+    /// Asigna el valor de una clave con el bloqueo ya adquirido (ver
+    /// `Db::set`).
+    ///
+    /// Retorna `true` si hay que notificar a la tarea en segundo plano porque
+    /// la nueva expiracion es la proxima.
+    fn insert(&mut self, key: String, value: Bytes, expire: Option<Duration>) -> bool {
+        // El Id almacenado en el estado es el que se utilizara para esta operacion.
+        let id = self.next_id;
+
+        // Se incremente el Id para proxima insercion. Gracias a la
+        // proteccion del bloqueo cada operacion 'set' tiene garantizado un Id unico.
+        self.next_id += 1;
+
+        // En caso de que se haya especificado una duracion para la expiracion
+        // del valor, se convierte este duracion en el momento exacto de
+        // la expiracion.
+        //
+        // Tambien se programa la expiracion en el mapa de expiraciones.
+        //
+        // En caso de que la nueva expiracion resulta ser la proxima a ejecutar
+        // se le enviara una notificacion a la tarea subyacente.
+        let (notify, expires_at) = if let Some(expire) = expire {
+            // Se calcula cuando la clave expirara.
+            let when = Instant::now() + expire;
+
+            // Unicamente se notificara a la tarea de gestion de las expiraciones si
+            // la expiracion del nuevo valor que se esta estableciendo resulta
+            // ser la proxima expiracion a ejecutarse (o si no habia ninguna).
+            let notify = self
+                .next_expiration()
+                .map(|expiration| expiration > when)
+                .unwrap_or(true);
+
+            // Track the expiration.
+            self.expirations.insert((when, id), key.clone());
+
+            // Resultado
+            (notify, Some(when))
+        } else {
+            (false, None)
+        };
+
+        // Se difunde la escritura con la expiracion en tiempo absoluto.
+        self.propagate(|| Set::absolute_frame(&key, &value, expires_at.map(to_system_time)));
+
+        // Se asigna la clave el nuevo valor en el HashMap principal.
+        // Si para esta misma clave habia un valor anterior, este se
+        // obtendra como resultado de la ejecucion.
+        let prev = self.entries.insert(
+            key,
+            Entry {
+                id,
+                data: value,
+                expires_at,
+            },
+        );
+
+        // Si previamente habia un valor asociado a la clave y ese valor tenia
+        // definida una expiracion entonces hay que aliminar la correpondiente
+        // entrada de mapa de expiraciones.
+        if let Some(prev) = prev {
+            if let Some(when) = prev.expires_at {
+                // clear expiration
+                self.expirations.remove(&(when, prev.id));
+            }
+        }
+
+        notify
+    }
+
+    /// Elimina una clave con el bloqueo ya adquirido. Retorna `true` si la
+    /// clave existia.
+    fn remove(&mut self, key: &str) -> bool {
+        let prev = match self.entries.remove(key) {
+            Some(prev) => prev,
+            None => return false,
+        };
+
+        if let Some(when) = prev.expires_at {
+            self.expirations.remove(&(when, prev.id));
+        }
+
+        self.propagate(|| Del::new(&[key]).into_frame());
+
+        true
+    }
+
+    /// Asigna un numero de secuencia a la escritura y la difunde por todos
+    /// los `WriteFeed`. Debe llamarse con el bloqueo adquirido para que todos
+    /// los receptores vean las escrituras en el mismo orden.
+    ///
+    /// La trama solo se construye si hay algun `WriteFeed` o un backlog de
+    /// replicacion.
+    fn propagate(&mut self, frame: impl FnOnce() -> Frame) {
+        self.write_seq += 1;
+
+        if self.feeds.is_empty() && self.backlog.is_none() {
+            return;
+        }
+
+        let seq = self.write_seq;
+        let frame = frame();
+
+        if let Some(backlog) = &mut self.backlog {
+            backlog.push(frame.clone());
+        }
+        self.feeds
+            .retain(|tx| tx.send((seq, frame.clone())).is_ok());
+    }
+
+    /// Copia de todas las entradas.
+    fn snapshot(&self) -> Vec<EntrySnapshot> {
+        self.entries
+            .iter()
+            .map(|(key, entry)| EntrySnapshot {
+                key: key.clone(),
+                value: entry.data.clone(),
+                expires_at: entry.expires_at.map(to_system_time),
+            })
+            .collect()
+    }
+
     /// Desde el mapa 'expiratons' (de tipo BTreeMap<(Instant, u64), String>) se
     /// obtiene un iterador que estara ordenado de la clave.
     /// Se hace avanzar el iterador a la primera posicion para obtener la primera clave
@@ -416,6 +1023,17 @@ impl State {
     }
 }
 
+/// Convierte un `Instant` en el `SystemTime` equivalente.
+fn to_system_time(when: Instant) -> SystemTime {
+    let now = Instant::now();
+
+    if when >= now {
+        SystemTime::now() + (when - now)
+    } else {
+        SystemTime::now() - (now - when)
+    }
+}
+
 /// Tarea ejecutada en segundo plano.
 ///
 /// La terea estara dormida esperando alguna notificacion.
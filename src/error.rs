@@ -0,0 +1,353 @@
+//! Errores del crate.
+//!
+//! `Error` enumera las causas por las que puede fallar una operacion, de modo
+//! que quien utilice el crate pueda distinguir entre una conexion cerrada, una
+//! violacion del protocolo, un error enviado por el servidor o un timeout.
+//!
+//! Redis responde a los comandos erroneos con una trama de tipo error cuya
+//! primera palabra identifica la clase del error (`ERR`, `WRONGTYPE`, ...).
+//! El resto de la linea es un mensaje legible. Estos errores se representan
+//! con `ServerError`.
+
+use crate::frame::FrameError;
+use crate::Frame;
+
+use std::sync::Arc;
+use std::{fmt, io};
+
+/// Error retornado por la mayoria de funciones del crate.
+#[derive(Debug)]
+pub enum Error {
+    /// Error de E/S en el transporte subyacente.
+    Io(io::Error),
+
+    /// El otro extremo cerro la conexion.
+    ConnectionClosed,
+
+    /// Se ha recibido una trama mal formada o una respuesta que no
+    /// corresponde con lo esperado.
+    Protocol(String),
+
+    /// El servidor respondio con un error.
+    Server(ServerError),
+
+    /// La operacion no se completo a tiempo.
+    Timeout,
+
+    /// Un servidor de un `ShardedClient` no esta disponible.
+    Shard(ShardError),
+
+    /// Cualquier otra causa.
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Clase de un error enviado por el servidor, obtenida del prefijo de la
+/// respuesta.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerErrorKind {
+    /// Error generico (`ERR`).
+    Err,
+
+    /// Operacion contra una clave que contiene un tipo de valor distinto
+    /// (`WRONGTYPE`).
+    WrongType,
+
+    /// No existe el script solicitado (`NOSCRIPT`).
+    NoScript,
+
+    /// La clave pertenece a otro nodo del cluster (`MOVED`).
+    Moved,
+
+    /// La clave se esta migrando a otro nodo del cluster (`ASK`).
+    Ask,
+
+    /// El servidor esta cargando los datos en memoria (`LOADING`).
+    Loading,
+
+    /// El servidor esta ocupado (`BUSY`).
+    Busy,
+
+    /// La clave de destino ya existe (`BUSYKEY`).
+    BusyKey,
+
+    /// Error de comunicacion con otra instancia (`IOERR`).
+    IoErr,
+
+    /// Se requiere autenticacion (`NOAUTH`).
+    NoAuth,
+
+    /// Usuario o contraseña incorrectos (`WRONGPASS`).
+    WrongPass,
+
+    /// El usuario no tiene permisos para la operacion (`NOPERM`).
+    NoPerm,
+
+    /// Escritura contra una replica de solo lectura (`READONLY`).
+    ReadOnly,
+
+    /// Comando con claves de distintos slots del cluster (`CROSSSLOT`).
+    CrossSlot,
+
+    /// Operacion que hay que reintentar mas tarde (`TRYAGAIN`).
+    TryAgain,
+
+    /// El cluster no esta disponible (`CLUSTERDOWN`).
+    ClusterDown,
+
+    /// El master no esta disponible (`MASTERDOWN`).
+    MasterDown,
+
+    /// No se ha podido persistir la escritura (`MISCONF`).
+    MisConf,
+
+    /// Cualquier otro prefijo no reconocido.
+    Other(String),
+}
+
+/// Error recuperable enviado por el servidor como respuesta a un comando.
+///
+/// A diferencia de los errores de protocolo, tras un `ServerError` la
+/// conexion sigue siendo valida.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerError {
+    kind: ServerErrorKind,
+    message: String,
+}
+
+/// Error de un servidor de un `ShardedClient` que ha fallado o al que aun no
+/// se vuelve a conectar tras un fallo.
+///
+/// Conserva la direccion del servidor y el error con el que fallo. Es barato
+/// de clonar, ya que todas las claves de ese servidor fallan con el mismo
+/// error.
+#[derive(Clone, Debug)]
+pub struct ShardError {
+    addr: String,
+    source: Arc<Error>,
+}
+
+impl ServerErrorKind {
+    /// Prefijo con el que se identifica esta clase de error en el protocolo.
+    pub fn prefix(&self) -> &str {
+        match self {
+            ServerErrorKind::Err => "ERR",
+            ServerErrorKind::WrongType => "WRONGTYPE",
+            ServerErrorKind::NoScript => "NOSCRIPT",
+            ServerErrorKind::Moved => "MOVED",
+            ServerErrorKind::Ask => "ASK",
+            ServerErrorKind::Loading => "LOADING",
+            ServerErrorKind::Busy => "BUSY",
+            ServerErrorKind::BusyKey => "BUSYKEY",
+            ServerErrorKind::IoErr => "IOERR",
+            ServerErrorKind::NoAuth => "NOAUTH",
+            ServerErrorKind::WrongPass => "WRONGPASS",
+            ServerErrorKind::NoPerm => "NOPERM",
+            ServerErrorKind::ReadOnly => "READONLY",
+            ServerErrorKind::CrossSlot => "CROSSSLOT",
+            ServerErrorKind::TryAgain => "TRYAGAIN",
+            ServerErrorKind::ClusterDown => "CLUSTERDOWN",
+            ServerErrorKind::MasterDown => "MASTERDOWN",
+            ServerErrorKind::MisConf => "MISCONF",
+            ServerErrorKind::Other(prefix) => prefix,
+        }
+    }
+
+    /// Obtiene la clase de error a partir de su prefijo.
+    fn from_prefix(prefix: &str) -> ServerErrorKind {
+        match prefix {
+            "ERR" => ServerErrorKind::Err,
+            "WRONGTYPE" => ServerErrorKind::WrongType,
+            "NOSCRIPT" => ServerErrorKind::NoScript,
+            "MOVED" => ServerErrorKind::Moved,
+            "ASK" => ServerErrorKind::Ask,
+            "LOADING" => ServerErrorKind::Loading,
+            "BUSY" => ServerErrorKind::Busy,
+            "BUSYKEY" => ServerErrorKind::BusyKey,
+            "IOERR" => ServerErrorKind::IoErr,
+            "NOAUTH" => ServerErrorKind::NoAuth,
+            "WRONGPASS" => ServerErrorKind::WrongPass,
+            "NOPERM" => ServerErrorKind::NoPerm,
+            "READONLY" => ServerErrorKind::ReadOnly,
+            "CROSSSLOT" => ServerErrorKind::CrossSlot,
+            "TRYAGAIN" => ServerErrorKind::TryAgain,
+            "CLUSTERDOWN" => ServerErrorKind::ClusterDown,
+            "MASTERDOWN" => ServerErrorKind::MasterDown,
+            "MISCONF" => ServerErrorKind::MisConf,
+            other => ServerErrorKind::Other(other.to_string()),
+        }
+    }
+}
+
+impl ServerError {
+    /// Crea un nuevo error de la clase indicada.
+    pub fn new(kind: ServerErrorKind, message: impl ToString) -> ServerError {
+        ServerError {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    /// Atajo para crear un error generico `ERR`.
+    pub fn err(message: impl ToString) -> ServerError {
+        ServerError::new(ServerErrorKind::Err, message)
+    }
+
+    /// Interpreta el contenido de una trama `Frame::Error`.
+    ///
+    /// Si la primera palabra esta en mayusculas se considera el prefijo que
+    /// identifica la clase del error. En caso contrario el error se considera
+    /// un `ERR` generico y todo el contenido es el mensaje.
+    pub fn parse(src: &str) -> ServerError {
+        let (prefix, message) = match src.split_once(' ') {
+            Some((prefix, message)) => (prefix, message),
+            None => (src, ""),
+        };
+
+        let is_prefix = !prefix.is_empty()
+            && prefix
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+
+        if is_prefix {
+            ServerError::new(ServerErrorKind::from_prefix(prefix), message)
+        } else {
+            ServerError::err(src)
+        }
+    }
+
+    /// Clase del error.
+    pub fn kind(&self) -> &ServerErrorKind {
+        &self.kind
+    }
+
+    /// Mensaje del error (sin el prefijo).
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self.message.is_empty() {
+            self.kind.prefix().fmt(fmt)
+        } else {
+            write!(fmt, "{} {}", self.kind.prefix(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+// Un `ServerError` se envia al cliente como una trama de error.
+impl From<ServerError> for Frame {
+    fn from(err: ServerError) -> Frame {
+        Frame::Error(err.to_string())
+    }
+}
+
+impl ShardError {
+    /// Crea el error de `addr`, que ha fallado con `source`.
+    pub(crate) fn new(addr: impl ToString, source: Error) -> ShardError {
+        ShardError {
+            addr: addr.to_string(),
+            source: Arc::new(source),
+        }
+    }
+
+    /// Direccion del servidor.
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Error con el que fallo el servidor.
+    pub fn error(&self) -> &Error {
+        &self.source
+    }
+}
+
+impl fmt::Display for ShardError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "shard {} unavailable: {}", self.addr, self.source)
+    }
+}
+
+impl std::error::Error for ShardError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => err.fmt(fmt),
+            Error::ConnectionClosed => "connection closed by peer".fmt(fmt),
+            Error::Protocol(msg) => msg.fmt(fmt),
+            Error::Server(err) => err.fmt(fmt),
+            Error::Timeout => "operation timed out".fmt(fmt),
+            Error::Shard(err) => err.fmt(fmt),
+            Error::Other(err) => err.fmt(fmt),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Server(err) => Some(err),
+            Error::Shard(err) => Some(err),
+            Error::Other(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(src: io::Error) -> Error {
+        Error::Io(src)
+    }
+}
+
+// Cualquier error al parsear una trama es una violacion del protocolo.
+impl From<FrameError> for Error {
+    fn from(src: FrameError) -> Error {
+        Error::Protocol(src.to_string())
+    }
+}
+
+impl From<ServerError> for Error {
+    fn from(src: ServerError) -> Error {
+        Error::Server(src)
+    }
+}
+
+impl From<ShardError> for Error {
+    fn from(src: ShardError) -> Error {
+        Error::Shard(src)
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for Error {
+    fn from(_src: tokio::time::error::Elapsed) -> Error {
+        Error::Timeout
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    fn from(src: Box<dyn std::error::Error + Send + Sync>) -> Error {
+        Error::Other(src)
+    }
+}
+
+impl From<String> for Error {
+    fn from(src: String) -> Error {
+        Error::Other(src.into())
+    }
+}
+
+impl From<&str> for Error {
+    fn from(src: &str) -> Error {
+        src.to_string().into()
+    }
+}
@@ -19,13 +19,46 @@ pub enum Frame {
     Array(Vec<Frame>),
 }
 
+/// Limites aplicados al parsear las tramas recibidas de un cliente.
+///
+/// Un cliente hostil podria declarar un "bulk string" o un array de un
+/// tamaño arbitrario, o anidar arrays hasta agotar la pila. Estos limites
+/// se comprueban en `Frame::check` antes de reservar memoria alguna.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Longitud maxima de un "bulk string" (`proto-max-bulk-len`).
+    pub max_bulk_len: usize,
+
+    /// Numero maximo de elementos de un array.
+    pub max_array_len: usize,
+
+    /// Profundidad maxima de anidamiento de arrays.
+    pub max_depth: usize,
+
+    /// Tamaño maximo que puede alcanzar el buffer de lectura de una
+    /// conexion sin haber completado una trama (`client-query-buffer-limit`).
+    pub max_query_buffer: usize,
+}
+
 #[derive(Debug)]
 pub enum FrameError {
     /// No hay suficientes datos para parsear un mensaje
     Incomplete,
 
     /// Codificacion invalida del mensaje
-    Other(crate::Error),
+    Other(String),
+}
+
+impl Default for Limits {
+    /// Los mismos valores por defecto que utiliza Redis.
+    fn default() -> Limits {
+        Limits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_array_len: 1024 * 1024,
+            max_depth: 32,
+            max_query_buffer: 1024 * 1024 * 1024,
+        }
+    }
 }
 
 impl Frame {
@@ -66,48 +99,15 @@ impl Frame {
 
     /// Ojo! No es un metodo.
     /// Es una funcion asociada a la estructura sin estado (en java seria un metodo estatico)
+    ///
+    /// Se aplican los limites por defecto (ver `Limits`).
     pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), FrameError> {
-        match get_u8(src)? {
-            b'+' => {
-                get_line(src)?;
-                Ok(())
-            }
-            b'-' => {
-                get_line(src)?;
-                Ok(())
-            }
-            b':' => {
-                let _ = get_decimal(src)?;
-                Ok(())
-            }
-            b'$' => {
-                if b'-' == peek_u8(src)? {
-                    // Saltamos -> '-1\r\n'
-                    skip(src, 4)
-                } else {
-                    // Leemos la longitud del "bulk string"
-                    let len: usize = get_decimal(src)?.try_into()?;
-
-                    // saltamos la longitud del "bulk string" + 2 (\r\n).
-                    skip(src, len + 2)
-                }
-            }
-            b'*' => {
-                // Leemos la longitud del array
-                let len = get_decimal(src)?;
-
-                // Mediante recursividad verificamos cada uno de los elementos del array
-                for _ in 0..len {
-                    Frame::check(src)?;
-                }
+        Frame::check_with_limits(src, &Limits::default())
+    }
 
-                Ok(())
-            }
-            actual => {
-                // Tipo de frame no soportado
-                Err(format!("protocol error; invalid frame type byte `{}`", actual).into())
-            }
-        }
+    /// Igual que `check` pero aplicando los limites indicados.
+    pub fn check_with_limits(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<(), FrameError> {
+        check_nested(src, limits, 0)
     }
 
     /// Ojo! No es un metodo.
@@ -202,7 +202,44 @@ impl Frame {
 
     /// Converts the frame to an "unexpected frame" error
     pub(crate) fn to_error(&self) -> crate::Error {
-        format!("unexpected frame: {}", self).into()
+        crate::Error::Protocol(format!("unexpected frame: {}", self))
+    }
+
+    /// Codifica la trama en `dst` con el formato del protocolo.
+    ///
+    /// A diferencia de `Connection::write_frame` la codificacion es
+    /// sincrona, asi que los arrays anidados se codifican recursivamente.
+    /// Se utiliza para escribir las tramas en los ficheros de persistencia.
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        match self {
+            Frame::Simple(val) => {
+                dst.push(b'+');
+                dst.extend_from_slice(val.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Error(val) => {
+                dst.push(b'-');
+                dst.extend_from_slice(val.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Integer(val) => {
+                dst.extend_from_slice(format!(":{}\r\n", val).as_bytes());
+            }
+            Frame::Null => {
+                dst.extend_from_slice(b"$-1\r\n");
+            }
+            Frame::Bulk(val) => {
+                dst.extend_from_slice(format!("${}\r\n", val.len()).as_bytes());
+                dst.extend_from_slice(val);
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Array(val) => {
+                dst.extend_from_slice(format!("*{}\r\n", val.len()).as_bytes());
+                for entry in val {
+                    entry.encode(dst);
+                }
+            }
+        }
     }
 }
 
@@ -243,6 +280,65 @@ impl fmt::Display for Frame {
     }
 }
 
+/// Verifica recursivamente una trama. `depth` es el nivel de anidamiento de
+/// la trama que se esta verificando (0 para la trama raiz).
+fn check_nested(src: &mut Cursor<&[u8]>, limits: &Limits, depth: usize) -> Result<(), FrameError> {
+    match get_u8(src)? {
+        b'+' => {
+            get_line(src)?;
+            Ok(())
+        }
+        b'-' => {
+            get_line(src)?;
+            Ok(())
+        }
+        b':' => {
+            let _ = get_decimal(src)?;
+            Ok(())
+        }
+        b'$' => {
+            if b'-' == peek_u8(src)? {
+                // Saltamos -> '-1\r\n'
+                skip(src, 4)
+            } else {
+                // Leemos la longitud del "bulk string"
+                let len: usize = get_decimal(src)?.try_into()?;
+
+                // Se rechaza antes de esperar a recibir los datos
+                if len > limits.max_bulk_len {
+                    return Err("protocol error; invalid bulk length".into());
+                }
+
+                // saltamos la longitud del "bulk string" + 2 (\r\n).
+                skip(src, len + 2)
+            }
+        }
+        b'*' => {
+            // Leemos la longitud del array
+            let len: usize = get_decimal(src)?.try_into()?;
+
+            if len > limits.max_array_len {
+                return Err("protocol error; invalid multibulk length".into());
+            }
+
+            if depth >= limits.max_depth {
+                return Err("protocol error; max nesting depth exceeded".into());
+            }
+
+            // Mediante recursividad verificamos cada uno de los elementos del array
+            for _ in 0..len {
+                check_nested(src, limits, depth + 1)?;
+            }
+
+            Ok(())
+        }
+        actual => {
+            // Tipo de frame no soportado
+            Err(format!("protocol error; invalid frame type byte `{}`", actual).into())
+        }
+    }
+}
+
 fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, FrameError> {
     // Cursor implementa bytes::buf::Buf como "Implementations on Foreign Types"
     // Es decir, la implementacion esta en el fichero con el codigo del Trait Buf
@@ -318,7 +414,7 @@ fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], FrameError> {
 // para conversion String -> mini_redis::frame::FrameError
 impl From<String> for FrameError {
     fn from(src: String) -> FrameError {
-        FrameError::Other(src.into())
+        FrameError::Other(src)
     }
 }
 
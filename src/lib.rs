@@ -13,30 +13,60 @@
 //!
 //! The major components are:
 //!
-//! * `server`: Redis server implementation. Includes a single `run` function
-//!   that takes a `TcpListener` and starts accepting redis client connections.
+//! * `server`: Redis server implementation. Includes a `run` function that
+//!   takes a `TcpListener` and starts accepting redis client connections,
+//!   optionally over TLS, and a `Builder` to embed the server in another
+//!   application.
 //!
 //! * `client`: an asynchronous Redis client implementation. Demonstrates how to
 //!   build clients with Tokio.
 //!
+//! * `cluster_client`: a client for a Redis cluster, routing each command to
+//!   the node that serves its key.
+//!
 //! * `cmd`: implementations of the supported Redis commands.
 //!
 //! * `frame`: represents a single Redis protocol frame. A frame is used as an
 //!   intermediate representation between a "command" and the byte
 //!   representation.
+//!
+//! * `error`: the crate `Error` type, including the error replies exchanged
+//!   between server and client.
+//!
+//! * `aof`: append only file persistence for the server.
+//!
+//! * `rdb`: point-in-time snapshot persistence for the server.
+//!
+//! * `cluster`: hash slots and key routing for the server's cluster mode.
+//!
+//! * `sentinel`: monitoring of masters and automatic failover to a replica.
+//!
+//! * `config`: server configuration in the `redis.conf` format, loaded from a
+//!   file and changed at runtime with `CONFIG`.
+//!
+//! * `tls`: TLS encryption of the connections between clients and server.
+
+pub mod aof;
+
+pub mod rdb;
+
+pub mod cluster;
 
 mod db;
-use db::Db;
-use db::DbDropGuard;
+pub use db::{Db, DbDropGuard};
 
 pub mod blocking_client;
 pub mod client;
+pub mod cluster_client;
 
 pub mod cmd;
 pub use cmd::Command;
 
 mod connection;
-pub use connection::Connection;
+pub use connection::{Connection, Transport};
+
+pub mod error;
+pub use error::{ServerError, ServerErrorKind, ShardError};
 
 pub mod frame;
 pub use frame::Frame;
@@ -46,29 +76,35 @@ use parse::{Parse, ParseError};
 
 pub mod server;
 
+pub mod sentinel;
+
+pub mod tls;
+
+pub mod config;
+
 mod buffer;
 pub use buffer::{buffer, Buffer};
 
 mod shutdown;
 use shutdown::Shutdown;
 
+mod replication;
+
+mod acl;
+
+mod stats;
+
+mod session;
+use session::Session;
+
 /// Puerto por defecto que se utilizara si no se especifica otro
 pub const DEFAULT_PORT: u16 = 6379;
 
-/// Error retornado pro la mayoria de funciones.
-///
-/// En una aplicacion real se puede considerar especializar la
-/// gestion de errores del crate por ejemplo definiendo el error
-/// como una enumeracion de causas.
-///
-/// Pero para este ejemplo se utilizara un boxed `std::error::Error`.
+/// Error retornado por la mayoria de funciones.
 ///
-/// Por motivos de rendimiento, se evitara el boxing en cualquier
-/// "hot path" o llamadas a metodos muy frecuentes utilizando en este
-/// casi un error definido mediante 'enum'. Se utilizara el error
-/// definido como una 'enum' pero de implementara `std::error:Error` lo
-/// cual permitira retornarlo para convertirlo en un `Box<dyn std::error::Error>`
-pub type Error = Box<dyn std::error::Error + Send + Sync>;
+/// Es una enumeracion de las posibles causas (conexion cerrada, error de
+/// protocolo, error enviado por el servidor, timeout...). Ver `error::Error`.
+pub use error::Error;
 
 // Un `Result`especializado para las operaciones del crate.
 pub type Result<T> = std::result::Result<T, Error>;
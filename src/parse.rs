@@ -20,13 +20,16 @@ pub(crate) struct Parse {
 
 /// Error encontrado mientras se parsea un frame.
 ///
-/// Unicamente en error `EndOfStream` es gestionado en runtime. Todos los
-/// otros errores terminan con el cierre de la conexion.
+/// Ninguno de estos errores cierra la conexion: `Command::from_frame` los
+/// convierte en la respuesta de error que se le envia al cliente.
 #[derive(Debug)]
 pub(crate) enum ParseError {
     /// El intentoi de extraer un frame a fallado porque se han consumido todos los frames.
     EndOfStream,
 
+    /// El comando contiene una opcion desconocida o mal combinada.
+    Syntax,
+
     /// Todos los otros errores
     Other(crate::Error),
 }
@@ -144,7 +147,7 @@ impl Parse {
     pub(crate) fn next_int(&mut self) -> Result<u64, ParseError> {
         use atoi::atoi;
 
-        const MSG: &str = "protocol error; invalid number";
+        const MSG: &str = "value is not an integer or out of range";
 
         match self.next()? {
             Frame::Integer(v) => {
@@ -204,6 +207,7 @@ impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ParseError::EndOfStream => "protocol error; unexpected end of stream".fmt(f),
+            ParseError::Syntax => "syntax error".fmt(f),
             ParseError::Other(err) => err.fmt(f),
         }
     }
@@ -0,0 +1,565 @@
+//! Persistencia mediante snapshots, al estilo de los ficheros RDB de Redis.
+//!
+//! Un snapshot es una copia binaria y compacta de todas las claves, sus
+//! valores y sus expiraciones en un instante concreto. Arrancar desde un
+//! snapshot es mucho mas rapido que reproducir un fichero AOF.
+//!
+//! # Formato del fichero
+//!
+//! Los enteros se codifican en big endian.
+//!
+//! ```text
+//! "MINIREDIS" version:u16
+//! { [0xFC expires_at:u64] tipo:u8 clave valor }*
+//! 0xFF crc32:u32
+//! ```
+//!
+//! * `expires_at` es el instante de la expiracion en milisegundos desde
+//!   'epoch'. Solo aparece si la clave tiene expiracion.
+//! * `clave` y `valor` se codifican con su longitud (u32) seguida de los
+//!   bytes. De momento el unico tipo de valor es `0`, una string.
+//! * `crc32` es el checksum de todo el fichero anterior a el.
+//!
+//! # Formato de `DUMP`
+//!
+//! `DUMP` serializa un unico valor con la misma codificacion que los
+//! snapshots, seguido de la version del formato y de un checksum:
+//!
+//! ```text
+//! tipo:u8 valor version:u16 crc32:u32
+//! ```
+//!
+//! `RESTORE` rechaza los valores con una version posterior o cuyo checksum
+//! no coincide.
+
+use crate::db::Db;
+use crate::{Error, ServerError};
+
+pub use crate::db::EntrySnapshot;
+
+use bytes::{Buf, BufMut, Bytes};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{oneshot, watch};
+use tokio::task::{self, JoinHandle};
+use tokio::time;
+use tracing::{error, info};
+
+/// Version del formato que se escribe. Se pueden leer ficheros de esta
+/// version o anteriores.
+pub const VERSION: u16 = 1;
+
+/// Cabecera de todos los ficheros.
+const MAGIC: &[u8] = b"MINIREDIS";
+
+/// Precede a una clave con expiracion.
+const OP_EXPIRE_MS: u8 = 0xFC;
+
+/// Marca el final de las entradas.
+const OP_EOF: u8 = 0xFF;
+
+/// Tipo de valor: string.
+const TYPE_STRING: u8 = 0;
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Condicion para guardar automaticamente un snapshot: han pasado al menos
+/// `seconds` segundos y se han realizado al menos `changes` escrituras desde
+/// el ultimo snapshot (`save 900 1`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SavePoint {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+/// Configuracion de los snapshots.
+#[derive(Clone, Debug)]
+pub struct RdbConfig {
+    /// Ruta del fichero.
+    pub path: PathBuf,
+
+    /// Condiciones para guardar automaticamente un snapshot. Si esta vacio
+    /// solo se guardan con `SAVE` y `BGSAVE`.
+    pub save_points: Vec<SavePoint>,
+}
+
+/// Acceso a los snapshots desde los comandos. Se guarda en la `Db`.
+#[derive(Clone, Debug)]
+pub(crate) struct RdbHandle {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    path: PathBuf,
+
+    /// Instante (segundos desde 'epoch') del ultimo snapshot guardado.
+    last_save: AtomicU64,
+
+    /// Numero de secuencia de la ultima escritura incluida en el ultimo
+    /// snapshot guardado.
+    saved_seq: AtomicU64,
+
+    /// `true` mientras se guarda un snapshot. Al parar el servidor se
+    /// espera a que cambie a `false`.
+    saving_tx: watch::Sender<bool>,
+    saving_rx: watch::Receiver<bool>,
+}
+
+/// Tarea que guarda los snapshots automaticamente. El servidor la detiene
+/// al pararse.
+#[derive(Debug)]
+pub(crate) struct Rdb {
+    handle: RdbHandle,
+    save_points: Vec<SavePoint>,
+    stop_tx: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl SavePoint {
+    /// Parsea una lista de condiciones con el formato de la opcion `save`
+    /// de Redis: pares de segundos y escrituras, por ejemplo `"900 1 300 10"`.
+    /// Una lista vacia desactiva el guardado automatico.
+    pub fn parse_list(src: &str) -> Result<Vec<SavePoint>, String> {
+        let values = src
+            .split_whitespace()
+            .map(|value| value.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid save points '{}'", src))?;
+
+        if values.len() % 2 != 0 {
+            return Err(format!("invalid save points '{}'", src));
+        }
+
+        Ok(values
+            .chunks(2)
+            .map(|pair| SavePoint {
+                seconds: pair[0],
+                changes: pair[1],
+            })
+            .collect())
+    }
+}
+
+impl RdbConfig {
+    /// Fichero en `path` sin guardado automatico.
+    pub fn new(path: impl Into<PathBuf>) -> RdbConfig {
+        RdbConfig {
+            path: path.into(),
+            save_points: vec![],
+        }
+    }
+}
+
+impl RdbHandle {
+    /// Guarda un snapshot y espera a que termine (`SAVE`).
+    pub(crate) async fn save(&self, db: &Db) -> Result<(), ServerError> {
+        self.start_saving()?;
+
+        let (entries, seq) = db.snapshot();
+        self.write(entries, seq).await.map_err(ServerError::err)
+    }
+
+    /// Guarda un snapshot en segundo plano (`BGSAVE`).
+    ///
+    /// La copia de la base de datos se obtiene antes de retornar, asi que el
+    /// snapshot refleja el estado en el momento de la llamada. El bloqueo de
+    /// la `Db` solo se mantiene mientras se copia, no mientras se escribe.
+    pub(crate) fn bgsave(&self, db: &Db) -> Result<(), ServerError> {
+        self.start_saving()?;
+
+        let (entries, seq) = db.snapshot();
+        let handle = self.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = handle.write(entries, seq).await {
+                error!(cause = %err, "background saving failed");
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Instante (segundos desde 'epoch') del ultimo snapshot guardado.
+    pub(crate) fn last_save(&self) -> u64 {
+        self.shared.last_save.load(Ordering::SeqCst)
+    }
+
+    /// Marca que se esta guardando un snapshot. Solo se puede guardar uno
+    /// a la vez.
+    fn start_saving(&self) -> Result<(), ServerError> {
+        let mut started = false;
+        self.shared.saving_tx.send_modify(|saving| {
+            started = !*saving;
+            *saving = true;
+        });
+
+        if !started {
+            return Err(ServerError::err("Background save already in progress"));
+        }
+
+        Ok(())
+    }
+
+    /// Escribe el snapshot en el fichero. La codificacion y la escritura son
+    /// operaciones bloqueantes, asi que se ejecutan con `spawn_blocking`.
+    async fn write(&self, entries: Vec<EntrySnapshot>, seq: u64) -> io::Result<()> {
+        let path = self.shared.path.clone();
+        let keys = entries.len();
+
+        let res = task::spawn_blocking(move || write_file(&path, &encode(&entries)))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)));
+
+        if res.is_ok() {
+            self.shared.last_save.store(unix_time(), Ordering::SeqCst);
+            self.shared.saved_seq.store(seq, Ordering::SeqCst);
+            info!(keys, path = ?self.shared.path, "DB saved on disk");
+        }
+
+        self.shared.saving_tx.send_modify(|saving| *saving = false);
+
+        res
+    }
+}
+
+impl Rdb {
+    /// Lanza la tarea que guarda los snapshots segun `config.save_points`.
+    ///
+    /// Si `load` es `true` antes se carga el snapshot en la `Db`.
+    pub(crate) async fn start(db: &Db, config: RdbConfig, load: bool) -> crate::Result<Rdb> {
+        if load {
+            self::load(db, &config.path).await?;
+        }
+
+        let (saving_tx, saving_rx) = watch::channel(false);
+        let handle = RdbHandle {
+            shared: Arc::new(Shared {
+                path: config.path,
+                last_save: AtomicU64::new(unix_time()),
+                saved_seq: AtomicU64::new(db.write_seq()),
+                saving_tx,
+                saving_rx,
+            }),
+        };
+
+        db.set_rdb(Some(handle.clone()));
+
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let task = tokio::spawn(save_points_task(
+            db.clone(),
+            handle.clone(),
+            config.save_points.clone(),
+            stop_rx,
+        ));
+
+        Ok(Rdb {
+            handle,
+            save_points: config.save_points,
+            stop_tx,
+            task,
+        })
+    }
+
+    /// Detiene la tarea. Como hace Redis, si hay guardado automatico se
+    /// guarda un ultimo snapshot antes de terminar.
+    pub(crate) async fn shutdown(self, db: &Db) {
+        let _ = self.stop_tx.send(());
+        let _ = self.task.await;
+
+        // Se espera a que termine un posible `BGSAVE` en curso.
+        let mut saving_rx = self.handle.shared.saving_rx.clone();
+        while *saving_rx.borrow() {
+            // `Shared` mantiene el `Sender`, asi que no puede fallar.
+            let _ = saving_rx.changed().await;
+        }
+
+        if !self.save_points.is_empty() {
+            if let Err(err) = self.handle.save(db).await {
+                error!(cause = %err, "failed to save the DB on shutdown");
+            }
+        }
+
+        db.set_rdb(None);
+    }
+}
+
+/// Comprueba cada segundo si se cumple alguna de las condiciones para
+/// guardar un snapshot.
+async fn save_points_task(
+    db: Db,
+    handle: RdbHandle,
+    save_points: Vec<SavePoint>,
+    mut stop_rx: oneshot::Receiver<()>,
+) {
+    if save_points.is_empty() {
+        let _ = stop_rx.await;
+        return;
+    }
+
+    let mut interval = time::interval(Duration::from_secs(1));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = &mut stop_rx => return,
+        }
+
+        let elapsed = unix_time().saturating_sub(handle.last_save());
+        let changes = db.write_seq() - handle.shared.saved_seq.load(Ordering::SeqCst);
+
+        let due = save_points
+            .iter()
+            .any(|point| elapsed >= point.seconds && changes >= point.changes && changes > 0);
+
+        if due {
+            info!(changes, elapsed, "save point reached, saving");
+            let _ = handle.bgsave(&db);
+        }
+    }
+}
+
+/// Carga el snapshot en la `Db`. Si el fichero no existe la base de datos
+/// se queda vacia. Las claves que ya han expirado se descartan.
+pub(crate) async fn load(db: &Db, path: &Path) -> crate::Result<()> {
+    let data = match tokio::fs::read(path).await {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let entries = decode(&data)?;
+    let now = SystemTime::now();
+    let mut keys = 0;
+
+    for entry in entries {
+        let expire = match entry.expires_at {
+            Some(when) => match when.duration_since(now) {
+                Ok(expire) => Some(expire),
+                // Ya ha expirado.
+                Err(_) => continue,
+            },
+            None => None,
+        };
+
+        db.set(entry.key, entry.value, expire);
+        keys += 1;
+    }
+
+    info!(keys, ?path, "DB loaded from disk");
+
+    Ok(())
+}
+
+/// Codifica las entradas con el formato de los snapshots.
+pub(crate) fn encode(entries: &[EntrySnapshot]) -> Vec<u8> {
+    let mut dst = Vec::new();
+
+    dst.put_slice(MAGIC);
+    dst.put_u16(VERSION);
+
+    for entry in entries {
+        if let Some(when) = entry.expires_at {
+            dst.put_u8(OP_EXPIRE_MS);
+            dst.put_u64(unix_millis(when));
+        }
+
+        dst.put_u8(TYPE_STRING);
+        put_bytes(&mut dst, entry.key.as_bytes());
+        put_value(&mut dst, &entry.value);
+    }
+
+    dst.put_u8(OP_EOF);
+
+    let checksum = CRC32.checksum(&dst);
+    dst.put_u32(checksum);
+
+    dst
+}
+
+/// Decodifica un snapshot verificando su formato, su version y su checksum.
+/// En caso de error se indica el desplazamiento del fichero donde se ha
+/// detectado.
+pub fn decode(src: &[u8]) -> crate::Result<Vec<EntrySnapshot>> {
+    let mut buf = src;
+
+    let bad = |buf: &[u8], msg: &str| {
+        Error::Protocol(format!(
+            "bad snapshot format at offset {}: {}",
+            src.len() - buf.len(),
+            msg
+        ))
+    };
+
+    if !buf.starts_with(MAGIC) {
+        return Err(bad(buf, "invalid header"));
+    }
+    buf.advance(MAGIC.len());
+
+    if buf.remaining() < 2 {
+        return Err(bad(buf, "unexpected end of file"));
+    }
+    let version = buf.get_u16();
+    if version > VERSION {
+        return Err(bad(buf, &format!("unsupported version {}", version)));
+    }
+
+    let mut entries = Vec::new();
+
+    loop {
+        if !buf.has_remaining() {
+            return Err(bad(buf, "unexpected end of file"));
+        }
+
+        let expires_at = match buf.get_u8() {
+            OP_EOF => break,
+            OP_EXPIRE_MS => {
+                if buf.remaining() < 9 {
+                    return Err(bad(buf, "unexpected end of file"));
+                }
+                let ms = buf.get_u64();
+                if buf.get_u8() != TYPE_STRING {
+                    return Err(bad(buf, "unknown value type"));
+                }
+                Some(UNIX_EPOCH + Duration::from_millis(ms))
+            }
+            TYPE_STRING => None,
+            _ => return Err(bad(buf, "unknown value type")),
+        };
+
+        let key = get_bytes(&mut buf).ok_or_else(|| bad(buf, "unexpected end of file"))?;
+        let key = String::from_utf8(key.to_vec()).map_err(|_| bad(buf, "invalid key"))?;
+        let value = get_value(&mut buf).ok_or_else(|| bad(buf, "unexpected end of file"))?;
+
+        entries.push(EntrySnapshot {
+            key,
+            value,
+            expires_at,
+        });
+    }
+
+    let len = src.len() - buf.len();
+
+    if buf.remaining() < 4 {
+        return Err(bad(buf, "unexpected end of file"));
+    }
+    if buf.get_u32() != CRC32.checksum(&src[..len]) {
+        return Err(bad(&src[len..], "checksum mismatch"));
+    }
+    if buf.has_remaining() {
+        return Err(bad(buf, "trailing data after the checksum"));
+    }
+
+    Ok(entries)
+}
+
+/// Serializa un valor con el formato de `DUMP`.
+pub(crate) fn dump_value(value: &[u8]) -> Bytes {
+    let mut dst = Vec::new();
+
+    dst.put_u8(TYPE_STRING);
+    put_value(&mut dst, value);
+    dst.put_u16(VERSION);
+
+    let checksum = CRC32.checksum(&dst);
+    dst.put_u32(checksum);
+
+    dst.into()
+}
+
+/// Deserializa un valor generado por `DUMP`. Retorna `None` si el formato
+/// no es valido, si la version no esta soportada o si el checksum no
+/// coincide.
+pub(crate) fn restore_value(payload: &[u8]) -> Option<Bytes> {
+    if payload.len() < 4 {
+        return None;
+    }
+
+    let (data, mut checksum) = payload.split_at(payload.len() - 4);
+    if checksum.get_u32() != CRC32.checksum(data) {
+        return None;
+    }
+
+    let mut buf = data;
+    if !buf.has_remaining() || buf.get_u8() != TYPE_STRING {
+        return None;
+    }
+
+    let value = get_value(&mut buf)?;
+
+    if buf.remaining() != 2 || buf.get_u16() > VERSION {
+        return None;
+    }
+
+    Some(value)
+}
+
+/// Escribe `data` en una ruta temporal y la renombra a `path`, de modo que
+/// el fichero nunca queda a medio escribir.
+fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let res = (|| {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+    })();
+
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+
+    res
+}
+
+fn put_bytes(dst: &mut Vec<u8>, src: &[u8]) {
+    dst.put_u32(src.len() as u32);
+    dst.put_slice(src);
+}
+
+/// Codifica un valor de tipo string. Es la parte comun de los snapshots y de
+/// `DUMP`.
+fn put_value(dst: &mut Vec<u8>, value: &[u8]) {
+    put_bytes(dst, value);
+}
+
+fn get_value(buf: &mut &[u8]) -> Option<Bytes> {
+    get_bytes(buf).map(Bytes::copy_from_slice)
+}
+
+fn get_bytes<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    if buf.remaining() < 4 {
+        return None;
+    }
+
+    let len = buf.get_u32() as usize;
+    if buf.remaining() < len {
+        return None;
+    }
+
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Some(bytes)
+}
+
+/// Segundos desde 'epoch'.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
+
+/// Milisegundos desde 'epoch' hasta `when`.
+fn unix_millis(when: SystemTime) -> u64 {
+    when.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,588 @@
+//! Replicacion master-replica.
+//!
+//! Una replica se conecta al master, autenticandose con `masterauth` y
+//! cifrando la conexion si el servidor usa TLS (ver
+//! `Config::peer_options`), y le envia `PSYNC` con el identificador
+//! de replicacion y el offset hasta el que ya tiene los datos:
+//!
+//! * Si el master no puede continuar desde ese offset responde
+//!   `+FULLRESYNC <replid> <offset>` seguido de un snapshot (ver `rdb`) con
+//!   todas las claves. La replica descarta sus datos y carga el snapshot.
+//! * Si el identificador coincide y el offset sigue en el backlog responde
+//!   `+CONTINUE <replid>` seguido de las escrituras que le faltan.
+//!
+//! A partir de ahi el master le envia cada escritura tal y como se difunde
+//! desde la `Db` (ver `Db::watch_writes`). El offset de replicacion es el
+//! numero de bytes enviados, de modo que una replica que se reconecta sabe
+//! exactamente desde donde continuar.
+//!
+//! El backlog guarda las ultimas escrituras difundidas. Se crea cuando se
+//! conecta la primera replica, igual que en Redis.
+//!
+//! La replica confirma cada segundo el offset que ha procesado con
+//! `REPLCONF ACK <offset>`, y tambien cuando el master se lo pide con
+//! `REPLCONF GETACK *`. `WAIT` utiliza estas confirmaciones.
+//!
+//! Antes de `PSYNC` la replica anuncia el puerto en el que escucha con
+//! `REPLCONF listening-port <port>`. Junto con la IP de la conexion es la
+//! direccion de la replica que muestra `ROLE`.
+
+use crate::cmd::Command;
+use crate::db::{Db, EntrySnapshot};
+use crate::{client, rdb, Connection, Error, Frame};
+
+use bytes::Bytes;
+use rand::Rng;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::net;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use tracing::{info, warn};
+
+/// Tamaño maximo del backlog en bytes.
+pub(crate) const BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// Tiempo de espera antes de reconectar con el master.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Cada cuanto confirma una replica el offset que ha procesado.
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Ultimas escrituras difundidas, junto con el offset en el que empieza
+/// cada una de ellas.
+#[derive(Debug)]
+pub(crate) struct Backlog {
+    /// Offset de replicacion: bytes difundidos desde que se creo el backlog.
+    offset: u64,
+
+    /// Escrituras guardadas y el offset en el que empieza cada una.
+    frames: VecDeque<(u64, Frame)>,
+
+    /// Bytes ocupados por `frames`.
+    size: usize,
+}
+
+/// Replicas conectadas a este master y el offset que ha confirmado cada una
+/// con `REPLCONF ACK`.
+///
+/// Es un handle que se puede clonar: todos los clones comparten el estado.
+#[derive(Debug, Clone)]
+pub(crate) struct Replicas {
+    shared: Arc<ReplicasShared>,
+}
+
+#[derive(Debug)]
+struct ReplicasShared {
+    /// Cada replica con su identificador, e identificador de la proxima.
+    acks: Mutex<(HashMap<u64, ReplicaInfo>, u64)>,
+
+    /// Se notifica cada vez que una replica confirma un offset.
+    acked_tx: watch::Sender<()>,
+    acked_rx: watch::Receiver<()>,
+
+    /// Se notifica para pedir a las replicas que confirmen su offset.
+    getack_tx: watch::Sender<()>,
+    getack_rx: watch::Receiver<()>,
+}
+
+/// Una replica conectada.
+#[derive(Debug)]
+struct ReplicaInfo {
+    /// Direccion en la que escucha la replica, si la ha anunciado.
+    addr: Option<(String, u16)>,
+
+    /// Offset confirmado con `REPLCONF ACK`.
+    offset: u64,
+}
+
+/// Rol de la instancia en la replicacion, tal y como lo muestra `ROLE`.
+#[derive(Debug)]
+pub(crate) enum Role {
+    /// Master, con su offset de replicacion y la direccion y el offset
+    /// confirmado de cada replica que ha anunciado su direccion.
+    Master {
+        offset: u64,
+        replicas: Vec<(String, u16, u64)>,
+    },
+
+    /// Replica de `host:port`. `connected` indica si la conexion con el
+    /// master esta establecida y `offset` es el ultimo offset procesado.
+    Replica {
+        host: String,
+        port: u16,
+        connected: bool,
+        offset: u64,
+    },
+}
+
+/// Respuesta del master a un `PSYNC`.
+#[derive(Debug)]
+pub(crate) enum Sync {
+    /// Sincronizacion completa: la replica recibe una copia de todas las
+    /// entradas, que corresponde al offset indicado.
+    Full {
+        replid: String,
+        offset: u64,
+        entries: Vec<EntrySnapshot>,
+    },
+
+    /// Sincronizacion parcial: la replica solo recibe las escrituras que
+    /// le faltan.
+    Partial { replid: String, frames: Vec<Frame> },
+}
+
+/// Conexion de una replica con su master.
+///
+/// La tarea se reconecta mientras no se pare con `stop`.
+#[derive(Debug)]
+pub(crate) struct MasterLink {
+    host: String,
+    port: u16,
+    status: Arc<Mutex<LinkStatus>>,
+    stop_tx: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+/// Estado de la conexion con el master, compartido con la tarea de la
+/// replica.
+#[derive(Debug, Default)]
+struct LinkStatus {
+    connected: bool,
+    offset: u64,
+}
+
+/// Estado de la replica respecto a su master.
+#[derive(Debug)]
+struct MasterState {
+    replid: String,
+    offset: u64,
+}
+
+impl Backlog {
+    pub(crate) fn new() -> Backlog {
+        Backlog {
+            offset: 0,
+            frames: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    /// Offset de replicacion actual.
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Añade una escritura y descarta las mas antiguas si se supera el
+    /// tamaño maximo.
+    pub(crate) fn push(&mut self, frame: Frame) {
+        let len = encoded_len(&frame);
+
+        self.frames.push_back((self.offset, frame));
+        self.offset += len as u64;
+        self.size += len;
+
+        while self.size > BACKLOG_SIZE {
+            match self.frames.pop_front() {
+                Some((_, frame)) => self.size -= encoded_len(&frame),
+                None => break,
+            }
+        }
+    }
+
+    /// Escrituras difundidas a partir de `offset`, o `None` si ya no estan
+    /// en el backlog.
+    pub(crate) fn since(&self, offset: u64) -> Option<Vec<Frame>> {
+        if offset == self.offset {
+            return Some(vec![]);
+        }
+
+        let start = self.frames.iter().position(|(start, _)| *start == offset)?;
+
+        Some(
+            self.frames
+                .range(start..)
+                .map(|(_, frame)| frame.clone())
+                .collect(),
+        )
+    }
+}
+
+impl Replicas {
+    pub(crate) fn new() -> Replicas {
+        let (acked_tx, acked_rx) = watch::channel(());
+        let (getack_tx, getack_rx) = watch::channel(());
+
+        Replicas {
+            shared: Arc::new(ReplicasShared {
+                acks: Mutex::new((HashMap::new(), 0)),
+                acked_tx,
+                acked_rx,
+                getack_tx,
+                getack_rx,
+            }),
+        }
+    }
+
+    /// Registra una replica que escucha en `addr`. Retorna su
+    /// identificador.
+    pub(crate) fn add(&self, addr: Option<(String, u16)>) -> u64 {
+        let mut acks = self.shared.acks.lock().unwrap();
+        let (acks, next_id) = &mut *acks;
+
+        let id = *next_id;
+        *next_id += 1;
+        acks.insert(id, ReplicaInfo { addr, offset: 0 });
+        id
+    }
+
+    pub(crate) fn remove(&self, id: u64) {
+        self.shared.acks.lock().unwrap().0.remove(&id);
+    }
+
+    /// Guarda el offset confirmado por una replica y despierta a los
+    /// clientes que estan esperando en `WAIT`.
+    pub(crate) fn ack(&self, id: u64, offset: u64) {
+        let mut acks = self.shared.acks.lock().unwrap();
+
+        if let Some(replica) = acks.0.get_mut(&id) {
+            replica.offset = offset.max(replica.offset);
+            let _ = self.shared.acked_tx.send(());
+        }
+    }
+
+    /// Numero de replicas que han confirmado al menos `offset`.
+    pub(crate) fn acked(&self, offset: u64) -> usize {
+        let acks = self.shared.acks.lock().unwrap();
+        acks.0
+            .values()
+            .filter(|replica| replica.offset >= offset)
+            .count()
+    }
+
+    /// Direccion y offset confirmado de las replicas que han anunciado su
+    /// direccion.
+    pub(crate) fn list(&self) -> Vec<(String, u16, u64)> {
+        let acks = self.shared.acks.lock().unwrap();
+
+        let mut replicas: Vec<_> = acks
+            .0
+            .values()
+            .filter_map(|replica| {
+                let (host, port) = replica.addr.clone()?;
+                Some((host, port, replica.offset))
+            })
+            .collect();
+
+        replicas.sort();
+        replicas
+    }
+
+    /// Receptor que se notifica con cada confirmacion.
+    pub(crate) fn watch_acks(&self) -> watch::Receiver<()> {
+        self.shared.acked_rx.clone()
+    }
+
+    /// Pide a todas las replicas que confirmen su offset.
+    pub(crate) fn request_acks(&self) {
+        let _ = self.shared.getack_tx.send(());
+    }
+
+    /// Receptor que se notifica cuando hay que pedir la confirmacion.
+    pub(crate) fn watch_getack(&self) -> watch::Receiver<()> {
+        self.shared.getack_rx.clone()
+    }
+}
+
+impl MasterLink {
+    /// Master al que esta conectada la replica.
+    pub(crate) fn master(&self) -> (&str, u16) {
+        (&self.host, self.port)
+    }
+
+    /// Rol de la replica: su master y el estado de la conexion.
+    pub(crate) fn role(&self) -> Role {
+        let status = self.status.lock().unwrap();
+
+        Role::Replica {
+            host: self.host.clone(),
+            port: self.port,
+            connected: status.connected,
+            offset: status.offset,
+        }
+    }
+
+    /// Para la replicacion y espera a que la tarea termine.
+    pub(crate) async fn stop(self) {
+        let _ = self.stop_tx.send(());
+        let _ = self.task.await;
+    }
+}
+
+/// Genera un identificador de replicacion: 40 caracteres hexadecimales
+/// aleatorios.
+pub(crate) fn new_replid() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40)
+        .map(|_| char::from_digit(rng.gen_range(0..16), 16).unwrap())
+        .collect()
+}
+
+/// Convierte la `Db` en replica de `master`, o en master si es `None`.
+///
+/// La replicacion anterior, si la habia, se para. Los datos se mantienen
+/// hasta que el nuevo master envie los suyos. Si ya era replica de `master`
+/// no se hace nada y se retorna `false`, como hace Redis: reiniciar la
+/// conexion forzaria una nueva sincronizacion.
+pub(crate) async fn replicaof(db: &Db, master: Option<(String, u16)>) -> bool {
+    if let (Some((host, port)), Some(current)) = (&master, db.master_addr()) {
+        if same_instance((host, *port), (&current.0, current.1)).await {
+            return false;
+        }
+    }
+
+    let link = master.map(|(host, port)| {
+        let status = Arc::new(Mutex::new(LinkStatus::default()));
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let task = tokio::spawn(run_replica(
+            db.clone(),
+            host.clone(),
+            port,
+            status.clone(),
+            stop_rx,
+        ));
+
+        MasterLink {
+            host,
+            port,
+            status,
+            stop_tx,
+            task,
+        }
+    });
+
+    if let Some(prev) = db.set_master(link) {
+        prev.stop().await;
+    }
+
+    true
+}
+
+/// Retorna `true` si las dos direcciones corresponden a la misma instancia:
+/// tienen el mismo puerto y el mismo host, o hosts que resuelven a alguna
+/// direccion comun (como `localhost` y `127.0.0.1`).
+pub(crate) async fn same_instance(a: (&str, u16), b: (&str, u16)) -> bool {
+    if a.1 != b.1 {
+        return false;
+    }
+    if a.0.eq_ignore_ascii_case(b.0) {
+        return true;
+    }
+
+    // Un host que no se puede resolver no coincide con ningun otro.
+    let (a, b) = match tokio::join!(net::lookup_host(a), net::lookup_host(b)) {
+        (Ok(a), Ok(b)) => (a.collect::<Vec<_>>(), b.collect::<Vec<_>>()),
+        _ => return false,
+    };
+
+    a.iter().any(|addr| b.contains(addr))
+}
+
+/// Tarea de la replica. Se sincroniza con el master y aplica las
+/// escrituras recibidas, reconectando cuando se pierde la conexion.
+async fn run_replica(
+    db: Db,
+    host: String,
+    port: u16,
+    status: Arc<Mutex<LinkStatus>>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let mut master = None;
+
+    loop {
+        tokio::select! {
+            res = sync(&db, &host, port, &status, &mut master) => {
+                if let Err(err) = res {
+                    warn!(cause = %err, %host, port, "replication link lost");
+                }
+            }
+            _ = &mut stop => return,
+        }
+
+        status.lock().unwrap().connected = false;
+
+        tokio::select! {
+            _ = time::sleep(RECONNECT_DELAY) => {}
+            _ = &mut stop => return,
+        }
+    }
+}
+
+/// Se conecta al master, se sincroniza y aplica las escrituras recibidas
+/// hasta que se pierde la conexion.
+async fn sync(
+    db: &Db,
+    host: &str,
+    port: u16,
+    status: &Mutex<LinkStatus>,
+    master: &mut Option<MasterState>,
+) -> crate::Result<()> {
+    let options = db.config().peer_options()?;
+    let mut connection = client::connect_peer(host, port, &options)
+        .await?
+        .into_connection();
+
+    command(&mut connection, &["ping"]).await?;
+    if let Some(listening_port) = db.listening_port() {
+        let listening_port = listening_port.to_string();
+        command(
+            &mut connection,
+            &["replconf", "listening-port", &listening_port],
+        )
+        .await?;
+    }
+    command(&mut connection, &["replconf", "capa", "psync2"]).await?;
+
+    // Con `?` y `-1` se pide una sincronizacion completa. En otro caso se
+    // pide el siguiente byte al ultimo recibido.
+    let (replid, offset) = match master {
+        Some(master) => (master.replid.clone(), (master.offset + 1).to_string()),
+        None => ("?".to_string(), "-1".to_string()),
+    };
+
+    let reply = match command(&mut connection, &["psync", &replid, &offset]).await? {
+        Frame::Simple(reply) => reply,
+        frame => return Err(frame.to_error()),
+    };
+
+    let mut parts = reply.split(' ');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("FULLRESYNC"), Some(replid), Some(offset)) => {
+            let offset = offset
+                .parse()
+                .map_err(|_| Error::Protocol(format!("invalid PSYNC reply: {}", reply)))?;
+
+            let entries = match connection.read_frame().await? {
+                Some(Frame::Bulk(data)) => rdb::decode(&data)?,
+                Some(frame) => return Err(frame.to_error()),
+                None => return Err(Error::ConnectionClosed),
+            };
+
+            info!(keys = entries.len(), %replid, offset, "full sync with master");
+
+            db.replace_all(entries);
+
+            *master = Some(MasterState {
+                replid: replid.to_string(),
+                offset,
+            });
+        }
+        (Some("CONTINUE"), replid, None) => {
+            let master = master
+                .as_mut()
+                .ok_or_else(|| Error::Protocol("unexpected CONTINUE reply".into()))?;
+
+            if let Some(replid) = replid {
+                master.replid = replid.to_string();
+            }
+
+            info!(replid = %master.replid, offset = master.offset, "partial sync with master");
+        }
+        _ => return Err(Error::Protocol(format!("invalid PSYNC reply: {}", reply))),
+    }
+
+    let master = master.as_mut().unwrap();
+
+    *status.lock().unwrap() = LinkStatus {
+        connected: true,
+        offset: master.offset,
+    };
+
+    // El offset procesado se confirma periodicamente y cada vez que el
+    // master lo pide.
+    let mut ack = time::interval(ACK_INTERVAL);
+
+    loop {
+        let frame = tokio::select! {
+            res = connection.read_frame() => match res? {
+                Some(frame) => frame,
+                None => return Err(Error::ConnectionClosed),
+            },
+            _ = ack.tick() => {
+                send_ack(&mut connection, master.offset).await?;
+                continue;
+            }
+        };
+
+        // `REPLCONF GETACK` se envia fuera del flujo de escrituras, asi que
+        // no cuenta para el offset.
+        if is_getack(&frame) {
+            send_ack(&mut connection, master.offset).await?;
+            continue;
+        }
+
+        master.offset += encoded_len(&frame) as u64;
+        status.lock().unwrap().offset = master.offset;
+
+        match Command::from_frame(frame) {
+            Ok(cmd) if cmd.is_write() => {
+                if let Err(err) = cmd.apply_write(db) {
+                    warn!(%err, "failed to apply replicated write");
+                }
+            }
+            Ok(_) => {}
+            Err(err) => warn!(%err, "invalid replicated command"),
+        }
+    }
+}
+
+/// Envia un comando al master y retorna su respuesta.
+async fn command(connection: &mut Connection, args: &[&str]) -> crate::Result<Frame> {
+    connection.write_frame(&request(args)).await?;
+
+    match connection.read_frame().await? {
+        Some(frame) => client::check_response(frame),
+        None => Err(Error::ConnectionClosed),
+    }
+}
+
+/// Confirma al master el offset procesado. El master no responde.
+async fn send_ack(connection: &mut Connection, offset: u64) -> crate::Result<()> {
+    let frame = request(&["replconf", "ack", &offset.to_string()]);
+    connection.write_frame(&frame).await?;
+    Ok(())
+}
+
+/// Retorna `true` si la trama es un `REPLCONF GETACK`.
+fn is_getack(frame: &Frame) -> bool {
+    let is = |frame: &Frame, name: &str| match frame {
+        Frame::Bulk(bytes) => bytes.eq_ignore_ascii_case(name.as_bytes()),
+        _ => false,
+    };
+
+    match frame {
+        Frame::Array(parts) if parts.len() >= 2 => {
+            is(&parts[0], "replconf") && is(&parts[1], "getack")
+        }
+        _ => false,
+    }
+}
+
+/// Trama de un comando con los argumentos indicados.
+pub(crate) fn request(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+            .collect(),
+    )
+}
+
+/// Bytes que ocupa la trama codificada. Es lo que avanza el offset de
+/// replicacion.
+fn encoded_len(frame: &Frame) -> usize {
+    let mut buf = Vec::new();
+    frame.encode(&mut buf);
+    buf.len()
+}
@@ -0,0 +1,790 @@
+//! Sentinel: supervision de masters y failover automatico.
+//!
+//! Un sentinel vigila uno o varios masters (ver `replication`) y sus
+//! replicas, y si un master deja de responder promociona una de sus replicas
+//! y reconfigura las demas para que la sigan. Varios sentinels vigilan el
+//! mismo master para que la decision no dependa de uno solo:
+//!
+//! 1. Cada sentinel envia `ROLE` al master periodicamente. Ademas de
+//!    comprobar que responde, asi conoce sus replicas.
+//! 2. Si el master no responde durante `down_after` el sentinel lo considera
+//!    caido (*subjectively down*) y pregunta a los demas sentinels con
+//!    `SENTINEL IS-MASTER-DOWN-BY-ADDR`. Si al menos `quorum` sentinels lo
+//!    consideran caido, el master esta caido (*objectively down*).
+//! 3. El sentinel inicia una nueva epoca y pide a los demas que le voten como
+//!    lider, con el mismo comando. Cada sentinel vota al primero que se lo
+//!    pide en cada epoca. Con los votos de la mayoria de los sentinels (y al
+//!    menos `quorum`) el lider hace el failover: envia `REPLICAOF NO ONE` a
+//!    la replica con mayor offset de replicacion y `REPLICAOF` a las demas.
+//! 4. El resto de sentinels detectan la replica promocionada al comprobar las
+//!    replicas del master caido. Cuando el antiguo master vuelve, se
+//!    reconfigura como replica del nuevo.
+//!
+//! Un sentinel que ha intentado un failover, o que ha votado a otro lider,
+//! no vuelve a intentarlo hasta que pasa `failover_timeout`.
+//!
+//! Los clientes obtienen la direccion del master actual con `SENTINEL
+//! GET-MASTER-ADDR-BY-NAME`.
+
+use crate::client::{self, Client, Credentials, PeerOptions, TlsOptions};
+use crate::cmd::Role;
+use crate::replication::{request, same_instance};
+use crate::{Connection, Error, Frame, Parse, ServerError, Shutdown};
+
+use bytes::Bytes;
+use rand::Rng;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::{self, Duration, Instant};
+use tracing::{debug, error, info, instrument, warn};
+
+/// Puerto por defecto de un sentinel.
+pub const DEFAULT_SENTINEL_PORT: u16 = 26379;
+
+/// Configuracion de un sentinel.
+#[derive(Clone, Debug)]
+pub struct SentinelConfig {
+    /// Masters que se vigilan.
+    pub masters: Vec<MasterConfig>,
+
+    /// Direcciones de los demas sentinels que vigilan los mismos masters.
+    pub sentinels: Vec<(String, u16)>,
+
+    /// Tiempo sin respuesta tras el que un master se considera caido.
+    pub down_after: Duration,
+
+    /// Tiempo minimo entre dos intentos de failover del mismo master.
+    pub failover_timeout: Duration,
+
+    /// Credenciales con las que se autentican las conexiones con los
+    /// masters y sus replicas. Las conexiones con los demas sentinels no se
+    /// autentican.
+    pub auth: Option<Credentials>,
+
+    /// Cifrado TLS de las conexiones con los masters y sus replicas. El
+    /// certificado de cada instancia se verifica con su host, de modo que
+    /// `server_name` no se utiliza.
+    pub tls: Option<TlsOptions>,
+}
+
+/// Un master vigilado.
+#[derive(Clone, Debug)]
+pub struct MasterConfig {
+    /// Nombre con el que los clientes se refieren al master.
+    pub name: String,
+
+    /// Direccion del master.
+    pub host: String,
+    pub port: u16,
+
+    /// Numero de sentinels que tienen que considerar caido el master para
+    /// hacer el failover.
+    pub quorum: usize,
+}
+
+/// Estado compartido por las tareas del sentinel.
+#[derive(Debug)]
+struct Sentinel {
+    /// Identificador de este sentinel. Es el que se vota como lider.
+    myid: String,
+
+    /// Los demas sentinels.
+    peers: Vec<(String, u16)>,
+
+    down_after: Duration,
+    failover_timeout: Duration,
+
+    /// Como se conecta con los masters y sus replicas.
+    instances: PeerOptions,
+
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Epoca actual. Cada eleccion de lider se hace en una epoca nueva.
+    current_epoch: u64,
+
+    masters: Vec<Master>,
+}
+
+/// Estado de un master vigilado.
+#[derive(Debug)]
+struct Master {
+    name: String,
+
+    /// Direccion actual del master.
+    addr: (String, u16),
+
+    quorum: usize,
+
+    /// Replicas conocidas. Incluye los antiguos masters.
+    replicas: Vec<(String, u16)>,
+
+    /// Instante en el que el master respondio por ultima vez.
+    last_ok: Instant,
+
+    /// Lider votado en la ultima eleccion y su epoca.
+    leader: Option<(String, u64)>,
+
+    /// Instante del ultimo failover intentado, propio o del lider votado.
+    failover_at: Option<Instant>,
+}
+
+/// Rol de una instancia segun su respuesta a `ROLE`.
+#[derive(Debug)]
+enum RoleReply {
+    Master { replicas: Vec<(String, u16)> },
+    Replica { master: (String, u16), offset: u64 },
+}
+
+/// Conexiones con los servidores y los demas sentinels. Una conexion que
+/// falla se descarta y se vuelve a abrir en la siguiente peticion.
+struct Links {
+    clients: HashMap<(String, u16), Client>,
+    timeout: Duration,
+
+    /// Como se conecta con los servidores. Las conexiones con los sentinels
+    /// no se autentican ni se cifran.
+    instances: PeerOptions,
+
+    /// Los demas sentinels.
+    peers: Vec<(String, u16)>,
+}
+
+impl Default for SentinelConfig {
+    fn default() -> SentinelConfig {
+        SentinelConfig {
+            masters: vec![],
+            sentinels: vec![],
+            down_after: Duration::from_secs(30),
+            failover_timeout: Duration::from_secs(180),
+            auth: None,
+            tls: None,
+        }
+    }
+}
+
+/// Ejecuta un sentinel.
+///
+/// Vigila los masters de `config` y acepta conexiones de clientes y de los
+/// demas sentinels en `listener`, hasta que el future `shutdown` se
+/// completa.
+pub async fn run(
+    listener: TcpListener,
+    config: SentinelConfig,
+    shutdown: impl Future,
+) -> crate::Result<()> {
+    let now = Instant::now();
+
+    let masters = config
+        .masters
+        .iter()
+        .map(|master| Master {
+            name: master.name.clone(),
+            addr: (master.host.clone(), master.port),
+            quorum: master.quorum,
+            replicas: vec![],
+            last_ok: now,
+            leader: None,
+            failover_at: None,
+        })
+        .collect();
+
+    let sentinel = Arc::new(Sentinel {
+        myid: crate::replication::new_replid(),
+        peers: config.sentinels,
+        down_after: config.down_after,
+        failover_timeout: config.failover_timeout,
+        instances: PeerOptions {
+            credentials: config.auth,
+            tls: config.tls,
+        },
+        state: Mutex::new(State {
+            current_epoch: 0,
+            masters,
+        }),
+    });
+
+    info!(myid = %sentinel.myid, "sentinel started");
+
+    let monitors: Vec<_> = config
+        .masters
+        .into_iter()
+        .map(|master| tokio::spawn(monitor(sentinel.clone(), master.name)))
+        .collect();
+
+    let (notify_shutdown, _) = broadcast::channel(1);
+
+    tokio::select! {
+        res = accept(&listener, &sentinel, &notify_shutdown) => {
+            if let Err(err) = &res {
+                error!(cause = %err, "failed to accept");
+            }
+        }
+        _ = shutdown => {
+            info!("shutting down");
+        }
+    }
+
+    for monitor in monitors {
+        monitor.abort();
+    }
+    drop(notify_shutdown);
+
+    Ok(())
+}
+
+/// Acepta las conexiones entrantes. Cada una se atiende en su propia tarea.
+async fn accept(
+    listener: &TcpListener,
+    sentinel: &Arc<Sentinel>,
+    notify_shutdown: &broadcast::Sender<()>,
+) -> crate::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let sentinel = sentinel.clone();
+        let shutdown = Shutdown::new(notify_shutdown.subscribe());
+
+        tokio::spawn(async move {
+            if let Err(err) = handle(sentinel, socket, shutdown).await {
+                debug!(cause = %err, "connection error");
+            }
+        });
+    }
+}
+
+/// Atiende los comandos de una conexion.
+async fn handle(
+    sentinel: Arc<Sentinel>,
+    socket: TcpStream,
+    mut shutdown: Shutdown,
+) -> crate::Result<()> {
+    let mut connection = Connection::boxed(socket);
+
+    loop {
+        let frame = tokio::select! {
+            res = connection.read_frame() => match res? {
+                Some(frame) => frame,
+                None => return Ok(()),
+            },
+            _ = shutdown.recv() => return Ok(()),
+        };
+
+        let response = sentinel.command(frame).unwrap_or_else(Frame::from);
+        connection.write_frame(&response).await?;
+    }
+}
+
+/// Vigila un master. Se ejecuta hasta que el sentinel para.
+async fn monitor(sentinel: Arc<Sentinel>, name: String) {
+    let mut links = Links {
+        clients: HashMap::new(),
+        timeout: sentinel.ping_period(),
+        instances: sentinel.instances.clone(),
+        peers: sentinel.peers.clone(),
+    };
+    let mut interval = time::interval(sentinel.ping_period());
+
+    loop {
+        interval.tick().await;
+        check(&sentinel, &name, &mut links).await;
+    }
+}
+
+/// Comprueba el estado del master y hace el failover si es necesario.
+#[instrument(skip(sentinel, links))]
+async fn check(sentinel: &Sentinel, name: &str, links: &mut Links) {
+    let (addr, replicas) = sentinel.master(name);
+
+    if let Ok(RoleReply::Master { replicas: found }) = links.role(&addr).await {
+        sentinel.master_ok(name, found);
+        reconfigure_replicas(&addr, &replicas, links).await;
+        return;
+    }
+
+    if !sentinel.is_down(name) {
+        return;
+    }
+
+    // Puede que otro sentinel ya haya promocionado una replica.
+    for replica in &replicas {
+        if let Ok(RoleReply::Master { .. }) = links.role(replica).await {
+            info!(%name, host = %replica.0, port = replica.1, "replica promoted by another sentinel");
+            sentinel.switch_master(name, replica.clone());
+            return;
+        }
+    }
+
+    let down = sentinel
+        .ask_peers(links, &addr, sentinel.current_epoch(), "*")
+        .await
+        .into_iter()
+        .filter(|(down, _)| *down)
+        .count();
+    let quorum = sentinel.quorum(name);
+
+    if down + 1 < quorum {
+        debug!(%name, down = down + 1, quorum, "master subjectively down");
+        return;
+    }
+
+    // Una pausa aleatoria evita que todos los sentinels pidan el voto a la
+    // vez y ninguno consiga la mayoria.
+    let pause = rand::thread_rng().gen_range(Duration::ZERO..sentinel.ping_period());
+    time::sleep(pause).await;
+
+    let epoch = match sentinel.start_election(name) {
+        Some(epoch) => epoch,
+        None => return,
+    };
+
+    let votes = sentinel
+        .ask_peers(links, &addr, epoch, &sentinel.myid)
+        .await
+        .into_iter()
+        .filter(|(_, leader)| *leader == Some((sentinel.myid.clone(), epoch)))
+        .count()
+        + 1;
+    let sentinels = sentinel.peers.len() + 1;
+    let majority = sentinels / 2 + 1;
+
+    if votes < quorum.max(majority) {
+        info!(%name, epoch, votes, "failover election lost");
+        return;
+    }
+
+    info!(%name, epoch, votes, "elected leader, starting failover");
+    failover(sentinel, name, &addr, &replicas, links).await;
+}
+
+/// Promociona la replica con mayor offset y reconfigura las demas para que
+/// la sigan.
+async fn failover(
+    sentinel: &Sentinel,
+    name: &str,
+    old_master: &(String, u16),
+    replicas: &[(String, u16)],
+    links: &mut Links,
+) {
+    let mut best: Option<(&(String, u16), u64)> = None;
+
+    for replica in replicas {
+        if let Ok(RoleReply::Replica { offset, .. }) = links.role(replica).await {
+            if best.is_none_or(|(_, best)| offset > best) {
+                best = Some((replica, offset));
+            }
+        }
+    }
+
+    let promoted = match best {
+        Some((replica, _)) => replica.clone(),
+        None => {
+            warn!(%name, "no replica available for failover");
+            return;
+        }
+    };
+
+    if let Err(err) = links.request(&promoted, &["replicaof", "no", "one"]).await {
+        warn!(%name, cause = %err, "failed to promote replica");
+        return;
+    }
+
+    info!(%name, host = %promoted.0, port = promoted.1, "replica promoted to master");
+
+    let port = promoted.1.to_string();
+    for replica in replicas.iter().filter(|replica| **replica != promoted) {
+        if let Err(err) = links
+            .request(replica, &["replicaof", &promoted.0, &port])
+            .await
+        {
+            warn!(host = %replica.0, port = replica.1, cause = %err, "failed to reconfigure replica");
+        }
+    }
+
+    debug!(host = %old_master.0, port = old_master.1, "old master becomes a replica");
+    sentinel.switch_master(name, promoted);
+}
+
+/// Hace que las replicas que no siguen al master lo sigan. Es el caso de un
+/// antiguo master que vuelve tras un failover.
+async fn reconfigure_replicas(
+    master: &(String, u16),
+    replicas: &[(String, u16)],
+    links: &mut Links,
+) {
+    for replica in replicas {
+        let follows = match links.role(replica).await {
+            // El host puede estar escrito de otra forma (`localhost` y
+            // `127.0.0.1`); si no se comparasen las direcciones la replica
+            // se resincronizaria en cada comprobacion.
+            Ok(RoleReply::Replica {
+                master: following, ..
+            }) => same_instance((&following.0, following.1), (&master.0, master.1)).await,
+            Ok(RoleReply::Master { .. }) => false,
+            // Una replica que no responde se reconfigura cuando vuelva.
+            Err(_) => true,
+        };
+
+        if !follows {
+            info!(host = %replica.0, port = replica.1, "reconfiguring replica");
+            let port = master.1.to_string();
+            let _ = links
+                .request(replica, &["replicaof", &master.0, &port])
+                .await;
+        }
+    }
+}
+
+impl Sentinel {
+    /// Cada cuanto se comprueba el estado de los masters.
+    fn ping_period(&self) -> Duration {
+        (self.down_after / 4).clamp(Duration::from_millis(10), Duration::from_secs(1))
+    }
+
+    /// Direccion y replicas de un master.
+    fn master(&self, name: &str) -> ((String, u16), Vec<(String, u16)>) {
+        self.with_master(name, |master| {
+            (master.addr.clone(), master.replicas.clone())
+        })
+    }
+
+    fn quorum(&self, name: &str) -> usize {
+        self.with_master(name, |master| master.quorum)
+    }
+
+    fn current_epoch(&self) -> u64 {
+        self.state.lock().unwrap().current_epoch
+    }
+
+    /// El master ha respondido. Se añaden las replicas que no se conocian.
+    fn master_ok(&self, name: &str, replicas: Vec<(String, u16)>) {
+        self.with_master(name, |master| {
+            master.last_ok = Instant::now();
+
+            for replica in replicas {
+                if !master.replicas.contains(&replica) {
+                    master.replicas.push(replica);
+                }
+            }
+        })
+    }
+
+    /// Retorna `true` si el master lleva mas de `down_after` sin responder.
+    fn is_down(&self, name: &str) -> bool {
+        let down_after = self.down_after;
+        self.with_master(name, |master| master.last_ok.elapsed() > down_after)
+    }
+
+    /// El master pasa a ser `addr`. El anterior se mantiene como replica.
+    fn switch_master(&self, name: &str, addr: (String, u16)) {
+        self.with_master(name, |master| {
+            let old = std::mem::replace(&mut master.addr, addr);
+            master.replicas.retain(|replica| *replica != master.addr);
+            if !master.replicas.contains(&old) {
+                master.replicas.push(old);
+            }
+            master.last_ok = Instant::now();
+        })
+    }
+
+    /// Inicia una eleccion de lider en una nueva epoca, votandose a si
+    /// mismo. Retorna `None` si no se puede intentar el failover porque ya
+    /// se ha intentado o se ha votado a otro lider recientemente.
+    fn start_election(&self, name: &str) -> Option<u64> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let master = state
+            .masters
+            .iter_mut()
+            .find(|master| master.name == name)?;
+
+        if matches!(master.failover_at, Some(at) if at.elapsed() < self.failover_timeout) {
+            return None;
+        }
+
+        state.current_epoch += 1;
+        master.leader = Some((self.myid.clone(), state.current_epoch));
+        master.failover_at = Some(Instant::now());
+
+        Some(state.current_epoch)
+    }
+
+    /// Pregunta a los demas sentinels si el master en `addr` esta caido,
+    /// pidiendo su voto si `runid` no es `*`. Retorna la respuesta de cada
+    /// sentinel que ha respondido: si considera caido el master y el lider
+    /// que ha votado.
+    async fn ask_peers(
+        &self,
+        links: &mut Links,
+        addr: &(String, u16),
+        epoch: u64,
+        runid: &str,
+    ) -> Vec<(bool, Option<(String, u64)>)> {
+        let port = addr.1.to_string();
+        let epoch = epoch.to_string();
+        let args = [
+            "sentinel",
+            "is-master-down-by-addr",
+            &addr.0,
+            &port,
+            &epoch,
+            runid,
+        ];
+
+        let mut replies = vec![];
+
+        for peer in &self.peers {
+            match links.request(peer, &args).await {
+                Ok(Frame::Array(reply)) => match &reply[..] {
+                    [Frame::Integer(down), Frame::Bulk(leader), Frame::Integer(leader_epoch)] => {
+                        let leader = match &leader[..] {
+                            b"*" => None,
+                            leader => {
+                                Some((String::from_utf8_lossy(leader).into_owned(), *leader_epoch))
+                            }
+                        };
+                        replies.push((*down == 1, leader));
+                    }
+                    _ => warn!(?reply, "invalid IS-MASTER-DOWN-BY-ADDR reply"),
+                },
+                Ok(frame) => warn!(?frame, "invalid IS-MASTER-DOWN-BY-ADDR reply"),
+                Err(err) => {
+                    debug!(host = %peer.0, port = peer.1, cause = %err, "sentinel unavailable")
+                }
+            }
+        }
+
+        replies
+    }
+
+    /// Ejecuta un comando recibido de un cliente o de otro sentinel.
+    fn command(&self, frame: Frame) -> Result<Frame, ServerError> {
+        let mut parse = Parse::new(frame).map_err(ServerError::err)?;
+        let name = parse
+            .next_string()
+            .map_err(ServerError::err)?
+            .to_lowercase();
+
+        let response = match &name[..] {
+            "ping" => Frame::Simple("PONG".to_string()),
+            "sentinel" => self.sentinel_command(&mut parse)?,
+            _ => return Err(ServerError::err(format!("unknown command '{}'", name))),
+        };
+
+        parse.finish().map_err(|_| {
+            ServerError::err(format!("wrong number of arguments for '{}' command", name))
+        })?;
+
+        Ok(response)
+    }
+
+    /// Subcomandos de `SENTINEL`:
+    ///
+    /// ```text
+    /// SENTINEL GET-MASTER-ADDR-BY-NAME name
+    /// SENTINEL REPLICAS name
+    /// SENTINEL IS-MASTER-DOWN-BY-ADDR ip port epoch runid|*
+    /// SENTINEL MYID
+    /// ```
+    fn sentinel_command(&self, parse: &mut Parse) -> Result<Frame, ServerError> {
+        let subcommand = parse
+            .next_string()
+            .map_err(ServerError::err)?
+            .to_lowercase();
+        let mut next = || parse.next_string().map_err(ServerError::err);
+        let bulk = |value: &str| Frame::Bulk(Bytes::copy_from_slice(value.as_bytes()));
+
+        match &subcommand[..] {
+            "get-master-addr-by-name" => {
+                let name = next()?;
+                let state = self.state.lock().unwrap();
+
+                Ok(
+                    match state.masters.iter().find(|master| master.name == name) {
+                        Some(master) => Frame::Array(vec![
+                            bulk(&master.addr.0),
+                            bulk(&master.addr.1.to_string()),
+                        ]),
+                        None => Frame::Null,
+                    },
+                )
+            }
+            "replicas" | "slaves" => {
+                let name = next()?;
+                let replicas = self.replicas(&name)?;
+
+                let replicas = replicas
+                    .iter()
+                    .map(|(host, port)| Frame::Array(vec![bulk(host), bulk(&port.to_string())]))
+                    .collect();
+                Ok(Frame::Array(replicas))
+            }
+            "is-master-down-by-addr" => {
+                let host = next()?;
+                let port = next()?
+                    .parse()
+                    .map_err(|_| ServerError::err("invalid port"))?;
+                let epoch = next()?
+                    .parse()
+                    .map_err(|_| ServerError::err("invalid epoch"))?;
+                let runid = next()?;
+
+                let (down, leader) = self.vote((host, port), epoch, &runid);
+                let (leader, leader_epoch) = leader.unwrap_or_else(|| ("*".to_string(), 0));
+
+                Ok(Frame::Array(vec![
+                    Frame::Integer(down as u64),
+                    bulk(&leader),
+                    Frame::Integer(leader_epoch),
+                ]))
+            }
+            "myid" => Ok(bulk(&self.myid)),
+            _ => Err(ServerError::err(format!(
+                "Unknown sentinel subcommand '{}'",
+                subcommand
+            ))),
+        }
+    }
+
+    /// Responde a `IS-MASTER-DOWN-BY-ADDR`: si el master esta caido segun
+    /// este sentinel y el lider votado en la epoca mas reciente. Si `runid`
+    /// no es `*` se le vota, salvo que ya se haya votado en esa epoca.
+    fn vote(&self, addr: (String, u16), epoch: u64, runid: &str) -> (bool, Option<(String, u64)>) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let master = match state.masters.iter_mut().find(|master| master.addr == addr) {
+            Some(master) => master,
+            None => return (false, None),
+        };
+
+        let down = master.last_ok.elapsed() > self.down_after;
+
+        let voted = matches!(&master.leader, Some((_, voted)) if *voted >= epoch);
+        if runid != "*" && !voted && epoch >= state.current_epoch {
+            state.current_epoch = epoch;
+            master.leader = Some((runid.to_string(), epoch));
+
+            // El lider votado hace el failover; mientras tanto este
+            // sentinel no lo intenta.
+            if runid != self.myid {
+                master.failover_at = Some(Instant::now());
+            }
+
+            info!(name = %master.name, leader = %runid, epoch, "voted for failover leader");
+        }
+
+        (down, master.leader.clone())
+    }
+
+    /// Replicas conocidas del master `name`, que puede no existir.
+    fn replicas(&self, name: &str) -> Result<Vec<(String, u16)>, ServerError> {
+        let state = self.state.lock().unwrap();
+
+        match state.masters.iter().find(|master| master.name == name) {
+            Some(master) => Ok(master.replicas.clone()),
+            None => Err(ServerError::err("No such master with that name")),
+        }
+    }
+
+    fn with_master<T>(&self, name: &str, f: impl FnOnce(&mut Master) -> T) -> T {
+        let mut state = self.state.lock().unwrap();
+        let master = state
+            .masters
+            .iter_mut()
+            .find(|master| master.name == name)
+            .expect("monitored master");
+        f(master)
+    }
+}
+
+impl Links {
+    /// Envia un comando, dado por sus argumentos, a `addr` y retorna la
+    /// respuesta.
+    async fn request(&mut self, addr: &(String, u16), args: &[&str]) -> crate::Result<Frame> {
+        self.command(addr, request(args)).await
+    }
+
+    /// Envia un comando a `addr` y retorna la respuesta.
+    async fn command(&mut self, addr: &(String, u16), frame: Frame) -> crate::Result<Frame> {
+        let res = time::timeout(self.timeout, self.send(addr, frame))
+            .await
+            .unwrap_or(Err(Error::Timeout));
+
+        if let Err(err) = &res {
+            if !matches!(err, Error::Server(_)) {
+                self.clients.remove(addr);
+            }
+        }
+
+        res
+    }
+
+    async fn send(&mut self, addr: &(String, u16), frame: Frame) -> crate::Result<Frame> {
+        if !self.clients.contains_key(addr) {
+            let client = if self.peers.contains(addr) {
+                client::connect((addr.0.as_str(), addr.1)).await?
+            } else {
+                client::connect_peer(&addr.0, addr.1, &self.instances).await?
+            };
+            self.clients.insert(addr.clone(), client);
+        }
+
+        let client = self.clients.get_mut(addr).unwrap();
+        let mut pipeline = client.pipeline();
+        pipeline.command(frame);
+
+        match pipeline.execute().await?.pop() {
+            Some(response) => response,
+            None => Err(Error::ConnectionClosed),
+        }
+    }
+
+    /// Rol de la instancia en `addr`.
+    async fn role(&mut self, addr: &(String, u16)) -> crate::Result<RoleReply> {
+        let reply = self.command(addr, Role::new().into_frame()).await?;
+        parse_role(&reply)
+            .ok_or_else(|| Error::Protocol(format!("invalid ROLE reply: {:?}", reply)))
+    }
+}
+
+/// Decodifica la respuesta a `ROLE`.
+fn parse_role(frame: &Frame) -> Option<RoleReply> {
+    let parts = match frame {
+        Frame::Array(parts) => parts,
+        _ => return None,
+    };
+
+    let string = |frame: &Frame| match frame {
+        Frame::Bulk(value) => String::from_utf8(value.to_vec()).ok(),
+        _ => None,
+    };
+
+    match &parts[..] {
+        [role, _, Frame::Array(replicas)] if *role == "master" => {
+            let replicas = replicas
+                .iter()
+                .filter_map(|replica| match replica {
+                    Frame::Array(replica) if replica.len() >= 2 => {
+                        Some((string(&replica[0])?, string(&replica[1])?.parse().ok()?))
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            Some(RoleReply::Master { replicas })
+        }
+        [role, host, Frame::Integer(port), _, Frame::Integer(offset)] if *role == "slave" => {
+            Some(RoleReply::Replica {
+                master: (string(host)?, *port as u16),
+                offset: *offset,
+            })
+        }
+        _ => None,
+    }
+}
@@ -3,39 +3,97 @@
 //! Proporciona una funcion asincrona `run` que escucha las conexiones
 //! entrantes, proporcionandole a cada una de ellas una terea para
 //! su ejecucion.
+//!
+//! Para integrar el servidor en otra aplicacion, `Builder` permite indicar
+//! la `Db`, añadir comandos y pararlo desde el `ServerHandle` que retorna.
 
-use crate::{Command, Connection, Db, DbDropGuard, Shutdown};
+use crate::aof::{Aof, AofConfig};
+use crate::cluster::ClusterHandle;
+use crate::cmd::{CommandHandler, Commands};
+use crate::config::{Config, ConfigHandle};
+use crate::frame::Limits;
+use crate::rdb::{Rdb, RdbConfig};
+use crate::stats::CloseReason;
+use crate::tls::{self, TlsConfig};
+use crate::{
+    replication, Command, Connection, Db, DbDropGuard, Frame, ServerError, ServerErrorKind,
+    Session, Shutdown, Transport,
+};
 
-use std::future::Future;
+use socket2::{SockRef, TcpKeepalive};
+use std::fmt;
+use std::fs::{self, Permissions};
+use std::future::{self, Future};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{broadcast, mpsc, Semaphore};
+use std::task::Poll;
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::sync::{broadcast, mpsc, oneshot, Semaphore};
+use tokio::task::JoinHandle;
 use tokio::time::{self, Duration};
 use tracing::{debug, error, info, instrument};
 
+pub use crate::stats::ServerStats;
+
+/// Tiempo maximo para completar el handshake TLS de una conexion. Sin el,
+/// un cliente que no lo termina ocuparia una conexion para siempre. Si
+/// `timeout` es menor se utiliza ese (ver `handshake_timeout`).
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Numero maximo de conexiones rechazadas a las que se responde a la vez.
+const MAX_PENDING_REJECTIONS: usize = 64;
+
+/// Tiempo maximo para responder a una conexion rechazada, handshake TLS
+/// incluido.
+const REJECT_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// Estado del servidor de conexiones. Se creara en la llamada a `run`.
 /// Incluye un metodo `run`el cual se encarga de escuchar las conexiones entrantes
-/// por TCP y de la iniciar el proceso de para cada conexion.
+/// por TCP o por el socket Unix y de la iniciar el proceso de para cada conexion.
 #[derive(Debug)]
 struct Listener {
     /// Base de datos compartida.
     /// Contiene tanto el almacen key/value asi como los canales de difucion
     /// para el pub/sub.
-    db_holder: DbDropGuard,
+    db: Db,
 
-    /// TCP listener
-    listener: TcpListener,
+    /// Si la `Db` la ha creado el servidor, detiene su tarea de purga al
+    /// parar. Una `Db` proporcionada con `Builder::db` sigue funcionando.
+    _db_guard: Option<DbDropGuard>,
 
-    /// Limita el numero maximo de conexiones.
-    ///
-    /// Un `Semaphore' es utilizado para limitar el numero maximo
-    /// de conexiones. Antes de intentar una conexion, un permiso tiene
-    /// que ser adquirido por el semaforo. Si no hay un permiso disponible,
-    /// el listener esperara por uno.
+    /// Fichero AOF, si esta configurado. Al parar se registran las
+    /// escrituras pendientes.
+    aof: Option<Aof>,
+
+    /// Snapshot, si esta configurado. Al parar se guarda uno ultimo.
+    rdb: Option<Rdb>,
+
+    /// TCP and Unix domain socket listeners
+    listeners: Listeners,
+
+    /// Si no es `None`, las conexiones aceptadas se cifran con TLS antes de
+    /// leer ninguna peticion.
+    tls: Option<tls::Acceptor>,
+
+    /// Numero de conexiones abiertas.
     ///
-    /// Cuando los handlers completan el procesado de una conexion, el permiso
-    /// es retornado al semaforo.
-    limit_connections: Arc<Semaphore>,
+    /// Cada tarea de conexion tiene un `ConnectionPermit` que lo descuenta
+    /// cuando termina. Antes de atender una conexion nueva se compara con
+    /// `maxclients`, que se puede modificar con `CONFIG SET`; si se ha
+    /// alcanzado, la conexion se rechaza con un error en lugar de dejarla
+    /// esperando.
+    connections: Arc<AtomicUsize>,
+
+    /// Limita las conexiones rechazadas a las que se esta respondiendo. Si
+    /// no quedan permisos, las conexiones se cierran sin respuesta: el
+    /// rechazo no puede consumir mas recursos que los que protege
+    /// `maxclients`.
+    rejections: Arc<Semaphore>,
 
     /// Difunde una senyal de parada para todas las conexiones activas.
     notify_shutdown: broadcast::Sender<()>,
@@ -57,17 +115,38 @@ struct Listener {
     shutdown_complete_tx: mpsc::Sender<()>,
 }
 
+/// Sockets en los que el servidor acepta conexiones: TCP, Unix o ambos.
+///
+/// Se puede obtener directamente de un `TcpListener` o de un `UnixListener`
+/// con `into()` para escuchar solo en uno de ellos.
+#[derive(Debug, Default)]
+pub struct Listeners {
+    /// Listeners TCP, uno por cada direccion. Las conexiones se cifran con
+    /// TLS si esta configurado.
+    pub tcp: Vec<TcpListener>,
+
+    /// Listener del socket Unix. Sus conexiones nunca se cifran.
+    pub unix: Option<UnixListener>,
+}
+
+/// Conexion aceptada por alguno de los `Listeners`.
+enum Socket {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
 /// Per-connection handler. Reads requests from `connection` and applies the
 /// commands to `db`.
 #[derive(Debug)]
 struct Handler {
     db: Db,
 
-    /// The TCP connection decorated with the redis protocol encoder / decoder
-    /// implemented using a buffered `TcpStream`.
+    /// The connection decorated with the redis protocol encoder / decoder
+    /// implemented using a buffered transport.
     ///
-    /// When `Listener` receives an inbound connection, the `TcpStream` is
-    /// passed to `Connection::new`, which initializes the associated buffers.
+    /// When `Listener` receives an inbound connection, the `TcpStream`, or the
+    /// TLS stream wrapping it, is passed to `Connection::boxed`, which
+    /// initializes the associated buffers.
     /// `Connection` allows the handler to operate at the "frame" level and keep
     /// the byte level protocol parsing details encapsulated in `Connection`.
     connection: Connection,
@@ -82,101 +161,643 @@ struct Handler {
     /// which point the connection is terminated.
     shutdown: Shutdown,
 
+    /// Estado propio de la conexion que necesitan algunos comandos.
+    session: Session,
+
+    /// Comandos añadidos con `Builder::command`.
+    commands: Arc<Commands>,
+
     /// Not used directly. Instead, when `Handler` is dropped...?
     _shutdown_complete: mpsc::Sender<()>,
 }
 
-const MAX_CONNECTIONS: usize = 250;
+/// Configuracion del servidor.
+///
+/// Se puede construir directamente o a partir de un fichero con el formato
+/// de `redis.conf` (ver `config::Config`).
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Direcciones en las que escucha el servidor (ver `Listeners::bind`).
+    pub bind: Vec<IpAddr>,
+
+    /// Puerto TCP en el que escucha el servidor. Con el puerto 0 no se
+    /// escucha por TCP.
+    pub port: u16,
+
+    /// Socket Unix en el que escucha el servidor, ademas de por TCP.
+    pub unixsocket: Option<PathBuf>,
+
+    /// Permisos del socket Unix. Si es `None` se mantienen los que tiene al
+    /// crearse.
+    pub unixsocketperm: Option<u32>,
+
+    /// Numero maximo de clientes conectados a la vez. Cuando se alcanza, las
+    /// nuevas conexiones se cierran tras responderles con un error. Se puede
+    /// modificar con `CONFIG SET`.
+    pub maxclients: usize,
+
+    /// Tiempo de inactividad tras el que se cierra la conexion de un
+    /// cliente. Con `Duration::ZERO` no se cierran nunca. Los clientes
+    /// suscritos a canales o bloqueados en un comando como `WAIT` no se
+    /// consideran inactivos. Tambien limita el handshake TLS, que nunca
+    /// puede durar mas de 10 segundos.
+    pub timeout: Duration,
+
+    /// Intervalo de los keepalive TCP de las conexiones aceptadas, con los
+    /// que se detectan los clientes que han desaparecido sin cerrar la
+    /// conexion. Con `Duration::ZERO` no se envian.
+    pub tcp_keepalive: Duration,
+
+    /// Numero de bases de datos. Solo existe por compatibilidad con los
+    /// ficheros de configuracion de Redis: los comandos siempre operan sobre
+    /// la base de datos 0.
+    pub databases: usize,
+
+    /// Limites del protocolo que se aplican a cada conexion.
+    pub limits: Limits,
+
+    /// Fichero AOF. Si es `None` las escrituras no se registran.
+    pub appendonly: Option<AofConfig>,
+
+    /// Fichero de snapshot. Si es `None` no se pueden guardar snapshots.
+    pub snapshot: Option<RdbConfig>,
+
+    /// Master del que el servidor es replica (host y puerto). Si es `None`
+    /// el servidor arranca como master.
+    pub replicaof: Option<(String, u16)>,
+
+    /// Usuario con el que el servidor se autentica en los demas nodos: en
+    /// el master si es una replica y en los nodos del cluster. Si es `None`
+    /// se autentica como el usuario `default`.
+    pub masteruser: Option<String>,
+
+    /// Contraseña con la que el servidor se autentica en los demas nodos.
+    /// Si es `None` no se autentica.
+    pub masterauth: Option<String>,
+
+    /// Activa el modo cluster. El nodo arranca sin slots asignados; se
+    /// configuran con los comandos `CLUSTER` (ver `cluster`).
+    pub cluster_enabled: bool,
+
+    /// Contraseña que tienen que enviar los clientes con `AUTH` antes de
+    /// cualquier otro comando. Si es `None` no se requiere autenticacion.
+    ///
+    /// Es la contraseña del usuario `default` (ver `acl`).
+    pub requirepass: Option<String>,
+
+    /// Fichero ACL del que se cargan los usuarios al arrancar y con
+    /// `ACL LOAD`, y en el que se guardan con `ACL SAVE`.
+    pub aclfile: Option<PathBuf>,
+
+    /// Cifrado TLS de las conexiones. Si es `None` las conexiones se
+    /// aceptan sin cifrar.
+    ///
+    /// Con TLS las conexiones con los demas nodos tambien se cifran: el
+    /// servidor presenta su certificado y verifica el del otro nodo con las
+    /// CA de `ca_cert_file`, que en ese caso es obligatorio.
+    pub tls: Option<TlsConfig>,
+
+    /// Fichero del que se ha cargado la configuracion. `CONFIG REWRITE`
+    /// guarda en el la configuracion actual.
+    pub config_file: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    /// Escucha en 127.0.0.1 en el puerto por defecto, sin persistencia ni
+    /// autenticacion.
+    fn default() -> ServerConfig {
+        ServerConfig {
+            bind: vec![Ipv4Addr::LOCALHOST.into()],
+            port: crate::DEFAULT_PORT,
+            unixsocket: None,
+            unixsocketperm: None,
+            maxclients: 10000,
+            timeout: Duration::ZERO,
+            tcp_keepalive: Duration::from_secs(300),
+            databases: 16,
+            limits: Limits::default(),
+            appendonly: None,
+            snapshot: None,
+            replicaof: None,
+            masteruser: None,
+            masterauth: None,
+            cluster_enabled: false,
+            requirepass: None,
+            aclfile: None,
+            tls: None,
+            config_file: None,
+        }
+    }
+}
 
 /// Ejecuta el servidor mini-redis.
 ///
-/// Acepta conexiones desde el listener proporcionado. Para cada conexion
-/// entrante se iniciara una tarea.
+/// Acepta conexiones desde el listener proporcionado, que puede ser un
+/// `TcpListener`, un `UnixListener` o ambos (ver `Listeners`). Para cada
+/// conexion entrante se iniciara una tarea.
 ///
 /// El servidor se ejecutara hasta que el future `shutdown` se complete.
 ///
 /// La senyal `tokio::signal::ctrl_c()` puede ser utilizada para iniciar la
 /// parada ordenada.
-pub async fn run(listener: TcpListener, shutdown: impl Future) {
-    // When the provided `shutdown` future completes, we must send a shutdown
-    // message to all active connections. We use a broadcast channel for this
-    // purpose. The call below ignores the receiver of the broadcast pair, and when
-    // a receiver is needed, the subscribe() method on the sender is used to create
-    // one.
+pub async fn run(listener: impl Into<Listeners>, shutdown: impl Future) {
+    run_with_limits(listener, Limits::default(), shutdown).await
+}
+
+/// Igual que `run` pero aplicando a cada conexion los limites del
+/// protocolo indicados en `limits`.
+///
+/// Cuando un cliente los excede se le responde con un error `-ERR` y se
+/// cierra su conexion.
+pub async fn run_with_limits(
+    listener: impl Into<Listeners>,
+    limits: Limits,
+    shutdown: impl Future,
+) {
+    let config = ServerConfig {
+        limits,
+        ..ServerConfig::default()
+    };
+
+    // Sin persistencia no hay nada que pueda fallar al arrancar.
+    let _ = run_with_config(listener, config, shutdown).await;
+}
+
+/// Igual que `run` pero con la configuracion indicada en `config`.
+///
+/// Antes de aceptar ninguna conexion se cargan los datos persistentes: del
+/// fichero AOF si esta configurado, ya que es el mas completo, o si no del
+/// snapshot. En caso de no poder cargarlos se retorna el error sin arrancar
+/// el servidor. Al parar el servidor se espera a que las escrituras
+/// pendientes se registren en el fichero AOF y, si hay guardado automatico,
+/// se guarda un ultimo snapshot.
+pub async fn run_with_config(
+    listener: impl Into<Listeners>,
+    config: ServerConfig,
+    shutdown: impl Future,
+) -> crate::Result<()> {
+    let server = Listener::new(listener.into(), config, None, Commands::new()).await?;
+    server.serve(shutdown).await;
+
+    Ok(())
+}
+
+/// Atiende los comandos recibidos por `socket` sobre `db` como si fuera una
+/// conexion aceptada por el servidor. Lo utilizan los clientes creados con
+/// `client::connect_in_process`.
+///
+/// La conexion termina cuando se cierra el otro extremo de `socket`.
+pub(crate) fn serve_in_process(db: Db, socket: impl Transport + 'static) {
+    let mut connection = Connection::boxed(socket);
+    connection.set_limits(db.config().limits());
+
+    // Nadie envia la senyal de parada: la conexion solo termina cuando el
+    // cliente la cierra.
     let (notify_shutdown, _) = broadcast::channel(1);
-    let (shutdown_complete_tx, shutdown_complete_rx) = mpsc::channel(1);
-
-    // Initialize the listener state
-    let mut server = Listener {
-        listener,
-        db_holder: DbDropGuard::new(),
-        limit_connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
-        notify_shutdown,
-        shutdown_complete_tx,
-        shutdown_complete_rx,
+    let (shutdown_complete, _) = mpsc::channel(1);
+
+    let session = Session {
+        user: db.acl().initial_user(),
+        ..Session::default()
     };
 
-    // Concurrently run the server and listen for the `shutdown` signal. The
-    // server task runs until an error is encountered, so under normal
-    // circumstances, this `select!` statement runs until the `shutdown` signal
-    // is received.
-    //
-    // `select!` statements are written in the form of:
-    //
-    // ```
-    // <result of async op> = <async op> => <step to perform with result>
-    // ```
-    //
-    // All `<async op>` statements are executed concurrently. Once the **first**
-    // op completes, its associated `<step to perform with result>` is
-    // performed.
-    //
-    // The `select!` macro is a foundational building block for writing
-    // asynchronous Rust. See the API docs for more details:
-    //
-    // https://docs.rs/tokio/*/tokio/macro.select.html
-    tokio::select! {
-        res = server.run() => {
-            // If an error is received here, accepting connections from the TCP
-            // listener failed multiple times and the server is giving up and
-            // shutting down.
-            //
-            // Errors encountered when handling individual connections do not
-            // bubble up to this point.
-            if let Err(err) = res {
-                error!(cause = %err, "failed to accept");
-            }
+    // Los mismos comandos añadidos que atienden las conexiones remotas.
+    let commands = db.commands();
+
+    let mut handler = Handler {
+        db: db.handle(),
+        connection,
+        shutdown: Shutdown::new(notify_shutdown.subscribe()),
+        session,
+        commands,
+        _shutdown_complete: shutdown_complete,
+    };
+
+    tokio::spawn(async move {
+        let _notify_shutdown = notify_shutdown;
+
+        handler.serve().await;
+    });
+}
+
+/// Configura y arranca un servidor desde otra aplicacion.
+///
+/// A diferencia de `run`, `start` retorna en cuanto el servidor acepta
+/// conexiones, con un `ServerHandle` para consultarlo y pararlo.
+///
+/// ```no_run
+/// use mini_redis::server::Builder;
+/// use mini_redis::{Db, DbDropGuard, Frame};
+///
+/// # async fn dox() -> mini_redis::Result<()> {
+/// let guard = DbDropGuard::new();
+/// let db = guard.db();
+/// db.set("hello".to_string(), "world".into(), None);
+///
+/// let server = Builder::new()
+///     .db(db)
+///     .command("hello", |_: &Db, _| Ok(Frame::Simple("world".to_string())))
+///     .start()
+///     .await?;
+///
+/// println!("listening on {:?}", server.local_addrs());
+/// server.shutdown().await
+/// # }
+/// ```
+pub struct Builder {
+    listeners: Listeners,
+    config: ServerConfig,
+    db: Option<Db>,
+    commands: Commands,
+    shutdown: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+/// Servidor arrancado con `Builder::start`.
+///
+/// Si se elimina sin llamar a `shutdown` el servidor sigue ejecutandose hasta
+/// que se completa el future indicado en `Builder::shutdown`, si lo hay.
+#[derive(Debug)]
+pub struct ServerHandle {
+    /// Direcciones TCP en las que escucha el servidor.
+    local_addrs: Vec<SocketAddr>,
+
+    /// Socket Unix en el que escucha el servidor.
+    unix_socket: Option<PathBuf>,
+
+    /// Base de datos del servidor.
+    db: Db,
+
+    /// Inicia la parada del servidor.
+    shutdown: oneshot::Sender<()>,
+
+    /// Tarea que acepta conexiones. Termina cuando el servidor ha parado.
+    task: JoinHandle<()>,
+}
+
+impl Builder {
+    /// Crea un `Builder` con la configuracion por defecto.
+    pub fn new() -> Builder {
+        Builder {
+            listeners: Listeners::default(),
+            config: ServerConfig::default(),
+            db: None,
+            commands: Commands::new(),
+            shutdown: None,
         }
-        _ = shutdown => {
-            // The shutdown signal has been received.
-            info!("shutting down");
+    }
+
+    /// Acepta conexiones en `listener`. Se puede llamar varias veces para
+    /// escuchar en varios sockets. Si no se indica ninguno se crean los de
+    /// la configuracion (ver `Listeners::bind`).
+    pub fn listener(mut self, listener: impl Into<Listeners>) -> Builder {
+        let listeners = listener.into();
+        self.listeners.tcp.extend(listeners.tcp);
+        if listeners.unix.is_some() {
+            self.listeners.unix = listeners.unix;
         }
+        self
+    }
+
+    /// Configuracion del servidor.
+    pub fn config(mut self, config: ServerConfig) -> Builder {
+        self.config = config;
+        self
+    }
+
+    /// Utiliza `db` en lugar de una base de datos vacia. La aplicacion puede
+    /// seguir leyendo y modificando sus claves mientras el servidor se
+    /// ejecuta, y siguen disponibles despues de pararlo.
+    ///
+    /// `db` se obtiene de un `DbDropGuard`, que la aplicacion tiene que
+    /// mantener mientras la use.
+    pub fn db(mut self, db: Db) -> Builder {
+        self.db = Some(db);
+        self
+    }
+
+    /// Añade el comando `name`. Si coincide con uno de los comandos propios
+    /// lo sustituye.
+    ///
+    /// Si el handler no declara claves ni escrituras (ver `CommandHandler`)
+    /// no pertenece a ninguna categoria, asi que solo lo pueden ejecutar los
+    /// usuarios con todos los comandos permitidos o con `+<name>`.
+    pub fn command(mut self, name: &str, handler: impl CommandHandler) -> Builder {
+        self.commands.insert(name, handler);
+        self
+    }
+
+    /// Para el servidor cuando se complete `signal`, ademas de con
+    /// `ServerHandle::shutdown`.
+    pub fn shutdown(mut self, signal: impl Future + Send + 'static) -> Builder {
+        self.shutdown = Some(Box::pin(async move {
+            signal.await;
+        }));
+        self
+    }
+
+    /// Arranca el servidor.
+    ///
+    /// Retorna un error si no se pueden crear los listeners o cargar los
+    /// datos persistentes.
+    pub async fn start(self) -> crate::Result<ServerHandle> {
+        let listeners = match self.listeners {
+            listeners if listeners.tcp.is_empty() && listeners.unix.is_none() => {
+                Listeners::bind(&self.config).await?
+            }
+            listeners => listeners,
+        };
+
+        let local_addrs = listeners
+            .tcp
+            .iter()
+            .map(TcpListener::local_addr)
+            .collect::<io::Result<_>>()?;
+        let unix_socket = match &listeners.unix {
+            Some(unix) => unix.local_addr()?.as_pathname().map(Path::to_path_buf),
+            None => None,
+        };
+
+        let server = Listener::new(listeners, self.config, self.db, self.commands).await?;
+        let db = server.db.clone();
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let signal = self.shutdown;
+        let task = tokio::spawn(server.serve(async move {
+            match signal {
+                Some(signal) => tokio::select! {
+                    _ = shutdown_rx => {}
+                    _ = signal => {}
+                },
+                None => {
+                    let _ = shutdown_rx.await;
+                }
+            }
+        }));
+
+        Ok(ServerHandle {
+            local_addrs,
+            unix_socket,
+            db,
+            shutdown: shutdown_tx,
+            task,
+        })
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("listeners", &self.listeners)
+            .field("config", &self.config)
+            .field("db", &self.db)
+            .field("commands", &self.commands)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ServerHandle {
+    /// Direcciones TCP en las que escucha el servidor. Si se ha configurado
+    /// con el puerto 0 incluyen el puerto asignado.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Primera direccion TCP en la que escucha el servidor.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addrs.first().copied()
+    }
+
+    /// Socket Unix en el que escucha el servidor.
+    pub fn unix_socket(&self) -> Option<&Path> {
+        self.unix_socket.as_deref()
     }
 
-    // Extract the `shutdown_complete` receiver and transmitter
-    // explicitly drop `shutdown_transmitter`. This is important, as the
-    // `.await` below would otherwise never complete.
-    let Listener {
-        mut shutdown_complete_rx,
-        shutdown_complete_tx,
-        notify_shutdown,
-        ..
-    } = server;
-
-    // When `notify_shutdown` is dropped, all tasks which have `subscribe`d will
-    // receive the shutdown signal and can exit
-    drop(notify_shutdown);
-    // Drop final `Sender` so the `Receiver` below can complete
-    drop(shutdown_complete_tx);
-
-    // Wait for all active connections to finish processing. As the `Sender`
-    // handle held by the listener has been dropped above, the only remaining
-    // `Sender` instances are held by connection handler tasks. When those drop,
-    // the `mpsc` channel will close and `recv()` will return `None`.
-    let _ = shutdown_complete_rx.recv().await;
+    /// Base de datos del servidor. Igual que `DbDropGuard::db`, cada llamada
+    /// retorna un acceso que lleva la cuenta de sus propias escrituras.
+    pub fn db(&self) -> Db {
+        self.db.handle()
+    }
+
+    /// Estadisticas actuales del servidor.
+    pub fn stats(&self) -> ServerStats {
+        self.db.stats().snapshot()
+    }
+
+    /// Para el servidor: deja de aceptar conexiones, espera a que terminen
+    /// las activas y guarda los datos persistentes.
+    pub async fn shutdown(self) -> crate::Result<()> {
+        // Si el servidor ya ha parado el receptor no existe.
+        let _ = self.shutdown.send(());
+
+        self.task
+            .await
+            .map_err(|err| format!("server task failed: {}", err).into())
+    }
 }
 
 impl Listener {
+    /// Prepara el servidor para aceptar conexiones en `listeners`.
+    ///
+    /// Si no se proporciona una `Db` se crea una nueva. Antes de retornar se
+    /// cargan los datos persistentes; si no se pueden cargar se retorna el
+    /// error.
+    async fn new(
+        listeners: Listeners,
+        config: ServerConfig,
+        db: Option<Db>,
+        commands: Commands,
+    ) -> crate::Result<Listener> {
+        if listeners.tcp.is_empty() && listeners.unix.is_none() {
+            return Err("no listener to accept connections on".into());
+        }
+
+        // La primera direccion TCP, si la hay. Las replicas y los nodos del
+        // cluster se anuncian con ella.
+        let addr = match listeners.tcp.first() {
+            Some(tcp) => Some(tcp.local_addr()?),
+            None => None,
+        };
+
+        // Un certificado o una clave invalidos impiden arrancar el servidor.
+        let tls = match &config.tls {
+            Some(tls_config) => Some(tls::acceptor(tls_config)?),
+            None => None,
+        };
+
+        let (db, db_guard) = match db {
+            Some(db) => (db, None),
+            None => {
+                let guard = DbDropGuard::new();
+                (guard.db(), Some(guard))
+            }
+        };
+
+        // La configuracion se puede consultar y modificar con `CONFIG`.
+        db.set_config(ConfigHandle::new(Config::from(&config)));
+
+        // Las replicas y los nodos del cluster se conectan a los demas nodos;
+        // con TLS pero sin CA con las que verificarlos no podrian hacerlo.
+        if config.replicaof.is_some() || config.cluster_enabled {
+            db.config().peer_options()?;
+        }
+
+        // Los clientes en el mismo proceso tambien atienden los comandos
+        // añadidos.
+        db.set_commands(Arc::new(commands));
+
+        // Las replicas anuncian al master el puerto en el que escuchan.
+        if let Some(addr) = addr {
+            db.set_listening_port(addr.port());
+        }
+
+        // Los usuarios del fichero ACL, si lo hay, sustituyen a los iniciales.
+        // `requirepass` se aplica despues sobre el usuario `default`.
+        let acl = db.acl();
+        if config.aclfile.is_some() {
+            acl.set_file(config.aclfile);
+            acl.load()?;
+        }
+        if config.requirepass.is_some() {
+            acl.set_default_password(config.requirepass.as_deref());
+        }
+
+        let load_snapshot = config.appendonly.is_none();
+
+        let aof = match config.appendonly {
+            Some(aof_config) => Some(Aof::start(&db, aof_config).await?),
+            None => None,
+        };
+
+        let rdb = match config.snapshot {
+            Some(rdb_config) => Some(Rdb::start(&db, rdb_config, load_snapshot).await?),
+            None => None,
+        };
+
+        if config.replicaof.is_some() {
+            replication::replicaof(&db, config.replicaof).await;
+        }
+
+        if config.cluster_enabled {
+            // Las redirecciones indican a los clientes la direccion en la que
+            // escucha el nodo.
+            let addr = addr.ok_or("cluster mode requires a TCP listener")?;
+            let host = match addr.ip() {
+                ip if ip.is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+                ip => ip,
+            };
+            let cluster = ClusterHandle::new(host.to_string(), addr.port());
+            db.set_cluster(Some(cluster));
+        }
+
+        // When the provided `shutdown` future completes, we must send a
+        // shutdown message to all active connections. We use a broadcast
+        // channel for this purpose. The call below ignores the receiver of the
+        // broadcast pair, and when a receiver is needed, the subscribe() method
+        // on the sender is used to create one.
+        let (notify_shutdown, _) = broadcast::channel(1);
+        let (shutdown_complete_tx, shutdown_complete_rx) = mpsc::channel(1);
+
+        Ok(Listener {
+            db,
+            _db_guard: db_guard,
+            aof,
+            rdb,
+            listeners,
+            tls,
+            connections: Arc::new(AtomicUsize::new(0)),
+            rejections: Arc::new(Semaphore::new(MAX_PENDING_REJECTIONS)),
+            notify_shutdown,
+            shutdown_complete_tx,
+            shutdown_complete_rx,
+        })
+    }
+
+    /// Acepta conexiones hasta que el future `shutdown` se completa. Despues
+    /// espera a que terminen las conexiones activas y guarda los datos
+    /// persistentes.
+    async fn serve(mut self, shutdown: impl Future) {
+        // Concurrently run the server and listen for the `shutdown` signal. The
+        // server task runs until an error is encountered, so under normal
+        // circumstances, this `select!` statement runs until the `shutdown` signal
+        // is received.
+        //
+        // `select!` statements are written in the form of:
+        //
+        // ```
+        // <result of async op> = <async op> => <step to perform with result>
+        // ```
+        //
+        // All `<async op>` statements are executed concurrently. Once the **first**
+        // op completes, its associated `<step to perform with result>` is
+        // performed.
+        //
+        // The `select!` macro is a foundational building block for writing
+        // asynchronous Rust. See the API docs for more details:
+        //
+        // https://docs.rs/tokio/*/tokio/macro.select.html
+        tokio::select! {
+            res = self.run() => {
+                // If an error is received here, accepting connections from the TCP
+                // listener failed multiple times and the server is giving up and
+                // shutting down.
+                //
+                // Errors encountered when handling individual connections do not
+                // bubble up to this point.
+                if let Err(err) = res {
+                    error!(cause = %err, "failed to accept");
+                }
+            }
+            _ = shutdown => {
+                // The shutdown signal has been received.
+                info!("shutting down");
+            }
+        }
+
+        // Extract the `shutdown_complete` receiver and transmitter
+        // explicitly drop `shutdown_transmitter`. This is important, as the
+        // `.await` below would otherwise never complete.
+        let Listener {
+            mut shutdown_complete_rx,
+            shutdown_complete_tx,
+            notify_shutdown,
+            db,
+            aof,
+            rdb,
+            _db_guard,
+            ..
+        } = self;
+
+        // When `notify_shutdown` is dropped, all tasks which have `subscribe`d will
+        // receive the shutdown signal and can exit
+        drop(notify_shutdown);
+        // Drop final `Sender` so the `Receiver` below can complete
+        drop(shutdown_complete_tx);
+
+        // Wait for all active connections to finish processing. As the `Sender`
+        // handle held by the listener has been dropped above, the only remaining
+        // `Sender` instances are held by connection handler tasks. When those drop,
+        // the `mpsc` channel will close and `recv()` will return `None`.
+        let _ = shutdown_complete_rx.recv().await;
+
+        // Una replica deja de recibir escrituras de su master.
+        replication::replicaof(&db, None).await;
+
+        // Once no connection can write anymore, the pending writes are logged.
+        if let Some(aof) = aof {
+            aof.shutdown().await;
+        }
+
+        if let Some(rdb) = rdb {
+            rdb.shutdown(&db).await;
+        }
+    }
+
     /// Run the server
     ///
     /// Listen for inbound connections. For each inbound connection, spawn a
@@ -196,52 +817,95 @@ impl Listener {
         info!("accepting inbound connections");
 
         loop {
-            // Wait for a permit to become available
-            //
-            // `acquire_owned` returns a permit that is bound to the semaphore.
-            // When the permit value is dropped, it is automatically returned
-            // to the semaphore.
-            //
-            // `acquire_owned()` returns `Err` when the semaphore has been
-            // closed. We don't ever close the semaphore, so `unwrap()` is safe.
-            let permit = self
-                .limit_connections
-                .clone()
-                .acquire_owned()
-                .await
-                .unwrap();
-
             // Accept a new socket. This will attempt to perform error handling.
             // The `accept` method internally attempts to recover errors, so an
             // error here is non-recoverable.
             let socket = self.accept().await?;
 
-            // Create the necessary per-connection handler state.
-            let mut handler = Handler {
-                // Get a handle to the shared database.
-                db: self.db_holder.db(),
+            // Solo el listener incrementa el contador, de modo que no puede
+            // superarse el limite entre la comprobacion y el incremento.
+            if self.connections.load(Ordering::Relaxed) >= self.db.config().maxclients() {
+                self.db.stats().connection_rejected();
+                debug!("max number of clients reached, rejecting connection");
 
-                // Initialize the connection state. This allocates read/write
-                // buffers to perform redis protocol frame parsing.
-                connection: Connection::new(socket),
+                // `try_acquire_owned` solo falla si no quedan permisos; la
+                // conexion se cierra al soltar `socket`.
+                if let Ok(permit) = self.rejections.clone().try_acquire_owned() {
+                    let tls = self.tls.clone();
+                    tokio::spawn(async move {
+                        reject(socket, tls).await;
+                        drop(permit);
+                    });
+                }
+                continue;
+            }
+
+            self.connections.fetch_add(1, Ordering::Relaxed);
+            let permit = ConnectionPermit(self.connections.clone());
+
+            let peer_ip = match &socket {
+                Socket::Tcp(socket) => socket.peer_addr().ok().map(|addr| addr.ip()),
+                Socket::Unix(_) => None,
+            };
+
+            let tls = self.tls.clone();
+
+            // Get a handle to the shared database. Each connection keeps
+            // track of its own writes to wait for them (`Db::sync_writes`).
+            let db = self.db.handle();
+
+            // Los limites se pueden modificar con `CONFIG SET`; se aplican a
+            // las conexiones nuevas.
+            let limits = db.config().limits();
+            let handshake_timeout = handshake_timeout(&db);
 
-                // Receive shutdown notifications.
-                shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
+            // Receive shutdown notifications.
+            let shutdown = Shutdown::new(self.notify_shutdown.subscribe());
 
-                // Notifies the receiver half once all clones are
-                // dropped.
-                _shutdown_complete: self.shutdown_complete_tx.clone(),
+            // Sin contraseña la conexion actua como el usuario `default`.
+            let session = Session {
+                peer_ip,
+                user: db.acl().initial_user(),
+                ..Session::default()
             };
 
+            let commands = db.commands();
+
+            // Notifies the receiver half once all clones are dropped.
+            let shutdown_complete = self.shutdown_complete_tx.clone();
+
             // Spawn a new task to process the connections. Tokio tasks are like
             // asynchronous green threads and are executed concurrently.
             tokio::spawn(async move {
-                // Process the connection. If an error is encountered, log it.
-                if let Err(err) = handler.run().await {
-                    error!(cause = ?err, "connection error");
-                }
+                // El handshake TLS se hace en la tarea de la conexion para no
+                // bloquear la aceptacion de otras conexiones.
+                //
+                // Initialize the connection state. This allocates read/write
+                // buffers to perform redis protocol frame parsing.
+                let mut connection = match open(socket, tls, handshake_timeout).await {
+                    Some(connection) => connection,
+                    None => {
+                        db.stats().handshake_failed();
+                        return;
+                    }
+                };
+                connection.set_limits(limits);
+
+                // Create the necessary per-connection handler state.
+                let mut handler = Handler {
+                    db,
+                    connection,
+                    shutdown,
+                    session,
+                    commands,
+                    _shutdown_complete: shutdown_complete,
+                };
+
+                // Process the connection.
+                handler.serve().await;
+
                 // Move the permit into the task and drop it after completion.
-                // This returns the permit back to the semaphore.
+                // This frees the connection slot for a new client.
                 drop(permit);
             });
         }
@@ -254,15 +918,26 @@ impl Listener {
     /// After the second failure, the task waits for 2 seconds. Each subsequent
     /// failure doubles the wait time. If accepting fails on the 6th try after
     /// waiting for 64 seconds, then this function returns with an error.
-    async fn accept(&mut self) -> crate::Result<TcpStream> {
+    async fn accept(&mut self) -> crate::Result<Socket> {
         let mut backoff = 1;
 
         // Try to accept a few times
         loop {
             // Perform the accept operation. If a socket is successfully
             // accepted, return it. Otherwise, save the error.
-            match self.listener.accept().await {
-                Ok((socket, _)) => return Ok(socket),
+            match self.listeners.accept().await {
+                Ok(socket) => {
+                    // Los keepalive detectan los clientes que desaparecen sin
+                    // cerrar la conexion, que si no ocuparian un permiso para
+                    // siempre. Se pueden modificar con `CONFIG SET`.
+                    if let Socket::Tcp(socket) = &socket {
+                        let interval = self.db.config().tcp_keepalive();
+                        if let Err(err) = set_keepalive(socket, interval) {
+                            debug!(cause = %err, "failed to set TCP keepalive");
+                        }
+                    }
+                    return Ok(socket);
+                }
                 Err(err) => {
                     if backoff > 64 {
                         // Accept has failed too many times. Return the error.
@@ -280,32 +955,246 @@ impl Listener {
     }
 }
 
+/// Plaza de una conexion abierta en `Listener::connections`. Se libera al
+/// soltarse.
+struct ConnectionPermit(Arc<AtomicUsize>);
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Crea la `Connection` de un socket aceptado, cifrada con TLS si se indica
+/// `tls`. Retorna `None` si falla el handshake TLS o no se completa en
+/// `handshake_timeout`.
+async fn open(
+    socket: Socket,
+    tls: Option<tls::Acceptor>,
+    handshake_timeout: Duration,
+) -> Option<Connection> {
+    match (socket, tls) {
+        (Socket::Tcp(socket), Some(acceptor)) => {
+            match time::timeout(handshake_timeout, acceptor.accept(socket)).await {
+                Ok(Ok(socket)) => Some(Connection::boxed(socket)),
+                Ok(Err(err)) => {
+                    debug!(cause = %err, "TLS handshake failed");
+                    None
+                }
+                Err(_) => {
+                    debug!("TLS handshake timed out");
+                    None
+                }
+            }
+        }
+        (Socket::Tcp(socket), None) => Some(Connection::boxed(socket)),
+        (Socket::Unix(socket), _) => Some(Connection::boxed(socket)),
+    }
+}
+
+/// Tiempo maximo para el handshake TLS de las conexiones nuevas: el tiempo
+/// de inactividad configurado, sin superar `TLS_HANDSHAKE_TIMEOUT`.
+fn handshake_timeout(db: &Db) -> Duration {
+    match db.config().timeout() {
+        timeout if timeout.is_zero() => TLS_HANDSHAKE_TIMEOUT,
+        timeout => timeout.min(TLS_HANDSHAKE_TIMEOUT),
+    }
+}
+
+/// Responde a una conexion que supera `maxclients` con un error y la
+/// cierra, como hace Redis, para que el cliente sepa por que no se le
+/// atiende. Si el handshake TLS y la respuesta no se completan en
+/// `REJECT_TIMEOUT` la conexion se cierra igualmente.
+async fn reject(socket: Socket, tls: Option<tls::Acceptor>) {
+    let res = time::timeout(REJECT_TIMEOUT, async {
+        let mut connection = match open(socket, tls, REJECT_TIMEOUT).await {
+            Some(connection) => connection,
+            None => return Ok(()),
+        };
+        let response: Frame = ServerError::err("max number of clients reached").into();
+        connection.write_frame(&response).await
+    })
+    .await;
+
+    match res {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => debug!(cause = %err, "failed to reject connection"),
+        Err(_) => debug!("timed out rejecting connection"),
+    }
+}
+
+/// Activa los keepalive TCP de `socket`: el primero se envia tras
+/// `interval` sin actividad y, si no hay respuesta, se repite cada tercio de
+/// `interval`, como hace Redis. Con `Duration::ZERO` no se activan.
+fn set_keepalive(socket: &TcpStream, interval: Duration) -> io::Result<()> {
+    if interval.is_zero() {
+        return Ok(());
+    }
+
+    let keepalive = TcpKeepalive::new()
+        .with_time(interval)
+        .with_interval((interval / 3).max(Duration::from_secs(1)));
+
+    SockRef::from(socket).set_tcp_keepalive(&keepalive)
+}
+
+impl Listeners {
+    /// Crea los listeners indicados en la configuracion: uno TCP por cada
+    /// direccion de `bind` (salvo con el puerto 0) y el socket Unix si se ha
+    /// configurado.
+    pub async fn bind(config: &ServerConfig) -> crate::Result<Listeners> {
+        let mut listeners = Listeners::default();
+
+        if config.port != 0 {
+            for ip in &config.bind {
+                listeners
+                    .tcp
+                    .push(TcpListener::bind((*ip, config.port)).await?);
+            }
+        }
+
+        if let Some(path) = &config.unixsocket {
+            // Un fichero que ha quedado de una ejecucion anterior impediria
+            // crear el socket.
+            let _ = fs::remove_file(path);
+            listeners.unix = Some(UnixListener::bind(path)?);
+
+            if let Some(mode) = config.unixsocketperm {
+                fs::set_permissions(path, Permissions::from_mode(mode))?;
+            }
+        }
+
+        Ok(listeners)
+    }
+
+    /// Acepta una conexion de cualquiera de los listeners.
+    async fn accept(&self) -> io::Result<Socket> {
+        let tcp = future::poll_fn(|cx| {
+            for listener in &self.tcp {
+                if let Poll::Ready(res) = listener.poll_accept(cx) {
+                    return Poll::Ready(res.map(|(socket, _)| Socket::Tcp(socket)));
+                }
+            }
+            Poll::Pending
+        });
+
+        let unix = async {
+            match &self.unix {
+                Some(listener) => listener
+                    .accept()
+                    .await
+                    .map(|(socket, _)| Socket::Unix(socket)),
+                None => future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            res = tcp => res,
+            res = unix => res,
+        }
+    }
+}
+
+impl From<TcpListener> for Listeners {
+    fn from(listener: TcpListener) -> Listeners {
+        Listeners {
+            tcp: vec![listener],
+            unix: None,
+        }
+    }
+}
+
+impl From<UnixListener> for Listeners {
+    fn from(listener: UnixListener) -> Listeners {
+        Listeners {
+            tcp: vec![],
+            unix: Some(listener),
+        }
+    }
+}
+
 impl Handler {
+    /// Atiende la conexion hasta que se cierra. El motivo del cierre se
+    /// registra en las estadisticas (ver `INFO`) y en el log.
+    async fn serve(&mut self) {
+        self.db.stats().client_connected();
+
+        let reason = match self.run().await {
+            Ok(reason) => reason,
+            Err(crate::Error::Protocol(_)) => CloseReason::ProtocolError,
+            Err(crate::Error::ConnectionClosed) => CloseReason::Client,
+            Err(err) => {
+                error!(cause = ?err, "connection error");
+                CloseReason::IoError
+            }
+        };
+
+        debug!(%reason, "connection closed");
+        self.db.stats().client_disconnected(reason);
+    }
+
     /// Process a single connection.
     ///
     /// Request frames are read from the socket and processed. Responses are
     /// written back to the socket.
     ///
-    /// Currently, pipelining is not implemented. Pipelining is the ability to
-    /// process more than one request concurrently per connection without
-    /// interleaving frames. See for more details:
+    /// Pipelined requests (several requests sent without waiting for the
+    /// responses) are processed in order. Responses to all the requests
+    /// already buffered are coalesced and flushed to the socket together,
+    /// see `Connection::write_frame`, except before a command that can block
+    /// such as `WAIT`. For more details:
     /// https://redis.io/topics/pipelining
     ///
     /// When the shutdown signal is received, the connection is processed until
     /// it reaches a safe state, at which point it is terminated.
+    ///
+    /// Retorna el motivo por el que se ha cerrado la conexion.
     #[instrument(skip(self))]
-    async fn run(&mut self) -> crate::Result<()> {
+    async fn run(&mut self) -> crate::Result<CloseReason> {
         // As long as the shutdown signal has not been received, try to read a
         // new request frame.
         while !self.shutdown.is_shutdown() {
+            // Con `timeout` se cierran las conexiones inactivas. Se lee en
+            // cada vuelta porque se puede cambiar con `CONFIG SET`.
+            //
+            // Solo se cuenta el tiempo esperando una peticion: los clientes
+            // suscritos o bloqueados (`WAIT`) estan dentro de `apply` y no se
+            // cierran por inactividad.
+            let timeout = self.db.config().timeout();
+            let idle = async {
+                if timeout.is_zero() {
+                    future::pending::<()>().await;
+                }
+                time::sleep(timeout).await;
+            };
+
             // While reading a request frame, also listen for the shutdown
             // signal.
             let maybe_frame = tokio::select! {
-                res = self.connection.read_frame() => res?,
+                res = self.connection.read_frame() => match res {
+                    Ok(maybe_frame) => maybe_frame,
+                    Err(err) => {
+                        // The peer sent an invalid frame or exceeded one of the
+                        // protocol limits. Let it know why before closing the
+                        // connection, as Redis does.
+                        if let crate::Error::Protocol(msg) = &err {
+                            let response = Frame::Error(format!("ERR {}", msg));
+                            let _ = self.connection.write_frame(&response).await;
+                        }
+                        return Err(err);
+                    }
+                },
                 _ = self.shutdown.recv() => {
                     // If a shutdown signal is received, return from `run`.
-                    // This will result in the task terminating.
-                    return Ok(());
+                    // This will result in the task terminating. Responses
+                    // held back while pipelining are sent first.
+                    self.connection.flush().await?;
+                    return Ok(CloseReason::Shutdown);
+                }
+                _ = idle => {
+                    debug!(?timeout, "closing idle connection");
+                    self.connection.flush().await?;
+                    return Ok(CloseReason::IdleTimeout);
                 }
             };
 
@@ -314,13 +1203,21 @@ impl Handler {
             // terminated.
             let frame = match maybe_frame {
                 Some(frame) => frame,
-                None => return Ok(()),
+                None => return Ok(CloseReason::Client),
             };
 
-            // Convert the redis frame into a command struct. This returns an
-            // error if the frame is not a valid redis command or it is an
-            // unsupported command.
-            let cmd = Command::from_frame(frame)?;
+            // Convert the redis frame into a command struct. If the frame is
+            // not a valid redis command (wrong arity, bad argument, ...) the
+            // error is sent back to the peer and the connection stays open.
+            // Only protocol level errors, handled above, are fatal.
+            let cmd = match Command::from_frame_with(frame, &self.commands) {
+                Ok(cmd) => cmd,
+                Err(err) => {
+                    debug!(%err, "invalid command");
+                    self.connection.write_frame(&err.into()).await?;
+                    continue;
+                }
+            };
 
             // Logs the `cmd` object. The syntax here is a shorthand provided by
             // the `tracing` crate. It can be thought of as similar to:
@@ -333,6 +1230,45 @@ impl Handler {
             // as key-value pairs.
             debug!(?cmd);
 
+            // Hasta que la conexion se autentica solo se acepta `AUTH`.
+            if self.session.user.is_none() && !cmd.allowed_unauthenticated() {
+                let err = ServerError::new(ServerErrorKind::NoAuth, "Authentication required.");
+                self.connection.write_frame(&err.into()).await?;
+                continue;
+            }
+
+            // En modo cluster las claves del comando tienen que pertenecer a
+            // un slot de este nodo; si no se redirige al cliente. `ASKING`
+            // solo afecta al comando siguiente.
+            if let Some(cluster) = self.db.cluster() {
+                let asking = std::mem::take(&mut self.session.asking) || cmd.is_asking();
+                let db = &self.db;
+
+                if let Err(err) = cluster.route(&cmd.keys(), asking, |key| db.contains_key(key)) {
+                    self.connection.write_frame(&err.into()).await?;
+                    continue;
+                }
+            }
+
+            // Una replica solo recibe escrituras de su master.
+            if cmd.modifies_keys() && self.db.is_replica() {
+                let err = ServerError::new(
+                    ServerErrorKind::ReadOnly,
+                    "You can't write against a read only replica.",
+                );
+                self.connection.write_frame(&err.into()).await?;
+                continue;
+            }
+
+            // Tras un error del fichero AOF las escrituras se rechazan sin
+            // aplicarlas, hasta que se vuelve a poder escribir en el.
+            if cmd.modifies_keys() {
+                if let Err(err) = self.db.check_writable() {
+                    self.connection.write_frame(&err.into()).await?;
+                    continue;
+                }
+            }
+
             // Perform the work needed to apply the command. This may mutate the
             // database state as a result.
             //
@@ -340,10 +1276,31 @@ impl Handler {
             // command to write response frames directly to the connection. In
             // the case of pub/sub, multiple frames may be send back to the
             // peer.
-            cmd.apply(&self.db, &mut self.connection, &mut self.shutdown)
-                .await?;
+            let writes = cmd.modifies_keys();
+            self.db.stats().command_processed();
+
+            // Las respuestas retenidas por el pipelining se envian antes de un
+            // comando que puede esperar; si no llegarian al cliente cuando
+            // este termine.
+            if cmd.may_block() {
+                self.connection.flush().await?;
+            }
+
+            cmd.apply(
+                &self.db,
+                &mut self.connection,
+                &mut self.shutdown,
+                &mut self.session,
+            )
+            .await?;
+
+            // `WAIT` espera a que las replicas confirmen hasta la ultima
+            // escritura de la conexion.
+            if writes {
+                self.session.write_offset = self.db.repl_offset();
+            }
         }
 
-        Ok(())
+        Ok(CloseReason::Shutdown)
     }
 }
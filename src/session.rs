@@ -0,0 +1,27 @@
+use std::net::IpAddr;
+
+/// Estado propio de cada conexion de un cliente con el servidor.
+///
+/// El `Handler` de la conexion lo mantiene mientras esta dura y se lo
+/// proporciona a los comandos que lo necesitan.
+#[derive(Debug, Default)]
+pub(crate) struct Session {
+    /// Offset de replicacion tras la ultima escritura de la conexion. Es el
+    /// offset que tienen que confirmar las replicas para `WAIT`.
+    pub(crate) write_offset: u64,
+
+    /// Se ha recibido `ASKING`: el siguiente comando se acepta aunque su
+    /// slot se este importando desde otro nodo (ver `cluster`).
+    pub(crate) asking: bool,
+
+    /// Usuario en cuyo nombre actua la conexion (ver `acl`). Es `None`
+    /// hasta que se autentica con `AUTH`.
+    pub(crate) user: Option<String>,
+
+    /// IP del cliente.
+    pub(crate) peer_ip: Option<IpAddr>,
+
+    /// Puerto que ha anunciado el cliente con `REPLCONF listening-port` si
+    /// es una replica.
+    pub(crate) listening_port: Option<u16>,
+}
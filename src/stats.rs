@@ -0,0 +1,249 @@
+//! Estadisticas del servidor.
+//!
+//! Contadores que se actualizan mientras el servidor atiende a los clientes.
+//! Se consultan con `INFO` y se ponen a cero con `CONFIG RESETSTAT`, salvo
+//! los que reflejan el estado actual (como los clientes conectados).
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Motivo por el que se ha cerrado la conexion de un cliente.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CloseReason {
+    /// El cliente ha cerrado la conexion.
+    Client,
+
+    /// El cliente ha superado el tiempo de inactividad (`timeout`).
+    IdleTimeout,
+
+    /// El cliente ha enviado una trama invalida o ha superado alguno de los
+    /// limites del protocolo.
+    ProtocolError,
+
+    /// Error de lectura o escritura en la conexion.
+    IoError,
+
+    /// El handshake TLS ha fallado o no se ha completado a tiempo.
+    Handshake,
+
+    /// El servidor se esta parando.
+    Shutdown,
+}
+
+impl CloseReason {
+    /// Todos los motivos, en el orden de sus contadores.
+    const ALL: [CloseReason; 6] = [
+        CloseReason::Client,
+        CloseReason::IdleTimeout,
+        CloseReason::ProtocolError,
+        CloseReason::IoError,
+        CloseReason::Handshake,
+        CloseReason::Shutdown,
+    ];
+
+    /// Nombre con el que se muestra en `INFO` y en los logs.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            CloseReason::Client => "closed_by_client",
+            CloseReason::IdleTimeout => "closed_idle_timeout",
+            CloseReason::ProtocolError => "closed_protocol_error",
+            CloseReason::IoError => "closed_io_error",
+            CloseReason::Handshake => "closed_handshake_error",
+            CloseReason::Shutdown => "closed_shutdown",
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Valor de los contadores en un momento dado (ver
+/// `server::ServerHandle::stats`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Clientes conectados.
+    pub connected_clients: u64,
+
+    /// Conexiones aceptadas.
+    pub total_connections_received: u64,
+
+    /// Comandos ejecutados.
+    pub total_commands_processed: u64,
+
+    /// Conexiones rechazadas por superar `maxclients`.
+    pub rejected_connections: u64,
+
+    /// Lecturas de claves que existian.
+    pub keyspace_hits: u64,
+
+    /// Lecturas de claves que no existian.
+    pub keyspace_misses: u64,
+
+    /// Conexiones cerradas por el cliente.
+    pub closed_by_client: u64,
+
+    /// Conexiones cerradas por inactividad.
+    pub closed_idle_timeout: u64,
+
+    /// Conexiones cerradas por un error del protocolo.
+    pub closed_protocol_error: u64,
+
+    /// Conexiones cerradas por un error de lectura o escritura.
+    pub closed_io_error: u64,
+
+    /// Conexiones cerradas porque el handshake TLS ha fallado o no se ha
+    /// completado a tiempo.
+    pub closed_handshake_error: u64,
+
+    /// Conexiones cerradas al parar el servidor.
+    pub closed_shutdown: u64,
+}
+
+/// Contadores del servidor. Se guardan en la `Db`.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    /// Clientes conectados en este momento.
+    connected_clients: AtomicU64,
+
+    /// Conexiones aceptadas.
+    total_connections_received: AtomicU64,
+
+    /// Comandos ejecutados.
+    total_commands_processed: AtomicU64,
+
+    /// Conexiones rechazadas por superar `maxclients`.
+    rejected_connections: AtomicU64,
+
+    /// Lecturas de claves que existian.
+    keyspace_hits: AtomicU64,
+
+    /// Lecturas de claves que no existian.
+    keyspace_misses: AtomicU64,
+
+    /// Conexiones cerradas por cada motivo, en el orden de
+    /// `CloseReason::ALL`.
+    closed: [AtomicU64; 6],
+}
+
+impl Stats {
+    /// Registra una conexion aceptada.
+    pub(crate) fn client_connected(&self) {
+        self.connected_clients.fetch_add(1, Ordering::Relaxed);
+        self.total_connections_received
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra una conexion rechazada por superar `maxclients`.
+    pub(crate) fn connection_rejected(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra el cierre de una conexion y su motivo.
+    pub(crate) fn client_disconnected(&self, reason: CloseReason) {
+        self.connected_clients.fetch_sub(1, Ordering::Relaxed);
+        self.closed[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra una conexion cerrada durante el handshake TLS, antes de
+    /// contarse como cliente conectado.
+    pub(crate) fn handshake_failed(&self) {
+        self.closed[CloseReason::Handshake as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra un comando ejecutado.
+    pub(crate) fn command_processed(&self) {
+        self.total_commands_processed
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra la lectura de una clave.
+    pub(crate) fn keyspace_read(&self, hit: bool) {
+        let counter = if hit {
+            &self.keyspace_hits
+        } else {
+            &self.keyspace_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Pone a cero los contadores (`CONFIG RESETSTAT`).
+    pub(crate) fn reset(&self) {
+        for counter in [
+            &self.total_connections_received,
+            &self.total_commands_processed,
+            &self.rejected_connections,
+            &self.keyspace_hits,
+            &self.keyspace_misses,
+        ]
+        .into_iter()
+        .chain(&self.closed)
+        {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Valor actual de los contadores.
+    pub(crate) fn snapshot(&self) -> ServerStats {
+        ServerStats {
+            connected_clients: self.connected_clients.load(Ordering::Relaxed),
+            total_connections_received: self.total_connections_received.load(Ordering::Relaxed),
+            total_commands_processed: self.total_commands_processed.load(Ordering::Relaxed),
+            rejected_connections: self.rejected_connections.load(Ordering::Relaxed),
+            keyspace_hits: self.keyspace_hits.load(Ordering::Relaxed),
+            keyspace_misses: self.keyspace_misses.load(Ordering::Relaxed),
+            closed_by_client: self.closed(CloseReason::Client),
+            closed_idle_timeout: self.closed(CloseReason::IdleTimeout),
+            closed_protocol_error: self.closed(CloseReason::ProtocolError),
+            closed_io_error: self.closed(CloseReason::IoError),
+            closed_handshake_error: self.closed(CloseReason::Handshake),
+            closed_shutdown: self.closed(CloseReason::Shutdown),
+        }
+    }
+
+    /// Contadores de los clientes, como pares nombre y valor.
+    pub(crate) fn clients(&self) -> Vec<(&'static str, u64)> {
+        vec![(
+            "connected_clients",
+            self.connected_clients.load(Ordering::Relaxed),
+        )]
+    }
+
+    /// Conexiones cerradas por el motivo `reason`.
+    fn closed(&self, reason: CloseReason) -> u64 {
+        self.closed[reason as usize].load(Ordering::Relaxed)
+    }
+
+    /// Contadores de la actividad del servidor, como pares nombre y valor.
+    pub(crate) fn counters(&self) -> Vec<(&'static str, u64)> {
+        let mut counters = vec![
+            (
+                "total_connections_received",
+                self.total_connections_received.load(Ordering::Relaxed),
+            ),
+            (
+                "total_commands_processed",
+                self.total_commands_processed.load(Ordering::Relaxed),
+            ),
+            (
+                "rejected_connections",
+                self.rejected_connections.load(Ordering::Relaxed),
+            ),
+            ("keyspace_hits", self.keyspace_hits.load(Ordering::Relaxed)),
+            (
+                "keyspace_misses",
+                self.keyspace_misses.load(Ordering::Relaxed),
+            ),
+        ];
+
+        counters.extend(
+            CloseReason::ALL
+                .into_iter()
+                .map(|reason| (reason.as_str(), self.closed(reason))),
+        );
+
+        counters
+    }
+}
@@ -0,0 +1,144 @@
+//! Cifrado TLS de las conexiones.
+//!
+//! El servidor acepta conexiones TLS cuando se configura con un `TlsConfig`
+//! (certificado y clave privada en ficheros PEM). Opcionalmente puede exigir
+//! a los clientes un certificado firmado por una CA concreta.
+//!
+//! Los clientes se conectan con `client::connect_tls`, verificando el
+//! certificado del servidor con la CA indicada en `client::TlsOptions`.
+
+use crate::client::TlsOptions;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::rustls::{self, Certificate, PrivateKey, RootCertStore, ServerName};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// Configuracion TLS del servidor.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// Fichero PEM con el certificado del servidor, seguido de los
+    /// certificados intermedios si los hay.
+    pub cert_file: PathBuf,
+
+    /// Fichero PEM con la clave privada del certificado.
+    pub key_file: PathBuf,
+
+    /// Fichero PEM con los certificados de las CA con las que se verifican
+    /// los certificados de los clientes. Si es `None` no se pide
+    /// certificado a los clientes; si se indica, las conexiones sin un
+    /// certificado valido se rechazan durante el handshake.
+    pub ca_cert_file: Option<PathBuf>,
+}
+
+/// Cifra las conexiones aceptadas por el servidor.
+#[derive(Clone)]
+pub(crate) struct Acceptor(TlsAcceptor);
+
+impl Acceptor {
+    /// Realiza el handshake TLS con el cliente conectado en `socket`.
+    pub(crate) async fn accept(&self, socket: TcpStream) -> io::Result<TlsStream<TcpStream>> {
+        self.0.accept(socket).await
+    }
+}
+
+impl fmt::Debug for Acceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Acceptor").finish_non_exhaustive()
+    }
+}
+
+/// Crea el `Acceptor` con el que el servidor cifra las conexiones
+/// aceptadas.
+pub(crate) fn acceptor(config: &TlsConfig) -> crate::Result<Acceptor> {
+    let certs = load_certs(&config.cert_file)?;
+    let key = load_key(&config.key_file)?;
+
+    let builder = rustls::ServerConfig::builder().with_safe_defaults();
+    let builder = match &config.ca_cert_file {
+        Some(path) => {
+            let roots = load_roots(path)?;
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let server_config = builder
+        .with_single_cert(certs, key)
+        .map_err(|err| tls_error(&config.cert_file, err))?;
+
+    Ok(Acceptor(TlsAcceptor::from(Arc::new(server_config))))
+}
+
+/// Crea el `TlsConnector` con el que un cliente cifra la conexion con el
+/// servidor, junto con el nombre con el que se verifica su certificado.
+pub(crate) fn connector(options: &TlsOptions) -> crate::Result<(TlsConnector, ServerName)> {
+    let roots = load_roots(&options.ca_cert_file)?;
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+
+    let client_config = match &options.client_cert {
+        Some((cert_file, key_file)) => builder
+            .with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)
+            .map_err(|err| tls_error(cert_file, err))?,
+        None => builder.with_no_client_auth(),
+    };
+
+    let server_name = ServerName::try_from(options.server_name.as_str())
+        .map_err(|_| format!("invalid TLS server name: {}", options.server_name))?;
+
+    Ok((TlsConnector::from(Arc::new(client_config)), server_name))
+}
+
+/// Lee los certificados de un fichero PEM.
+fn load_certs(path: &Path) -> crate::Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader)?;
+
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {}", path.display()).into());
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Lee la primera clave privada (PKCS#8, RSA o EC) de un fichero PEM.
+fn load_key(path: &Path) -> crate::Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+
+    Err(format!("no private key found in {}", path.display()).into())
+}
+
+/// Lee los certificados de las CA de confianza de un fichero PEM.
+fn load_roots(path: &Path) -> crate::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+
+    for cert in load_certs(path)? {
+        roots
+            .add(&cert)
+            .map_err(|err| format!("invalid CA certificate in {}: {}", path.display(), err))?;
+    }
+
+    Ok(roots)
+}
+
+/// Error de configuracion TLS relativo al fichero `path`.
+fn tls_error(path: &Path, err: rustls::Error) -> crate::Error {
+    format!("invalid TLS configuration for {}: {}", path.display(), err).into()
+}
@@ -0,0 +1,268 @@
+use mini_redis::client::{self, Credentials};
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::{Error, Frame, ServerErrorKind};
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+
+/// Users are limited to their command categories and key patterns.
+#[tokio::test]
+async fn command_and_key_permissions() {
+    let addr = start_server(None).await;
+    let mut admin = client::connect(addr).await.unwrap();
+
+    let reply = admin
+        .command(&[
+            "acl", "setuser", "reader", "on", ">secret", "~cache:*", "-@all", "+@read",
+        ])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+    admin.set("cache:a", "1".into()).await.unwrap();
+    admin.set("other", "2".into()).await.unwrap();
+
+    let credentials = Credentials::with_username("reader", "secret");
+    let mut reader = client::connect_with_credentials(addr, &credentials)
+        .await
+        .unwrap();
+
+    let value = reader.get("cache:a").await.unwrap().unwrap();
+    assert_eq!(b"1", &value[..]);
+
+    let err = noperm(reader.get("other").await);
+    assert_eq!(
+        "this user has no permissions to access one of the keys used as arguments",
+        err
+    );
+    let err = noperm(reader.set("cache:a", "3".into()).await);
+    assert_eq!("this user has no permissions to run the 'set' command", err);
+
+    // Single commands can be allowed on top of the categories
+    admin
+        .command(&["acl", "setuser", "reader", "+set"])
+        .await
+        .unwrap();
+    reader.set("cache:a", "3".into()).await.unwrap();
+
+    let reply = admin.command(&["acl", "whoami"]).await.unwrap();
+    assert_eq!(reply, "default");
+
+    // Unknown rules leave the user untouched
+    match admin
+        .command(&["acl", "setuser", "reader", "off", "+@nope"])
+        .await
+    {
+        Err(Error::Server(err)) => assert_eq!(
+            "Error in ACL SETUSER modifier '+@nope': Unknown command or category name in ACL",
+            err.message()
+        ),
+        res => panic!("unexpected result {:?}", res),
+    }
+    match admin.command(&["acl", "getuser", "reader"]).await.unwrap() {
+        Frame::Array(fields) => {
+            assert_eq!(fields[0], "flags");
+            assert!(matches!(&fields[1], Frame::Array(flags) if flags[0] == "on"));
+            assert_eq!(fields[4], "commands");
+            assert_eq!(fields[5], "-@all +@read +set");
+            assert_eq!(fields[7], "~cache:*");
+        }
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+/// Publishing and subscribing are limited to the user's channel patterns,
+/// also for the subscriptions made while subscribed.
+#[tokio::test]
+async fn channel_permissions() {
+    let addr = start_server(None).await;
+    let mut admin = client::connect(addr).await.unwrap();
+
+    admin
+        .command(&[
+            "acl", "setuser", "news", "on", "nopass", "&news.*", "+@pubsub",
+        ])
+        .await
+        .unwrap();
+
+    let credentials = Credentials::with_username("news", "any");
+    let mut publisher = client::connect_with_credentials(addr, &credentials)
+        .await
+        .unwrap();
+    assert_eq!(
+        0,
+        publisher.publish("news.tech", "hi".into()).await.unwrap()
+    );
+    noperm(publisher.publish("sports", "hi".into()).await);
+
+    let subscriber = client::connect_with_credentials(addr, &credentials)
+        .await
+        .unwrap();
+    assert!(subscriber.subscribe(vec!["sports".into()]).await.is_err());
+
+    let subscriber = client::connect_with_credentials(addr, &credentials)
+        .await
+        .unwrap();
+    let mut subscriber = subscriber
+        .subscribe(vec!["news.tech".into()])
+        .await
+        .unwrap();
+    assert!(subscriber.subscribe(&["sports".into()]).await.is_err());
+}
+
+/// Denied attempts and failed authentications are logged, grouping the
+/// repeated ones.
+#[tokio::test]
+async fn acl_log() {
+    let addr = start_server(None).await;
+    let mut admin = client::connect(addr).await.unwrap();
+
+    admin
+        .command(&["acl", "setuser", "limited", "on", ">pw", "allkeys", "+get"])
+        .await
+        .unwrap();
+
+    let credentials = Credentials::with_username("limited", "pw");
+    let mut limited = client::connect_with_credentials(addr, &credentials)
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        noperm(limited.set("foo", "bar".into()).await);
+    }
+    assert!(limited
+        .auth(&Credentials::with_username("limited", "wrong"))
+        .await
+        .is_err());
+
+    let entries = match admin.command(&["acl", "log"]).await.unwrap() {
+        Frame::Array(entries) => entries,
+        frame => panic!("unexpected frame {:?}", frame),
+    };
+    assert_eq!(2, entries.len());
+
+    let field = |entry: &Frame, name: &str| match entry {
+        Frame::Array(fields) => fields
+            .chunks(2)
+            .find(|pair| pair[0] == name)
+            .map(|pair| pair[1].clone())
+            .unwrap(),
+        frame => panic!("unexpected frame {:?}", frame),
+    };
+
+    // The most recent entry first
+    assert_eq!(field(&entries[0], "reason"), "auth");
+    assert_eq!(field(&entries[1], "reason"), "command");
+    assert_eq!(field(&entries[1], "object"), "set");
+    assert_eq!(field(&entries[1], "username"), "limited");
+    assert!(matches!(field(&entries[1], "count"), Frame::Integer(2)));
+
+    assert_eq!(admin.command(&["acl", "log", "reset"]).await.unwrap(), "OK");
+    assert!(matches!(
+        admin.command(&["acl", "log"]).await.unwrap(),
+        Frame::Array(entries) if entries.is_empty()
+    ));
+}
+
+/// Users are loaded from the ACL file on startup and saved back with
+/// ACL SAVE.
+#[tokio::test]
+async fn acl_file() {
+    let path = acl_path("file");
+    std::fs::write(
+        &path,
+        "# Users\n\
+         user default on >admin ~* &* +@all\n\
+         user reader on >secret ~* resetchannels -@all +@read\n",
+    )
+    .unwrap();
+
+    let addr = start_server(Some(path.clone())).await;
+
+    let mut client = client::connect(addr).await.unwrap();
+    match client.get("foo").await {
+        Err(Error::Server(err)) => assert_eq!(&ServerErrorKind::NoAuth, err.kind()),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let credentials = Credentials::with_username("reader", "secret");
+    let mut reader = client::connect_with_credentials(addr, &credentials)
+        .await
+        .unwrap();
+    assert!(reader.get("foo").await.unwrap().is_none());
+    noperm(reader.set("foo", "bar".into()).await);
+
+    let mut admin = client::connect_with_credentials(addr, &Credentials::new("admin"))
+        .await
+        .unwrap();
+    admin
+        .command(&["acl", "setuser", "writer", "on", ">w", "allkeys", "+set"])
+        .await
+        .unwrap();
+    assert_eq!(
+        1,
+        integer(admin.command(&["acl", "deluser", "reader"]).await.unwrap())
+    );
+    assert_eq!(admin.command(&["acl", "save"]).await.unwrap(), "OK");
+
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("user writer on #"), "{}", saved);
+    assert!(!saved.contains("user reader"), "{}", saved);
+
+    // An invalid file is rejected as a whole
+    std::fs::write(&path, "user writer on +@nope\n").unwrap();
+    assert!(admin.command(&["acl", "load"]).await.is_err());
+    let users = admin.command(&["acl", "users"]).await.unwrap();
+    assert!(matches!(users, Frame::Array(users) if users.len() == 2));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+fn noperm<T: std::fmt::Debug>(res: mini_redis::Result<T>) -> String {
+    match res {
+        Err(Error::Server(err)) => {
+            assert_eq!(&ServerErrorKind::NoPerm, err.kind());
+            err.message().to_string()
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+fn integer(frame: Frame) -> u64 {
+    match frame {
+        Frame::Integer(value) => value,
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+async fn start_server(aclfile: Option<PathBuf>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = ServerConfig {
+        aclfile,
+        ..ServerConfig::default()
+    };
+
+    tokio::spawn(server::run_with_config(
+        listener,
+        config,
+        std::future::pending::<()>(),
+    ));
+
+    addr
+}
+
+fn acl_path(name: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+
+    std::env::temp_dir().join(format!(
+        "mini-redis-{}-{}-{}.acl",
+        name,
+        std::process::id(),
+        nanos
+    ))
+}
@@ -0,0 +1,286 @@
+use mini_redis::aof::{self, AofConfig, AofError, Fsync};
+use mini_redis::client::{self, Client};
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::{Error, Frame, ServerErrorKind};
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::process::Command;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+
+/// Writes are replayed from the append only file when a new server starts,
+/// including the keys' expirations.
+#[tokio::test]
+async fn writes_survive_restart() {
+    let path = aof_path("restart");
+
+    let server = TestServer::start(&path, Fsync::Always).await;
+    let mut client = server.client().await;
+
+    client.set("hello", "world".into()).await.unwrap();
+    client.set("hello", "again".into()).await.unwrap();
+    client
+        .set_expires("short", "lived".into(), Duration::from_millis(200))
+        .await
+        .unwrap();
+    client
+        .set_expires("long", "lived".into(), Duration::from_secs(3600))
+        .await
+        .unwrap();
+
+    drop(client);
+    server.stop().await;
+
+    // Wait until `short` expires. The restart must not extend its life.
+    time::sleep(Duration::from_millis(300)).await;
+
+    let server = TestServer::start(&path, Fsync::Always).await;
+    let mut client = server.client().await;
+
+    assert_eq!(b"again", &client.get("hello").await.unwrap().unwrap()[..]);
+    assert_eq!(b"lived", &client.get("long").await.unwrap().unwrap()[..]);
+
+    // The expired key may be removed by the purge task after the load.
+    time::sleep(Duration::from_millis(50)).await;
+    assert!(client.get("short").await.unwrap().is_none());
+
+    drop(client);
+    server.stop().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+/// BGREWRITEAOF compacts the file to one command per key, and writes made
+/// after the rewrite are still logged.
+#[tokio::test]
+async fn rewrite_compacts_log() {
+    let path = aof_path("rewrite");
+
+    // With `always`, every write is in the file once acknowledged.
+    let server = TestServer::start(&path, Fsync::Always).await;
+    let mut client = server.client().await;
+
+    for i in 0..100 {
+        client.set("counter", i.to_string().into()).await.unwrap();
+    }
+
+    let before = std::fs::metadata(&path).unwrap().len();
+
+    let mut pipeline = client.pipeline();
+    pipeline.command(Frame::Array(vec![Frame::Bulk("bgrewriteaof".into())]));
+    let replies = pipeline.execute().await.unwrap();
+    assert!(replies[0].is_ok(), "{:?}", replies[0]);
+
+    // Wait for the background rewrite
+    for _ in 0..50 {
+        if std::fs::metadata(&path).unwrap().len() < before {
+            break;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert!(std::fs::metadata(&path).unwrap().len() < before);
+
+    client.set("after", "rewrite".into()).await.unwrap();
+
+    drop(client);
+    server.stop().await;
+
+    let server = TestServer::start(&path, Fsync::EverySec).await;
+    let mut client = server.client().await;
+
+    assert_eq!(b"99", &client.get("counter").await.unwrap().unwrap()[..]);
+    assert_eq!(b"rewrite", &client.get("after").await.unwrap().unwrap()[..]);
+
+    drop(client);
+    server.stop().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+/// A command cut in half at the end of the file is discarded.
+#[tokio::test]
+async fn truncated_tail_is_ignored() {
+    let path = aof_path("truncated");
+
+    std::fs::write(
+        &path,
+        b"*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n$5\r\nworld\r\n*3\r\n$3\r\nSET\r\n$3\r\nfoo",
+    )
+    .unwrap();
+
+    let server = TestServer::start(&path, Fsync::No).await;
+    let mut client = server.client().await;
+
+    assert_eq!(b"world", &client.get("hello").await.unwrap().unwrap()[..]);
+    assert!(client.get("foo").await.unwrap().is_none());
+
+    drop(client);
+    server.stop().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+/// A write the file can't hold is replied with MISCONF and cut from the
+/// file, and later writes are rejected without being applied. The server
+/// starts again from the file afterwards.
+#[tokio::test]
+async fn write_failure_rejects_writes() {
+    let path = aof_path("failure");
+    let socket = path.with_extension("sock");
+    let dbfilename = path.with_extension("rdb");
+
+    // The server can't write files beyond 512 bytes, so appending a large
+    // value fails half-way. SIGXFSZ is ignored for the write to fail with
+    // EFBIG instead of killing the server.
+    let mut server = Command::new("sh")
+        .arg("-c")
+        .arg("trap '' XFSZ; ulimit -f 1; exec \"$0\" \"$@\"")
+        .arg(env!("CARGO_BIN_EXE_mini-redis-server"))
+        .args(["--port", "0", "--save", "", "--appendonly"])
+        .args(["--appendfsync", "always"])
+        .arg("--unixsocket")
+        .arg(&socket)
+        .arg("--dir")
+        .arg(path.parent().unwrap())
+        .arg("--appendfilename")
+        .arg(path.file_name().unwrap())
+        .arg("--dbfilename")
+        .arg(dbfilename.file_name().unwrap())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let mut client = None;
+    for _ in 0..100 {
+        if let Ok(connected) = client::connect_unix(&socket).await {
+            client = Some(connected);
+            break;
+        }
+        time::sleep(Duration::from_millis(50)).await;
+    }
+    let mut client = client.expect("server not started");
+
+    client.set("small", "1".into()).await.unwrap();
+
+    let assert_misconf = |res: mini_redis::Result<()>| match res {
+        Err(Error::Server(err)) => assert_eq!(&ServerErrorKind::MisConf, err.kind()),
+        res => panic!("unexpected result {:?}", res),
+    };
+    assert_misconf(client.set("big", "x".repeat(2048).into()).await);
+    assert_misconf(client.set("other", "2".into()).await);
+    assert!(client.get("other").await.unwrap().is_none());
+
+    drop(client);
+    server.kill().await.unwrap();
+
+    // The partial write was truncated
+    let check = aof::check(&std::fs::read(&path).unwrap());
+    assert!(check.error.is_none(), "{:?}", check.error);
+    assert_eq!(1, check.commands.len());
+
+    let server = TestServer::start(&path, Fsync::Always).await;
+    let mut client = server.client().await;
+
+    assert_eq!(b"1", &client.get("small").await.unwrap().unwrap()[..]);
+    assert!(client.get("big").await.unwrap().is_none());
+    client.set("other", "2".into()).await.unwrap();
+
+    drop(client);
+    server.stop().await;
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&socket);
+}
+
+/// `aof::check` stops at the first invalid command and tells a torn tail
+/// apart from a corrupted command.
+#[test]
+fn check_reports_first_bad_offset() {
+    let set = b"*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n$5\r\nworld\r\n";
+
+    let check = aof::check(set);
+    assert_eq!(1, check.commands.len());
+    assert_eq!(set.len() as u64, check.valid_len);
+    assert!(check.error.is_none());
+
+    let mut torn = set.to_vec();
+    torn.extend_from_slice(b"*3\r\n$3\r\nSET\r\n$3\r\nfoo");
+
+    let check = aof::check(&torn);
+    assert_eq!(1, check.commands.len());
+    assert_eq!(
+        Some(AofError::Truncated {
+            offset: set.len() as u64
+        }),
+        check.error
+    );
+
+    let mut corrupted = set.to_vec();
+    corrupted.extend_from_slice(b"*2\r\n$3\r\nGET\r\n$5\r\nhello\r\n");
+    corrupted.extend_from_slice(set);
+
+    let check = aof::check(&corrupted);
+    assert_eq!(1, check.commands.len());
+    assert_eq!(set.len() as u64, check.valid_len);
+    assert!(matches!(
+        check.error,
+        Some(AofError::Corrupted { offset, .. }) if offset == set.len() as u64
+    ));
+}
+
+struct TestServer {
+    addr: SocketAddr,
+    shutdown_tx: oneshot::Sender<()>,
+    handle: JoinHandle<mini_redis::Result<()>>,
+}
+
+impl TestServer {
+    async fn start(path: &Path, fsync: Fsync) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let config = ServerConfig {
+            appendonly: Some(AofConfig {
+                path: path.to_path_buf(),
+                fsync,
+            }),
+            ..ServerConfig::default()
+        };
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let handle =
+            tokio::spawn(
+                async move { server::run_with_config(listener, config, shutdown_rx).await },
+            );
+
+        TestServer {
+            addr,
+            shutdown_tx,
+            handle,
+        }
+    }
+
+    async fn client(&self) -> Client {
+        client::connect(self.addr).await.unwrap()
+    }
+
+    async fn stop(self) {
+        let _ = self.shutdown_tx.send(());
+        self.handle.await.unwrap().unwrap();
+    }
+}
+
+fn aof_path(name: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+
+    std::env::temp_dir().join(format!(
+        "mini-redis-{}-{}-{}.aof",
+        name,
+        std::process::id(),
+        nanos
+    ))
+}
@@ -0,0 +1,109 @@
+use mini_redis::blocking_client;
+use mini_redis::client::{self, Credentials};
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::{Error, ServerErrorKind};
+
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Until the connection authenticates every command is rejected with
+/// NOAUTH, and a wrong password with WRONGPASS.
+#[tokio::test]
+async fn requires_password() {
+    let addr = start_server(Some("secret")).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    match client.get("foo").await {
+        Err(Error::Server(err)) => {
+            assert_eq!(&ServerErrorKind::NoAuth, err.kind());
+            assert_eq!("Authentication required.", err.message());
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    for credentials in [
+        Credentials::new("wrong"),
+        Credentials::with_username("other", "secret"),
+    ] {
+        match client.auth(&credentials).await {
+            Err(Error::Server(err)) => assert_eq!(&ServerErrorKind::WrongPass, err.kind()),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    client.auth(&Credentials::new("secret")).await.unwrap();
+    client.set("foo", "bar".into()).await.unwrap();
+
+    // Credentials given when connecting
+    let credentials = Credentials::with_username("default", "secret");
+    let mut client = client::connect_with_credentials(addr, &credentials)
+        .await
+        .unwrap();
+    let value = client.get("foo").await.unwrap().unwrap();
+    assert_eq!(b"bar", &value[..]);
+
+    match client::connect_with_credentials(addr, &Credentials::new("wrong")).await {
+        Err(Error::Server(err)) => assert_eq!(&ServerErrorKind::WrongPass, err.kind()),
+        res => panic!("unexpected result {:?}", res.map(|_| ())),
+    }
+}
+
+/// Without a password every connection is accepted, and AUTH with a bare
+/// password is a configuration error.
+#[tokio::test]
+async fn no_password() {
+    let addr = start_server(None).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("foo", "bar".into()).await.unwrap();
+
+    match client.auth(&Credentials::new("secret")).await {
+        Err(Error::Server(err)) => {
+            assert_eq!(&ServerErrorKind::Err, err.kind());
+            assert!(err.message().starts_with("AUTH <password> called without"));
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    client
+        .auth(&Credentials::with_username("default", "anything"))
+        .await
+        .unwrap();
+}
+
+/// The blocking client authenticates when connecting.
+#[tokio::test]
+async fn blocking_client_credentials() {
+    let addr = start_server(Some("secret")).await;
+
+    tokio::task::spawn_blocking(move || {
+        let credentials = Credentials::new("secret");
+        let mut client = blocking_client::connect_with_credentials(addr, &credentials).unwrap();
+        client.set("foo", "bar".into()).unwrap();
+
+        let mut client = blocking_client::connect(addr).unwrap();
+        assert!(client.get("foo").is_err());
+        client.auth(&credentials).unwrap();
+        assert_eq!(b"bar", &client.get("foo").unwrap().unwrap()[..]);
+    })
+    .await
+    .unwrap();
+}
+
+async fn start_server(requirepass: Option<&str>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = ServerConfig {
+        requirepass: requirepass.map(str::to_string),
+        ..ServerConfig::default()
+    };
+
+    tokio::spawn(server::run_with_config(
+        listener,
+        config,
+        std::future::pending::<()>(),
+    ));
+
+    addr
+}
@@ -1,5 +1,6 @@
-use mini_redis::{buffer, client, server};
+use mini_redis::{buffer, client, server, Error};
 use std::net::SocketAddr;
+use tokio::io::AsyncReadExt;
 use tokio::net::TcpListener;
 use tokio::task::JoinHandle;
 
@@ -12,7 +13,7 @@ async fn pool_key_value_get_set() {
     let (addr, _) = start_server().await;
 
     let client = client::connect(addr).await.unwrap();
-    let mut client = buffer(client);
+    let client = buffer(client);
 
     client.set("hello", "world".into()).await.unwrap();
 
@@ -20,6 +21,63 @@ async fn pool_key_value_get_set() {
     assert_eq!(b"world", &value[..])
 }
 
+/// Several tasks share clones of the same buffer. Every request gets its own
+/// response even though they are all in flight over a single connection.
+#[tokio::test]
+async fn concurrent_requests() {
+    let (addr, _) = start_server().await;
+
+    let client = client::connect(addr).await.unwrap();
+    let client = buffer(client);
+
+    let mut handles = vec![];
+
+    for i in 0..50 {
+        let client = client.clone();
+
+        handles.push(tokio::spawn(async move {
+            let key = format!("key{}", i);
+            let value = format!("value{}", i);
+
+            client.set(&key, value.clone().into()).await.unwrap();
+            let got = client.get(&key).await.unwrap().unwrap();
+            assert_eq!(value.as_bytes(), &got[..]);
+
+            let pong = client.ping(Some(key.clone())).await.unwrap();
+            assert_eq!(key.as_bytes(), &pong[..]);
+        }));
+    }
+
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+/// When the connection is lost, requests waiting for a response fail with
+/// `Error::ConnectionClosed`, and so do the requests sent afterwards.
+#[tokio::test]
+async fn connection_failure_fails_in_flight_requests() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+
+        // Read the first request and close without replying.
+        let mut buf = [0; 1];
+        socket.read_exact(&mut buf).await.unwrap();
+    });
+
+    let client = client::connect(addr).await.unwrap();
+    let client = buffer(client);
+
+    let res = client.get("hello").await;
+    assert!(matches!(res, Err(Error::ConnectionClosed)), "{:?}", res);
+
+    let res = client.get("hello").await;
+    assert!(matches!(res, Err(Error::ConnectionClosed)), "{:?}", res);
+}
+
 async fn start_server() -> (SocketAddr, JoinHandle<()>) {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
@@ -0,0 +1,262 @@
+use mini_redis::client::{self, Credentials};
+use mini_redis::cmd::CommandHandler;
+use mini_redis::server::{Builder, ServerConfig};
+use mini_redis::{Db, DbDropGuard, Error, Frame, ServerError, ServerErrorKind};
+
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+/// A server started with a pre-populated `Db` shares its keys with the
+/// embedding application.
+#[tokio::test]
+async fn shared_db() {
+    let guard = DbDropGuard::new();
+    let db = guard.db();
+    db.set("hello".to_string(), "world".into(), None);
+
+    let server = Builder::new()
+        .config(ServerConfig {
+            port: 0,
+            ..ServerConfig::default()
+        })
+        .listener(TcpListener::bind("127.0.0.1:0").await.unwrap())
+        .db(db.clone())
+        .start()
+        .await
+        .unwrap();
+    assert_eq!(1, server.local_addrs().len());
+
+    let mut client = client::connect(server.local_addr().unwrap()).await.unwrap();
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+
+    client.set("foo", "bar".into()).await.unwrap();
+    assert_eq!(Some(Bytes::from("bar")), db.get("foo"));
+
+    let stats = server.stats();
+    assert_eq!(1, stats.connected_clients);
+    assert_eq!(2, stats.total_commands_processed);
+    assert_eq!(1, stats.keyspace_hits);
+
+    server.shutdown().await.unwrap();
+
+    // The keys outlive the server
+    assert_eq!(Some(Bytes::from("bar")), db.get("foo"));
+}
+
+/// Custom commands receive their arguments and can use the `Db`. The keys
+/// they declare are checked against the user's key patterns.
+#[tokio::test]
+async fn custom_command() {
+    let server = Builder::new()
+        .listener(TcpListener::bind("127.0.0.1:0").await.unwrap())
+        .command("append", Append)
+        .start()
+        .await
+        .unwrap();
+
+    let mut client = client::connect(server.local_addr().unwrap()).await.unwrap();
+    client.set("greeting", "hello".into()).await.unwrap();
+
+    let reply = client.command(&["APPEND", "greeting", " world"]).await;
+    assert!(matches!(reply.unwrap(), Frame::Integer(11)));
+    let value = client.get("greeting").await.unwrap().unwrap();
+    assert_eq!(b"hello world", &value[..]);
+
+    let reply = client.command(&["append", "greeting"]).await;
+    assert!(reply.is_err());
+
+    // In-process clients see the same commands
+    let mut local = client::connect_in_process(&server.db());
+    let reply = local.command(&["append", "greeting", "!"]).await;
+    assert!(matches!(reply.unwrap(), Frame::Integer(12)));
+
+    let reply = client
+        .command(&[
+            "acl",
+            "setuser",
+            "writer",
+            "on",
+            ">pw",
+            "~allowed:*",
+            "-@all",
+            "+@write",
+        ])
+        .await;
+    assert_eq!(reply.unwrap(), "OK");
+    let credentials = Credentials::with_username("writer", "pw");
+    let mut writer = client::connect_with_credentials(server.local_addr().unwrap(), &credentials)
+        .await
+        .unwrap();
+
+    let reply = writer.command(&["append", "allowed:x", "1"]).await;
+    assert!(matches!(reply.unwrap(), Frame::Integer(1)));
+    match writer.command(&["append", "greeting", "!"]).await {
+        Err(Error::Server(err)) => assert_eq!(&ServerErrorKind::NoPerm, err.kind()),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    server.shutdown().await.unwrap();
+}
+
+/// `APPEND key suffix`, which writes to its first argument.
+struct Append;
+
+impl CommandHandler for Append {
+    fn call(&self, db: &Db, args: Vec<Bytes>) -> Result<Frame, ServerError> {
+        let [key, suffix]: [Bytes; 2] = args
+            .try_into()
+            .map_err(|_| ServerError::err("wrong number of arguments for 'append' command"))?;
+        let key = String::from_utf8_lossy(&key).to_string();
+
+        let mut value = db.get(&key).map(|value| value.to_vec()).unwrap_or_default();
+        value.extend_from_slice(&suffix);
+        let len = value.len() as u64;
+        db.set(key, value.into(), None);
+
+        Ok(Frame::Integer(len))
+    }
+
+    fn keys(&self, _args: &[Bytes]) -> Vec<usize> {
+        vec![0]
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+}
+
+/// Shutting down waits for the connections to finish, and the server also
+/// stops when the given shutdown future completes.
+#[tokio::test]
+async fn shutdown() {
+    let (tx, rx) = oneshot::channel::<()>();
+    let server = Builder::new()
+        .listener(TcpListener::bind("127.0.0.1:0").await.unwrap())
+        .shutdown(rx)
+        .start()
+        .await
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let mut client = client::connect(addr).await.unwrap();
+    client.ping(None).await.unwrap();
+
+    tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Open connections are closed and no new ones are accepted
+    assert!(client.ping(None).await.is_err());
+    assert!(client::connect(addr).await.is_err());
+    assert_eq!(0, server.stats().connected_clients);
+
+    // Shutting down an already stopped server is fine
+    server.shutdown().await.unwrap();
+}
+
+/// Idle clients are closed unless they are subscribed, and the reason every
+/// connection was closed is counted.
+#[tokio::test]
+async fn close_reasons() {
+    let server = Builder::new()
+        .listener(TcpListener::bind("127.0.0.1:0").await.unwrap())
+        .config(ServerConfig {
+            timeout: Duration::from_secs(1),
+            tcp_keepalive: Duration::from_secs(60),
+            ..ServerConfig::default()
+        })
+        .start()
+        .await
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let mut idle = client::connect(addr).await.unwrap();
+    let mut subscriber = client::connect(addr)
+        .await
+        .unwrap()
+        .subscribe(vec!["news".to_string()])
+        .await
+        .unwrap();
+    let mut publisher = client::connect(addr).await.unwrap();
+
+    // Invalid frames close the connection
+    let mut invalid = TcpStream::connect(addr).await.unwrap();
+    invalid.write_all(b"*1\r\n:x\r\n").await.unwrap();
+    let mut reply = vec![];
+    invalid.read_to_end(&mut reply).await.unwrap();
+
+    // And so do the clients
+    client::connect(addr)
+        .await
+        .unwrap()
+        .ping(None)
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    assert!(idle.ping(None).await.is_err());
+    assert!(publisher.ping(None).await.is_err());
+
+    let mut publisher = client::connect(addr).await.unwrap();
+    publisher
+        .publish("news", "still here".into())
+        .await
+        .unwrap();
+    let message = subscriber.next_message().await.unwrap().unwrap();
+    assert_eq!(Bytes::from("still here"), message.content);
+
+    let stats = server.stats();
+    assert_eq!(2, stats.closed_idle_timeout);
+    assert_eq!(1, stats.closed_protocol_error);
+    assert_eq!(1, stats.closed_by_client);
+    assert_eq!(2, stats.connected_clients);
+
+    server.shutdown().await.unwrap();
+}
+
+/// Connections over `maxclients` get an error instead of waiting, and the
+/// limit can be changed at runtime.
+#[tokio::test]
+async fn max_clients() {
+    let server = Builder::new()
+        .listener(TcpListener::bind("127.0.0.1:0").await.unwrap())
+        .config(ServerConfig {
+            maxclients: 1,
+            ..ServerConfig::default()
+        })
+        .start()
+        .await
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let mut client = client::connect(addr).await.unwrap();
+    client.ping(None).await.unwrap();
+
+    let mut rejected = TcpStream::connect(addr).await.unwrap();
+    let mut reply = vec![];
+    rejected.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(&b"-ERR max number of clients reached\r\n"[..], &reply[..]);
+    assert_eq!(1, server.stats().rejected_connections);
+
+    let reply = client.command(&["config", "set", "maxclients", "2"]).await;
+    assert_eq!(reply.unwrap(), "OK");
+
+    let mut other = client::connect(addr).await.unwrap();
+    other.ping(None).await.unwrap();
+
+    // Closing a connection frees its slot
+    drop(other);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut other = client::connect(addr).await.unwrap();
+    other.ping(None).await.unwrap();
+
+    let stats = server.stats();
+    assert_eq!(1, stats.rejected_connections);
+    assert_eq!(2, stats.connected_clients);
+
+    server.shutdown().await.unwrap();
+}
@@ -1,7 +1,10 @@
-use mini_redis::{client, server};
+use mini_redis::{client, server, Error, Frame, ServerErrorKind};
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 use tokio::task::JoinHandle;
+use tokio::time;
 
 /// A PING PONG test without message provided.
 /// It should return "PONG".
@@ -45,7 +48,7 @@ async fn key_value_get_set() {
 async fn receive_message_subscribed_channel() {
     let (addr, _) = start_server().await;
 
-    let client = client::connect(addr.clone()).await.unwrap();
+    let client = client::connect(addr).await.unwrap();
     let mut subscriber = client.subscribe(vec!["hello".into()]).await.unwrap();
 
     tokio::spawn(async move {
@@ -63,7 +66,7 @@ async fn receive_message_subscribed_channel() {
 async fn receive_message_multiple_subscribed_channels() {
     let (addr, _) = start_server().await;
 
-    let client = client::connect(addr.clone()).await.unwrap();
+    let client = client::connect(addr).await.unwrap();
     let mut subscriber = client
         .subscribe(vec!["hello".into(), "world".into()])
         .await
@@ -94,7 +97,7 @@ async fn receive_message_multiple_subscribed_channels() {
 async fn unsubscribes_from_channels() {
     let (addr, _) = start_server().await;
 
-    let client = client::connect(addr.clone()).await.unwrap();
+    let client = client::connect(addr).await.unwrap();
     let mut subscriber = client
         .subscribe(vec!["hello".into(), "world".into()])
         .await
@@ -104,6 +107,306 @@ async fn unsubscribes_from_channels() {
     assert_eq!(subscriber.get_subscribed().len(), 0);
 }
 
+/// Several commands are sent in a single pipeline. Each one gets its own
+/// reply, in order, and a failing command does not affect the others.
+#[tokio::test]
+async fn pipeline_commands() {
+    let (addr, _) = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    // A `GET` without a key is rejected by the server
+    let invalid = Frame::Array(vec![Frame::Bulk("get".into())]);
+
+    let replies = client
+        .pipeline()
+        .set("hello", "world".into())
+        .get("hello")
+        .command(invalid)
+        .ping(None)
+        .execute()
+        .await
+        .unwrap();
+
+    assert_eq!(4, replies.len());
+    assert_eq!(replies[0].as_ref().unwrap(), &"OK");
+    assert_eq!(replies[1].as_ref().unwrap(), &"world");
+    assert!(matches!(replies[2], Err(Error::Server(_))));
+    assert_eq!(replies[3].as_ref().unwrap(), &"PONG");
+
+    // The client is still usable
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+}
+
+/// A value serialized with DUMP is restored under a new key, keeping its
+/// TTL when one is given.
+#[tokio::test]
+async fn dump_and_restore() {
+    let (addr, _) = start_server().await;
+
+    let mut client = client::connect(addr).await.unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+
+    assert!(client.dump("missing").await.unwrap().is_none());
+
+    let payload = client.dump("hello").await.unwrap().unwrap();
+
+    client
+        .restore("copy", payload.clone(), None, false)
+        .await
+        .unwrap();
+    let value = client.get("copy").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+
+    client
+        .restore("short", payload, Some(Duration::from_millis(100)), false)
+        .await
+        .unwrap();
+    assert!(client.get("short").await.unwrap().is_some());
+
+    time::sleep(Duration::from_millis(200)).await;
+    assert!(client.get("short").await.unwrap().is_none());
+}
+
+/// RESTORE refuses to overwrite a key unless REPLACE is given and rejects
+/// payloads with a wrong checksum.
+#[tokio::test]
+async fn restore_errors() {
+    let (addr, _) = start_server().await;
+
+    let mut client = client::connect(addr).await.unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+    client.set("other", "value".into()).await.unwrap();
+
+    let payload = client.dump("other").await.unwrap().unwrap();
+
+    match client.restore("hello", payload.clone(), None, false).await {
+        Err(Error::Server(err)) => assert_eq!(&ServerErrorKind::BusyKey, err.kind()),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    client
+        .restore("hello", payload.clone(), None, true)
+        .await
+        .unwrap();
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"value", &value[..]);
+
+    let mut corrupted = payload.to_vec();
+    corrupted[5] ^= 0xff;
+    match client.restore("new", corrupted.into(), None, false).await {
+        Err(Error::Server(err)) => {
+            assert_eq!("DUMP payload version or checksum are wrong", err.message())
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(client.get("new").await.unwrap().is_none());
+}
+
+/// Error replies sent by the server are surfaced as a typed `ServerError`,
+/// and the client can keep issuing commands afterwards.
+#[tokio::test]
+async fn server_error_is_typed() {
+    // A fake server answering the first request with an error and the
+    // second one with a value.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        // `*2\r\n$3\r\nget\r\n$5\r\nhello\r\n`
+        let mut buf = [0; 24];
+
+        socket.read_exact(&mut buf).await.unwrap();
+        socket
+            .write_all(b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n")
+            .await
+            .unwrap();
+
+        socket.read_exact(&mut buf).await.unwrap();
+        socket.write_all(b"$5\r\nworld\r\n").await.unwrap();
+    });
+
+    let mut client = client::connect(addr).await.unwrap();
+
+    let err = match client.get("hello").await {
+        Err(Error::Server(err)) => err,
+        res => panic!("unexpected result {:?}", res),
+    };
+    assert_eq!(&ServerErrorKind::WrongType, err.kind());
+    assert_eq!(
+        "Operation against a key holding the wrong kind of value",
+        err.message()
+    );
+
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+}
+
+/// A server closing the connection before replying is reported as
+/// `Error::ConnectionClosed`.
+#[tokio::test]
+async fn connection_closed_is_typed() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+
+        // Read the request and hang up
+        let mut buf = [0; 24];
+        socket.read_exact(&mut buf).await.unwrap();
+    });
+
+    let mut client = client::connect(addr).await.unwrap();
+
+    match client.get("hello").await {
+        Err(Error::ConnectionClosed) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+/// A sharded client spreads the keys among the servers, keeping the keys
+/// with the same hashtag together.
+#[tokio::test]
+async fn sharded_client_spreads_keys() {
+    let mut addrs = vec![];
+    for _ in 0..3 {
+        addrs.push(start_server().await.0);
+    }
+
+    let mut client = client::connect_sharded(&addrs).await.unwrap();
+
+    let keys: Vec<_> = (0..60).map(|i| format!("key:{}", i)).collect();
+    for key in &keys {
+        client.set(key, key.clone().into()).await.unwrap();
+    }
+
+    // Each key is stored only in its shard, and every shard got some keys
+    for addr in &addrs {
+        let mut shard = client::connect(addr).await.unwrap();
+        let mut count = 0;
+
+        for key in &keys {
+            let value = shard.get(key).await.unwrap();
+            assert_eq!(client.shard_for(key) == addr.to_string(), value.is_some());
+            count += value.is_some() as usize;
+        }
+
+        assert!(count > 0, "no keys in {}", addr);
+    }
+
+    let keys: Vec<_> = keys.iter().map(String::as_str).collect();
+    let values = client.get_many(&keys).await;
+    for (key, value) in keys.iter().zip(values) {
+        assert_eq!(key.as_bytes(), &value.unwrap().unwrap()[..]);
+    }
+
+    assert_eq!(
+        client.shard_for("{user:1}:name"),
+        client.shard_for("{user:1}:email")
+    );
+}
+
+/// An unavailable server only fails the requests for its keys.
+#[tokio::test]
+async fn sharded_client_shard_failure() {
+    let (up, _) = start_server().await;
+
+    // An address nobody listens on
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let down = listener.local_addr().unwrap();
+    drop(listener);
+
+    let mut client = client::connect_sharded(&[up, down]).await.unwrap();
+
+    // The port of `down` may be taken by the server of another test, so the
+    // keys must not clash with the keys of other tests.
+    let key_on = |shard: SocketAddr| {
+        (0..)
+            .map(|i| format!("shard-failure:{}", i))
+            .find(|key| client.shard_for(key) == shard.to_string())
+            .unwrap()
+    };
+    let (up_key, down_key) = (key_on(up), key_on(down));
+
+    client.set(&up_key, "1".into()).await.unwrap();
+    match client.set(&down_key, "2".into()).await {
+        Err(Error::Shard(err)) => {
+            assert_eq!(down.to_string(), err.addr());
+            assert!(matches!(err.error(), Error::Io(_)), "{:?}", err);
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    // While the server is waited on, its requests fail with the same error
+    let mut values = client.get_many(&[&down_key, &up_key]).await.into_iter();
+    match values.next().unwrap() {
+        Err(Error::Shard(err)) => {
+            assert_eq!(down.to_string(), err.addr());
+            assert!(matches!(err.error(), Error::Io(_)), "{:?}", err);
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert_eq!(b"1", &values.next().unwrap().unwrap().unwrap()[..]);
+
+    let pings = client.ping(None).await;
+    assert!(pings[0].is_ok());
+    assert!(pings[1].is_err());
+
+    // Once the server is back it is connected to again
+    let listener = TcpListener::bind(down).await.unwrap();
+    tokio::spawn(async move { server::run(listener, tokio::signal::ctrl_c()).await });
+
+    let mut res = client.set(&down_key, "2".into()).await;
+    for _ in 0..20 {
+        if res.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        res = client.set(&down_key, "2".into()).await;
+    }
+    res.unwrap();
+}
+
+/// A server that does not answer the connection does not delay the
+/// requests for the other servers.
+#[tokio::test]
+async fn sharded_client_unreachable_shard() {
+    let (up, _) = start_server().await;
+
+    // A non routable address, where connecting hangs or fails
+    let unreachable = "10.255.255.1:6379";
+
+    let start = Instant::now();
+    let mut client = client::connect_sharded(&[up.to_string(), unreachable.to_string()])
+        .await
+        .unwrap();
+
+    let up_key = (0..)
+        .map(|i| format!("key:{}", i))
+        .find(|key| client.shard_for(key) == up.to_string())
+        .unwrap();
+    let down_key = (0..)
+        .map(|i| format!("key:{}", i))
+        .find(|key| client.shard_for(key) == unreachable)
+        .unwrap();
+
+    client.set(&up_key, "1".into()).await.unwrap();
+    for _ in 0..3 {
+        let values = client.get_many(&[&up_key, &down_key]).await;
+        assert!(values[0].is_ok());
+        match &values[1] {
+            Err(Error::Shard(err)) => {
+                assert_eq!(unreachable, err.addr());
+                assert!(matches!(err.error(), Error::Timeout | Error::Io(_)));
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+    assert!(start.elapsed() < Duration::from_secs(6));
+}
+
 async fn start_server() -> (SocketAddr, JoinHandle<()>) {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
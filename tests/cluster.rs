@@ -0,0 +1,317 @@
+use mini_redis::client::{self, Client, Credentials};
+use mini_redis::cluster;
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::{Error, Frame, ServerError, ServerErrorKind};
+
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Keys hash to CRC16 slots, and only the hashtag counts when there is one.
+#[tokio::test]
+async fn key_slots() {
+    assert_eq!(12739, cluster::key_slot(b"123456789"));
+    assert_eq!(12182, cluster::key_slot(b"foo"));
+    assert_eq!(
+        cluster::key_slot(b"user1000"),
+        cluster::key_slot(b"{user1000}.following")
+    );
+    assert_eq!(
+        cluster::key_slot(b"{user1000}.following"),
+        cluster::key_slot(b"{user1000}.followers")
+    );
+    // An empty hashtag is not a hashtag
+    assert_ne!(cluster::key_slot(b"{}foo"), cluster::key_slot(b"foo"));
+
+    let addr = start_server(true).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let slot = client
+        .command(&["cluster", "keyslot", "{user1000}.following"])
+        .await
+        .unwrap();
+    assert_eq!(cluster::key_slot(b"user1000") as u64, integer(slot));
+
+    // Without cluster mode the CLUSTER commands are rejected
+    let addr = start_server(false).await;
+    let mut client = client::connect(addr).await.unwrap();
+    let err = client.command(&["cluster", "keyslot", "foo"]).await;
+    assert_eq!(
+        "This instance has cluster support disabled",
+        server_error(err).message()
+    );
+}
+
+/// Each node serves its own slots and redirects the rest with MOVED.
+#[tokio::test]
+async fn moved_redirects() {
+    let (a, b) = (start_server(true).await, start_server(true).await);
+    let mut a_client = client::connect(a).await.unwrap();
+    let mut b_client = client::connect(b).await.unwrap();
+
+    let foo_slot = cluster::key_slot(b"foo");
+    let bar_slot = cluster::key_slot(b"bar");
+    assert!(bar_slot < foo_slot);
+
+    // Unassigned slots are not served
+    let err = a_client.get("foo").await;
+    assert_eq!(&ServerErrorKind::ClusterDown, server_error(err).kind());
+
+    add_slots(&mut a_client, 0, foo_slot).await;
+    add_slots(&mut b_client, foo_slot, cluster::SLOTS).await;
+
+    // Meeting each other the nodes learn who serves the other slots
+    join(&mut a_client, b).await;
+    join(&mut b_client, a).await;
+
+    let err = a_client.command(&["cluster", "addslots", "0"]).await;
+    assert_eq!("Slot 0 is already busy", server_error(err).message());
+
+    a_client.set("bar", "1".into()).await.unwrap();
+    b_client.set("foo", "2".into()).await.unwrap();
+
+    let err = server_error(a_client.get("foo").await);
+    assert_eq!(&ServerErrorKind::Moved, err.kind());
+    assert_eq!(format!("{} {}", foo_slot, b), err.message());
+
+    let err = server_error(b_client.set("bar", "3".into()).await);
+    assert_eq!(&ServerErrorKind::Moved, err.kind());
+    assert_eq!(format!("{} {}", bar_slot, a), err.message());
+
+    // Multi-key commands need all the keys in the same slot
+    let err = a_client.command(&["del", "bar", "foo"]).await;
+    assert_eq!(&ServerErrorKind::CrossSlot, server_error(err).kind());
+
+    let deleted = b_client
+        .command(&["del", "{foo}a", "{foo}b", "foo"])
+        .await
+        .unwrap();
+    assert_eq!(1, integer(deleted));
+
+    let slots = a_client.command(&["cluster", "slots"]).await.unwrap();
+    let b_id = node_id(&mut b_client).await;
+    let ranges = match slots {
+        Frame::Array(ranges) => ranges,
+        frame => panic!("unexpected frame {:?}", frame),
+    };
+    assert_eq!(2, ranges.len());
+
+    match &ranges[1] {
+        Frame::Array(range) => match &range[..] {
+            [Frame::Integer(start), Frame::Integer(end), Frame::Array(node)] => {
+                assert_eq!(foo_slot as u64, *start);
+                assert_eq!(cluster::SLOTS as u64 - 1, *end);
+                assert_eq!(node[0], b.ip().to_string().as_str());
+                assert_eq!(b.port() as u64, integer(node[1].clone()));
+                assert_eq!(node[2], b_id.as_str());
+            }
+            range => panic!("unexpected range {:?}", range),
+        },
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+/// A slot moves to another node while its keys keep being served, with ASK
+/// redirects for the keys that were already migrated.
+#[tokio::test]
+async fn slot_migration() {
+    let (a, b) = (start_server(true).await, start_server(true).await);
+    let mut a_client = client::connect(a).await.unwrap();
+    let mut b_client = client::connect(b).await.unwrap();
+
+    let slot = cluster::key_slot(b"foo");
+    let slot_arg = slot.to_string();
+    add_slots(&mut a_client, slot, slot + 1).await;
+
+    join(&mut a_client, b).await;
+    join(&mut b_client, a).await;
+
+    a_client.set("{foo}a", "1".into()).await.unwrap();
+    a_client.set("{foo}b", "2".into()).await.unwrap();
+
+    let a_id = node_id(&mut a_client).await;
+    let b_id = node_id(&mut b_client).await;
+
+    let setslot = ["cluster", "setslot", &slot_arg];
+    b_client
+        .command(&[&setslot[..], &["importing", &a_id]].concat())
+        .await
+        .unwrap();
+    a_client
+        .command(&[&setslot[..], &["migrating", &b_id]].concat())
+        .await
+        .unwrap();
+
+    let keys = a_client
+        .command(&["cluster", "getkeysinslot", &slot_arg, "10"])
+        .await
+        .unwrap();
+    match keys {
+        Frame::Array(keys) => assert_eq!(2, keys.len()),
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+
+    let port = b.port().to_string();
+    let reply = a_client
+        .command(&["migrate", "127.0.0.1", &port, "{foo}a", "0", "1000"])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+
+    // The keys still in the source are served there, the migrated ones are
+    // redirected with ASK.
+    let value = a_client.get("{foo}b").await.unwrap().unwrap();
+    assert_eq!(b"2", &value[..]);
+
+    let err = server_error(a_client.get("{foo}a").await);
+    assert_eq!(&ServerErrorKind::Ask, err.kind());
+    assert_eq!(format!("{} {}", slot, b), err.message());
+
+    let err = a_client.command(&["del", "{foo}a", "{foo}b"]).await;
+    assert_eq!(&ServerErrorKind::TryAgain, server_error(err).kind());
+
+    // The target only serves the importing slot after ASKING, and only for
+    // the next command.
+    let err = server_error(b_client.get("{foo}a").await);
+    assert_eq!(&ServerErrorKind::Moved, err.kind());
+
+    let mut pipeline = b_client.pipeline();
+    pipeline.command(Frame::Array(vec![Frame::Bulk("asking".into())]));
+    pipeline.get("{foo}a");
+    pipeline.get("{foo}a");
+    let mut responses = pipeline.execute().await.unwrap().into_iter();
+    assert_eq!(responses.next().unwrap().unwrap(), "OK");
+    assert_eq!(responses.next().unwrap().unwrap(), "1");
+    assert!(responses.next().unwrap().is_err());
+
+    // Finish the migration
+    let reply = a_client
+        .command(&["migrate", "127.0.0.1", &port, "{foo}b", "0", "1000"])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+
+    for client in [&mut a_client, &mut b_client] {
+        client
+            .command(&[&setslot[..], &["node", &b_id]].concat())
+            .await
+            .unwrap();
+    }
+
+    let value = b_client.get("{foo}b").await.unwrap().unwrap();
+    assert_eq!(b"2", &value[..]);
+
+    let err = server_error(a_client.get("{foo}a").await);
+    assert_eq!(&ServerErrorKind::Moved, err.kind());
+
+    let count = b_client
+        .command(&["cluster", "countkeysinslot", &slot_arg])
+        .await
+        .unwrap();
+    assert_eq!(2, integer(count));
+}
+
+/// Sends `CLUSTER MEET` for the node at `other`.
+async fn join(client: &mut Client, other: SocketAddr) {
+    let host = other.ip().to_string();
+    let port = other.port().to_string();
+
+    let reply = client
+        .command(&["cluster", "meet", &host, &port])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+}
+
+/// Assigns the slots in `start..end` to the node.
+async fn add_slots(client: &mut Client, start: u16, end: u16) {
+    let slots: Vec<_> = (start..end).map(|slot| slot.to_string()).collect();
+    let args: Vec<_> = ["cluster", "addslots"]
+        .into_iter()
+        .chain(slots.iter().map(String::as_str))
+        .collect();
+
+    let reply = client.command(&args).await.unwrap();
+    assert_eq!(reply, "OK");
+}
+
+async fn node_id(client: &mut Client) -> String {
+    match client.command(&["cluster", "myid"]).await.unwrap() {
+        Frame::Bulk(id) => String::from_utf8(id.to_vec()).unwrap(),
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+/// CLUSTER MEET authenticates with the other node using `masterauth`.
+#[tokio::test]
+async fn meet_authenticates() {
+    let a = start_with_config(ServerConfig {
+        cluster_enabled: true,
+        requirepass: Some("secret".to_string()),
+        masterauth: Some("secret".to_string()),
+        ..ServerConfig::default()
+    })
+    .await;
+    let b = start_with_config(ServerConfig {
+        cluster_enabled: true,
+        requirepass: Some("secret".to_string()),
+        ..ServerConfig::default()
+    })
+    .await;
+
+    let credentials = Credentials::new("secret");
+    let mut a_client = client::connect_with_credentials(a, &credentials)
+        .await
+        .unwrap();
+    let mut b_client = client::connect_with_credentials(b, &credentials)
+        .await
+        .unwrap();
+
+    // B has no password for A
+    let port = a.port().to_string();
+    let err = b_client
+        .command(&["cluster", "meet", "127.0.0.1", &port])
+        .await;
+    assert!(server_error(err)
+        .message()
+        .starts_with("Can't connect to the node"));
+
+    let port = b.port().to_string();
+    let reply = a_client
+        .command(&["cluster", "meet", "127.0.0.1", &port])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+}
+
+fn integer(frame: Frame) -> u64 {
+    match frame {
+        Frame::Integer(n) => n,
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+fn server_error<T: std::fmt::Debug>(res: mini_redis::Result<T>) -> ServerError {
+    match res {
+        Err(Error::Server(err)) => err,
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+async fn start_server(cluster_enabled: bool) -> SocketAddr {
+    start_with_config(ServerConfig {
+        cluster_enabled,
+        ..ServerConfig::default()
+    })
+    .await
+}
+
+async fn start_with_config(config: ServerConfig) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        server::run_with_config(listener, config, std::future::pending::<()>()).await
+    });
+
+    addr
+}
@@ -0,0 +1,169 @@
+use mini_redis::client::{self, Client};
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::{cluster, cluster_client, Frame};
+
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Commands are routed to the node serving each key, starting from a single
+/// seed.
+#[tokio::test]
+async fn routes_by_slot() {
+    let (a, b) = start_cluster().await;
+    let mut client = cluster_client::connect(&[a]).await.unwrap();
+
+    // "bar" is served by `a` and "foo" by `b`
+    client.set("bar", "1".into()).await.unwrap();
+    client.set("foo", "2".into()).await.unwrap();
+    client.set("{foo}x", "3".into()).await.unwrap();
+
+    let mut a_client = client::connect(a).await.unwrap();
+    let mut b_client = client::connect(b).await.unwrap();
+    let count = a_client
+        .command(&["cluster", "countkeysinslot", &slot("bar")])
+        .await
+        .unwrap();
+    assert!(matches!(count, Frame::Integer(1)), "{:?}", count);
+    let count = b_client
+        .command(&["cluster", "countkeysinslot", &slot("foo")])
+        .await
+        .unwrap();
+    assert!(matches!(count, Frame::Integer(2)), "{:?}", count);
+
+    let values = client
+        .get_many(&["foo", "missing", "bar", "{foo}x"])
+        .await
+        .unwrap();
+    let values: Vec<_> = values.iter().map(|value| value.as_deref()).collect();
+    assert_eq!(
+        vec![Some(&b"2"[..]), None, Some(&b"1"[..]), Some(&b"3"[..])],
+        values
+    );
+
+    assert_eq!(
+        3,
+        client
+            .del(&["foo", "bar", "{foo}x", "missing"])
+            .await
+            .unwrap()
+    );
+    assert!(client.get("foo").await.unwrap().is_none());
+}
+
+/// A stale slot map is fixed following MOVED, and keys being migrated are
+/// reached following ASK.
+#[tokio::test]
+async fn follows_redirects() {
+    let (a, b) = start_cluster().await;
+    let mut client = cluster_client::connect(&[a, b]).await.unwrap();
+
+    client.set("{foo}a", "1".into()).await.unwrap();
+    client.set("{foo}b", "2".into()).await.unwrap();
+
+    // Start moving the slot of "foo" from `b` to `a`
+    let mut a_client = client::connect(a).await.unwrap();
+    let mut b_client = client::connect(b).await.unwrap();
+    let a_id = node_id(&mut a_client).await;
+    let b_id = node_id(&mut b_client).await;
+    let slot = slot("foo");
+
+    a_client
+        .command(&["cluster", "setslot", &slot, "importing", &b_id])
+        .await
+        .unwrap();
+    b_client
+        .command(&["cluster", "setslot", &slot, "migrating", &a_id])
+        .await
+        .unwrap();
+    migrate(&mut b_client, a, "{foo}a").await;
+
+    let values = client.get_many(&["{foo}a", "{foo}b"]).await.unwrap();
+    assert_eq!(Some(&b"1"[..]), values[0].as_deref());
+    assert_eq!(Some(&b"2"[..]), values[1].as_deref());
+
+    // Finish the migration
+    migrate(&mut b_client, a, "{foo}b").await;
+    for node in [&mut a_client, &mut b_client] {
+        node.command(&["cluster", "setslot", &slot, "node", &a_id])
+            .await
+            .unwrap();
+    }
+
+    client.set("{foo}a", "3".into()).await.unwrap();
+    let value = a_client.get("{foo}a").await.unwrap().unwrap();
+    assert_eq!(b"3", &value[..]);
+
+    let value = client.get("{foo}b").await.unwrap().unwrap();
+    assert_eq!(b"2", &value[..]);
+}
+
+/// Starts two cluster nodes that know each other. The slots before the one
+/// of "foo" are served by the first one and the rest by the second.
+async fn start_cluster() -> (SocketAddr, SocketAddr) {
+    let (a, b) = (start_server().await, start_server().await);
+    let mut a_client = client::connect(a).await.unwrap();
+    let mut b_client = client::connect(b).await.unwrap();
+
+    let foo_slot = cluster::key_slot(b"foo");
+    add_slots(&mut a_client, 0, foo_slot).await;
+    add_slots(&mut b_client, foo_slot, cluster::SLOTS).await;
+
+    a_client
+        .command(&["cluster", "meet", "127.0.0.1", &b.port().to_string()])
+        .await
+        .unwrap();
+    b_client
+        .command(&["cluster", "meet", "127.0.0.1", &a.port().to_string()])
+        .await
+        .unwrap();
+
+    (a, b)
+}
+
+async fn add_slots(client: &mut Client, start: u16, end: u16) {
+    let slots: Vec<_> = (start..end).map(|slot| slot.to_string()).collect();
+    let args: Vec<_> = ["cluster", "addslots"]
+        .into_iter()
+        .chain(slots.iter().map(String::as_str))
+        .collect();
+
+    client.command(&args).await.unwrap();
+}
+
+async fn migrate(client: &mut Client, target: SocketAddr, key: &str) {
+    let port = target.port().to_string();
+    let reply = client
+        .command(&["migrate", "127.0.0.1", &port, key, "0", "1000"])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+}
+
+async fn node_id(client: &mut Client) -> String {
+    match client.command(&["cluster", "myid"]).await.unwrap() {
+        Frame::Bulk(id) => String::from_utf8(id.to_vec()).unwrap(),
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+fn slot(key: &str) -> String {
+    cluster::key_slot(key.as_bytes()).to_string()
+}
+
+async fn start_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = ServerConfig {
+        cluster_enabled: true,
+        ..ServerConfig::default()
+    };
+
+    tokio::spawn(server::run_with_config(
+        listener,
+        config,
+        std::future::pending::<()>(),
+    ));
+
+    addr
+}
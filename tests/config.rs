@@ -0,0 +1,241 @@
+use mini_redis::client::{self, Client};
+use mini_redis::config::Config;
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::{Error, Frame};
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+
+/// The config file is loaded with the command line overrides on top, and
+/// rewritten with CONFIG REWRITE keeping its comments.
+#[tokio::test]
+async fn config_file() {
+    let path = temp_path("file");
+    let dir = std::env::temp_dir();
+    std::fs::write(
+        &path,
+        format!(
+            "# Network\n\
+             bind 127.0.0.1 ::1\n\
+             timeout 30\n\
+             \n\
+             # Persistence\n\
+             dir {}\n\
+             dbfilename {}.rdb\n\
+             save 900 1\n\
+             save 60 10000\n\
+             maxclients 100\n",
+            dir.display(),
+            path.file_stem().unwrap().to_str().unwrap(),
+        ),
+    )
+    .unwrap();
+
+    let mut config = Config::load(&path).unwrap();
+    config.set("maxclients", "50").unwrap();
+    assert_eq!(Some("900 1 60 10000"), config.get("save"));
+    assert!(config.set("maxclients", "many").is_err());
+    assert!(config.set("nope", "1").is_err());
+
+    let server_config = config.server_config().unwrap();
+    assert_eq!(2, server_config.bind.len());
+    assert_eq!(50, server_config.maxclients);
+    assert_eq!(Duration::from_secs(30), server_config.timeout);
+    assert_eq!(
+        path.with_extension("rdb"),
+        server_config.snapshot.as_ref().unwrap().path
+    );
+
+    let addr = start_server(server_config).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let reply = client.command(&["config", "set", "timeout", "0"]).await;
+    assert_eq!(reply.unwrap(), "OK");
+    let reply = client.command(&["config", "rewrite"]).await;
+    assert_eq!(reply.unwrap(), "OK");
+
+    let rewritten = std::fs::read_to_string(&path).unwrap();
+    assert!(rewritten.starts_with("# Network\n"), "{}", rewritten);
+    assert!(rewritten.contains("# Persistence\n"), "{}", rewritten);
+    assert!(rewritten.contains("timeout 0\n"), "{}", rewritten);
+    assert!(rewritten.contains("maxclients 50\n"), "{}", rewritten);
+    assert!(rewritten.contains("save 900 1 60 10000\n"), "{}", rewritten);
+
+    // The rewritten file loads back to the same configuration
+    let config = Config::load(&path).unwrap();
+    assert_eq!(Some("0"), config.get("timeout"));
+    assert_eq!(Some("900 1 60 10000"), config.get("save"));
+
+    // Errors point to the offending line
+    std::fs::write(&path, "port 6379\nport many\n").unwrap();
+    let err = Config::load(&path).unwrap_err().to_string();
+    assert!(err.contains(":2:"), "{}", err);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+/// CONFIG GET matches parameter names with globs and CONFIG SET only
+/// changes the runtime tunable parameters, all of them or none.
+#[tokio::test]
+async fn config_get_and_set() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let params = pairs(client.command(&["config", "get", "max*"]).await.unwrap());
+    assert_eq!(
+        vec![
+            ("max-multibulk-len".to_string(), "1048576".to_string()),
+            ("max-nesting-depth".to_string(), "32".to_string()),
+            ("maxclients".to_string(), "10000".to_string()),
+        ],
+        params
+    );
+
+    let reply = client
+        .command(&["config", "set", "timeout", "60", "max-nesting-depth", "8"])
+        .await;
+    assert_eq!(reply.unwrap(), "OK");
+    let params = pairs(
+        client
+            .command(&["config", "get", "timeout", "max-nesting-depth"])
+            .await
+            .unwrap(),
+    );
+    assert_eq!(
+        vec![
+            ("max-nesting-depth".to_string(), "8".to_string()),
+            ("timeout".to_string(), "60".to_string()),
+        ],
+        params
+    );
+
+    // Nothing is changed when one of the parameters can not be set
+    match client
+        .command(&["config", "set", "timeout", "5", "port", "1"])
+        .await
+    {
+        Err(Error::Server(err)) => assert_eq!(
+            "CONFIG SET failed (possibly related to argument 'port') - can't set immutable config",
+            err.message()
+        ),
+        res => panic!("unexpected result {:?}", res),
+    }
+    match client.command(&["config", "set", "nope", "1"]).await {
+        Err(Error::Server(err)) => assert_eq!(
+            "Unknown option or number of arguments for CONFIG SET - 'nope'",
+            err.message()
+        ),
+        res => panic!("unexpected result {:?}", res),
+    }
+    let params = pairs(client.command(&["config", "get", "timeout"]).await.unwrap());
+    assert_eq!(vec![("timeout".to_string(), "60".to_string())], params);
+
+    // Without a config file there is nothing to rewrite
+    assert!(client.command(&["config", "rewrite"]).await.is_err());
+
+    // Setting `requirepass` takes effect on new connections
+    let reply = client
+        .command(&["config", "set", "requirepass", "secret"])
+        .await;
+    assert_eq!(reply.unwrap(), "OK");
+    let mut other = client::connect(addr).await.unwrap();
+    assert!(other.get("foo").await.is_err());
+}
+
+/// Connections idle for longer than `timeout` are closed.
+#[tokio::test]
+async fn idle_timeout() {
+    let addr = start_server(ServerConfig {
+        timeout: Duration::from_secs(1),
+        ..ServerConfig::default()
+    })
+    .await;
+
+    let mut idle = client::connect(addr).await.unwrap();
+    let mut active = client::connect(addr).await.unwrap();
+
+    for _ in 0..3 {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        active.ping(None).await.unwrap();
+    }
+
+    assert!(idle.ping(None).await.is_err());
+}
+
+/// INFO reports the clients and command counters, which CONFIG RESETSTAT
+/// sets back to zero.
+#[tokio::test]
+async fn info_and_resetstat() {
+    let addr = start_server(ServerConfig::default()).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("foo", "bar".into()).await.unwrap();
+    client.get("foo").await.unwrap();
+    client.get("missing").await.unwrap();
+
+    let info = server_info(&mut client).await;
+    assert!(info.contains("# Clients\r\n"), "{}", info);
+    assert!(info.contains("connected_clients:1\r\n"), "{}", info);
+    assert!(info.contains("keyspace_hits:1\r\n"), "{}", info);
+    assert!(info.contains("keyspace_misses:1\r\n"), "{}", info);
+    assert!(info.contains("total_commands_processed:4\r\n"), "{}", info);
+
+    let reply = client.command(&["config", "resetstat"]).await;
+    assert_eq!(reply.unwrap(), "OK");
+
+    let info = server_info(&mut client).await;
+    assert!(info.contains("connected_clients:1\r\n"), "{}", info);
+    assert!(info.contains("keyspace_hits:0\r\n"), "{}", info);
+    assert!(info.contains("total_commands_processed:1\r\n"), "{}", info);
+}
+
+async fn server_info(client: &mut Client) -> String {
+    match client.command(&["info"]).await.unwrap() {
+        Frame::Bulk(info) => String::from_utf8(info.to_vec()).unwrap(),
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+fn pairs(frame: Frame) -> Vec<(String, String)> {
+    let text = |frame: &Frame| match frame {
+        Frame::Bulk(value) => String::from_utf8(value.to_vec()).unwrap(),
+        frame => panic!("unexpected frame {:?}", frame),
+    };
+
+    match frame {
+        Frame::Array(values) => values
+            .chunks(2)
+            .map(|pair| (text(&pair[0]), text(&pair[1])))
+            .collect(),
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+async fn start_server(config: ServerConfig) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(server::run_with_config(
+        listener,
+        config,
+        std::future::pending::<()>(),
+    ));
+
+    addr
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+
+    std::env::temp_dir().join(format!(
+        "mini-redis-{}-{}-{}.conf",
+        name,
+        std::process::id(),
+        nanos
+    ))
+}
@@ -0,0 +1,92 @@
+use mini_redis::{client, DbDropGuard, ServerErrorKind};
+
+use bytes::Bytes;
+use std::time::Duration;
+
+/// Keys, expirations and pub/sub used directly on a `Db`.
+#[tokio::test]
+async fn keyspace_and_pub_sub() {
+    let guard = DbDropGuard::new();
+    let db = guard.db();
+
+    db.set("hello".to_string(), "world".into(), None);
+    db.set(
+        "temp".to_string(),
+        "value".into(),
+        Some(Duration::from_millis(100)),
+    );
+    assert_eq!(Some(Bytes::from("world")), db.get("hello"));
+    assert_eq!(Some(Bytes::from("value")), db.get("temp"));
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(None, db.get("temp"));
+
+    assert_eq!(1, db.del(&["hello", "missing"]));
+    assert_eq!(None, db.get("hello"));
+
+    let mut subscriber = db.subscribe("news".to_string());
+    assert_eq!(1, db.publish("news", "hi".into()));
+    assert_eq!(Bytes::from("hi"), subscriber.recv().await.unwrap());
+    assert_eq!(0, db.publish("other", "hi".into()));
+}
+
+/// `dump` and `restore` behave as DUMP and RESTORE.
+#[tokio::test]
+async fn dump_and_restore() {
+    let guard = DbDropGuard::new();
+    let db = guard.db();
+    db.set("src".to_string(), "payload".into(), None);
+
+    let payload = db.dump("src").unwrap();
+    assert!(db.dump("missing").is_none());
+
+    db.restore("dst".to_string(), &payload, None, false)
+        .unwrap();
+    assert_eq!(Some(Bytes::from("payload")), db.get("dst"));
+
+    let err = db
+        .restore("dst".to_string(), &payload, None, false)
+        .unwrap_err();
+    assert_eq!(&ServerErrorKind::BusyKey, err.kind());
+    db.restore("dst".to_string(), &payload, None, true).unwrap();
+
+    let err = db
+        .restore("bad".to_string(), b"garbage", None, false)
+        .unwrap_err();
+    assert_eq!("DUMP payload version or checksum are wrong", err.message());
+}
+
+/// An in-process client sees the same keys as the `Db` it was created for,
+/// with the same replies a remote client gets.
+#[tokio::test]
+async fn in_process_client() {
+    let guard = DbDropGuard::new();
+    let db = guard.db();
+    let mut client = client::connect_in_process(&db);
+
+    client.set("foo", "bar".into()).await.unwrap();
+    assert_eq!(Some(Bytes::from("bar")), db.get("foo"));
+
+    db.set("hello".to_string(), "world".into(), None);
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+
+    let payload = client.dump("hello").await.unwrap().unwrap();
+    assert_eq!(db.dump("hello").unwrap(), payload);
+
+    let pong = client.ping(None).await.unwrap();
+    assert_eq!(b"PONG", &pong[..]);
+
+    // Pub/sub between in-process clients and the `Db`
+    let mut subscriber = client::connect_in_process(&db)
+        .subscribe(vec!["news".to_string()])
+        .await
+        .unwrap();
+    client.publish("news", "from client".into()).await.unwrap();
+    db.publish("news", "from db".into());
+
+    let message = subscriber.next_message().await.unwrap().unwrap();
+    assert_eq!(Bytes::from("from client"), message.content);
+    let message = subscriber.next_message().await.unwrap().unwrap();
+    assert_eq!(Bytes::from("from db"), message.content);
+}
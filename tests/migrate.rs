@@ -0,0 +1,184 @@
+use mini_redis::client::{self, Client, Credentials};
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::{Error, Frame};
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// MIGRATE moves a key with its TTL and deletes it from the source.
+#[tokio::test]
+async fn migrate_key() {
+    let source = start_server(None).await;
+    let target = start_server(None).await;
+
+    let mut client = client::connect(source).await.unwrap();
+    client
+        .set_expires("hello", "world".into(), Duration::from_secs(60))
+        .await
+        .unwrap();
+
+    let reply = migrate(&mut client, target, &["hello"], &[]).await.unwrap();
+    assert_eq!(reply, "OK");
+
+    assert!(client.get("hello").await.unwrap().is_none());
+
+    let mut target_client = client::connect(target).await.unwrap();
+    let value = target_client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+
+    // Nothing left to migrate
+    let reply = migrate(&mut client, target, &["hello"], &[]).await;
+    assert_eq!(reply.unwrap(), "NOKEY");
+}
+
+/// With KEYS and COPY several keys are copied and kept in the source.
+#[tokio::test]
+async fn migrate_keys_copy() {
+    let source = start_server(None).await;
+    let target = start_server(None).await;
+
+    let mut client = client::connect(source).await.unwrap();
+    client.set("one", "1".into()).await.unwrap();
+    client.set("two", "2".into()).await.unwrap();
+
+    let reply = migrate(&mut client, target, &["one", "two", "missing"], &["COPY"])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+
+    let mut target = client::connect(target).await.unwrap();
+    for (key, value) in [("one", "1"), ("two", "2")] {
+        let copied = target.get(key).await.unwrap().unwrap();
+        assert_eq!(value.as_bytes(), &copied[..]);
+
+        let kept = client.get(key).await.unwrap().unwrap();
+        assert_eq!(value.as_bytes(), &kept[..]);
+    }
+    assert!(target.get("missing").await.unwrap().is_none());
+}
+
+/// A key that already exists in the target is only overwritten with REPLACE,
+/// and a key the target rejects is kept in the source.
+#[tokio::test]
+async fn migrate_existing_key() {
+    let source = start_server(None).await;
+    let target = start_server(None).await;
+
+    let mut client = client::connect(source).await.unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+
+    let mut target_client = client::connect(target).await.unwrap();
+    target_client.set("hello", "old".into()).await.unwrap();
+
+    match migrate(&mut client, target, &["hello"], &[]).await {
+        Err(Error::Server(err)) => assert!(
+            err.message()
+                .starts_with("Target instance replied with error: BUSYKEY"),
+            "{}",
+            err
+        ),
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(client.get("hello").await.unwrap().is_some());
+
+    let reply = migrate(&mut client, target, &["hello"], &["REPLACE"])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+
+    assert!(client.get("hello").await.unwrap().is_none());
+    let value = target_client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+}
+
+/// With AUTH or AUTH2 the connection with the target is authenticated, and
+/// credentials the target rejects fail the migration.
+#[tokio::test]
+async fn migrate_auth() {
+    let source = start_server(None).await;
+    let target = start_server(Some("secret")).await;
+
+    let mut client = client::connect(source).await.unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+
+    for options in [&[][..], &["AUTH", "wrong"]] {
+        match migrate(&mut client, target, &["hello"], options).await {
+            Err(Error::Server(err)) => assert!(
+                err.message()
+                    .starts_with("Target instance replied with error:"),
+                "{}",
+                err
+            ),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+    assert!(client.get("hello").await.unwrap().is_some());
+
+    let reply = migrate(
+        &mut client,
+        target,
+        &["hello"],
+        &["AUTH2", "default", "secret"],
+    )
+    .await
+    .unwrap();
+    assert_eq!(reply, "OK");
+
+    let credentials = Credentials::new("secret");
+    let mut target_client = client::connect_with_credentials(target, &credentials)
+        .await
+        .unwrap();
+    let value = target_client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+}
+
+/// Sends `MIGRATE` for `keys` to the server `client` is connected to.
+async fn migrate(
+    client: &mut Client,
+    target: SocketAddr,
+    keys: &[&str],
+    options: &[&str],
+) -> mini_redis::Result<Frame> {
+    let mut args = vec![
+        "migrate".to_string(),
+        target.ip().to_string(),
+        target.port().to_string(),
+    ];
+
+    if let [key] = keys {
+        args.push(key.to_string());
+    } else {
+        args.push(String::new());
+    }
+
+    args.push("0".to_string());
+    args.push("1000".to_string());
+    args.extend(options.iter().map(|option| option.to_string()));
+
+    if keys.len() > 1 {
+        args.push("KEYS".to_string());
+        args.extend(keys.iter().map(|key| key.to_string()));
+    }
+
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    client.command(&args).await
+}
+
+async fn start_server(requirepass: Option<&str>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = ServerConfig {
+        requirepass: requirepass.map(str::to_string),
+        ..ServerConfig::default()
+    };
+
+    tokio::spawn(server::run_with_config(
+        listener,
+        config,
+        std::future::pending::<()>(),
+    ));
+
+    addr
+}
@@ -0,0 +1,204 @@
+use mini_redis::client::{self, Client};
+use mini_redis::rdb::{RdbConfig, SavePoint};
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::{Error, Frame};
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+
+/// SAVE writes a snapshot that is loaded when a new server starts.
+#[tokio::test]
+async fn save_and_load() {
+    let path = rdb_path("save");
+
+    let server = TestServer::start(RdbConfig::new(&path)).await;
+    let mut client = server.client().await;
+
+    client.set("hello", "world".into()).await.unwrap();
+    client
+        .set_expires("long", "lived".into(), Duration::from_secs(3600))
+        .await
+        .unwrap();
+    client
+        .set_expires("short", "lived".into(), Duration::from_millis(100))
+        .await
+        .unwrap();
+
+    assert_eq!(client.command(&["save"]).await.unwrap(), "OK");
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    match client.command(&["lastsave"]).await.unwrap() {
+        Frame::Integer(time) => assert!(now - time <= 1),
+        frame => panic!("unexpected frame: {:?}", frame),
+    }
+
+    drop(client);
+    server.stop().await;
+
+    // `short` expires before the new server starts
+    time::sleep(Duration::from_millis(200)).await;
+
+    let server = TestServer::start(RdbConfig::new(&path)).await;
+    let mut client = server.client().await;
+
+    assert_eq!(b"world", &client.get("hello").await.unwrap().unwrap()[..]);
+    assert_eq!(b"lived", &client.get("long").await.unwrap().unwrap()[..]);
+    assert!(client.get("short").await.unwrap().is_none());
+
+    drop(client);
+    server.stop().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+/// BGSAVE replies right away and writes the snapshot in the background.
+#[tokio::test]
+async fn bgsave() {
+    let path = rdb_path("bgsave");
+
+    let server = TestServer::start(RdbConfig::new(&path)).await;
+    let mut client = server.client().await;
+
+    client.set("hello", "world".into()).await.unwrap();
+
+    assert_eq!(
+        client.command(&["bgsave"]).await.unwrap(),
+        "Background saving started"
+    );
+
+    wait_for_file(&path).await;
+
+    drop(client);
+    server.stop().await;
+
+    let server = TestServer::start(RdbConfig::new(&path)).await;
+    let mut client = server.client().await;
+
+    assert_eq!(b"world", &client.get("hello").await.unwrap().unwrap()[..]);
+
+    drop(client);
+    server.stop().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+/// A save point triggers a snapshot once enough writes were performed.
+#[tokio::test]
+async fn save_point() {
+    let path = rdb_path("savepoint");
+
+    let config = RdbConfig {
+        path: path.clone(),
+        save_points: SavePoint::parse_list("1 2").unwrap(),
+    };
+
+    let server = TestServer::start(config).await;
+    let mut client = server.client().await;
+
+    // A single write is not enough
+    client.set("hello", "world".into()).await.unwrap();
+    time::sleep(Duration::from_millis(2100)).await;
+    assert!(!path.exists());
+
+    client.set("hello", "again".into()).await.unwrap();
+    wait_for_file(&path).await;
+
+    drop(client);
+    server.stop().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+/// A snapshot that fails its checksum prevents the server from starting.
+#[tokio::test]
+async fn corrupted_snapshot_is_rejected() {
+    let path = rdb_path("corrupted");
+
+    let server = TestServer::start(RdbConfig::new(&path)).await;
+    let mut client = server.client().await;
+    client.set("hello", "world".into()).await.unwrap();
+    assert_eq!(client.command(&["save"]).await.unwrap(), "OK");
+    drop(client);
+    server.stop().await;
+
+    let mut data = std::fs::read(&path).unwrap();
+    let pos = data.len() - 8;
+    data[pos] ^= 0xff;
+    std::fs::write(&path, data).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = ServerConfig {
+        snapshot: Some(RdbConfig::new(&path)),
+        ..ServerConfig::default()
+    };
+    let res = server::run_with_config(listener, config, std::future::pending::<()>()).await;
+    assert!(matches!(res, Err(Error::Protocol(_))), "{:?}", res);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+async fn wait_for_file(path: &Path) {
+    for _ in 0..100 {
+        if path.exists() {
+            return;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("{:?} was not written", path);
+}
+
+struct TestServer {
+    addr: SocketAddr,
+    shutdown_tx: oneshot::Sender<()>,
+    handle: JoinHandle<mini_redis::Result<()>>,
+}
+
+impl TestServer {
+    async fn start(snapshot: RdbConfig) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let config = ServerConfig {
+            snapshot: Some(snapshot),
+            ..ServerConfig::default()
+        };
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(server::run_with_config(listener, config, shutdown_rx));
+
+        TestServer {
+            addr,
+            shutdown_tx,
+            handle,
+        }
+    }
+
+    async fn client(&self) -> Client {
+        client::connect(self.addr).await.unwrap()
+    }
+
+    async fn stop(self) {
+        let _ = self.shutdown_tx.send(());
+        self.handle.await.unwrap().unwrap();
+    }
+}
+
+fn rdb_path(name: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+
+    std::env::temp_dir().join(format!(
+        "mini-redis-{}-{}-{}.rdb",
+        name,
+        std::process::id(),
+        nanos
+    ))
+}
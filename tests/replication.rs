@@ -0,0 +1,305 @@
+use mini_redis::client::{self, Client, Credentials};
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::{Connection, Error, Frame, ServerErrorKind};
+
+use bytes::Bytes;
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{self, Duration};
+
+/// A replica receives the master's keys on the initial sync and every write
+/// afterwards, and rejects writes from its own clients.
+#[tokio::test]
+async fn replica_follows_master() {
+    let master = start_server(None).await;
+
+    let mut master_client = client::connect(master).await.unwrap();
+    master_client.set("hello", "world".into()).await.unwrap();
+
+    let replica = start_server(Some(master)).await;
+    let mut replica_client = client::connect(replica).await.unwrap();
+
+    // Initial sync
+    wait_for(&mut replica_client, "hello", Some("world")).await;
+
+    // Streamed writes
+    master_client.set("foo", "bar".into()).await.unwrap();
+    wait_for(&mut replica_client, "foo", Some("bar")).await;
+
+    master_client.command(&["del", "foo"]).await.unwrap();
+    wait_for(&mut replica_client, "foo", None).await;
+
+    match replica_client.set("foo", "baz".into()).await {
+        Err(Error::Server(err)) => assert_eq!(&ServerErrorKind::ReadOnly, err.kind()),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+/// A replica of a master that requires a password authenticates with
+/// `masterauth`.
+#[tokio::test]
+async fn replica_authenticates() {
+    let master = start_with_config(ServerConfig {
+        requirepass: Some("secret".to_string()),
+        ..ServerConfig::default()
+    })
+    .await;
+
+    let credentials = Credentials::new("secret");
+    let mut master_client = client::connect_with_credentials(master, &credentials)
+        .await
+        .unwrap();
+    master_client.set("hello", "world".into()).await.unwrap();
+
+    let replica = start_with_config(ServerConfig {
+        replicaof: Some((master.ip().to_string(), master.port())),
+        masterauth: Some("secret".to_string()),
+        ..ServerConfig::default()
+    })
+    .await;
+
+    let mut replica_client = client::connect(replica).await.unwrap();
+    wait_for(&mut replica_client, "hello", Some("world")).await;
+}
+
+/// `REPLICAOF` starts replicating at runtime, does nothing when given the
+/// current master, and `REPLICAOF NO ONE` turns the replica back into a
+/// writable master that keeps its data.
+#[tokio::test]
+async fn replicaof_command() {
+    let master = start_server(None).await;
+    let replica = start_server(None).await;
+
+    let mut master_client = client::connect(master).await.unwrap();
+    master_client.set("hello", "world".into()).await.unwrap();
+
+    let mut replica_client = client::connect(replica).await.unwrap();
+    replica_client.set("stale", "value".into()).await.unwrap();
+
+    let port = master.port().to_string();
+    let reply = replica_client
+        .command(&["replicaof", "127.0.0.1", &port])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+
+    // The full sync replaces the replica's data
+    wait_for(&mut replica_client, "hello", Some("world")).await;
+    assert!(replica_client.get("stale").await.unwrap().is_none());
+
+    // The same master, even written another way, keeps the link
+    let reply = replica_client
+        .command(&["replicaof", "localhost", &port])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK Already connected to specified master");
+
+    let reply = replica_client
+        .command(&["replicaof", "no", "one"])
+        .await
+        .unwrap();
+    assert_eq!(reply, "OK");
+
+    replica_client.set("foo", "bar".into()).await.unwrap();
+    master_client.set("hello", "again".into()).await.unwrap();
+
+    time::sleep(Duration::from_millis(100)).await;
+    let value = replica_client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+}
+
+/// A replica reconnecting with the replication ID and offset it already has
+/// only receives the writes it missed.
+#[tokio::test]
+async fn partial_resync() {
+    let master = start_server(None).await;
+
+    let mut master_client = client::connect(master).await.unwrap();
+    master_client.set("one", "1".into()).await.unwrap();
+
+    // Full sync
+    let mut replica = connect(master).await;
+    let reply = psync(&mut replica, "?", "-1").await;
+    let parts: Vec<_> = reply.split(' ').collect();
+    assert_eq!("FULLRESYNC", parts[0]);
+    let replid = parts[1].to_string();
+    let mut offset: u64 = parts[2].parse().unwrap();
+
+    match replica.read_frame().await.unwrap().unwrap() {
+        Frame::Bulk(_) => {}
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+
+    master_client.set("two", "2".into()).await.unwrap();
+    offset += read_write(&mut replica, "two").await;
+    drop(replica);
+
+    // Writes missed while disconnected
+    master_client.set("three", "3".into()).await.unwrap();
+    master_client.set("four", "4".into()).await.unwrap();
+
+    let mut replica = connect(master).await;
+    let reply = psync(&mut replica, &replid, &(offset + 1).to_string()).await;
+    assert_eq!(format!("CONTINUE {}", replid), reply);
+
+    read_write(&mut replica, "three").await;
+    read_write(&mut replica, "four").await;
+
+    // An unknown replication ID requires a full sync
+    let mut replica = connect(master).await;
+    let reply = psync(&mut replica, "0000", &(offset + 1).to_string()).await;
+    assert!(reply.starts_with("FULLRESYNC"), "{}", reply);
+}
+
+/// WAIT returns once enough replicas acknowledged the connection's writes,
+/// or when the timeout elapses with the number of replicas that did.
+#[tokio::test]
+async fn wait_for_replicas() {
+    let master = start_server(None).await;
+    let mut replicas = vec![];
+    for _ in 0..2 {
+        let replica = start_server(Some(master)).await;
+        replicas.push(client::connect(replica).await.unwrap());
+    }
+
+    let mut master_client = client::connect(master).await.unwrap();
+    master_client.set("hello", "world".into()).await.unwrap();
+    for replica in &mut replicas {
+        wait_for(replica, "hello", Some("world")).await;
+    }
+
+    master_client.set("foo", "bar".into()).await.unwrap();
+
+    // The replicas are asked to acknowledge right away
+    let start = time::Instant::now();
+    let acked = master_client.wait(2, Duration::from_secs(5)).await.unwrap();
+    assert_eq!(2, acked);
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    // Not enough replicas: WAIT times out
+    let start = time::Instant::now();
+    let acked = master_client
+        .wait(3, Duration::from_millis(200))
+        .await
+        .unwrap();
+    assert_eq!(2, acked);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    match replicas[0].wait(1, Duration::from_millis(100)).await {
+        Err(Error::Server(err)) => {
+            assert_eq!("WAIT cannot be used with replica instances.", err.message())
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+/// ROLE reports the master's replicas, with the port they listen on, and the
+/// replica's master and link state.
+#[tokio::test]
+async fn role_command() {
+    let master = start_server(None).await;
+    let replica = start_server(Some(master)).await;
+
+    let mut master_client = client::connect(master).await.unwrap();
+    master_client.set("hello", "world".into()).await.unwrap();
+    let mut replica_client = client::connect(replica).await.unwrap();
+    wait_for(&mut replica_client, "hello", Some("world")).await;
+
+    match master_client.command(&["role"]).await.unwrap() {
+        Frame::Array(parts) => {
+            assert_eq!(parts[0], "master");
+            match &parts[2] {
+                Frame::Array(replicas) if replicas.len() == 1 => match &replicas[0] {
+                    Frame::Array(replica_info) => {
+                        assert_eq!(replica_info[0], "127.0.0.1");
+                        assert_eq!(replica_info[1], &replica.port().to_string()[..]);
+                    }
+                    frame => panic!("unexpected frame {:?}", frame),
+                },
+                frame => panic!("unexpected frame {:?}", frame),
+            }
+        }
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+
+    match replica_client.command(&["role"]).await.unwrap() {
+        Frame::Array(parts) => {
+            assert_eq!(parts[0], "slave");
+            assert_eq!(parts[1], "127.0.0.1");
+            assert!(matches!(parts[2], Frame::Integer(port) if port == master.port() as u64));
+            assert_eq!(parts[3], "connected");
+        }
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+async fn connect(addr: SocketAddr) -> Connection<TcpStream> {
+    Connection::new(TcpStream::connect(addr).await.unwrap())
+}
+
+async fn psync(connection: &mut Connection<TcpStream>, replid: &str, offset: &str) -> String {
+    let frame = Frame::Array(
+        ["psync", replid, offset]
+            .iter()
+            .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+            .collect(),
+    );
+    connection.write_frame(&frame).await.unwrap();
+
+    match connection.read_frame().await.unwrap().unwrap() {
+        Frame::Simple(reply) => reply,
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+/// Reads a replicated `SET` of `key` and returns its size in bytes.
+async fn read_write(connection: &mut Connection<TcpStream>, key: &str) -> u64 {
+    let frame = time::timeout(Duration::from_secs(1), connection.read_frame())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    match &frame {
+        Frame::Array(parts) => assert_eq!(parts[1], key),
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+
+    let mut buf = Vec::new();
+    frame.encode(&mut buf);
+    buf.len() as u64
+}
+
+/// Waits until `key` holds `value` in the server `client` is connected to.
+async fn wait_for(client: &mut Client, key: &str, value: Option<&str>) {
+    for _ in 0..100 {
+        let current = client.get(key).await.unwrap();
+        if current.as_deref() == value.map(str::as_bytes) {
+            return;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("{} did not reach {:?}", key, value);
+}
+
+async fn start_server(master: Option<SocketAddr>) -> SocketAddr {
+    start_with_config(ServerConfig {
+        replicaof: master.map(|master| (master.ip().to_string(), master.port())),
+        ..ServerConfig::default()
+    })
+    .await
+}
+
+async fn start_with_config(config: ServerConfig) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(server::run_with_config(
+        listener,
+        config,
+        std::future::pending::<()>(),
+    ));
+
+    addr
+}
@@ -0,0 +1,172 @@
+use mini_redis::client::{self, Client};
+use mini_redis::sentinel::{self, MasterConfig, SentinelConfig};
+use mini_redis::server::{self, ServerConfig};
+use mini_redis::Frame;
+
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::time::{self, Duration};
+
+/// When the master goes down the sentinels agree on it, promote one of the
+/// replicas and make the other one follow it.
+#[tokio::test]
+async fn failover_to_replica() {
+    let (master, stop_master) = start_server(None).await;
+    let (replica_a, _stop_a) = start_server(Some(master)).await;
+    let (replica_b, _stop_b) = start_server(Some(master)).await;
+
+    let mut master_client = client::connect(master).await.unwrap();
+    master_client.set("hello", "world".into()).await.unwrap();
+    for replica in [replica_a, replica_b] {
+        let mut client = client::connect(replica).await.unwrap();
+        wait_for(&mut client, "hello", Some("world")).await;
+    }
+
+    let sentinels = start_sentinels(master, 3).await;
+    let mut sentinel_client = client::connect(sentinels[0]).await.unwrap();
+    assert_eq!(Some(master), master_addr(&mut sentinel_client).await);
+
+    // Let the sentinels learn the replicas before the master fails
+    time::sleep(Duration::from_millis(300)).await;
+    stop_master.send(()).unwrap();
+    drop(master_client);
+
+    let promoted = wait_for_failover(&mut sentinel_client, master).await;
+    assert!(
+        promoted == replica_a || promoted == replica_b,
+        "{}",
+        promoted
+    );
+
+    // Every sentinel reports the new master
+    for sentinel in &sentinels[1..] {
+        let mut client = client::connect(sentinel).await.unwrap();
+        assert_eq!(promoted, wait_for_failover(&mut client, master).await);
+    }
+
+    // The other replica follows the new master
+    let other = if promoted == replica_a {
+        replica_b
+    } else {
+        replica_a
+    };
+    let mut promoted_client = client::connect(promoted).await.unwrap();
+    promoted_client.set("foo", "bar".into()).await.unwrap();
+
+    let mut other_client = client::connect(other).await.unwrap();
+    wait_for(&mut other_client, "foo", Some("bar")).await;
+}
+
+/// Unknown masters have no address.
+#[tokio::test]
+async fn unknown_master() {
+    let (master, _stop) = start_server(None).await;
+    let sentinels = start_sentinels(master, 1).await;
+    let mut client = client::connect(sentinels[0]).await.unwrap();
+
+    let reply = client
+        .command(&["sentinel", "get-master-addr-by-name", "other"])
+        .await
+        .unwrap();
+    assert!(matches!(reply, Frame::Null), "{:?}", reply);
+}
+
+/// Waits until the sentinel reports a master other than `old`.
+async fn wait_for_failover(client: &mut Client, old: SocketAddr) -> SocketAddr {
+    for _ in 0..250 {
+        match master_addr(client).await {
+            Some(addr) if addr != old => return addr,
+            _ => time::sleep(Duration::from_millis(20)).await,
+        }
+    }
+
+    panic!("no failover");
+}
+
+async fn master_addr(client: &mut Client) -> Option<SocketAddr> {
+    match client
+        .command(&["sentinel", "get-master-addr-by-name", "mymaster"])
+        .await
+        .unwrap()
+    {
+        Frame::Array(parts) => {
+            let part = |i: usize| match &parts[i] {
+                Frame::Bulk(value) => String::from_utf8(value.to_vec()).unwrap(),
+                frame => panic!("unexpected frame {:?}", frame),
+            };
+            Some(format!("{}:{}", part(0), part(1)).parse().unwrap())
+        }
+        Frame::Null => None,
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+/// Waits until `key` holds `value` in the server `client` is connected to.
+async fn wait_for(client: &mut Client, key: &str, value: Option<&str>) {
+    for _ in 0..100 {
+        let current = client.get(key).await.unwrap();
+        if current.as_deref() == value.map(str::as_bytes) {
+            return;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("{} did not reach {:?}", key, value);
+}
+
+/// Starts `count` sentinels monitoring `master` that know each other.
+async fn start_sentinels(master: SocketAddr, count: usize) -> Vec<SocketAddr> {
+    let mut listeners = vec![];
+    for _ in 0..count {
+        listeners.push(TcpListener::bind("127.0.0.1:0").await.unwrap());
+    }
+
+    let addrs: Vec<_> = listeners
+        .iter()
+        .map(|listener| listener.local_addr().unwrap())
+        .collect();
+
+    for listener in listeners {
+        let addr = listener.local_addr().unwrap();
+        let config = SentinelConfig {
+            masters: vec![MasterConfig {
+                name: "mymaster".to_string(),
+                host: master.ip().to_string(),
+                port: master.port(),
+                quorum: 2.min(count),
+            }],
+            sentinels: addrs
+                .iter()
+                .filter(|other| **other != addr)
+                .map(|other| (other.ip().to_string(), other.port()))
+                .collect(),
+            down_after: Duration::from_millis(300),
+            failover_timeout: Duration::from_secs(3),
+            ..SentinelConfig::default()
+        };
+
+        tokio::spawn(sentinel::run(
+            listener,
+            config,
+            std::future::pending::<()>(),
+        ));
+    }
+
+    addrs
+}
+
+async fn start_server(master: Option<SocketAddr>) -> (SocketAddr, oneshot::Sender<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = ServerConfig {
+        replicaof: master.map(|master| (master.ip().to_string(), master.port())),
+        ..ServerConfig::default()
+    };
+
+    let (stop, stopped) = oneshot::channel();
+    tokio::spawn(server::run_with_config(listener, config, stopped));
+
+    (addr, stop)
+}
@@ -1,3 +1,4 @@
+use mini_redis::frame::Limits;
 use mini_redis::server;
 
 use std::net::SocketAddr;
@@ -397,6 +398,205 @@ async fn send_error_get_set_after_subscribe() {
     assert_eq!(b"-ERR unknown command \'get\'\r\n", &response);
 }
 
+// Several requests sent in a single write are all answered, in order.
+#[tokio::test]
+async fn pipelined_requests() {
+    let addr = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(
+            b"*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n$5\r\nworld\r\n\
+              *2\r\n$3\r\nGET\r\n$5\r\nhello\r\n\
+              *1\r\n$4\r\nPING\r\n",
+        )
+        .await
+        .unwrap();
+
+    let mut response = [0; 23];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(&b"+OK\r\n$5\r\nworld\r\n+PONG\r\n"[..], &response[..]);
+}
+
+// Responses held back while pipelining are sent before a command that
+// blocks, instead of waiting for it to finish.
+#[tokio::test]
+async fn pipelined_requests_before_blocking_command() {
+    let addr = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    // WAIT blocks for its whole timeout, as there are no replicas
+    stream
+        .write_all(
+            b"*1\r\n$4\r\nPING\r\n\
+              *3\r\n$4\r\nWAIT\r\n$1\r\n1\r\n$4\r\n5000\r\n",
+        )
+        .await
+        .unwrap();
+
+    let mut response = [0; 7];
+    time::timeout(Duration::from_secs(1), stream.read_exact(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&b"+PONG\r\n"[..], &response[..]);
+}
+
+// Invalid commands are answered with an error and the connection stays open,
+// as it does with Redis.
+#[tokio::test]
+async fn send_error_invalid_command_keeps_connection() {
+    let addr = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    // GET without a key
+    stream.write_all(b"*1\r\n$3\r\nGET\r\n").await.unwrap();
+
+    let mut response = [0; 50];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        &b"-ERR wrong number of arguments for 'get' command\r\n"[..],
+        &response[..]
+    );
+
+    // GET with too many arguments
+    stream
+        .write_all(b"*3\r\n$3\r\nGET\r\n$5\r\nhello\r\n$5\r\nworld\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 50];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        &b"-ERR wrong number of arguments for 'get' command\r\n"[..],
+        &response[..]
+    );
+
+    // SET with an expiration which is not a number
+    stream
+        .write_all(b"*5\r\n$3\r\nSET\r\n$5\r\nhello\r\n$5\r\nworld\r\n$2\r\nEX\r\n$3\r\nabc\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 46];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        &b"-ERR value is not an integer or out of range\r\n"[..],
+        &response[..]
+    );
+
+    // SET with an unknown option
+    stream
+        .write_all(b"*4\r\n$3\r\nSET\r\n$5\r\nhello\r\n$5\r\nworld\r\n$3\r\nFOO\r\n")
+        .await
+        .unwrap();
+
+    let mut response = [0; 19];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(&b"-ERR syntax error\r\n"[..], &response[..]);
+
+    // The connection is still usable
+    stream.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+
+    let mut response = [0; 7];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+PONG\r\n", &response);
+}
+
+// A client declaring a bulk string larger than the configured limit is
+// answered with an error and disconnected, without the server waiting for the
+// data to arrive.
+#[tokio::test]
+async fn reject_bulk_over_limit() {
+    let addr = start_server_with_limits(Limits {
+        max_bulk_len: 16,
+        ..Limits::default()
+    })
+    .await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(b"*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n$1000\r\n")
+        .await
+        .unwrap();
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await.unwrap();
+    assert_eq!(
+        &b"-ERR protocol error; invalid bulk length\r\n"[..],
+        &response[..]
+    );
+}
+
+// Arrays with too many elements are rejected as soon as the header is read.
+#[tokio::test]
+async fn reject_array_over_limit() {
+    let addr = start_server_with_limits(Limits {
+        max_array_len: 4,
+        ..Limits::default()
+    })
+    .await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream.write_all(b"*5\r\n").await.unwrap();
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await.unwrap();
+    assert_eq!(
+        &b"-ERR protocol error; invalid multibulk length\r\n"[..],
+        &response[..]
+    );
+}
+
+// Deeply nested arrays are rejected before they can exhaust the stack.
+#[tokio::test]
+async fn reject_nesting_over_limit() {
+    let addr = start_server_with_limits(Limits {
+        max_depth: 2,
+        ..Limits::default()
+    })
+    .await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream.write_all(b"*1\r\n*1\r\n*1\r\n").await.unwrap();
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await.unwrap();
+    assert_eq!(
+        &b"-ERR protocol error; max nesting depth exceeded\r\n"[..],
+        &response[..]
+    );
+}
+
+// A frame that never completes can not grow the read buffer past the limit.
+#[tokio::test]
+async fn reject_query_buffer_over_limit() {
+    let addr = start_server_with_limits(Limits {
+        max_query_buffer: 64,
+        ..Limits::default()
+    })
+    .await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    // A simple string without the trailing CRLF is never complete
+    stream.write_all(b"+").await.unwrap();
+    stream.write_all(&[b'a'; 128]).await.unwrap();
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await.unwrap();
+    assert_eq!(
+        &b"-ERR protocol error; max query buffer length exceeded\r\n"[..],
+        &response[..]
+    );
+}
+
 async fn start_server() -> SocketAddr {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
@@ -405,3 +605,14 @@ async fn start_server() -> SocketAddr {
 
     addr
 }
+
+async fn start_server_with_limits(limits: Limits) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        server::run_with_limits(listener, limits, tokio::signal::ctrl_c()).await
+    });
+
+    addr
+}
@@ -0,0 +1,288 @@
+use mini_redis::client::{self, Credentials, TlsOptions};
+use mini_redis::server::{self, Builder, ServerConfig};
+use mini_redis::tls::TlsConfig;
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+
+/// Clients connect over TLS verifying the server certificate. Plain text
+/// clients and clients that do not trust the server CA are rejected.
+#[tokio::test]
+async fn tls_connection() {
+    let certs = Certs::generate("tls");
+    let addr = start_server(certs.server_config(false)).await;
+
+    let mut client = client::connect_tls(addr, &certs.client_options())
+        .await
+        .unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+
+    // Plain text requests are not understood by the server
+    let mut client = client::connect(addr).await.unwrap();
+    assert!(client.get("hello").await.is_err());
+
+    // A server certificate signed by another CA is not trusted
+    let other = Certs::generate("other");
+    let res = client::connect_tls(addr, &other.client_options()).await;
+    assert!(res.is_err());
+
+    // Nor one issued for another name
+    let options = TlsOptions::new("example.com", &certs.ca_cert);
+    assert!(client::connect_tls(addr, &options).await.is_err());
+}
+
+/// When the server verifies clients, only those presenting a certificate
+/// signed by its CA can issue commands.
+#[tokio::test]
+async fn client_certificate() {
+    let certs = Certs::generate("mtls");
+    let addr = start_server(certs.server_config(true)).await;
+
+    // Depending on the TLS version the missing certificate is detected
+    // during the handshake or on the first request.
+    let res = async {
+        let mut client = client::connect_tls(addr, &certs.client_options()).await?;
+        client.ping(None).await
+    };
+    assert!(res.await.is_err());
+
+    let options = certs
+        .client_options()
+        .with_client_cert(&certs.client_cert, &certs.client_key);
+    let mut client = client::connect_tls(addr, &options).await.unwrap();
+    let pong = client.ping(None).await.unwrap();
+    assert_eq!(b"PONG", &pong[..]);
+}
+
+/// Connections that do not complete the TLS handshake are closed after the
+/// idle timeout instead of holding a client slot, and rejecting connections
+/// over the limit does not wait for them either.
+#[tokio::test]
+async fn handshake_timeout() {
+    let certs = Certs::generate("handshake");
+    let server = Builder::new()
+        .listener(TcpListener::bind("127.0.0.1:0").await.unwrap())
+        .config(ServerConfig {
+            tls: Some(certs.server_config(false)),
+            timeout: Duration::from_secs(1),
+            maxclients: 1,
+            ..ServerConfig::default()
+        })
+        .start()
+        .await
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let _stalled = TcpStream::connect(addr).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // A connection over the limit that does not complete the handshake
+    // either is closed without waiting for the client
+    let mut rejected = TcpStream::connect(addr).await.unwrap();
+    let mut reply = vec![];
+    tokio::time::timeout(Duration::from_secs(2), rejected.read_to_end(&mut reply))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(1, server.stats().rejected_connections);
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(1, server.stats().closed_handshake_error);
+
+    let mut client = client::connect_tls(addr, &certs.client_options())
+        .await
+        .unwrap();
+    let pong = client.ping(None).await.unwrap();
+    assert_eq!(b"PONG", &pong[..]);
+
+    server.shutdown().await.unwrap();
+}
+
+/// Invalid certificate files prevent the server from starting.
+#[tokio::test]
+async fn invalid_certificate() {
+    let certs = Certs::generate("invalid");
+    std::fs::write(&certs.server_key, "not a key").unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = ServerConfig {
+        tls: Some(certs.server_config(false)),
+        ..ServerConfig::default()
+    };
+
+    let res = server::run_with_config(listener, config, std::future::pending::<()>()).await;
+    assert!(res.is_err());
+}
+
+/// A replica of a TLS master connects over TLS, presenting its own
+/// certificate, and authenticates with `masterauth`.
+#[tokio::test]
+async fn tls_replication() {
+    let certs = Certs::generate("replication");
+    let master = start_with_config(ServerConfig {
+        tls: Some(certs.server_config(true)),
+        requirepass: Some("secret".to_string()),
+        ..ServerConfig::default()
+    })
+    .await;
+
+    let options = certs
+        .client_options()
+        .with_client_cert(&certs.client_cert, &certs.client_key);
+    let mut master_client = client::connect_tls(master, &options).await.unwrap();
+    master_client
+        .auth(&Credentials::new("secret"))
+        .await
+        .unwrap();
+    master_client.set("hello", "world".into()).await.unwrap();
+
+    let replica = start_with_config(ServerConfig {
+        tls: Some(certs.server_config(true)),
+        replicaof: Some(("localhost".to_string(), master.port())),
+        masterauth: Some("secret".to_string()),
+        ..ServerConfig::default()
+    })
+    .await;
+
+    let mut replica_client = client::connect_tls(replica, &options).await.unwrap();
+    for _ in 0..100 {
+        if replica_client.get("hello").await.unwrap().is_some() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("the replica did not sync with the master");
+}
+
+/// Without a CA to verify the other nodes with, a TLS server cannot start
+/// as a replica.
+#[tokio::test]
+async fn tls_replication_requires_ca() {
+    let certs = Certs::generate("replication-ca");
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = ServerConfig {
+        tls: Some(certs.server_config(false)),
+        replicaof: Some(("localhost".to_string(), 6379)),
+        ..ServerConfig::default()
+    };
+
+    let res = server::run_with_config(listener, config, std::future::pending::<()>()).await;
+    assert!(res.is_err());
+}
+
+/// Self-signed CA with a server and a client certificate signed by it, all
+/// of them written to PEM files.
+struct Certs {
+    ca_cert: PathBuf,
+    server_cert: PathBuf,
+    server_key: PathBuf,
+    client_cert: PathBuf,
+    client_key: PathBuf,
+}
+
+impl Certs {
+    fn generate(name: &str) -> Certs {
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = Certificate::from_params(params).unwrap();
+
+        let server =
+            Certificate::from_params(CertificateParams::new(vec!["localhost".to_string()]))
+                .unwrap();
+        let client =
+            Certificate::from_params(CertificateParams::new(vec!["client".to_string()])).unwrap();
+
+        let certs = Certs {
+            ca_cert: temp_path(name, "ca.crt"),
+            server_cert: temp_path(name, "server.crt"),
+            server_key: temp_path(name, "server.key"),
+            client_cert: temp_path(name, "client.crt"),
+            client_key: temp_path(name, "client.key"),
+        };
+
+        let write = |path: &PathBuf, pem: String| std::fs::write(path, pem).unwrap();
+        write(&certs.ca_cert, ca.serialize_pem().unwrap());
+        write(
+            &certs.server_cert,
+            server.serialize_pem_with_signer(&ca).unwrap(),
+        );
+        write(&certs.server_key, server.serialize_private_key_pem());
+        write(
+            &certs.client_cert,
+            client.serialize_pem_with_signer(&ca).unwrap(),
+        );
+        write(&certs.client_key, client.serialize_private_key_pem());
+
+        certs
+    }
+
+    fn server_config(&self, verify_clients: bool) -> TlsConfig {
+        TlsConfig {
+            cert_file: self.server_cert.clone(),
+            key_file: self.server_key.clone(),
+            ca_cert_file: verify_clients.then(|| self.ca_cert.clone()),
+        }
+    }
+
+    fn client_options(&self) -> TlsOptions {
+        TlsOptions::new("localhost", &self.ca_cert)
+    }
+}
+
+impl Drop for Certs {
+    fn drop(&mut self) {
+        for path in [
+            &self.ca_cert,
+            &self.server_cert,
+            &self.server_key,
+            &self.client_cert,
+            &self.client_key,
+        ] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+async fn start_server(tls: TlsConfig) -> SocketAddr {
+    start_with_config(ServerConfig {
+        tls: Some(tls),
+        ..ServerConfig::default()
+    })
+    .await
+}
+
+async fn start_with_config(config: ServerConfig) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(server::run_with_config(
+        listener,
+        config,
+        std::future::pending::<()>(),
+    ));
+
+    addr
+}
+
+fn temp_path(name: &str, file: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+
+    std::env::temp_dir().join(format!(
+        "mini-redis-{}-{}-{}-{}",
+        name,
+        std::process::id(),
+        nanos,
+        file
+    ))
+}
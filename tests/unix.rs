@@ -0,0 +1,69 @@
+use mini_redis::server::{self, Listeners};
+use mini_redis::{blocking_client, client};
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::{TcpListener, UnixListener};
+
+/// Clients connect to a server listening only on a Unix domain socket.
+#[tokio::test]
+async fn unix_socket() {
+    let path = socket_path("unix");
+    let listener = UnixListener::bind(&path).unwrap();
+    tokio::spawn(server::run(listener, std::future::pending::<()>()));
+
+    let mut client = client::connect_unix(&path).await.unwrap();
+    client.set("hello", "world".into()).await.unwrap();
+    let value = client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+
+    // The blocking client needs its own thread to run its runtime
+    let blocking_path = path.clone();
+    let value = tokio::task::spawn_blocking(move || {
+        let mut client = blocking_client::connect_unix(blocking_path).unwrap();
+        client.get("hello").unwrap()
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(b"world", &value[..]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+/// The same server accepts connections over TCP and the Unix domain socket,
+/// all of them sharing the same keys.
+#[tokio::test]
+async fn tcp_and_unix_socket() {
+    let path = socket_path("both");
+    let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = tcp.local_addr().unwrap();
+    let listeners = Listeners {
+        tcp: vec![tcp],
+        unix: Some(UnixListener::bind(&path).unwrap()),
+    };
+    tokio::spawn(server::run(listeners, std::future::pending::<()>()));
+
+    let mut tcp_client = client::connect(addr).await.unwrap();
+    let mut unix_client = client::connect_unix(&path).await.unwrap();
+
+    tcp_client.set("hello", "world".into()).await.unwrap();
+    let value = unix_client.get("hello").await.unwrap().unwrap();
+    assert_eq!(b"world", &value[..]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+fn socket_path(name: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+
+    std::env::temp_dir().join(format!(
+        "mini-redis-{}-{}-{}.sock",
+        name,
+        std::process::id(),
+        nanos
+    ))
+}